@@ -19,12 +19,195 @@ function M.stop(all)
     return core().stop(all)
 end
 
-function M.show_url()
-    return core().show_url()
+function M.show_url(bufnr)
+    return core().show_url(bufnr)
 end
 
-function M.start()
-    return core().start()
+function M.start(opts)
+    return core().start(opts)
+end
+
+function M.task_progress()
+    return core().task_progress()
+end
+
+function M.server_status()
+    return core().server_status()
+end
+
+function M.open()
+    return core().open()
+end
+
+function M.toc()
+    return core().toc()
+end
+
+function M.format_table()
+    return core().format_table()
+end
+
+function M.lint()
+    return core().lint()
+end
+
+function M.sessions(callback)
+    return core().sessions(callback)
+end
+
+function M.stop_session(bufnr)
+    return core().stop_session(bufnr)
+end
+
+function M.pick()
+    M.sessions(function(sessions)
+        if #sessions == 0 then
+            vim.notify("[live-markdown.nvim] no active preview sessions")
+            return
+        end
+
+        vim.ui.select(sessions, {
+            prompt = "Preview sessions",
+            format_item = function(session)
+                return string.format("%s (buf %d, %d clients)", session.filename, session.bufnr, session.clients)
+            end,
+        }, function(session)
+            if session == nil then
+                return
+            end
+
+            local actions = {
+                {
+                    label = "Jump to buffer",
+                    run = function()
+                        vim.api.nvim_set_current_buf(session.bufnr)
+                    end,
+                },
+                {
+                    label = "Copy URL",
+                    run = function()
+                        vim.fn.setreg("+", session.url)
+                        vim.fn.setreg('"', session.url)
+                    end,
+                },
+                {
+                    label = "Stop session",
+                    run = function()
+                        M.stop_session(session.bufnr)
+                    end,
+                },
+            }
+
+            vim.ui.select(actions, {
+                prompt = session.filename,
+                format_item = function(action)
+                    return action.label
+                end,
+            }, function(action)
+                if action ~= nil then
+                    action.run()
+                end
+            end)
+        end)
+    end)
+end
+
+function M.recent()
+    return core().recent()
+end
+
+function M.pick_recent()
+    local recent = M.recent()
+    if #recent == 0 then
+        vim.notify("[live-markdown.nvim] no recently previewed files")
+        return
+    end
+
+    vim.ui.select(recent, {
+        prompt = "Recent previews",
+        format_item = function(file)
+            return string.format("%s (%s)", vim.fn.fnamemodify(file.path, ":~:."), os.date("%Y-%m-%d %H:%M", file.opened_at))
+        end,
+    }, function(file)
+        if file == nil then
+            return
+        end
+
+        vim.cmd.edit(vim.fn.fnameescape(file.path))
+        M.start()
+    end)
+end
+
+function M.bookmarks(bufnr)
+    return core().bookmarks(bufnr)
+end
+
+function M.pick_bookmark()
+    local bufnr = vim.api.nvim_get_current_buf()
+    local bookmarks = M.bookmarks(bufnr)
+    if #bookmarks == 0 then
+        vim.notify("[live-markdown.nvim] no bookmarks in this preview")
+        return
+    end
+
+    vim.ui.select(bookmarks, {
+        prompt = "Bookmarks",
+        format_item = function(bookmark)
+            return string.format("%d: %s", bookmark.line, bookmark.text)
+        end,
+    }, function(bookmark)
+        if bookmark == nil or not vim.api.nvim_buf_is_valid(bufnr) then
+            return
+        end
+
+        local line = math.min(bookmark.line, vim.api.nvim_buf_line_count(bufnr))
+        vim.api.nvim_set_current_buf(bufnr)
+        vim.api.nvim_win_set_cursor(0, { line, 0 })
+    end)
+end
+
+function M.comments(bufnr)
+    return core().comments(bufnr)
+end
+
+function M.resolve_comment(bufnr, id)
+    return core().resolve_comment(bufnr, id)
+end
+
+function M.show_comments()
+    return core().show_comments()
+end
+
+function M.export_comments(path)
+    return core().export_comments(path)
+end
+
+function M.info()
+    return core().info()
+end
+
+function M.split()
+    return core().split()
+end
+
+function M.refresh()
+    return core().refresh()
+end
+
+function M.clients()
+    return core().clients()
+end
+
+function M.share(duration)
+    return core().share(duration)
+end
+
+function M.rotate_token()
+    return core().rotate_token()
+end
+
+function M.set(assignments)
+    return core().set(assignments)
 end
 
 function M.shutdown()
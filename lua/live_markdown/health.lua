@@ -0,0 +1,30 @@
+local M = {}
+
+function M.check()
+    vim.health.start("live-markdown.nvim")
+
+    local ok, native = pcall(require, "live_markdown_native")
+    if not ok then
+        vim.health.error("native module failed to load: " .. tostring(native), {
+            "Run ./scripts/build-nvim-module.sh release to build lua/live_markdown_native.so",
+        })
+        return
+    end
+    vim.health.ok("native module loaded")
+
+    local status = native.server_status()
+    if status.state == "running" then
+        vim.health.ok("preview server running at " .. status.url)
+        return
+    end
+    vim.health.info("preview server is " .. status.state)
+
+    local err = native.check_port()
+    if err == nil then
+        vim.health.ok("preview port is free")
+    else
+        vim.health.error(err)
+    end
+end
+
+return M
@@ -0,0 +1,150 @@
+use crate::protocol::{AccessEntry, ConnectionInfo};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ACCESS_LOG_CAPACITY: usize = 200;
+const SHARE_TOKEN_PREFIX_LEN: usize = 6;
+
+/// Who is on the other end of a request, as seen by the access middleware.
+#[derive(Debug, Clone, Default)]
+pub struct Peer {
+    pub remote: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct AccessState {
+    entries: VecDeque<AccessEntry>,
+    connections: BTreeMap<u64, ConnectionInfo>,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccessLog {
+    state: Arc<Mutex<AccessState>>,
+}
+
+impl AccessLog {
+    pub fn record(&self, path: &str, client: Option<String>, peer: &Peer) {
+        let entry = AccessEntry {
+            path: redact_share_token(path),
+            client,
+            remote: peer.remote.clone(),
+            user_agent: peer.user_agent.clone(),
+            at_ms: now_ms(),
+        };
+
+        let mut state = self.lock();
+        if state.entries.len() == ACCESS_LOG_CAPACITY {
+            state.entries.pop_front();
+        }
+        state.entries.push_back(entry);
+    }
+
+    /// Newest first.
+    pub fn recent(&self) -> Vec<AccessEntry> {
+        self.lock().entries.iter().rev().cloned().collect()
+    }
+
+    /// Tracks an open event stream until `disconnect` is called with the
+    /// returned id.
+    pub fn connect(&self, bufnr: i64, client: Option<String>, shared: bool, peer: &Peer) -> u64 {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.connections.insert(
+            id,
+            ConnectionInfo {
+                id,
+                bufnr,
+                client,
+                shared,
+                remote: peer.remote.clone(),
+                user_agent: peer.user_agent.clone(),
+                connected_at_ms: now_ms(),
+            },
+        );
+        id
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        self.lock().connections.remove(&id);
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.lock().connections.values().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AccessState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Keeps share links recognisable in the log without exposing usable tokens.
+fn redact_share_token(path: &str) -> String {
+    let Some(rest) = path.strip_prefix("/share/") else {
+        return path.to_string();
+    };
+
+    let (token, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let prefix: String = token.chars().take(SHARE_TOKEN_PREFIX_LEN).collect();
+    format!("/share/{prefix}\u{2026}{tail}")
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ACCESS_LOG_CAPACITY, AccessLog, Peer};
+
+    #[test]
+    fn keeps_a_bounded_newest_first_log() {
+        let log = AccessLog::default();
+        let peer = Peer {
+            remote: Some(String::from("127.0.0.1:5000")),
+            user_agent: Some(String::from("curl/8")),
+        };
+
+        for index in 0..ACCESS_LOG_CAPACITY + 5 {
+            log.record(&format!("/snapshot?n={index}"), None, &peer);
+        }
+        log.record(
+            "/share/0123456789abcdef/events",
+            Some(String::from("c1")),
+            &peer,
+        );
+
+        let recent = log.recent();
+        assert_eq!(recent.len(), ACCESS_LOG_CAPACITY);
+        assert_eq!(recent[0].path, "/share/012345\u{2026}/events");
+        assert_eq!(recent[0].client.as_deref(), Some("c1"));
+        assert_eq!(recent[0].user_agent.as_deref(), Some("curl/8"));
+        assert_eq!(
+            recent.last().map(|entry| entry.path.as_str()),
+            Some("/snapshot?n=6")
+        );
+    }
+
+    #[test]
+    fn tracks_open_connections() {
+        let log = AccessLog::default();
+        let first = log.connect(1, Some(String::from("a")), false, &Peer::default());
+        let second = log.connect(2, None, true, &Peer::default());
+
+        log.disconnect(first);
+
+        let connections = log.connections();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id, second);
+        assert_eq!(connections[0].bufnr, 2);
+        assert!(connections[0].shared);
+    }
+}
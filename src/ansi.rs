@@ -0,0 +1,255 @@
+use crate::render::push_escaped_html;
+
+const ESCAPE_PREFIXES: &[&str] = &["\u{1b}[", "\\e[", "\\x1b[", "\\033[", "\\u001b[", "^[["];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnsiStyle {
+    fg: Option<AnsiColor>,
+    bg: Option<AnsiColor>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiColor {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+pub fn contains_ansi(text: &str) -> bool {
+    ESCAPE_PREFIXES.iter().any(|prefix| text.contains(prefix))
+}
+
+pub fn push_ansi_html(out: &mut String, text: &str, style: &mut AnsiStyle) {
+    let mut rest = text;
+    let mut span_open = open_span(out, style);
+
+    loop {
+        let next = ESCAPE_PREFIXES
+            .iter()
+            .filter_map(|prefix| rest.find(prefix).map(|idx| (idx, prefix.len())))
+            .min_by_key(|(idx, _)| *idx);
+
+        let Some((start, prefix_len)) = next else {
+            push_escaped_html(out, rest);
+            break;
+        };
+
+        push_escaped_html(out, &rest[..start]);
+        let params_start = start + prefix_len;
+        let Some(final_len) = csi_final(&rest[params_start..]) else {
+            // Not a complete sequence: keep it as text.
+            push_escaped_html(out, &rest[start..params_start]);
+            rest = &rest[params_start..];
+            continue;
+        };
+
+        let params = &rest[params_start..params_start + final_len];
+        let command = rest.as_bytes()[params_start + final_len];
+        rest = &rest[params_start + final_len + 1..];
+
+        if command != b'm' {
+            continue;
+        }
+
+        if span_open {
+            out.push_str("</span>");
+        }
+        apply_sgr(style, params);
+        span_open = open_span(out, style);
+    }
+
+    if span_open {
+        out.push_str("</span>");
+    }
+}
+
+/// Where the final byte of a control sequence starting at `text` is: after
+/// parameter bytes (`0-9:;<=>?`) and intermediate bytes (space to `/`), a
+/// byte from `@` to `~`. `None` when anything else comes first.
+fn csi_final(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let params = bytes
+        .iter()
+        .position(|byte| !(0x30..=0x3f).contains(byte))?;
+    let intermediates = bytes[params..]
+        .iter()
+        .position(|byte| !(0x20..=0x2f).contains(byte))?;
+    let end = params + intermediates;
+    (0x40..=0x7e).contains(&bytes[end]).then_some(end)
+}
+
+fn apply_sgr(style: &mut AnsiStyle, params: &str) {
+    let codes: Vec<u16> = if params.is_empty() {
+        vec![0]
+    } else {
+        params
+            .split(';')
+            .map(|code| code.parse::<u16>().unwrap_or(0))
+            .collect()
+    };
+
+    let mut index = 0;
+    while index < codes.len() {
+        let code = codes[index];
+        match code {
+            0 => *style = AnsiStyle::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            30..=37 => style.fg = Some(AnsiColor::Indexed((code - 30) as u8)),
+            39 => style.fg = None,
+            40..=47 => style.bg = Some(AnsiColor::Indexed((code - 40) as u8)),
+            49 => style.bg = None,
+            90..=97 => style.fg = Some(AnsiColor::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg = Some(AnsiColor::Indexed((code - 100 + 8) as u8)),
+            38 | 48 => {
+                let (color, consumed) = extended_color(&codes[index + 1..]);
+                if code == 38 {
+                    style.fg = color;
+                } else {
+                    style.bg = color;
+                }
+                index += consumed;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+}
+
+fn extended_color(codes: &[u16]) -> (Option<AnsiColor>, usize) {
+    match codes {
+        [5, n, ..] => (Some(AnsiColor::Indexed((*n).min(255) as u8)), 2),
+        [2, r, g, b, ..] => (
+            Some(AnsiColor::Rgb(
+                (*r).min(255) as u8,
+                (*g).min(255) as u8,
+                (*b).min(255) as u8,
+            )),
+            4,
+        ),
+        _ => (None, codes.len()),
+    }
+}
+
+fn open_span(out: &mut String, style: &AnsiStyle) -> bool {
+    if *style == AnsiStyle::default() {
+        return false;
+    }
+
+    let mut classes = Vec::new();
+    let mut inline = String::new();
+    for (color, kind, property) in [
+        (style.fg, "fg", "color"),
+        (style.bg, "bg", "background-color"),
+    ] {
+        match color {
+            Some(AnsiColor::Indexed(index)) if index < 16 => {
+                classes.push(format!("ansi-{kind}-{index}"));
+            }
+            Some(AnsiColor::Indexed(index)) => {
+                let (r, g, b) = xterm_256_rgb(index);
+                inline.push_str(&format!("{property}:#{r:02x}{g:02x}{b:02x};"));
+            }
+            Some(AnsiColor::Rgb(r, g, b)) => {
+                inline.push_str(&format!("{property}:#{r:02x}{g:02x}{b:02x};"));
+            }
+            None => {}
+        }
+    }
+    for (enabled, class) in [
+        (style.bold, "ansi-bold"),
+        (style.dim, "ansi-dim"),
+        (style.italic, "ansi-italic"),
+        (style.underline, "ansi-underline"),
+    ] {
+        if enabled {
+            classes.push(class.to_string());
+        }
+    }
+
+    out.push_str("<span");
+    if !classes.is_empty() {
+        out.push_str(" class=\"");
+        out.push_str(&classes.join(" "));
+        out.push('"');
+    }
+    if !inline.is_empty() {
+        out.push_str(" style=\"");
+        out.push_str(&inline);
+        out.push('"');
+    }
+    out.push('>');
+    true
+}
+
+fn xterm_256_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            (level(index / 36), level((index / 6) % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        _ => (0, 0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnsiStyle, contains_ansi, push_ansi_html};
+
+    fn render(text: &str) -> String {
+        let mut out = String::new();
+        push_ansi_html(&mut out, text, &mut AnsiStyle::default());
+        out
+    }
+
+    #[test]
+    fn converts_sgr_sequences_to_spans() {
+        assert_eq!(
+            render("ok \u{1b}[1;32mPASS\u{1b}[0m <done>"),
+            "ok <span class=\"ansi-fg-2 ansi-bold\">PASS</span> &lt;done&gt;"
+        );
+        assert_eq!(
+            render("\\e[38;5;196mred\\e[m \u{1b}[48;2;1;2;3mbg\u{1b}[K"),
+            "<span style=\"color:#ff0000;\">red</span> <span style=\"background-color:#010203;\">bg</span>"
+        );
+    }
+
+    #[test]
+    fn carries_style_across_chunks() {
+        let mut style = AnsiStyle::default();
+        let mut out = String::new();
+        push_ansi_html(&mut out, "\u{1b}[31mfirst\n", &mut style);
+        push_ansi_html(&mut out, "second\u{1b}[0m\n", &mut style);
+
+        assert_eq!(
+            out,
+            "<span class=\"ansi-fg-1\">first\n</span><span class=\"ansi-fg-1\">second</span>\n"
+        );
+        assert!(contains_ansi("x\\033[0m"));
+        assert!(!contains_ansi("plain [0m"));
+    }
+
+    #[test]
+    fn keeps_incomplete_sequences_as_text() {
+        assert_eq!(
+            render("\u{1b}[31mred\u{1b}[0m then \\e[1\nmore \\e[3"),
+            "<span class=\"ansi-fg-1\">red</span> then \\e[1\nmore \\e[3"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+/// Where local image references in rendered HTML point. The renderer keeps
+/// sources as written; whoever ships the HTML picks the strategy that works
+/// where it ends up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetLinks {
+    /// Through the preview server's `/asset` route, for pages opened from
+    /// the running server.
+    Server { bufnr: i64 },
+    /// As paths relative to `base`, the directory the HTML is saved in, or
+    /// as `file://` URLs when that directory is unknown.
+    OnDisk { base: Option<PathBuf> },
+    /// Embedded as `data:` URIs up to `max_bytes`; larger images are linked
+    /// on disk.
+    DataUri { max_bytes: u64 },
+}
+
+/// The `assets=` choice of `/export.html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetLinkMode {
+    Server,
+    Relative,
+    Data,
+}
+
+impl AssetLinks {
+    /// The reference to write for the local image `src`, resolved to `path`.
+    /// `DataUri` callers embed the file themselves and only ask for the
+    /// fallback of images too large to embed.
+    pub fn link(&self, src: &str, path: &Path) -> String {
+        match self {
+            Self::Server { bufnr } => {
+                format!("/asset?buf={bufnr}&path={}", percent_encode(src, false))
+            }
+            Self::OnDisk { base: Some(base) } => match relative_path(path, base) {
+                Some(relative) => percent_encode(&relative.to_string_lossy(), true),
+                None => file_url(path),
+            },
+            Self::OnDisk { base: None } | Self::DataUri { .. } => file_url(path),
+        }
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    format!("file://{}", percent_encode(&path.to_string_lossy(), true))
+}
+
+/// `path` relative to the directory `base`, when both are absolute.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() || !base.is_absolute() {
+        return None;
+    }
+    let path: Vec<Component<'_>> = path.components().collect();
+    let base: Vec<Component<'_>> = base.components().collect();
+    let shared = path
+        .iter()
+        .zip(&base)
+        .take_while(|(left, right)| left == right)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in shared..base.len() {
+        relative.push("..");
+    }
+    for component in &path[shared..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// Escapes everything but unreserved characters, and `/` when `keep_slashes`.
+fn percent_encode(value: &str, keep_slashes: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'_' | b'.' | b'~')
+            || (keep_slashes && byte == b'/')
+        {
+            out.push(char::from(byte));
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AssetLinks;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn links_images_for_each_strategy() {
+        let image = Path::new("/notes/img/a chart.png");
+
+        assert_eq!(
+            AssetLinks::Server { bufnr: 3 }.link("img/a chart.png", image),
+            "/asset?buf=3&path=img%2Fa%20chart.png"
+        );
+        assert_eq!(
+            AssetLinks::OnDisk {
+                base: Some(PathBuf::from("/notes/exports"))
+            }
+            .link("img/a chart.png", image),
+            "../img/a%20chart.png"
+        );
+        assert_eq!(
+            AssetLinks::OnDisk { base: None }.link("img/a chart.png", image),
+            "file:///notes/img/a%20chart.png"
+        );
+        assert_eq!(
+            AssetLinks::DataUri { max_bytes: 10 }.link("img/a chart.png", image),
+            "file:///notes/img/a%20chart.png"
+        );
+    }
+}
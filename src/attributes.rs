@@ -0,0 +1,186 @@
+use crate::render::is_reserved_data_attribute;
+
+/// A pandoc-style `{#id .class key=val}` attribute block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes {
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    /// Key-value pairs with the key already mapped to the HTML attribute it
+    /// is written as.
+    pub pairs: Vec<(String, String)>,
+}
+
+/// Keys written as themselves; any other key becomes a `data-` attribute,
+/// as in pandoc's HTML output, so a block can never add event handlers or
+/// change where a link or image points. The renderer's own `data-` names are
+/// dropped.
+const PLAIN_KEYS: &[&str] = &["dir", "height", "lang", "title", "width"];
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.classes.is_empty() && self.pairs.is_empty()
+    }
+
+    /// Adds `key=value`, dropping keys that are not plain names.
+    pub fn push_pair(&mut self, key: &str, value: &str) {
+        if let Some(name) = attribute_name(key) {
+            self.push_named(name, value.to_string());
+        }
+    }
+
+    fn push_named(&mut self, name: String, value: String) {
+        if !is_reserved_data_attribute(&name) {
+            self.pairs.push((name, value));
+        }
+    }
+
+    /// Reads the inside of an attribute block; `None` when any part of it is
+    /// not an attribute, so ordinary text in braces stays text.
+    pub fn parse(inner: &str) -> Option<Self> {
+        let mut attributes = Self::default();
+        let mut rest = inner.trim();
+        while !rest.is_empty() {
+            let (token, remainder) = next_token(rest)?;
+            if let Some(id) = token.strip_prefix('#') {
+                if !is_name(id) {
+                    return None;
+                }
+                attributes.id = Some(id.to_string());
+            } else if let Some(class) = token.strip_prefix('.') {
+                if !is_name(class) {
+                    return None;
+                }
+                attributes.classes.push(class.to_string());
+            } else {
+                let (key, value) = token.split_once('=')?;
+                let name = attribute_name(key)?;
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                attributes.push_named(name, value.to_string());
+            }
+            rest = remainder.trim_start();
+        }
+        (!attributes.is_empty()).then_some(attributes)
+    }
+}
+
+/// Splits a trailing `{...}` block off `text`, returning the text before it.
+pub fn split_trailing(text: &str) -> Option<(&str, Attributes)> {
+    let trimmed = text.trim_end();
+    let body = trimmed.strip_suffix('}')?;
+    let open = body.rfind('{')?;
+    let attributes = Attributes::parse(&body[open + 1..])?;
+    Some((trimmed[..open].trim_end(), attributes))
+}
+
+/// Splits a leading `{...}` block off `text`, returning the text after it.
+pub fn split_leading(text: &str) -> Option<(Attributes, &str)> {
+    let body = text.strip_prefix('{')?;
+    let close = body.find('}')?;
+    let attributes = Attributes::parse(&body[..close])?;
+    Some((attributes, &body[close + 1..]))
+}
+
+/// Splits a fenced code info string such as `rust {#main .numbered}` or
+/// `{.rust}` into the language and its attributes. Without a language before
+/// the block, the first class names it, as in pandoc.
+pub fn split_info_string(info: &str) -> Option<(String, Attributes)> {
+    let open = info.find('{')?;
+    let body = info[open + 1..].trim_end().strip_suffix('}')?;
+    let mut attributes = Attributes::parse(body)?;
+    let mut lang = info[..open].trim().to_string();
+    if lang.is_empty() && !attributes.classes.is_empty() {
+        lang = attributes.classes.remove(0);
+    }
+    Some((lang, attributes))
+}
+
+fn next_token(text: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (index, ch) in text.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            ch if ch.is_whitespace() && !quoted => return Some((&text[..index], &text[index..])),
+            _ => {}
+        }
+    }
+    (!quoted).then_some((text, ""))
+}
+
+fn is_name(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '-' | '_' | ':' | '.'))
+}
+
+fn attribute_name(key: &str) -> Option<String> {
+    let key = key.to_ascii_lowercase();
+    let valid = key.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_'));
+    if !valid {
+        return None;
+    }
+    if PLAIN_KEYS.contains(&key.as_str()) || key.starts_with("data-") {
+        Some(key)
+    } else {
+        Some(format!("data-{key}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attributes, split_info_string, split_leading, split_trailing};
+
+    #[test]
+    fn parses_attribute_blocks_and_leaves_other_braces() {
+        assert_eq!(
+            split_trailing("A note. {#intro .lead .wide onclick=x title=\"Hi there\"}"),
+            Some((
+                "A note.",
+                Attributes {
+                    id: Some(String::from("intro")),
+                    classes: vec![String::from("lead"), String::from("wide")],
+                    pairs: vec![
+                        (String::from("data-onclick"), String::from("x")),
+                        (String::from("title"), String::from("Hi there")),
+                    ],
+                }
+            ))
+        );
+        assert_eq!(split_trailing("a set {1, 2}"), None);
+        assert_eq!(split_trailing("empty {}"), None);
+
+        let (attributes, rest) = split_leading("{width=50%} caption").expect("leading");
+        assert_eq!(
+            attributes.pairs,
+            vec![(String::from("width"), String::from("50%"))]
+        );
+        assert_eq!(rest, " caption");
+
+        let (lang, attributes) = split_info_string("{.python #setup .numbered}").expect("info");
+        assert_eq!(lang, "python");
+        assert_eq!(attributes.id.as_deref(), Some("setup"));
+        assert_eq!(attributes.classes, vec![String::from("numbered")]);
+        assert_eq!(split_info_string("rust"), None);
+    }
+
+    #[test]
+    fn drops_data_attributes_the_renderer_owns() {
+        let (lang, attributes) = split_info_string(
+            "{.py data-preview-html=\"<img src=x onerror=alert(4)>\" line=3 data-tasks-total=9 data-kind=demo}",
+        )
+        .expect("info");
+        assert_eq!(lang, "py");
+        assert_eq!(
+            attributes.pairs,
+            vec![(String::from("data-kind"), String::from("demo"))]
+        );
+
+        assert_eq!(split_trailing("Text {data-footnote=1}"), None);
+    }
+}
@@ -0,0 +1,338 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub const CITATION_URL_PREFIX: &str = "cite:";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BibEntry {
+    pub key: String,
+    pub authors: Vec<String>,
+    pub year: Option<String>,
+    pub title: Option<String>,
+    pub container: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bibliography {
+    entries: HashMap<String, BibEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CitationItem {
+    pub key: String,
+    pub prefix: String,
+    pub suffix: String,
+    pub suppress_author: bool,
+}
+
+impl Bibliography {
+    pub fn load(path: &Path) -> Self {
+        let Ok(raw) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        let is_json = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        if is_json {
+            Self::from_csl_json(&raw)
+        } else {
+            Self::from_bibtex(&raw)
+        }
+    }
+
+    pub fn from_csl_json(raw: &str) -> Self {
+        let Ok(items) = serde_json::from_str::<Vec<CslItem>>(raw) else {
+            return Self::default();
+        };
+
+        let entries = items
+            .into_iter()
+            .map(|item| {
+                let entry = BibEntry {
+                    key: item.id.clone(),
+                    authors: item
+                        .author
+                        .iter()
+                        .filter_map(|name| name.family.clone().or_else(|| name.literal.clone()))
+                        .collect(),
+                    year: item
+                        .issued
+                        .and_then(|issued| issued.date_parts.into_iter().next())
+                        .and_then(|parts| parts.into_iter().next())
+                        .map(|year| match year {
+                            serde_json::Value::String(year) => year,
+                            other => other.to_string(),
+                        }),
+                    title: item.title,
+                    container: item.container_title.or(item.publisher),
+                    url: item.url,
+                };
+                (item.id, entry)
+            })
+            .collect();
+
+        Self { entries }
+    }
+
+    pub fn from_bibtex(raw: &str) -> Self {
+        let mut entries = HashMap::new();
+        let mut rest = raw;
+
+        while let Some(at) = rest.find('@') {
+            rest = &rest[at + 1..];
+            let Some(open) = rest.find(['{', '(']) else {
+                break;
+            };
+            let kind = rest[..open].trim().to_ascii_lowercase();
+            let Some(body_len) = balanced_body_len(&rest[open..]) else {
+                break;
+            };
+            let body = &rest[open + 1..open + body_len - 1];
+            rest = &rest[open + body_len..];
+
+            if matches!(kind.as_str(), "comment" | "preamble" | "string") {
+                continue;
+            }
+
+            if let Some(entry) = parse_bibtex_body(body) {
+                entries.insert(entry.key.clone(), entry);
+            }
+        }
+
+        Self { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BibEntry> {
+        self.entries.get(key)
+    }
+}
+
+impl BibEntry {
+    pub fn author_label(&self) -> String {
+        match self.authors.as_slice() {
+            [] => self.key.clone(),
+            [one] => one.clone(),
+            [first, second] => format!("{first} and {second}"),
+            [first, ..] => format!("{first} et al."),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CslItem {
+    id: String,
+    #[serde(default)]
+    author: Vec<CslName>,
+    issued: Option<CslDate>,
+    title: Option<String>,
+    #[serde(rename = "container-title")]
+    container_title: Option<String>,
+    publisher: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslName {
+    family: Option<String>,
+    literal: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<serde_json::Value>>,
+}
+
+pub fn parse_citation_group(raw: &str) -> Vec<CitationItem> {
+    raw.split(';').filter_map(parse_citation_item).collect()
+}
+
+fn parse_citation_item(raw: &str) -> Option<CitationItem> {
+    let bytes = raw.as_bytes();
+    let at = (0..bytes.len()).find(|&idx| {
+        bytes[idx] == b'@'
+            && (idx == 0 || bytes[idx - 1].is_ascii_whitespace() || bytes[idx - 1] == b'-')
+    })?;
+
+    let suppress_author = at > 0 && bytes[at - 1] == b'-';
+    let prefix_end = if suppress_author { at - 1 } else { at };
+    let key_start = at + 1;
+    let key_len = raw[key_start..]
+        .find(|ch: char| !is_citation_key_char(ch))
+        .unwrap_or(raw.len() - key_start);
+    let key = raw[key_start..key_start + key_len].trim_end_matches(['.', ':', '-', '/', '?']);
+    if key.is_empty() {
+        return None;
+    }
+
+    let suffix = raw[key_start + key.len()..].trim();
+    let suffix = suffix.strip_prefix(',').unwrap_or(suffix).trim();
+
+    Some(CitationItem {
+        key: key.to_string(),
+        prefix: raw[..prefix_end].trim().to_string(),
+        suffix: suffix.to_string(),
+        suppress_author,
+    })
+}
+
+fn is_citation_key_char(ch: char) -> bool {
+    ch.is_alphanumeric()
+        || matches!(
+            ch,
+            '_' | ':' | '.' | '#' | '$' | '%' | '&' | '-' | '+' | '?' | '<' | '>' | '~' | '/'
+        )
+}
+
+fn balanced_body_len(input: &str) -> Option<usize> {
+    let (open, close) = match input.as_bytes().first()? {
+        b'{' => (b'{', b'}'),
+        b'(' => (b'(', b')'),
+        _ => return None,
+    };
+
+    let mut depth = 0usize;
+    for (idx, byte) in input.bytes().enumerate() {
+        if byte == open {
+            depth += 1;
+        } else if byte == close {
+            depth = depth.saturating_sub(1);
+            if depth == 0 {
+                return Some(idx + 1);
+            }
+        }
+    }
+
+    None
+}
+
+fn parse_bibtex_body(body: &str) -> Option<BibEntry> {
+    let (key, mut fields) = body.split_once(',')?;
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+
+    let mut entry = BibEntry {
+        key: key.to_string(),
+        ..BibEntry::default()
+    };
+
+    loop {
+        fields = fields.trim_start_matches(|ch: char| ch.is_whitespace() || ch == ',');
+        let Some((name, value_and_rest)) = fields.split_once('=') else {
+            break;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value_and_rest = value_and_rest.trim_start();
+        let (value, rest) = split_bibtex_value(value_and_rest);
+        fields = rest;
+
+        let value = clean_bibtex_value(value);
+        match name.as_str() {
+            "author" | "editor" if entry.authors.is_empty() => {
+                entry.authors = value.split(" and ").map(bibtex_family_name).collect();
+            }
+            "year" => entry.year = Some(value),
+            "date" if entry.year.is_none() => {
+                entry.year = Some(value.chars().take(4).collect());
+            }
+            "title" => entry.title = Some(value),
+            "journal" | "journaltitle" | "booktitle" | "publisher" if entry.container.is_none() => {
+                entry.container = Some(value);
+            }
+            "url" => entry.url = Some(value),
+            _ => {}
+        }
+    }
+
+    Some(entry)
+}
+
+fn split_bibtex_value(input: &str) -> (&str, &str) {
+    match input.as_bytes().first() {
+        Some(b'{') => match balanced_body_len(input) {
+            Some(len) => (&input[1..len - 1], &input[len..]),
+            None => (input, ""),
+        },
+        Some(b'"') => match input[1..].find('"') {
+            Some(end) => (&input[1..end + 1], &input[end + 2..]),
+            None => (input, ""),
+        },
+        _ => {
+            let end = input.find(',').unwrap_or(input.len());
+            (input[..end].trim(), &input[end..])
+        }
+    }
+}
+
+fn clean_bibtex_value(value: &str) -> String {
+    let stripped: String = value
+        .chars()
+        .filter(|ch| *ch != '{' && *ch != '}')
+        .collect();
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn bibtex_family_name(name: &str) -> String {
+    let name = name.trim();
+    if let Some((family, _given)) = name.split_once(',') {
+        return family.trim().to_string();
+    }
+
+    name.rsplit(' ').next().unwrap_or(name).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bibliography, parse_citation_group};
+
+    #[test]
+    fn parses_bibtex_entries() {
+        let bib = Bibliography::from_bibtex(
+            "@article{doe99,\n  author = {Doe, Jane and Roe, Richard},\n  title = {On {Things}},\n  journal = \"Journal of Stuff\",\n  year = 1999\n}\n@comment{ignored}\n@book{smith04, author={John Smith}, title={A Book}, year={2004}}",
+        );
+
+        let doe = bib.get("doe99").expect("doe99 entry");
+        assert_eq!(doe.authors, vec!["Doe", "Roe"]);
+        assert_eq!(doe.title.as_deref(), Some("On Things"));
+        assert_eq!(doe.container.as_deref(), Some("Journal of Stuff"));
+        assert_eq!(doe.year.as_deref(), Some("1999"));
+        assert_eq!(doe.author_label(), "Doe and Roe");
+
+        let smith = bib.get("smith04").expect("smith04 entry");
+        assert_eq!(smith.authors, vec!["Smith"]);
+        assert_eq!(smith.year.as_deref(), Some("2004"));
+    }
+
+    #[test]
+    fn parses_csl_json_entries() {
+        let bib = Bibliography::from_csl_json(
+            r#"[{"id":"item1","author":[{"family":"Doe","given":"Jane"},{"family":"Roe"},{"family":"Poe"}],"issued":{"date-parts":[[2020,5]]},"title":"Title"}]"#,
+        );
+
+        let entry = bib.get("item1").expect("item1 entry");
+        assert_eq!(entry.year.as_deref(), Some("2020"));
+        assert_eq!(entry.author_label(), "Doe et al.");
+    }
+
+    #[test]
+    fn parses_citation_groups_with_prefix_and_locator() {
+        let items = parse_citation_group("see @doe99, pp. 33-35; -@smith04");
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].key, "doe99");
+        assert_eq!(items[0].prefix, "see");
+        assert_eq!(items[0].suffix, "pp. 33-35");
+        assert!(!items[0].suppress_author);
+        assert_eq!(items[1].key, "smith04");
+        assert!(items[1].suppress_author);
+    }
+}
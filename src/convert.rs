@@ -0,0 +1,336 @@
+use crate::render::{RenderBackend, RenderOutput, push_escaped_attr, push_escaped_html};
+use crate::sanitize::sanitize_html;
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CACHE_CAPACITY: usize = 8;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a converter that timed out or would not start is left alone
+/// before it is tried again.
+const FAILURE_BACKOFF: Duration = Duration::from_secs(10);
+
+/// The sanitized HTML body of a conversion, or why it failed.
+type Conversion = Result<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConverterCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl ConverterCommand {
+    pub fn from_argv(argv: Vec<String>) -> Option<Self> {
+        let mut argv = argv.into_iter();
+        let program = argv.next().filter(|program| !program.trim().is_empty())?;
+
+        Some(Self {
+            program,
+            args: argv.collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExternalConverter {
+    format: String,
+    command: ConverterCommand,
+    timeout: Duration,
+    /// Recent results by content key, failures included, so an edit that
+    /// changes nothing never converts twice.
+    cache: Arc<Mutex<VecDeque<(u64, Conversion)>>>,
+    /// When the converter last timed out or failed to start, and why.
+    backoff: Arc<Mutex<Option<(Instant, String)>>>,
+}
+
+impl ExternalConverter {
+    pub fn new(format: &str, command: ConverterCommand, timeout: Duration) -> Self {
+        Self {
+            format: format.to_string(),
+            command,
+            timeout,
+            cache: Arc::new(Mutex::new(VecDeque::with_capacity(CACHE_CAPACITY))),
+            backoff: Arc::default(),
+        }
+    }
+
+    fn cached(&self, key: u64) -> Option<Conversion> {
+        let cache = self.cache.lock().ok()?;
+        cache
+            .iter()
+            .find(|(cached_key, _)| *cached_key == key)
+            .map(|(_, html)| html.clone())
+    }
+
+    fn store(&self, key: u64, html: &Conversion) {
+        let Ok(mut cache) = self.cache.lock() else {
+            return;
+        };
+
+        cache.retain(|(cached_key, _)| *cached_key != key);
+        if cache.len() == CACHE_CAPACITY {
+            cache.pop_front();
+        }
+        cache.push_back((key, html.clone()));
+    }
+
+    /// The error of a timeout or failed start within the last
+    /// [`FAILURE_BACKOFF`], which a conversion now would only repeat.
+    fn backing_off(&self) -> Option<String> {
+        let backoff = self.backoff.lock().ok()?;
+        backoff
+            .as_ref()
+            .filter(|(failed_at, _)| failed_at.elapsed() < FAILURE_BACKOFF)
+            .map(|(_, err)| err.clone())
+    }
+
+    fn back_off(&self, err: &str) {
+        if let Ok(mut backoff) = self.backoff.lock() {
+            *backoff = Some((Instant::now(), err.to_string()));
+        }
+    }
+
+    fn convert(&self, source: &str) -> Result<String, String> {
+        let mut child = Command::new(&self.command.program)
+            .args(&self.command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| {
+                let err = format!("failed to start `{}`: {err}", self.command.program);
+                self.back_off(&err);
+                err
+            })?;
+
+        let mut stdin = child.stdin.take();
+        let input = source.to_string();
+        let writer = thread::spawn(move || {
+            if let Some(stdin) = stdin.as_mut() {
+                let _ = stdin.write_all(input.as_bytes());
+            }
+        });
+        let stdout = child.stdout.take().map(read_to_end_in_background);
+        let stderr = child.stderr.take().map(read_to_end_in_background);
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let err = format!(
+                        "`{}` timed out after {} ms",
+                        self.command.program,
+                        self.timeout.as_millis()
+                    );
+                    self.back_off(&err);
+                    return Err(err);
+                }
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(err) => {
+                    return Err(format!(
+                        "failed to wait for `{}`: {err}",
+                        self.command.program
+                    ));
+                }
+            }
+        };
+
+        let _ = writer.join();
+        let stdout = stdout
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        let stderr = stderr
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(format!(
+                "`{}` exited with {status}: {}",
+                self.command.program,
+                stderr.trim()
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+}
+
+impl ExternalConverter {
+    pub fn convert_to_html(&self, source: &str) -> Result<String, String> {
+        let key = content_key(&self.format, source);
+        if let Some(body) = self.cached(key) {
+            return body;
+        }
+        if let Some(err) = self.backing_off() {
+            return Err(err);
+        }
+
+        let body = self
+            .convert(source)
+            .map(|converted| source_positions_to_data_lines(&sanitize_html(&converted)));
+        self.store(key, &body);
+        body
+    }
+
+    pub fn document(&self, body: &str) -> RenderOutput {
+        let mut html = String::with_capacity(body.len() + 64);
+        html.push_str("<article id=\"md-root\" data-format=\"");
+        push_escaped_attr(&mut html, &self.format);
+        html.push_str("\">");
+        html.push_str(body);
+        html.push_str("</article>");
+
+        RenderOutput {
+            html,
+            ..RenderOutput::default()
+        }
+    }
+}
+
+impl RenderBackend for ExternalConverter {
+    fn render_document(&self, source: &str) -> RenderOutput {
+        match self.convert_to_html(source) {
+            Ok(body) => self.document(&body),
+            Err(err) => RenderOutput {
+                failure: Some(err.clone()),
+                ..self.document(&render_conversion_error(&err, source))
+            },
+        }
+    }
+}
+
+fn source_positions_to_data_lines(html: &str) -> String {
+    const NEEDLE: &str = "data-pos=\"";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(NEEDLE) {
+        let value_start = start + NEEDLE.len();
+        let Some(value_len) = rest[value_start..].find('"') else {
+            break;
+        };
+
+        out.push_str(&rest[..start]);
+        let value = &rest[value_start..value_start + value_len];
+        let position = value.rsplit('@').next().unwrap_or(value);
+        match position
+            .split(':')
+            .next()
+            .and_then(|line| line.parse::<usize>().ok())
+        {
+            Some(line) => out.push_str(&format!("data-line=\"{line}\"")),
+            None => out.push_str(&rest[start..value_start + value_len + 1]),
+        }
+        rest = &rest[value_start + value_len + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn read_to_end_in_background<R: Read + Send + 'static>(
+    mut reader: R,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = reader.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+fn render_conversion_error(err: &str, source: &str) -> String {
+    let mut out = String::with_capacity(source.len() + 128);
+    out.push_str("<div class=\"converter-error\"><p>");
+    push_escaped_html(&mut out, err);
+    out.push_str("</p></div><pre><code>");
+    push_escaped_html(&mut out, source);
+    out.push_str("</code></pre>");
+    out
+}
+
+fn content_key(format: &str, source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format.hash(&mut hasher);
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConverterCommand, ExternalConverter, source_positions_to_data_lines};
+    use crate::render::RenderBackend;
+    use std::time::{Duration, Instant};
+
+    fn shell(script: &str) -> ConverterCommand {
+        ConverterCommand::from_argv(vec![
+            String::from("sh"),
+            String::from("-c"),
+            script.to_string(),
+        ])
+        .expect("command")
+    }
+
+    #[test]
+    fn sanitizes_converter_output_inside_preview_root() {
+        let converter = ExternalConverter::new(
+            "adoc",
+            shell("cat >/dev/null; printf '<p onclick=\"x()\">ok</p><script>bad()</script>'"),
+            Duration::from_secs(5),
+        );
+
+        let rendered = converter.render_document("= Title");
+
+        assert_eq!(
+            rendered.html,
+            "<article id=\"md-root\" data-format=\"adoc\"><p>ok</p></article>"
+        );
+    }
+
+    #[test]
+    fn reports_failures_and_timeouts_with_escaped_source() {
+        let failing = ExternalConverter::new(
+            "rst",
+            shell("echo broken >&2; exit 3"),
+            Duration::from_secs(5),
+        );
+        let rendered = failing.render_document("<b>");
+        assert!(rendered.html.contains("class=\"converter-error\""));
+        assert!(rendered.html.contains("broken"));
+        assert!(rendered.html.contains("<pre><code>&lt;b&gt;</code></pre>"));
+
+        let slow = ExternalConverter::new("rst", shell("sleep 5"), Duration::from_millis(50));
+        assert!(slow.render_document("x").html.contains("timed out"));
+    }
+
+    #[test]
+    fn backs_off_after_a_timeout_instead_of_waiting_on_every_edit() {
+        let slow = ExternalConverter::new("rst", shell("sleep 5"), Duration::from_millis(50));
+        assert!(slow.convert_to_html("x").is_err());
+
+        let started = Instant::now();
+        let err = slow.convert_to_html("xy").expect_err("still backing off");
+        assert!(err.contains("timed out"));
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn maps_pandoc_source_positions_to_data_lines() {
+        let html = source_positions_to_data_lines(
+            "<h1 data-pos=\"1:1-2:1\">A</h1><p data-pos=\"stdin@3:1-4:1\">B</p><p data-pos=\"x\">C</p>",
+        );
+
+        assert_eq!(
+            html,
+            "<h1 data-line=\"1\">A</h1><p data-line=\"3\">B</p><p data-pos=\"x\">C</p>"
+        );
+    }
+}
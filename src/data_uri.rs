@@ -0,0 +1,110 @@
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn data_uri(content_type: &str, bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(content_type.len() + 13 + bytes.len().div_ceil(3) * 4);
+    out.push_str("data:");
+    out.push_str(content_type);
+    out.push_str(";base64,");
+    push_base64(&mut out, bytes);
+    out
+}
+
+fn push_base64(out: &mut String, bytes: &[u8]) {
+    for chunk in bytes.chunks(3) {
+        let b0 = u32::from(chunk[0]);
+        let b1 = chunk.get(1).copied().map_or(0, u32::from);
+        let b2 = chunk.get(2).copied().map_or(0, u32::from);
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (triple >> (18 - index * 6)) & 0x3f;
+                out.push(char::from(BASE64_ALPHABET[sextet as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+}
+
+/// Calls `rewrite` with every `<img>` source in rendered HTML (unescaped) and
+/// substitutes the returned value, leaving sources it declines untouched.
+pub fn rewrite_image_sources(
+    html: &str,
+    mut rewrite: impl FnMut(&str) -> Option<String>,
+) -> String {
+    const SRC_ATTR: &str = " src=\"";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(tag_start) = rest.find("<img") {
+        let tag_end = rest[tag_start..]
+            .find('>')
+            .map_or(rest.len(), |end| tag_start + end);
+        let Some(attr) = rest[tag_start..tag_end].find(SRC_ATTR) else {
+            out.push_str(&rest[..tag_end]);
+            rest = &rest[tag_end..];
+            continue;
+        };
+
+        let value_start = tag_start + attr + SRC_ATTR.len();
+        let Some(value_len) = rest[value_start..tag_end].find('"') else {
+            out.push_str(&rest[..tag_end]);
+            rest = &rest[tag_end..];
+            continue;
+        };
+        let value_end = value_start + value_len;
+
+        out.push_str(&rest[..value_start]);
+        match rewrite(&unescape_attr(&rest[value_start..value_end])) {
+            Some(replacement) => out.push_str(&replacement.replace('"', "&quot;")),
+            None => out.push_str(&rest[value_start..value_end]),
+        }
+        rest = &rest[value_end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn unescape_attr(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_uri, rewrite_image_sources};
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(data_uri("text/plain", b""), "data:text/plain;base64,");
+        assert_eq!(data_uri("image/png", b"M"), "data:image/png;base64,TQ==");
+        assert_eq!(data_uri("image/png", b"Ma"), "data:image/png;base64,TWE=");
+        assert_eq!(
+            data_uri("image/png", b"Man\xff"),
+            "data:image/png;base64,TWFu/w=="
+        );
+    }
+
+    #[test]
+    fn rewrites_only_accepted_image_sources() {
+        let html = "<p><img src=\"a&amp;b.png\" alt=\"x\" /> <img alt=\"y\" src=\"https://e.com/c.png\" /><img alt=\"z\"></p>";
+
+        let mut seen = Vec::new();
+        let out = rewrite_image_sources(html, |src| {
+            seen.push(src.to_string());
+            (src == "a&b.png").then(|| String::from("data:image/png;base64,AA=="))
+        });
+
+        assert_eq!(seen, vec!["a&b.png", "https://e.com/c.png"]);
+        assert_eq!(
+            out,
+            "<p><img src=\"data:image/png;base64,AA==\" alt=\"x\" /> <img alt=\"y\" src=\"https://e.com/c.png\" /><img alt=\"z\"></p>"
+        );
+    }
+}
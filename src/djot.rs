@@ -0,0 +1,84 @@
+use crate::render::{RenderBackend, RenderOutput};
+use crate::sanitize::sanitize_html;
+use jotdown::{Container, Event, Parser};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DjotRenderer;
+
+impl RenderBackend for DjotRenderer {
+    fn render_document(&self, source: &str) -> RenderOutput {
+        let mut in_raw = false;
+        let events = Parser::new(source).filter(move |event| {
+            if in_raw {
+                if matches!(event, Event::End(..)) {
+                    in_raw = false;
+                }
+                return false;
+            }
+
+            if matches!(
+                event,
+                Event::Start(Container::RawBlock { .. } | Container::RawInline { .. }, ..)
+            ) {
+                in_raw = true;
+                return false;
+            }
+
+            true
+        });
+
+        // Link targets and `{key=value}` attributes reach the HTML as
+        // written, so the body goes through the same sanitizer as raw HTML.
+        let body = sanitize_html(&jotdown::html::render_to_string(events));
+        let mut html = String::with_capacity(body.len() + 64);
+        html.push_str("<article id=\"md-root\" data-format=\"djot\">");
+        html.push_str(&body);
+        html.push_str("</article>");
+
+        RenderOutput {
+            html,
+            ..RenderOutput::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DjotRenderer;
+    use crate::render::RenderBackend;
+
+    #[test]
+    fn renders_djot_inside_preview_root_without_raw_html() {
+        let rendered =
+            DjotRenderer.render_document("hello *world*\n\n`<script>alert(1)</script>`{=html}");
+
+        assert!(
+            rendered
+                .html
+                .starts_with("<article id=\"md-root\" data-format=\"djot\">")
+        );
+        assert!(rendered.html.contains("<strong>world</strong>"));
+        assert!(!rendered.html.contains("<script>"));
+    }
+
+    #[test]
+    fn sanitizes_link_targets_and_attributes() {
+        let rendered = DjotRenderer.render_document(
+            "[x](javascript:alert(1)) ![y](javascript:alert(2)) <javascript:alert(3)> [ok](https://example.com)\n\n[z]{onclick=\"alert(4)\" data-line=9 .note}",
+        );
+
+        assert!(rendered.html.contains("<a href=\"#\">x</a>"));
+        assert!(rendered.html.contains("<img alt=\"y\" src=\"#\">"));
+        assert!(
+            rendered
+                .html
+                .contains("<a href=\"#\">javascript:alert(3)</a>")
+        );
+        assert!(
+            rendered
+                .html
+                .contains("<a href=\"https://example.com\">ok</a>")
+        );
+        assert!(rendered.html.contains("<span class=\"note\">z</span>"));
+    }
+}
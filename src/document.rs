@@ -0,0 +1,119 @@
+use ropey::Rope;
+
+/// A session's buffer content, kept as a rope so edits patch the lines they
+/// touch instead of replacing the whole text.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    rope: Rope,
+}
+
+/// Lines `first..last_old` replaced by `lines`, in 0-based rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineDelta {
+    pub first: usize,
+    pub last_old: usize,
+    pub lines: Vec<String>,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+        }
+    }
+
+    /// Rows in the document, counting an empty last line after a trailing
+    /// newline as the buffer does not: `"a\n"` has one line.
+    pub fn line_count(&self) -> usize {
+        let lines = self.rope.len_lines();
+        if lines > 1 && self.rope.line(lines - 1).len_chars() == 0 {
+            lines - 1
+        } else {
+            lines
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    /// Replaces the rows of `delta`. A delta past the end of the document
+    /// is clamped to it.
+    pub fn apply(&mut self, delta: &LineDelta) {
+        let count = self.line_count();
+        let last_old = delta.last_old.min(count);
+        let first = delta.first.min(last_old);
+        // Keep a document without a final newline that way.
+        let unterminated = last_old == count
+            && (self.rope.len_chars() == 0 || self.rope.char(self.rope.len_chars() - 1) != '\n');
+        let mut start = self.rope.line_to_char(first);
+        let end = if last_old == count {
+            self.rope.len_chars()
+        } else {
+            self.rope.line_to_char(last_old)
+        };
+
+        let mut inserted = delta.lines.join("\n");
+        if !unterminated && !delta.lines.is_empty() {
+            inserted.push('\n');
+        }
+        if unterminated && start > 0 {
+            if !delta.lines.is_empty() {
+                if self.rope.char(start - 1) != '\n' {
+                    inserted.insert(0, '\n');
+                }
+            } else if first < last_old {
+                // The row before becomes the last one and loses its break.
+                start -= 1;
+            }
+        }
+        self.rope.remove(start..end);
+        self.rope.insert(start, &inserted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, LineDelta};
+
+    fn delta(first: usize, last_old: usize, lines: &[&str]) -> LineDelta {
+        LineDelta {
+            first,
+            last_old,
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn patches_the_rows_of_a_delta() {
+        let mut document = Document::new("# Title\n\none\ntwo\n");
+        assert_eq!(document.line_count(), 4);
+
+        document.apply(&delta(2, 3, &["ONE", "one and a half"]));
+        assert_eq!(document.text(), "# Title\n\nONE\none and a half\ntwo\n");
+
+        document.apply(&delta(0, 2, &[]));
+        assert_eq!(document.text(), "ONE\none and a half\ntwo\n");
+        document.apply(&delta(3, 3, &["three"]));
+        assert_eq!(document.text(), "ONE\none and a half\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn keeps_a_buffer_without_a_final_newline_that_way() {
+        let mut document = Document::new("a\nb");
+        document.apply(&delta(2, 2, &[]));
+        assert_eq!(document.text(), "a\nb");
+        document.apply(&delta(2, 2, &["c"]));
+        assert_eq!(document.text(), "a\nb\nc");
+
+        document.apply(&delta(1, 3, &[]));
+        assert_eq!(document.text(), "a");
+
+        document.apply(&delta(0, 1, &["x", "y"]));
+        assert_eq!(document.text(), "x\ny");
+
+        let mut empty = Document::new("");
+        empty.apply(&delta(0, 1, &["typed"]));
+        assert_eq!(empty.text(), "typed");
+    }
+}
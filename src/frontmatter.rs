@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrontmatterValue {
+    Scalar(String),
+    List(Vec<String>),
+    Map(Vec<(String, String)>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frontmatter {
+    fields: HashMap<String, FrontmatterValue>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentOverrides {
+    pub theme: Option<String>,
+    pub toc: Option<bool>,
+    pub max_width: Option<u32>,
+    pub preview: Option<bool>,
+}
+
+/// Lines at either end of a document searched for an off marker, like Vim's
+/// `modelines`.
+const MARKER_LINES: usize = 5;
+
+/// True when the document opts out of previews with `live_markdown: { preview: off }`
+/// in its frontmatter or a `<!-- live-markdown: off -->` line near its start or end.
+pub fn preview_disabled(markdown: &str) -> bool {
+    if Frontmatter::parse(markdown)
+        .is_some_and(|frontmatter| frontmatter.overrides().preview == Some(false))
+    {
+        return true;
+    }
+
+    markdown.lines().take(MARKER_LINES).any(is_off_marker)
+        || markdown.lines().rev().take(MARKER_LINES).any(is_off_marker)
+}
+
+fn is_off_marker(line: &str) -> bool {
+    let Some(comment) = line
+        .trim()
+        .strip_prefix("<!--")
+        .and_then(|rest| rest.strip_suffix("-->"))
+    else {
+        return false;
+    };
+    let Some((key, value)) = split_key_value(comment.trim()) else {
+        return false;
+    };
+
+    matches!(key, "live-markdown" | "live_markdown") && parse_bool(value) == Some(false)
+}
+
+impl Frontmatter {
+    pub fn parse(markdown: &str) -> Option<Self> {
+        let body = yaml_block(markdown)?;
+        let lines: Vec<&str> = body
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .collect();
+
+        let mut fields = HashMap::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            index += 1;
+            if line.starts_with(char::is_whitespace) {
+                continue;
+            }
+
+            let Some((key, value)) = split_key_value(line) else {
+                continue;
+            };
+
+            let value = if value.is_empty() {
+                let start = index;
+                while index < lines.len() && lines[index].starts_with(char::is_whitespace) {
+                    index += 1;
+                }
+                parse_nested(&lines[start..index])
+            } else {
+                parse_inline(value)
+            };
+            fields.insert(key.to_string(), value);
+        }
+
+        Some(Self { fields })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&FrontmatterValue> {
+        self.fields.get(key)
+    }
+
+    /// The document's `tags`, given as a list or a comma or space separated
+    /// string, without a leading `#` and without duplicates.
+    pub fn tags(&self) -> Vec<String> {
+        let raw: Vec<&str> = match self.get("tags") {
+            Some(FrontmatterValue::List(items)) => items.iter().map(String::as_str).collect(),
+            Some(FrontmatterValue::Scalar(value)) => value
+                .split(|ch: char| ch == ',' || ch.is_whitespace())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in raw {
+            let tag = tag.trim().trim_start_matches('#');
+            if !tag.is_empty() && !tags.iter().any(|known| known == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        tags
+    }
+
+    pub fn overrides(&self) -> DocumentOverrides {
+        let Some(FrontmatterValue::Map(entries)) = self
+            .get("live_markdown")
+            .or_else(|| self.get("live-markdown"))
+        else {
+            return DocumentOverrides::default();
+        };
+
+        let mut overrides = DocumentOverrides::default();
+        for (key, value) in entries {
+            match key.replace('-', "_").as_str() {
+                "theme" if matches!(value.as_str(), "dark" | "light" | "auto") => {
+                    overrides.theme = Some(value.clone());
+                }
+                "toc" => overrides.toc = parse_bool(value),
+                "preview" => overrides.preview = parse_bool(value),
+                "max_width" => {
+                    overrides.max_width = value
+                        .trim_end_matches("px")
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|width| (320..=4096).contains(width));
+                }
+                _ => {}
+            }
+        }
+
+        overrides
+    }
+}
+
+fn yaml_block(markdown: &str) -> Option<&str> {
+    let rest = markdown
+        .strip_prefix("---\n")
+        .or_else(|| markdown.strip_prefix("---\r\n"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some(&rest[..offset]);
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let key = unquote(key.trim());
+    if key.is_empty() {
+        return None;
+    }
+
+    Some((key, value.trim()))
+}
+
+fn parse_inline(value: &str) -> FrontmatterValue {
+    if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        return FrontmatterValue::List(
+            items
+                .split(',')
+                .map(|item| unquote(item.trim()).to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        );
+    }
+
+    if let Some(entries) = value.strip_prefix('{').and_then(|v| v.strip_suffix('}')) {
+        return FrontmatterValue::Map(
+            entries
+                .split(',')
+                .filter_map(split_key_value)
+                .map(|(key, value)| (key.to_string(), unquote(value).to_string()))
+                .collect(),
+        );
+    }
+
+    FrontmatterValue::Scalar(unquote(value).to_string())
+}
+
+fn parse_nested(lines: &[&str]) -> FrontmatterValue {
+    if lines.iter().all(|line| line.trim_start().starts_with('-')) {
+        return FrontmatterValue::List(
+            lines
+                .iter()
+                .map(|line| unquote(line.trim_start()[1..].trim()).to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        );
+    }
+
+    FrontmatterValue::Map(
+        lines
+            .iter()
+            .filter_map(|line| split_key_value(line.trim()))
+            .map(|(key, value)| (key.to_string(), unquote(value).to_string()))
+            .collect(),
+    )
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DocumentOverrides, Frontmatter, FrontmatterValue, preview_disabled};
+
+    #[test]
+    fn parses_inline_and_nested_frontmatter_values() {
+        let frontmatter = Frontmatter::parse(
+            "---\ntitle: \"Spec\"\ntags: [a, 'b']\naliases:\n  - one\n  - two\nlive_markdown:\n  theme: light\n  max_width: 900px\n---\n# Body",
+        )
+        .expect("frontmatter");
+
+        assert_eq!(
+            frontmatter.get("title"),
+            Some(&FrontmatterValue::Scalar(String::from("Spec")))
+        );
+        assert_eq!(
+            frontmatter.get("tags"),
+            Some(&FrontmatterValue::List(vec![
+                String::from("a"),
+                String::from("b")
+            ]))
+        );
+        assert_eq!(
+            frontmatter.get("aliases"),
+            Some(&FrontmatterValue::List(vec![
+                String::from("one"),
+                String::from("two")
+            ]))
+        );
+        assert_eq!(
+            frontmatter.overrides(),
+            DocumentOverrides {
+                theme: Some(String::from("light")),
+                toc: None,
+                max_width: Some(900),
+                preview: None,
+            }
+        );
+    }
+
+    #[test]
+    fn reads_tags_from_lists_and_strings() {
+        let tags = |markdown: &str| {
+            Frontmatter::parse(markdown)
+                .map(|frontmatter| frontmatter.tags())
+                .unwrap_or_default()
+        };
+
+        assert_eq!(
+            tags("---\ntags: [rust, '#notes', rust]\n---\n"),
+            ["rust", "notes"]
+        );
+        assert_eq!(tags("---\ntags:\n  - a\n  - b\n---\n"), ["a", "b"]);
+        assert_eq!(tags("---\ntags: \"#a, b c\"\n---\n"), ["a", "b", "c"]);
+        assert!(tags("---\ntitle: x\n---\n").is_empty());
+    }
+
+    #[test]
+    fn reads_flow_mapping_overrides_and_ignores_invalid_values() {
+        let frontmatter = Frontmatter::parse(
+            "---\nlive_markdown: { theme: neon, toc: true, max_width: 12 }\n---\n",
+        )
+        .expect("frontmatter");
+
+        assert_eq!(
+            frontmatter.overrides(),
+            DocumentOverrides {
+                theme: None,
+                toc: Some(true),
+                max_width: None,
+                preview: None,
+            }
+        );
+        assert!(Frontmatter::parse("# No frontmatter").is_none());
+    }
+
+    #[test]
+    fn documents_can_disable_their_preview() {
+        assert!(preview_disabled(
+            "---\nlive_markdown: { preview: off }\n---\n# Big"
+        ));
+        assert!(preview_disabled("<!-- live-markdown: off -->\n# Generated"));
+        assert!(preview_disabled(
+            "# Generated\n\nrows\n<!--live_markdown:false-->\n"
+        ));
+        assert!(!preview_disabled("<!-- live-markdown: on -->\n# Doc"));
+        assert!(!preview_disabled(
+            "# Doc\n\n1\n2\n3\n```md\n<!-- live-markdown: off -->\n```\n\n4\n5\n6"
+        ));
+    }
+}
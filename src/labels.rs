@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_LABELS: &[(&str, &str)] = &[
+    ("note", "Note"),
+    ("tip", "Tip"),
+    ("important", "Important"),
+    ("warning", "Warning"),
+    ("caution", "Caution"),
+    ("references", "References"),
+    ("connecting", "Connecting..."),
+    ("connected", "Connected"),
+    ("live", "Live"),
+    ("switching", "Switching..."),
+    ("reconnecting", "Disconnected - reconnecting..."),
+    ("session_ended", "Session ended"),
+    ("session_paused", "Buffer not focused"),
+    ("waiting_for_preview", "Waiting for active preview..."),
+    ("waiting_for_snapshot", "Waiting for snapshot..."),
+    ("tasks_done", "{done}/{total} done"),
+    ("follow_editor", "Follow editor"),
+    ("detach", "Detach"),
+    ("reattach", "Follow cursor"),
+    ("add_comment", "Comment on this block:"),
+    ("resolve_comment", "Resolve"),
+    ("all_tags", "All tags"),
+    ("skip_to_content", "Skip to content"),
+    ("outline", "Outline"),
+    ("document", "Document"),
+    ("document_clipped", "Showing the first {shown} of {total}"),
+    ("render_anyway", "Render anyway"),
+    ("preview_disabled", "Preview disabled by this document"),
+    (
+        "render_truncated",
+        "Preview truncated: the document exceeds the {reason} limit",
+    ),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    overrides: HashMap<String, String>,
+}
+
+impl Labels {
+    pub fn new(overrides: HashMap<String, String>) -> Self {
+        let overrides = overrides
+            .into_iter()
+            .filter(|(key, value)| {
+                !value.trim().is_empty() && DEFAULT_LABELS.iter().any(|(name, _)| name == key)
+            })
+            .collect();
+
+        Self { overrides }
+    }
+
+    pub fn get(&self, key: &str) -> &str {
+        if let Some(value) = self.overrides.get(key) {
+            return value;
+        }
+
+        DEFAULT_LABELS
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map_or("", |(_, value)| value)
+    }
+
+    pub fn to_json(&self) -> String {
+        let merged: BTreeMap<&str, &str> = DEFAULT_LABELS
+            .iter()
+            .map(|(name, _)| (*name, self.get(name)))
+            .collect();
+
+        serde_json::to_string(&merged)
+            .unwrap_or_else(|_| String::from("{}"))
+            .replace("</", "<\\/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Labels;
+    use std::collections::HashMap;
+
+    #[test]
+    fn falls_back_to_english_and_ignores_unknown_keys() {
+        let labels = Labels::new(HashMap::from([
+            (String::from("note"), String::from("Hinweis")),
+            (String::from("bogus"), String::from("x")),
+            (String::from("tip"), String::from("  ")),
+        ]));
+
+        assert_eq!(labels.get("note"), "Hinweis");
+        assert_eq!(labels.get("tip"), "Tip");
+        assert_eq!(labels.get("bogus"), "");
+
+        let json = labels.to_json();
+        assert!(json.contains("\"note\":\"Hinweis\""));
+        assert!(!json.contains("bogus"));
+    }
+}
@@ -1,10 +1,34 @@
+mod access;
+mod ansi;
+mod asset_links;
+mod attributes;
+mod citation;
+mod convert;
+mod data_uri;
+mod djot;
+pub mod document;
+mod frontmatter;
+mod labels;
 mod nvim;
+mod port_owner;
+mod positions;
+mod recent;
+mod review;
+mod sanitize;
+mod theme;
+mod thumbnail;
+mod token;
 
+pub mod paths;
 pub mod plugin;
 pub mod protocol;
+pub mod recording;
 pub mod render;
 pub mod server;
 pub mod session;
+pub mod spell;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
 
 #[nvim_oxi::plugin]
 fn live_markdown_native() -> nvim_oxi::Result<nvim_oxi::Dictionary> {
@@ -1,9 +1,14 @@
+use live_markdown_native::paths::Paths;
 use live_markdown_native::plugin::LiveMarkdownPlugin;
+use live_markdown_native::protocol::SessionOptions;
+use live_markdown_native::recording;
 use live_markdown_native::server::ServerConfig;
 use live_markdown_native::session::BufferSnapshot;
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -16,6 +21,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
+    if args.get(1).is_some_and(|arg| arg == "replay") {
+        let Some(path) = args.get(2) else {
+            print_help();
+            return Ok(());
+        };
+        return replay(Path::new(path)).await;
+    }
+
     let source_path = args.get(1).cloned();
     let markdown = if let Some(path) = args.get(1) {
         fs::read_to_string(path)?
@@ -23,16 +36,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
         String::from("# Live Markdown\n\nOpen a file path argument to preview file contents.")
     };
 
-    let plugin = LiveMarkdownPlugin::new(ServerConfig::default());
+    let plugin = LiveMarkdownPlugin::new(config_from_env());
     let url = plugin
-        .start_preview(BufferSnapshot {
-            bufnr: 1,
-            changedtick: 1,
-            markdown,
-            cursor_line: 1,
-            cursor_col: 0,
-            source_path,
-        })
+        .start_preview(
+            BufferSnapshot {
+                bufnr: 1,
+                changedtick: 1,
+                markdown,
+                cursor_line: 1,
+                cursor_col: 0,
+                source_path,
+                backend: None,
+            },
+            SessionOptions::default(),
+        )
         .await?;
 
     println!("Markdown preview running at: {url}");
@@ -43,7 +60,76 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+async fn replay(path: &Path) -> Result<(), Box<dyn Error>> {
+    let events = recording::read_recording(path)?;
+    let Some(first) = events.first() else {
+        return Err(format!("{} has no recorded events", path.display()).into());
+    };
+
+    let plugin = LiveMarkdownPlugin::new(config_from_env());
+    let url = plugin
+        .start_preview(
+            BufferSnapshot {
+                bufnr: first.event.bufnr(),
+                changedtick: 1,
+                markdown: String::new(),
+                cursor_line: 1,
+                cursor_col: 0,
+                source_path: None,
+                backend: None,
+            },
+            SessionOptions::default(),
+        )
+        .await?;
+
+    println!("Replaying {} events at: {url}", events.len());
+    println!("Waiting for a browser to connect...");
+    let sessions = plugin.sessions();
+    while sessions
+        .list_sessions()
+        .await
+        .iter()
+        .all(|session| session.clients == 0)
+    {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    recording::replay(&sessions, &events).await;
+    println!("Replay finished. Press Ctrl+C to stop.");
+
+    tokio::signal::ctrl_c().await?;
+    plugin.shutdown().await;
+    Ok(())
+}
+
+/// The defaults with the `LIVE_MARKDOWN_*` environment variables applied,
+/// keeping state and cache in the XDG directories unless they say otherwise.
+fn config_from_env() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    for problem in config.apply_env() {
+        eprintln!("warning: {problem}");
+    }
+    let paths = Paths::xdg();
+    config.state_dir = config.state_dir.or(paths.state);
+    config.cache_dir = config.cache_dir.or(paths.cache);
+    config
+}
+
 fn print_help() {
     println!("live-markdown.nvim [path/to/file.md]");
     println!("Starts preview server and serves live markdown snapshot for the provided file.");
+    println!();
+    println!("live-markdown.nvim replay <recording.jsonl>");
+    println!("Replays a session recorded with `record_dir` once a browser connects.");
+    println!();
+    println!("Environment:");
+    println!("  LIVE_MARKDOWN_PORT          port to listen on (default 6419)");
+    println!("  LIVE_MARKDOWN_BIND          address to listen on (default 127.0.0.1)");
+    println!("  LIVE_MARKDOWN_THEME         dark, light or auto");
+    println!("  LIVE_MARKDOWN_OPEN_BROWSER  always, never or if_no_clients");
+    println!("  LIVE_MARKDOWN_RENDERER      builtin or pandoc");
+    println!("  LIVE_MARKDOWN_PROFILE       github, commonmark, obsidian or pandoc");
+    println!("  LIVE_MARKDOWN_STATE_DIR     where recent files and reading positions are kept");
+    println!("  LIVE_MARKDOWN_CACHE_DIR     where files that can be rebuilt are kept");
+    println!("  LIVE_MARKDOWN_RECORD_DIR    write each session's events here");
 }
@@ -1,105 +1,1035 @@
-use crate::plugin::LiveMarkdownPlugin;
-use crate::server::ServerConfig;
-use crate::session::BufferSnapshot;
+mod setup_schema;
+
+use crate::paths::Paths;
+use crate::plugin::browser::{self, Host};
+use crate::plugin::edits::{EditTracker, PendingEdit};
+use crate::plugin::open::link_at;
+use crate::plugin::toc::toc_range;
+use crate::plugin::{LiveBuffers, LiveMarkdownPlugin, OpenAction, PluginError};
+use crate::protocol::{
+    CommentAction, ConnectionInfo, FollowGroup, FollowScope, MAX_SCROLL_DURATION_MS, OpenBrowser,
+    ScrollAnchor, ScrollBehavior, SessionEndReason, SessionOptions, SessionSummary, ThemeMode,
+    UrlEcho, Viewport,
+};
+use crate::render::{
+    Accessibility, Backend, ConverterCommand, Labels, RenderProfile, RendererKind, SlugStyle,
+    TaskProgress,
+};
+use crate::review;
+use crate::server::{ServerConfig, ServerStatus};
+use crate::session::{BufferEdit, BufferSnapshot, EditOutcome, SessionEndHook};
+use crate::theme::{self, EditorHighlight};
 use nvim_oxi::api;
-use nvim_oxi::api::opts::{CreateAugroupOpts, CreateAutocmdOpts, CreateCommandOpts, OptionOpts};
-use nvim_oxi::api::types::{AutocmdCallbackArgs, CommandArgs, CommandNArgs};
+use nvim_oxi::api::opts::{
+    BufAttachOpts, CreateAugroupOpts, CreateAutocmdOpts, CreateCommandOpts, OnDetachArgs,
+    OnLinesArgs, OnReloadArgs, OptionOpts, SetKeymapOpts,
+};
+use nvim_oxi::api::types::{
+    AutocmdCallbackArgs, CommandArgs, CommandComplete, CommandNArgs, LogLevel, Mode, WindowBorder,
+    WindowConfig, WindowRelativeTo, WindowStyle, WindowTitle,
+};
 use nvim_oxi::conversion::FromObject;
-use nvim_oxi::{Dictionary, Function, Object, Result};
-use std::path::Path;
+use nvim_oxi::libuv::AsyncHandle;
+use nvim_oxi::{Array, Dictionary, Function, Object, Result};
+use setup_schema::{SetupIssue, flatten_groups, validate_setup};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::runtime::{Builder, Runtime};
+use tokio::sync::{oneshot, watch};
 
 static APP_STATE: OnceLock<Mutex<Option<Arc<AppState>>>> = OnceLock::new();
 static CALLBACKS_REGISTERED: AtomicBool = AtomicBool::new(false);
+const DEFAULT_SHARE_TTL: Duration = Duration::from_secs(30 * 60);
+/// `v:stderr`: writes reach the terminal Neovim is drawn in.
+const STDERR_CHANNEL: u32 = 2;
+/// `vim.diagnostic.severity.WARN`.
+const DIAGNOSTIC_WARN: i64 = 2;
 
 #[derive(Debug)]
 struct AppState {
     plugin: LiveMarkdownPlugin,
     runtime: Runtime,
+    text_browser: Vec<String>,
+    follow_scope: FollowScope,
+    open_browser: OpenBrowser,
+    browser_openers: Vec<Vec<String>>,
+    url_echo: UrlEcho,
+    sync_colorscheme: bool,
+    diagnostics: bool,
+    main_loop: MainLoop,
+    hooks: Hooks,
+    session_bufnrs: watch::Receiver<HashSet<i64>>,
+    /// The rows of each snapshotted buffer edited since its last snapshot
+    /// or edit was sent, by bufnr.
+    edits: Arc<Mutex<HashMap<i64, EditTracker>>>,
+    /// Buffers edited since their last render, with one render scheduled.
+    pending_renders: Arc<Mutex<HashSet<i64>>>,
+}
+
+type MainLoopTask = Box<dyn FnOnce() + Send>;
+
+/// Carries work finished on runtime threads, such as warnings and command
+/// results, over to the Neovim main loop.
+#[derive(Clone)]
+struct MainLoop {
+    handle: AsyncHandle,
+    pending: Arc<Mutex<Vec<MainLoopTask>>>,
+}
+
+impl std::fmt::Debug for MainLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MainLoop").finish_non_exhaustive()
+    }
+}
+
+impl MainLoop {
+    fn new() -> std::result::Result<Self, String> {
+        let pending: Arc<Mutex<Vec<MainLoopTask>>> = Arc::default();
+        let queued = Arc::clone(&pending);
+        let handle = AsyncHandle::new(move || {
+            let tasks = std::mem::take(
+                &mut *queued
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            );
+            nvim_oxi::schedule(move |_| {
+                for task in tasks {
+                    task();
+                }
+                Ok::<_, Infallible>(())
+            });
+            Ok::<_, Infallible>(())
+        })
+        .map_err(|err| format!("failed to create async handle: {err}"))?;
+
+        Ok(Self { handle, pending })
+    }
+
+    fn run(&self, task: impl FnOnce() + Send + 'static) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Box::new(task));
+        let _ = self.handle.send();
+    }
+
+    fn warn(&self, message: String) {
+        self.run(move || notify_warn(&message));
+    }
+}
+
+/// The `on_start(url, bufnr)` and `on_stop(bufnr, reason)` callbacks from
+/// `setup()`.
+#[derive(Clone, Default)]
+struct Hooks {
+    on_start: Option<Function<(String, i64), ()>>,
+    on_stop: Option<Function<(i64, String), ()>>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_start", &self.on_start.is_some())
+            .field("on_stop", &self.on_stop.is_some())
+            .finish()
+    }
+}
+
+impl Hooks {
+    fn started(&self, url: &str, bufnr: i64) {
+        if let Some(on_start) = &self.on_start
+            && let Err(err) = on_start.call((url.to_string(), bufnr))
+        {
+            notify_err(&format!("[live-markdown.nvim] on_start failed: {err}"));
+        }
+    }
+
+    /// Reports sessions that ended in an error and calls `on_stop` on the
+    /// main loop for every session that ends, which may happen on a
+    /// runtime thread.
+    fn session_end_hook(&self, main_loop: &MainLoop) -> SessionEndHook {
+        let on_stop = self.on_stop.clone();
+        let main_loop = main_loop.clone();
+        SessionEndHook::new(move |bufnr, reason| {
+            if let SessionEndReason::Error { .. } = reason {
+                main_loop.warn(format!(
+                    "[live-markdown.nvim] preview of buffer {bufnr} ended: {}",
+                    reason.detail()
+                ));
+            }
+            let Some(on_stop) = on_stop.clone() else {
+                return;
+            };
+            let reason = reason.name().to_string();
+            main_loop.run(move || {
+                if let Err(err) = on_stop.call((bufnr, reason)) {
+                    notify_err(&format!("[live-markdown.nvim] on_stop failed: {err}"));
+                }
+            });
+        })
+    }
+}
+
+/// Reads the `on_start` and `on_stop` callbacks of `setup()`.
+fn parse_hooks(opts: Option<&Dictionary>) -> std::result::Result<Hooks, String> {
+    let Some(opts) = opts else {
+        return Ok(Hooks::default());
+    };
+
+    Ok(Hooks {
+        on_start: get_dict_function(opts, "on_start")?,
+        on_stop: get_dict_function(opts, "on_stop")?,
+    })
+}
+
+/// Lists the buffers that still exist on the Neovim main loop for the
+/// session supervisor running on the runtime.
+struct BufferProbe {
+    handle: AsyncHandle,
+    waiting: Arc<Mutex<Vec<oneshot::Sender<HashSet<i64>>>>>,
+}
+
+impl BufferProbe {
+    fn new() -> std::result::Result<Self, String> {
+        let waiting: Arc<Mutex<Vec<oneshot::Sender<HashSet<i64>>>>> = Arc::default();
+        let queued = Arc::clone(&waiting);
+        let handle = AsyncHandle::new(move || {
+            let senders = std::mem::take(
+                &mut *queued
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            );
+            nvim_oxi::schedule(move |_| {
+                let live: HashSet<i64> = api::list_bufs()
+                    .map(|buffer| i64::from(buffer.handle()))
+                    .collect();
+                for sender in senders {
+                    let _ = sender.send(live.clone());
+                }
+                Ok::<_, Infallible>(())
+            });
+            Ok::<_, Infallible>(())
+        })
+        .map_err(|err| format!("failed to create async handle: {err}"))?;
+
+        Ok(Self { handle, waiting })
+    }
+
+    fn into_live_buffers(self) -> LiveBuffers {
+        Box::new(move || {
+            let (sender, receiver) = oneshot::channel();
+            self.waiting
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(sender);
+            let _ = self.handle.send();
+            receiver
+        })
+    }
 }
 
 impl AppState {
-    fn new(config: ServerConfig) -> std::result::Result<Self, String> {
+    fn new(config: ServerConfig, hooks: Hooks) -> std::result::Result<Self, String> {
         let runtime = Builder::new_multi_thread()
             .thread_name("live-markdown.nvim")
             .enable_all()
             .build()
             .map_err(|err| format!("failed to start runtime: {err}"))?;
+        let main_loop = MainLoop::new()?;
+        let plugin = LiveMarkdownPlugin::new(config.clone())
+            .with_session_end_hook(hooks.session_end_hook(&main_loop));
+        let live_buffers = BufferProbe::new()?.into_live_buffers();
+        runtime.spawn(plugin.clone().supervise_sessions(live_buffers));
 
         Ok(Self {
-            plugin: LiveMarkdownPlugin::new(config),
+            main_loop,
+            session_bufnrs: plugin.sessions().watch_bufnrs(),
+            edits: Arc::default(),
+            pending_renders: Arc::default(),
+            text_browser: config.text_browser,
+            follow_scope: config.follow_scope,
+            open_browser: config.open_browser,
+            browser_openers: resolve_browser_openers(config.browser_openers, Host::detect()),
+            url_echo: config.url_echo,
+            sync_colorscheme: config.sync_colorscheme,
+            diagnostics: config.diagnostics,
+            hooks,
+            plugin,
             runtime,
         })
     }
 
     fn shutdown(&self) {
+        // Detaches the edit trackers on their next callback.
+        self.edits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
         self.runtime.block_on(self.plugin.shutdown());
     }
 
+    /// The tabpage or window previews started here follow buffers in.
+    fn current_follow_group(&self) -> Option<FollowGroup> {
+        match self.follow_scope {
+            FollowScope::Global => None,
+            FollowScope::Tabpage => Some(FollowGroup::Tabpage(i64::from(
+                api::get_current_tabpage().handle(),
+            ))),
+            FollowScope::Window => Some(FollowGroup::Window(i64::from(
+                api::get_current_win().handle(),
+            ))),
+        }
+    }
+
+    /// Reads the buffer along with the backend its filetype renders with.
+    /// Edits from here on are sent as deltas against this snapshot.
+    fn snapshot(&self, buffer: &api::Buffer) -> std::result::Result<BufferSnapshot, String> {
+        let mut snapshot = snapshot_with_text(buffer, read_buffer_text(buffer)?)?;
+        snapshot.backend = self.buffer_backend(buffer);
+        self.with_edit_tracker(buffer, |tracker| tracker.reset(snapshot.changedtick));
+        Ok(snapshot)
+    }
+
+    fn buffer_backend(&self, buffer: &api::Buffer) -> Option<Backend> {
+        buffer_filetype(buffer).and_then(|filetype| self.plugin.backend_for_filetype(&filetype))
+    }
+
+    /// Runs `f` on the edit tracker of `buffer`, attached on first use. A
+    /// buffer that cannot be attached has none and is always read whole.
+    fn with_edit_tracker<T>(
+        &self,
+        buffer: &api::Buffer,
+        f: impl FnOnce(&mut EditTracker) -> T,
+    ) -> Option<T> {
+        let bufnr = i64::from(buffer.handle());
+        let mut trackers = self
+            .edits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let tracker = match trackers.entry(bufnr) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.attach_edit_tracker(buffer).ok()?;
+                entry.insert(EditTracker::default())
+            }
+        };
+        Some(f(tracker))
+    }
+
+    /// Keeps `buffer`'s edit tracker told about every edit, and renders
+    /// buffers with a session once the edits of a command are in. The
+    /// callbacks detach once the tracker is gone.
+    fn attach_edit_tracker(&self, buffer: &api::Buffer) -> Result<()> {
+        let on_lines = Arc::clone(&self.edits);
+        let on_reload = Arc::clone(&self.edits);
+        let on_detach = Arc::clone(&self.edits);
+        let lines_render = self.render_scheduler();
+        let reload_render = self.render_scheduler();
+        let opts = BufAttachOpts::builder()
+            .on_lines(
+                move |(_, buffer, _, first, last_old, last_new, ..): OnLinesArgs| {
+                    let bufnr = i64::from(buffer.handle());
+                    {
+                        let mut trackers = on_lines
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner());
+                        let Some(tracker) = trackers.get_mut(&bufnr) else {
+                            return Ok::<_, Infallible>(true);
+                        };
+                        tracker.mark_changed(first, last_old, last_new);
+                    }
+                    lines_render(buffer);
+                    Ok(false)
+                },
+            )
+            .on_reload(move |(_, buffer): OnReloadArgs| {
+                let bufnr = i64::from(buffer.handle());
+                {
+                    let mut trackers = on_reload
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    let Some(tracker) = trackers.get_mut(&bufnr) else {
+                        return Ok::<_, Infallible>(true);
+                    };
+                    tracker.invalidate();
+                }
+                reload_render(buffer);
+                Ok(false)
+            })
+            .on_detach(move |(_, buffer): OnDetachArgs| {
+                on_detach
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .remove(&i64::from(buffer.handle()));
+                Ok::<_, Infallible>(false)
+            })
+            .build();
+        buffer.attach(false, &opts)?;
+        Ok(())
+    }
+
+    /// Schedules one render for a buffer with a session, however many
+    /// `on_lines` calls a single command such as `:%s` makes.
+    fn render_scheduler(&self) -> impl Fn(api::Buffer) + 'static {
+        let sessions = self.session_bufnrs.clone();
+        let pending = Arc::clone(&self.pending_renders);
+        move |buffer| {
+            let bufnr = i64::from(buffer.handle());
+            if !sessions.borrow().contains(&bufnr) {
+                return;
+            }
+            let newly_pending = pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(bufnr);
+            if newly_pending {
+                nvim_oxi::schedule(move |_| {
+                    if let Some(state) = state() {
+                        state.render_edited(buffer);
+                    }
+                    Ok::<_, Infallible>(())
+                });
+            }
+        }
+    }
+
+    /// Answers from the published session set; autocmds on every keystroke
+    /// ask, and must not wait on the runtime.
     fn has_session(&self, bufnr: i64) -> bool {
-        self.runtime.block_on(self.plugin.has_session(bufnr))
+        self.session_bufnrs.borrow().contains(&bufnr)
     }
 
     fn has_active_previews(&self) -> bool {
-        self.runtime
-            .block_on(self.plugin.sessions().session_count())
-            > 0
+        !self.session_bufnrs.borrow().is_empty()
+    }
+
+    /// Runs `task` on the runtime and hands its result to `then` on the main
+    /// loop, so a command returns before a slow bind or shutdown finishes.
+    fn spawn_then<T: Send + 'static>(
+        &self,
+        task: impl Future<Output = T> + Send + 'static,
+        then: impl FnOnce(T) + Send + 'static,
+    ) {
+        let main_loop = self.main_loop.clone();
+        self.runtime.spawn(async move {
+            let result = task.await;
+            main_loop.run(move || then(result));
+        });
     }
 
-    fn start_current(&self) -> std::result::Result<String, String> {
+    /// Starts the current buffer's preview and opens it in the system browser
+    /// as `open_browser` (or the configured policy) says. Reading the buffer
+    /// fails right away; starting the server reports back once it is up.
+    fn start_current(
+        &self,
+        mut options: SessionOptions,
+        open_browser: Option<OpenBrowser>,
+    ) -> std::result::Result<(), String> {
         let buffer = api::get_current_buf();
-        if !is_markdown_buffer(&buffer) {
+        options.follow_group = self.current_follow_group();
+        if !is_previewable_buffer(&buffer) {
             return Err(String::from(
                 "current buffer is not markdown (filetype or extension mismatch)",
             ));
         }
 
+        let snapshot = self.snapshot(&buffer)?;
+        let bufnr = snapshot.bufnr;
+        if self.diagnostics
+            && is_markdown_buffer(&buffer)
+            && let Err(err) = self.publish_diagnostics(bufnr, &snapshot.markdown)
+        {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+        }
+        let plugin = self.plugin.clone();
+        self.spawn_then(
+            async move {
+                let url = plugin.start_preview(snapshot, options).await?;
+                let clients = plugin.sessions().client_count(bufnr).await;
+                Ok::<_, PluginError>((url, clients))
+            },
+            move |started| match (started, state()) {
+                (Ok((url, clients)), Some(state)) => {
+                    state.preview_started(&url, bufnr, clients, open_browser)
+                }
+                (Err(err), _) => report_plugin_error(&err),
+                (Ok(_), None) => {}
+            },
+        );
+
+        Ok(())
+    }
+
+    fn preview_started(
+        &self,
+        url: &str,
+        bufnr: i64,
+        clients: usize,
+        open_browser: Option<OpenBrowser>,
+    ) {
+        if open_browser
+            .unwrap_or(self.open_browser)
+            .should_open(clients)
+        {
+            self.open_in_browser(url.to_string());
+        }
+        self.announce_url(url);
+        self.hooks.started(url, bufnr);
+    }
+
+    /// Tries the configured openers off the main loop and reports the URL
+    /// once all of them failed. Without openers (a remote shell), it explains
+    /// how to reach the preview instead.
+    fn open_in_browser(&self, url: String) {
+        if self.browser_openers.is_empty() {
+            notify_info(&format!(
+                "[live-markdown.nvim] {}",
+                browser::forwarding_hint(&url)
+            ));
+            return;
+        }
+
+        let openers = self.browser_openers.clone();
+        let main_loop = self.main_loop.clone();
+        self.runtime.spawn_blocking(move || {
+            if let Err(err) = browser::open_url(&openers, &url) {
+                main_loop.warn(format!(
+                    "[live-markdown.nvim] could not open a browser ({err}); open {url} manually"
+                ));
+            }
+        });
+    }
+
+    fn announce_url(&self, url: &str) {
+        if self.url_echo == UrlEcho::None {
+            return;
+        }
+
+        notify_info(&format!("[live-markdown.nvim] preview started: {url}"));
+        if self.url_echo == UrlEcho::Osc8 {
+            let tmux = std::env::var_os("TMUX").is_some();
+            let _ = api::chan_send(STDERR_CHANNEL, &browser::osc8_hyperlink(url, url, tmux));
+        }
+    }
+
+    /// Whether any preview was running to stop.
+    fn stop_all(&self) -> impl Future<Output = bool> + Send + 'static {
+        let plugin = self.plugin.clone();
+        async move {
+            if plugin.sessions().session_count().await == 0 {
+                return false;
+            }
+
+            plugin.stop_all_previews().await;
+            true
+        }
+    }
+
+    fn show_url_current(
+        &self,
+    ) -> impl Future<Output = std::result::Result<Option<String>, PluginError>> + Send + 'static
+    {
+        let bufnr = i64::from(api::get_current_buf().handle());
+        let plugin = self.plugin.clone();
+        async move { plugin.open_preview(bufnr).await }
+    }
+
+    /// The preview link pinned to `bufnr`, which keeps showing that buffer
+    /// whichever one the editor follows.
+    fn show_url_buffer(
+        &self,
+        bufnr: i64,
+    ) -> impl Future<Output = std::result::Result<Option<String>, PluginError>> + Send + 'static
+    {
+        let plugin = self.plugin.clone();
+        async move {
+            plugin
+                .open_preview(bufnr)
+                .await
+                .map(|url| url.map(|base| format!("{base}?buf={bufnr}")))
+        }
+    }
+
+    fn session_bufnrs(&self) -> Vec<i64> {
+        let mut bufnrs = self
+            .runtime
+            .block_on(self.plugin.sessions().session_bufnrs());
+        bufnrs.sort_unstable();
+        bufnrs
+    }
+
+    fn share_current(
+        &self,
+        ttl: Duration,
+    ) -> impl Future<Output = std::result::Result<Option<String>, PluginError>> + Send + 'static
+    {
+        let bufnr = i64::from(api::get_current_buf().handle());
+        let plugin = self.plugin.clone();
+        async move { plugin.share_preview(bufnr, ttl).await }
+    }
+
+    fn rotate_share_current(
+        &self,
+    ) -> impl Future<Output = std::result::Result<Option<String>, PluginError>> + Send + 'static
+    {
+        let bufnr = i64::from(api::get_current_buf().handle());
+        let plugin = self.plugin.clone();
+        async move { plugin.rotate_share(bufnr).await }
+    }
+
+    fn open_under_cursor(
+        &self,
+    ) -> std::result::Result<impl Future<Output = OpenAction> + Send + 'static, String> {
+        let buffer = api::get_current_buf();
+        let (line, col) = cursor_for_buffer(&buffer);
+        let text = buffer
+            .get_lines(line - 1..line, false)
+            .map_err(|err| format!("failed to read cursor line: {err}"))?
+            .next()
+            .map(|line| line.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let Some(target) = link_at(&text, col) else {
+            return Err(String::from("no link or image under cursor"));
+        };
+
+        let bufnr = i64::from(buffer.handle());
+        let plugin = self.plugin.clone();
+        Ok(async move { plugin.open_link(bufnr, target).await })
+    }
+
+    fn update_toc_current(&self) -> std::result::Result<bool, String> {
+        let mut buffer = api::get_current_buf();
+        if !is_markdown_buffer(&buffer) {
+            return Err(String::from("current buffer is not markdown"));
+        }
+
+        let lines: Vec<String> = buffer
+            .get_lines(.., false)
+            .map_err(|err| format!("failed to read buffer lines: {err}"))?
+            .map(|line| line.to_string_lossy().into_owned())
+            .collect();
+        let toc = self.plugin.toc_lines(&lines.join("\n"));
+
+        let (range, updated) = match toc_range(&lines) {
+            Some((start, stop)) => (start..stop + 1, true),
+            None => {
+                let (line, _) = cursor_for_buffer(&buffer);
+                (line - 1..line - 1, false)
+            }
+        };
+
+        buffer
+            .set_lines(range, false, toc)
+            .map_err(|err| format!("failed to write table of contents: {err}"))?;
+        Ok(updated)
+    }
+
+    fn format_table_current(&self) -> std::result::Result<(), String> {
+        let mut buffer = api::get_current_buf();
+        let markdown = snapshot_from_buffer(&buffer)?.markdown;
+        let (line, _) = cursor_for_buffer(&buffer);
+
+        let Some(table) = self.plugin.format_table(&markdown, line - 1) else {
+            return Err(String::from("no table under cursor"));
+        };
+
+        buffer
+            .set_lines(table.start_line..table.end_line + 1, false, table.lines)
+            .map_err(|err| format!("failed to write table: {err}"))
+    }
+
+    /// Replaces the buffer's live-markdown diagnostics with the lint issues
+    /// in `markdown`; the number of issues.
+    fn publish_diagnostics(
+        &self,
+        bufnr: i64,
+        markdown: &str,
+    ) -> std::result::Result<usize, String> {
+        let issues = self.plugin.lint(markdown);
+        let items: Array = issues
+            .iter()
+            .map(|issue| {
+                Object::from(Dictionary::from_iter([
+                    ("lnum", Object::from(issue.line.saturating_sub(1) as i64)),
+                    ("col", Object::from(0)),
+                    ("severity", Object::from(DIAGNOSTIC_WARN)),
+                    ("source", Object::from("live-markdown")),
+                    ("message", Object::from(issue.message.as_str())),
+                ]))
+            })
+            .collect();
+        api::call_function::<_, Object>(
+            "luaeval",
+            (
+                "vim.diagnostic.set(vim.api.nvim_create_namespace('live_markdown'), _A[1], _A[2])",
+                Array::from_iter([Object::from(bufnr), Object::from(items)]),
+            ),
+        )
+        .map_err(|err| format!("failed to set diagnostics: {err}"))?;
+        Ok(issues.len())
+    }
+
+    fn lint_current(&self) -> std::result::Result<usize, String> {
+        let buffer = api::get_current_buf();
+        if !is_markdown_buffer(&buffer) {
+            return Err(String::from("current buffer is not markdown"));
+        }
         let snapshot = snapshot_from_buffer(&buffer)?;
-        let url = self
+        self.publish_diagnostics(snapshot.bufnr, &snapshot.markdown)
+    }
+
+    fn session_list(&self) -> impl Future<Output = (Vec<SessionSummary>, String)> + Send + 'static {
+        let plugin = self.plugin.clone();
+        async move {
+            (
+                plugin.list_sessions().await,
+                plugin.preview_url().await.unwrap_or_default(),
+            )
+        }
+    }
+
+    fn session_array(sessions: Vec<SessionSummary>, url: String) -> Array {
+        sessions
+            .into_iter()
+            .map(|session| {
+                Object::from(Dictionary::from_iter([
+                    ("bufnr", Object::from(session.bufnr)),
+                    ("filename", Object::from(session.filename)),
+                    ("clients", Object::from(session.clients as i64)),
+                    ("url", Object::from(url.clone())),
+                ]))
+            })
+            .collect()
+    }
+
+    fn recent_list(&self) -> Array {
+        self.plugin
+            .recent_files()
+            .into_iter()
+            .map(|file| {
+                Object::from(Dictionary::from_iter([
+                    ("path", Object::from(file.path)),
+                    ("opened_at", Object::from(file.opened_at as i64)),
+                ]))
+            })
+            .collect()
+    }
+
+    fn bookmark_list(&self, bufnr: i64) -> Array {
+        self.runtime
+            .block_on(self.plugin.sessions().bookmarks(bufnr))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|bookmark| {
+                Object::from(Dictionary::from_iter([
+                    ("line", Object::from(bookmark.line as i64)),
+                    ("text", Object::from(bookmark.text)),
+                ]))
+            })
+            .collect()
+    }
+
+    fn comment_list(&self, bufnr: i64) -> Array {
+        self.runtime
+            .block_on(self.plugin.sessions().comments(bufnr))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|comment| {
+                Object::from(Dictionary::from_iter([
+                    ("id", Object::from(comment.id as i64)),
+                    ("line", Object::from(comment.line as i64)),
+                    ("text", Object::from(comment.text)),
+                ]))
+            })
+            .collect()
+    }
+
+    /// Puts the review comments of the current buffer's preview in the
+    /// window's location list; the number of comments, `None` without a
+    /// preview.
+    fn comments_to_loclist(&self) -> std::result::Result<Option<usize>, String> {
+        let bufnr = i64::from(api::get_current_buf().handle());
+        let Some(comments) = self
             .runtime
-            .block_on(self.plugin.start_preview(snapshot))
+            .block_on(self.plugin.sessions().comments(bufnr))
+        else {
+            return Ok(None);
+        };
+
+        let items: Array = comments
+            .iter()
+            .map(|comment| {
+                Object::from(Dictionary::from_iter([
+                    ("bufnr", Object::from(bufnr)),
+                    ("lnum", Object::from(comment.line as i64)),
+                    (
+                        "text",
+                        Object::from(
+                            comment
+                                .text
+                                .split_whitespace()
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                        ),
+                    ),
+                ]))
+            })
+            .collect();
+        let what = Dictionary::from_iter([
+            ("title", Object::from("live-markdown comments")),
+            ("items", Object::from(items)),
+        ]);
+        api::call_function::<_, i64>("setloclist", (0, Array::new(), " ", what))
             .map_err(|err| err.to_string())?;
+        Ok(Some(comments.len()))
+    }
+
+    /// Writes the current buffer's review comments as markdown to `path`, or
+    /// next to the file; the path written and the number of comments.
+    fn export_comments_current(
+        &self,
+        path: Option<PathBuf>,
+    ) -> std::result::Result<(PathBuf, usize), String> {
+        let buffer = api::get_current_buf();
+        let snapshot = self.snapshot(&buffer)?;
+        let comments = self
+            .runtime
+            .block_on(self.plugin.sessions().comments(snapshot.bufnr))
+            .ok_or_else(|| String::from("no active preview for current buffer"))?;
+        if comments.is_empty() {
+            return Err(String::from("no review comments to export"));
+        }
 
-        Ok(url)
+        let source = snapshot.source_path.as_deref().map(PathBuf::from);
+        let path = match (path, &source) {
+            (Some(path), _) => path,
+            (None, Some(source)) => review::default_review_path(source),
+            (None, None) => return Err(String::from("buffer has no file; give a path")),
+        };
+        let filename = source.as_deref().and_then(Path::file_name).map_or_else(
+            || String::from("buffer"),
+            |name| name.to_string_lossy().into_owned(),
+        );
+
+        let review = review::review_markdown(&filename, &snapshot.markdown, &comments);
+        std::fs::write(&path, review)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        Ok((path, comments.len()))
     }
 
-    fn stop_active(&self) -> std::result::Result<bool, String> {
+    fn resolve_comment(&self, bufnr: i64, id: u64) -> bool {
         self.runtime
-            .block_on(async {
-                let sessions = self.plugin.sessions();
-                let Some(bufnr) = sessions.active_bufnr().await else {
-                    return Ok(false);
-                };
+            .block_on(
+                self.plugin
+                    .sessions()
+                    .update_comments(bufnr, CommentAction::Resolve { id }),
+            )
+            .is_some()
+    }
 
-                self.plugin.stop_preview(bufnr).await
+    fn client_list(&self) -> Array {
+        self.plugin
+            .connections()
+            .connections
+            .into_iter()
+            .map(|connection| {
+                Object::from(Dictionary::from_iter([
+                    ("id", Object::from(connection.id as i64)),
+                    ("bufnr", Object::from(connection.bufnr)),
+                    (
+                        "client",
+                        Object::from(connection.client.unwrap_or_default()),
+                    ),
+                    ("shared", Object::from(connection.shared)),
+                    (
+                        "remote",
+                        Object::from(connection.remote.unwrap_or_default()),
+                    ),
+                    (
+                        "user_agent",
+                        Object::from(connection.user_agent.unwrap_or_default()),
+                    ),
+                    (
+                        "connected_at_ms",
+                        Object::from(connection.connected_at_ms as i64),
+                    ),
+                ]))
             })
-            .map_err(|err| err.to_string())
+            .collect()
+    }
+
+    fn stop_buffer(
+        &self,
+        bufnr: i64,
+    ) -> impl Future<Output = std::result::Result<bool, PluginError>> + Send + 'static {
+        let plugin = self.plugin.clone();
+        async move { plugin.stop_preview(bufnr).await }
+    }
+
+    fn info_current(&self) -> impl Future<Output = Option<(Vec<String>, String)>> + Send + 'static {
+        let plugin = self.plugin.clone();
+        async move {
+            let sessions = plugin.sessions();
+            let bufnr = sessions.active_bufnr().await?;
+            let session = plugin
+                .list_sessions()
+                .await
+                .into_iter()
+                .find(|session| session.bufnr == bufnr)?;
+            let tasks = sessions.task_progress(bufnr).await.unwrap_or_default();
+            let url = plugin.preview_url().await?;
+            Some(info_lines(url, session, tasks))
+        }
+    }
+
+    fn refresh_current(
+        &self,
+    ) -> std::result::Result<
+        impl Future<Output = std::result::Result<(), PluginError>> + Send + 'static,
+        String,
+    > {
+        let buffer = api::get_current_buf();
+        let snapshot = self.snapshot(&buffer)?;
+        let plugin = self.plugin.clone();
+        Ok(async move { plugin.refresh(snapshot).await })
+    }
+
+    /// Opens the current preview in `text_browser` in a split once the
+    /// server is up.
+    fn split_current(&self) {
+        let bufnr = i64::from(api::get_current_buf().handle());
+        let plugin = self.plugin.clone();
+        let text_browser = self.text_browser.clone();
+        self.spawn_then(
+            async move { plugin.open_preview(bufnr).await },
+            move |url| {
+                let opened = match url {
+                    Ok(Some(url)) => open_text_browser(&text_browser, &url, bufnr),
+                    Ok(None) => Err(String::from("no active preview for current buffer")),
+                    Err(err) => Err(plugin_error_message(&err)),
+                };
+                if let Err(err) = opened {
+                    notify_err(&format!("[live-markdown.nvim] {err}"));
+                }
+            },
+        );
+    }
+
+    /// Applies `assignments` in order to the current buffer's session, or
+    /// the active one; false without a session. Stops at the first rejected
+    /// assignment.
+    fn set_options_current(
+        &self,
+        assignments: Vec<(String, String)>,
+    ) -> impl Future<Output = std::result::Result<bool, String>> + Send + 'static {
+        let current = i64::from(api::get_current_buf().handle());
+        let plugin = self.plugin.clone();
+        async move {
+            let bufnr = if plugin.has_session(current).await {
+                current
+            } else {
+                match plugin.sessions().active_bufnr().await {
+                    Some(bufnr) => bufnr,
+                    None => return Ok(false),
+                }
+            };
+
+            for (key, value) in assignments {
+                plugin
+                    .set_session_option(bufnr, &key, &value)
+                    .await
+                    .map_err(|err| plugin_error_message(&err))?;
+            }
+            Ok(true)
+        }
     }
 
-    fn show_url_current(&self) -> std::result::Result<Option<String>, String> {
+    fn task_progress_current(&self) -> Option<TaskProgress> {
         let bufnr = i64::from(api::get_current_buf().handle());
         self.runtime
-            .block_on(self.plugin.open_preview(bufnr))
-            .map_err(|err| err.to_string())
+            .block_on(self.plugin.sessions().task_progress(bufnr))
     }
 
-    fn on_text_changed(&self, buffer: api::Buffer) {
+    /// Renders a buffer its attach callbacks saw edited, sending only the
+    /// rows edited since the session's document last caught up.
+    fn render_edited(&self, buffer: api::Buffer) {
         let bufnr = i64::from(buffer.handle());
-        if !self.has_session(bufnr) {
+        self.pending_renders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&bufnr);
+        if !buffer.is_valid() || !is_previewable_buffer(&buffer) || !self.has_session(bufnr) {
             return;
         }
 
-        let snapshot = match snapshot_from_buffer(&buffer) {
-            Ok(snapshot) => snapshot,
-            Err(_) => return,
+        let Ok(current) = snapshot_with_text(&buffer, String::new()) else {
+            return;
         };
-
+        let pending = self
+            .with_edit_tracker(&buffer, |tracker| {
+                tracker.take(current.changedtick, |rows| read_buffer_lines(&buffer, rows))
+            })
+            .unwrap_or(Ok(PendingEdit::Full));
         let plugin = self.plugin.clone();
-        self.runtime.spawn(async move {
-            plugin.on_text_changed(snapshot).await;
-        });
+        let main_loop = self.main_loop.clone();
+        match pending {
+            Ok(PendingEdit::Delta { base, delta }) => {
+                let edit = BufferEdit {
+                    bufnr,
+                    base_changedtick: base,
+                    changedtick: current.changedtick,
+                    delta,
+                    cursor_line: current.cursor_line,
+                    cursor_col: current.cursor_col,
+                    source_path: current.source_path,
+                    backend: self.buffer_backend(&buffer),
+                };
+                self.runtime.spawn(async move {
+                    match plugin.on_buffer_edit(edit).await {
+                        (EditOutcome::OutOfSync, _) => main_loop.run(move || {
+                            if let Some(state) = state() {
+                                state.resync(buffer);
+                            }
+                        }),
+                        (_, Some(elapsed)) => main_loop.warn(format_slow_render(bufnr, elapsed)),
+                        (_, None) => {}
+                    }
+                });
+            }
+            Ok(PendingEdit::Full) => {
+                let Ok(snapshot) = self.snapshot(&buffer) else {
+                    return;
+                };
+                self.runtime.spawn(async move {
+                    if let Some(elapsed) = plugin.on_text_changed(snapshot).await {
+                        main_loop.warn(format_slow_render(bufnr, elapsed));
+                    }
+                });
+            }
+            Ok(PendingEdit::Unchanged) | Err(_) => {}
+        }
+    }
+
+    /// Sends the whole of a buffer whose session's document an edit could
+    /// not patch.
+    fn resync(&self, buffer: api::Buffer) {
+        let bufnr = i64::from(buffer.handle());
+        if let Some(tracker) = self
+            .edits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get_mut(&bufnr)
+        {
+            tracker.invalidate();
+        }
+        self.render_edited(buffer);
     }
 
     fn on_buf_write(&self, buffer: api::Buffer) {
@@ -108,14 +1038,23 @@ impl AppState {
             return;
         }
 
-        let snapshot = match snapshot_from_buffer(&buffer) {
+        let snapshot = match self.snapshot(&buffer) {
             Ok(snapshot) => snapshot,
             Err(_) => return,
         };
+        if self.diagnostics
+            && is_markdown_buffer(&buffer)
+            && let Err(err) = self.publish_diagnostics(bufnr, &snapshot.markdown)
+        {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+        }
 
         let plugin = self.plugin.clone();
+        let main_loop = self.main_loop.clone();
         self.runtime.spawn(async move {
-            plugin.on_buf_write(snapshot).await;
+            if let Some(elapsed) = plugin.on_buf_write(snapshot).await {
+                main_loop.warn(format_slow_render(bufnr, elapsed));
+            }
         });
     }
 
@@ -133,111 +1072,908 @@ impl AppState {
         });
     }
 
+    fn on_win_scrolled(&self) {
+        let win = api::get_current_win();
+        let Ok(buffer) = win.get_buf() else {
+            return;
+        };
+        let bufnr = i64::from(buffer.handle());
+        if !is_previewable_buffer(&buffer) || !self.has_session(bufnr) {
+            return;
+        }
+
+        let Some(viewport) = viewport_for_window(&win) else {
+            return;
+        };
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_win_scrolled(bufnr, viewport).await;
+        });
+    }
+
+    fn on_background_changed(&self) {
+        if !self.has_active_previews() {
+            return;
+        }
+        let global_opts = OptionOpts::builder().build();
+        let Ok(background) = api::get_option_value::<String>("background", &global_opts) else {
+            return;
+        };
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_background_changed(&background).await;
+        });
+    }
+
+    fn on_colorscheme_changed(&self) {
+        if !self.sync_colorscheme {
+            return;
+        }
+        let highlights = editor_highlights();
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_colorscheme_changed(highlights).await;
+        });
+    }
+
     fn on_buf_enter(&self, buffer: api::Buffer) {
-        if !is_markdown_buffer(&buffer) || !self.has_active_previews() {
+        if !is_previewable_buffer(&buffer) || !self.has_active_previews() {
             return;
         }
 
-        let bufnr = i64::from(buffer.handle());
-        if self.has_session(bufnr) {
-            return;
-        }
+        let snapshot = match self.snapshot(&buffer) {
+            Ok(snapshot) => snapshot,
+            Err(_) => return,
+        };
+
+        let group = self.current_follow_group();
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_buf_enter(snapshot, group).await;
+        });
+    }
+
+    fn on_scope_enter(&self) {
+        let Some(group) = self.current_follow_group() else {
+            return;
+        };
+
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_scope_enter(group).await;
+        });
+    }
+
+    fn on_buf_file_post(&self, buffer: api::Buffer) {
+        let bufnr = i64::from(buffer.handle());
+        if !self.has_session(bufnr) {
+            return;
+        }
+
+        let Ok(source_path) = buffer_source_path(&buffer) else {
+            return;
+        };
+
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            plugin.on_buf_rename(bufnr, source_path).await;
+        });
+    }
+
+    fn on_buf_wipeout(&self, bufnr: i64) {
+        self.edits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&bufnr);
+        if !self.has_session(bufnr) {
+            return;
+        }
+
+        let plugin = self.plugin.clone();
+        self.runtime.spawn(async move {
+            let _ = plugin.on_buf_wipeout(bufnr).await;
+        });
+    }
+}
+
+pub fn module() -> Result<Dictionary> {
+    Ok(Dictionary::from_iter([
+        ("setup", Object::from(Function::from_fn(setup))),
+        ("stop", Object::from(Function::from_fn(stop))),
+        ("show_url", Object::from(Function::from_fn(show_url))),
+        ("start", Object::from(Function::from_fn(start))),
+        ("shutdown", Object::from(Function::from_fn(shutdown))),
+        ("open", Object::from(Function::from_fn(open))),
+        ("toc", Object::from(Function::from_fn(toc))),
+        ("sessions", Object::from(Function::from_fn(sessions))),
+        ("recent", Object::from(Function::from_fn(recent))),
+        ("bookmarks", Object::from(Function::from_fn(bookmarks))),
+        ("comments", Object::from(Function::from_fn(comments))),
+        (
+            "resolve_comment",
+            Object::from(Function::from_fn(resolve_comment)),
+        ),
+        (
+            "show_comments",
+            Object::from(Function::from_fn(show_comments)),
+        ),
+        (
+            "export_comments",
+            Object::from(Function::from_fn(export_comments)),
+        ),
+        ("clients", Object::from(Function::from_fn(clients))),
+        (
+            "server_status",
+            Object::from(Function::from_fn(server_status)),
+        ),
+        ("check_port", Object::from(Function::from_fn(check_port))),
+        ("info", Object::from(Function::from_fn(info))),
+        ("split", Object::from(Function::from_fn(split))),
+        ("refresh", Object::from(Function::from_fn(refresh))),
+        ("share", Object::from(Function::from_fn(share))),
+        (
+            "rotate_token",
+            Object::from(Function::from_fn(rotate_token)),
+        ),
+        ("set", Object::from(Function::from_fn(set))),
+        (
+            "stop_session",
+            Object::from(Function::from_fn(stop_session)),
+        ),
+        (
+            "format_table",
+            Object::from(Function::from_fn(format_table)),
+        ),
+        ("lint", Object::from(Function::from_fn(lint))),
+        (
+            "task_progress",
+            Object::from(Function::from_fn(task_progress)),
+        ),
+    ]))
+}
+
+/// Applies `opts` and returns what in them could not be used, as
+/// `{ ok = bool, issues = { { key, kind, message }, ... } }`. Options with
+/// issues keep their defaults.
+fn setup(opts: Option<Dictionary>) -> Dictionary {
+    let issues = opts.as_ref().map(validate_setup).unwrap_or_default();
+    if !issues.is_empty() {
+        let lines: Vec<&str> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        notify_warn(&format!(
+            "[live-markdown.nvim] setup: {}",
+            lines.join("\n[live-markdown.nvim] setup: ")
+        ));
+    }
+    if let Err(err) = setup_impl(opts) {
+        notify_err(&format!("[live-markdown.nvim] setup failed: {err}"));
+    }
+    setup_report(&issues)
+}
+
+fn setup_report(issues: &[SetupIssue]) -> Dictionary {
+    let issues: Array = issues
+        .iter()
+        .map(|issue| {
+            Object::from(Dictionary::from_iter([
+                ("key", Object::from(issue.key.as_str())),
+                ("kind", Object::from(issue.kind.name())),
+                ("message", Object::from(issue.message.as_str())),
+            ]))
+        })
+        .collect();
+    Dictionary::from_iter([
+        ("ok", Object::from(issues.is_empty())),
+        ("issues", Object::from(issues)),
+    ])
+}
+
+fn setup_impl(opts: Option<Dictionary>) -> Result<()> {
+    ensure_callbacks_registered()?;
+
+    let opts = opts.map(flatten_groups);
+    let set = |keys: [&str; 2]| {
+        opts.as_ref()
+            .is_some_and(|opts| keys.iter().any(|key| opts.get(key).is_some()))
+    };
+    let state_dir_set = set(["state_dir", "stateDir"]);
+    let cache_dir_set = set(["cache_dir", "cacheDir"]);
+    let hooks = match parse_hooks(opts.as_ref()) {
+        Ok(hooks) => hooks,
+        Err(err) => {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+            Hooks::default()
+        }
+    };
+    let mut base = ServerConfig::default();
+    for problem in base.apply_env() {
+        notify_warn(&format!("[live-markdown.nvim] {problem}"));
+    }
+    let mut config = parse_server_config(base, opts);
+    let defaults = editor_paths();
+    if !state_dir_set && config.state_dir.is_none() {
+        config.state_dir = defaults.state;
+    }
+    if !cache_dir_set && config.cache_dir.is_none() {
+        config.cache_dir = defaults.cache;
+    }
+    let state = match AppState::new(config, hooks) {
+        Ok(state) => Arc::new(state),
+        Err(err) => {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+            return Ok(());
+        }
+    };
+
+    state.on_colorscheme_changed();
+    let old = replace_state(state);
+    if let Some(old) = old {
+        old.shutdown();
+    }
+
+    Ok(())
+}
+
+fn stop(_: Option<bool>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    state.spawn_then(state.stop_all(), |stopped| {
+        if stopped {
+            notify_info("[live-markdown.nvim] stopped preview server");
+        } else {
+            notify_info("[live-markdown.nvim] no active preview session");
+        }
+    });
+}
+
+fn start(opts: Option<Dictionary>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    let open_browser = match parse_open_browser(opts.as_ref()) {
+        Ok(open_browser) => open_browser,
+        Err(err) => {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+            return;
+        }
+    };
+    let options = match parse_session_options(opts) {
+        Ok(options) => options,
+        Err(err) => {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+            return;
+        }
+    };
+
+    if let Err(err) = state.start_current(options, open_browser) {
+        notify_err(&format!("[live-markdown.nvim] {err}"));
+    }
+}
+
+fn show_url(bufnr: Option<i64>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    let report = |url: std::result::Result<Option<String>, PluginError>| match url {
+        Ok(Some(url)) => notify_info(&format!("[live-markdown.nvim] preview URL: {url}")),
+        Ok(None) => notify_info("[live-markdown.nvim] no active preview for current buffer"),
+        Err(err) => report_plugin_error(&err),
+    };
+    match bufnr {
+        Some(bufnr) => state.spawn_then(state.show_url_buffer(bufnr), report),
+        None => state.spawn_then(state.show_url_current(), report),
+    }
+}
+
+fn share(ttl: Option<String>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    let ttl = match ttl.as_deref().map(parse_share_ttl) {
+        None => DEFAULT_SHARE_TTL,
+        Some(Ok(ttl)) => ttl,
+        Some(Err(err)) => {
+            notify_err(&format!("[live-markdown.nvim] {err}"));
+            return;
+        }
+    };
+
+    state.spawn_then(state.share_current(ttl), move |url| match url {
+        Ok(Some(url)) => notify_info(&format!(
+            "[live-markdown.nvim] read-only link (expires in {}m): {url}",
+            ttl.as_secs().div_ceil(60)
+        )),
+        Ok(None) => notify_info("[live-markdown.nvim] no active preview for current buffer"),
+        Err(err) => report_plugin_error(&err),
+    });
+}
+
+fn rotate_token(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    state.spawn_then(state.rotate_share_current(), |url| match url {
+        Ok(Some(url)) => notify_info(&format!(
+            "[live-markdown.nvim] previous link revoked; new read-only link: {url}"
+        )),
+        Ok(None) => notify_info("[live-markdown.nvim] current buffer has no live share link"),
+        Err(err) => report_plugin_error(&err),
+    });
+}
+
+/// Parses `90s`, `30m`, `2h` or a bare number of minutes.
+fn parse_share_ttl(raw: &str) -> std::result::Result<Duration, String> {
+    let raw = raw.trim();
+    let (digits, unit) = match raw.find(|ch: char| !ch.is_ascii_digit()) {
+        Some(idx) => raw.split_at(idx),
+        None => (raw, "m"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid share duration: {raw} (use e.g. 90s, 30m, 2h)"
+            ));
+        }
+    };
+
+    match digits.parse::<u64>() {
+        Ok(value) if value > 0 => Ok(Duration::from_secs(value * scale)),
+        _ => Err(format!(
+            "invalid share duration: {raw} (use e.g. 90s, 30m, 2h)"
+        )),
+    }
+}
+
+fn open(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    match state.open_under_cursor() {
+        Ok(open) => state.spawn_then(open, run_open_action),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+fn run_open_action(action: OpenAction) {
+    match action {
+        OpenAction::Navigated(anchor) => {
+            notify_info(&format!("[live-markdown.nvim] preview jumped to #{anchor}"))
+        }
+        OpenAction::External(target) => {
+            if let Err(err) = open_externally(&target) {
+                notify_err(&format!(
+                    "[live-markdown.nvim] failed to open {target}: {err}"
+                ));
+            }
+        }
+        OpenAction::Edit(path) => {
+            let escaped = api::call_function::<_, String>(
+                "fnameescape",
+                (path.to_string_lossy().into_owned(),),
+            )
+            .unwrap_or_else(|_| path.to_string_lossy().into_owned());
+            if let Err(err) = api::command(&format!("edit {escaped}")) {
+                notify_err(&format!("[live-markdown.nvim] {err}"));
+            }
+        }
+        OpenAction::Unresolved(target) => {
+            notify_err(&format!("[live-markdown.nvim] could not resolve {target}"))
+        }
+    }
+}
+
+fn open_externally(target: &str) -> std::result::Result<(), String> {
+    let err = api::call_function::<_, String>(
+        "luaeval",
+        (
+            "(function(t) local _, err = vim.ui.open(t) return err or '' end)(_A)",
+            target,
+        ),
+    )
+    .map_err(|err| err.to_string())?;
+
+    if err.is_empty() { Ok(()) } else { Err(err) }
+}
+
+fn toc(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    match state.update_toc_current() {
+        Ok(true) => notify_info("[live-markdown.nvim] updated table of contents"),
+        Ok(false) => notify_info("[live-markdown.nvim] inserted table of contents"),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+fn format_table(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    if let Err(err) = state.format_table_current() {
+        notify_err(&format!("[live-markdown.nvim] {err}"));
+    }
+}
+
+fn lint(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    match state.lint_current() {
+        Ok(0) => notify_info("[live-markdown.nvim] no problems found"),
+        Ok(_) => {}
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+/// Calls `callback` with the running sessions once the runtime lists them.
+fn sessions(callback: Function<Array, ()>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    // Lua values stay on the main loop; only the registry reference travels.
+    let callback = callback.lua_ref();
+    state.spawn_then(state.session_list(), move |(sessions, url)| {
+        let Ok(callback) = Function::<Array, ()>::from_object(Object::from_luaref(callback)) else {
+            return;
+        };
+        if let Err(err) = callback.call(AppState::session_array(sessions, url)) {
+            notify_err(&format!(
+                "[live-markdown.nvim] sessions callback failed: {err}"
+            ));
+        }
+        callback.remove_from_lua_registry();
+    });
+}
+
+/// `{ state = "running", url = ... }`; the url only while the server runs.
+fn server_status(_: ()) -> Dictionary {
+    let status = state()
+        .map(|state| state.plugin.server_status())
+        .unwrap_or_default();
+    let mut info = Dictionary::from_iter([("state", Object::from(status.name()))]);
+    if let ServerStatus::Running(addr) = status {
+        info.insert("url", Object::from(format!("http://{addr}/")));
+    }
+    info
+}
+
+/// Why the preview server could not start right now, or `nil` when it
+/// could; for `:checkhealth`.
+fn check_port(_: ()) -> Option<String> {
+    let Some(state) = state() else {
+        return Some(String::from("setup() has not been called"));
+    };
+    state
+        .runtime
+        .block_on(state.plugin.check_port())
+        .err()
+        .map(|err| plugin_error_message(&err))
+}
+
+fn recent(_: ()) -> Array {
+    state().map(|state| state.recent_list()).unwrap_or_default()
+}
+
+fn bookmarks(bufnr: Option<i64>) -> Array {
+    let bufnr = bufnr.unwrap_or_else(|| i64::from(api::get_current_buf().handle()));
+    state()
+        .map(|state| state.bookmark_list(bufnr))
+        .unwrap_or_default()
+}
+
+fn comments(bufnr: Option<i64>) -> Array {
+    let bufnr = bufnr.unwrap_or_else(|| i64::from(api::get_current_buf().handle()));
+    state()
+        .map(|state| state.comment_list(bufnr))
+        .unwrap_or_default()
+}
+
+fn resolve_comment((bufnr, id): (i64, i64)) -> bool {
+    let Ok(id) = u64::try_from(id) else {
+        return false;
+    };
+    state().is_some_and(|state| state.resolve_comment(bufnr, id))
+}
+
+fn show_comments(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    match state.comments_to_loclist() {
+        Ok(Some(0)) => notify_info("[live-markdown.nvim] no review comments"),
+        Ok(Some(_)) => {
+            if let Err(err) = api::command("lopen") {
+                notify_err(&format!("[live-markdown.nvim] {err}"));
+            }
+        }
+        Ok(None) => notify_info("[live-markdown.nvim] no active preview for current buffer"),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+fn export_comments(path: Option<String>) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    let path = path.filter(|path| !path.trim().is_empty()).map(|path| {
+        // `expand()` resolves `~`, `%` and environment variables like `:write` does.
+        PathBuf::from(api::call_function::<_, String>("expand", (path.clone(),)).unwrap_or(path))
+    });
+    match state.export_comments_current(path) {
+        Ok((path, count)) => notify_info(&format!(
+            "[live-markdown.nvim] wrote {count} review comment(s) to {}",
+            path.display()
+        )),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+fn clients(_: ()) -> Array {
+    state().map(|state| state.client_list()).unwrap_or_default()
+}
+
+fn show_clients(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
+
+    let response = state.plugin.connections();
+    if response.connections.is_empty() {
+        notify_info(&format!(
+            "[live-markdown.nvim] no connected preview clients ({} recent requests)",
+            response.recent.len()
+        ));
+        return;
+    }
+
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    let mut lines = vec![format!(
+        "[live-markdown.nvim] {} connected client(s):",
+        response.connections.len()
+    )];
+    lines.extend(
+        response
+            .connections
+            .iter()
+            .map(|connection| format_connection(connection, now_ms)),
+    );
+    notify_info(&lines.join("\n"));
+}
+
+fn format_slow_render(bufnr: i64, elapsed: Duration) -> String {
+    format!(
+        "[live-markdown.nvim] rendering buffer {bufnr} took {} ms; the preview will lag behind edits (see slow_render_ms)",
+        elapsed.as_millis()
+    )
+}
+
+fn format_connection(connection: &ConnectionInfo, now_ms: u64) -> String {
+    let age = now_ms.saturating_sub(connection.connected_at_ms) / 1000;
+    let age = match age {
+        0..60 => format!("{age}s"),
+        60..3600 => format!("{}m", age / 60),
+        _ => format!("{}h", age / 3600),
+    };
+
+    let mut line = format!(
+        "  buf {}  {}  {age}",
+        connection.bufnr,
+        connection.remote.as_deref().unwrap_or("unknown"),
+    );
+    if connection.shared {
+        line.push_str("  shared");
+    }
+    if let Some(agent) = &connection.user_agent {
+        line.push_str("  ");
+        line.push_str(agent);
+    }
+    line
+}
+
+fn stop_session(bufnr: i64) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
 
-        let snapshot = match snapshot_from_buffer(&buffer) {
-            Ok(snapshot) => snapshot,
-            Err(_) => return,
-        };
+    state.spawn_then(state.stop_buffer(bufnr), move |stopped| match stopped {
+        Ok(true) => notify_info(&format!(
+            "[live-markdown.nvim] stopped preview for buffer {bufnr}"
+        )),
+        Ok(false) => notify_info(&format!(
+            "[live-markdown.nvim] no preview session for buffer {bufnr}"
+        )),
+        Err(err) => report_plugin_error(&err),
+    });
+}
 
-        let plugin = self.plugin.clone();
-        self.runtime.spawn(async move {
-            plugin.on_buf_enter(snapshot).await;
-        });
-    }
+fn info(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
+    };
 
-    fn on_buf_wipeout(&self, bufnr: i64) {
-        if !self.has_session(bufnr) {
+    state.spawn_then(state.info_current(), |info| {
+        let Some((lines, url)) = info else {
+            notify_info("[live-markdown.nvim] no active preview session");
             return;
+        };
+
+        if let Err(err) = open_info_float(lines, &url) {
+            notify_err(&format!(
+                "[live-markdown.nvim] failed to open info window: {err}"
+            ));
         }
+    });
+}
 
-        let plugin = self.plugin.clone();
-        self.runtime.spawn(async move {
-            let _ = plugin.on_buf_wipeout(bufnr).await;
-        });
+/// The lines of the `:LiveMarkdownInfo` window, and the URL it opens.
+fn info_lines(url: String, session: SessionSummary, tasks: TaskProgress) -> (Vec<String>, String) {
+    let mut lines = vec![
+        format!(" Server   {url}"),
+        format!(" Buffer   {} (buf {})", session.filename, session.bufnr),
+        format!(" Clients  {}", session.clients),
+        format!(
+            " Render   {:.2} ms (avg {:.2} ms, max {:.2} ms over {})",
+            session.render.last_us as f64 / 1000.0,
+            session.render.average_us as f64 / 1000.0,
+            session.render.slowest_us as f64 / 1000.0,
+            session.render.renders
+        ),
+    ];
+    let tasks = format_task_progress(tasks);
+    if !tasks.is_empty() {
+        lines.push(format!(" Tasks    {tasks}"));
     }
-}
+    lines.push(String::new());
+    lines.push(String::from(" [o] open  [y] copy URL  [s] stop  [q] close"));
 
-pub fn module() -> Result<Dictionary> {
-    Ok(Dictionary::from_iter([
-        ("setup", Object::from(Function::from_fn(setup))),
-        ("stop", Object::from(Function::from_fn(stop))),
-        ("show_url", Object::from(Function::from_fn(show_url))),
-        ("start", Object::from(Function::from_fn(start))),
-        ("shutdown", Object::from(Function::from_fn(shutdown))),
-    ]))
+    (lines, url)
 }
 
-fn setup(opts: Option<Dictionary>) {
-    if let Err(err) = setup_impl(opts) {
-        notify_err(&format!("[live-markdown.nvim] setup failed: {err}"));
+fn open_info_float(lines: Vec<String>, url: &str) -> Result<()> {
+    let mut buffer = api::create_buf(false, true)?;
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let height = lines.len();
+    buffer.set_lines(.., false, lines)?;
+
+    let buffer_opts = OptionOpts::builder().buffer(buffer.clone()).build();
+    api::set_option_value("bufhidden", "wipe", &buffer_opts)?;
+    api::set_option_value("modifiable", false, &buffer_opts)?;
+
+    let keymap_opts = SetKeymapOpts::builder()
+        .noremap(true)
+        .nowait(true)
+        .silent(true)
+        .build();
+    for (lhs, rhs) in [
+        ("q", String::from("<cmd>close<CR>")),
+        ("<Esc>", String::from("<cmd>close<CR>")),
+        ("o", format!("<cmd>close | lua vim.ui.open({url:?})<CR>")),
+        ("y", format!("<cmd>call setreg('+', {url:?}) | close<CR>")),
+        ("s", String::from("<cmd>close | LiveMarkdownStop<CR>")),
+    ] {
+        buffer.set_keymap(Mode::Normal, lhs, &rhs, &keymap_opts)?;
     }
-}
 
-fn setup_impl(opts: Option<Dictionary>) -> Result<()> {
-    ensure_callbacks_registered()?;
+    let global_opts = OptionOpts::builder().build();
+    let columns = api::get_option_value::<i64>("columns", &global_opts).unwrap_or(80);
+    let rows = api::get_option_value::<i64>("lines", &global_opts).unwrap_or(24);
+    let config = WindowConfig::builder()
+        .relative(WindowRelativeTo::Editor)
+        .width(width as u32)
+        .height(height as u32)
+        .col(((columns - width as i64) / 2).max(0) as f32)
+        .row(((rows - height as i64) / 2).max(0) as f32)
+        .style(WindowStyle::Minimal)
+        .border(WindowBorder::Rounded)
+        .title(WindowTitle::SimpleString(" live-markdown ".into()))
+        .build();
+    api::open_win(&buffer, true, &config)?;
 
-    let config = parse_server_config(opts);
-    let state = match AppState::new(config) {
-        Ok(state) => Arc::new(state),
-        Err(err) => {
-            notify_err(&format!("[live-markdown.nvim] {err}"));
-            return Ok(());
-        }
+    Ok(())
+}
+
+fn split(_: ()) {
+    let Some(state) = state() else {
+        notify_err("[live-markdown.nvim] plugin is not configured");
+        return;
     };
 
-    let old = replace_state(state);
-    if let Some(old) = old {
-        old.shutdown();
+    state.split_current();
+}
+
+fn open_text_browser(
+    text_browser: &[String],
+    url: &str,
+    bufnr: i64,
+) -> std::result::Result<(), String> {
+    let argv = text_browser_argv(text_browser, url, bufnr);
+    api::command("botright vnew").map_err(|err| err.to_string())?;
+    let job = api::call_function::<_, i64>("termopen", (Array::from_iter(argv),))
+        .map_err(|err| err.to_string())?;
+    if job <= 0 {
+        return Err(format!(
+            "failed to start text browser `{}`",
+            text_browser.first().map_or("", String::as_str)
+        ));
     }
 
     Ok(())
 }
 
-fn stop(_: Option<bool>) {
+fn refresh(_: ()) {
     let Some(state) = state() else {
         notify_err("[live-markdown.nvim] plugin is not configured");
         return;
     };
 
-    match state.stop_active() {
-        Ok(true) => notify_info("[live-markdown.nvim] stopped preview server"),
-        Ok(false) => notify_info("[live-markdown.nvim] no active preview session"),
+    match state.refresh_current() {
+        Ok(refresh) => state.spawn_then(refresh, |refreshed| {
+            if let Err(err) = refreshed {
+                notify_err(&format!(
+                    "[live-markdown.nvim] {}",
+                    plugin_error_message(&err)
+                ));
+            }
+        }),
         Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
     }
 }
 
-fn start(_: ()) {
+/// Configured openers win; otherwise the host's usual ones, and none at all
+/// over SSH, where a browser would open on the wrong machine.
+fn resolve_browser_openers(configured: Vec<Vec<String>>, host: Host) -> Vec<Vec<String>> {
+    if !configured.is_empty() {
+        configured
+    } else if host == Host::Ssh {
+        Vec::new()
+    } else {
+        browser::default_openers(host)
+    }
+}
+
+/// Text browsers without JavaScript cannot follow the live stream, so they get
+/// the static export of the buffer instead.
+fn text_browser_argv(browser: &[String], preview_url: &str, bufnr: i64) -> Vec<String> {
+    let program = browser.first().map_or("w3m", String::as_str);
+    let runs_javascript = Path::new(program)
+        .file_stem()
+        .is_some_and(|stem| stem == "carbonyl");
+    let url = if runs_javascript {
+        preview_url.to_string()
+    } else {
+        format!("{preview_url}export.html?buf={bufnr}")
+    };
+
+    let mut argv = if browser.is_empty() {
+        vec![String::from(program)]
+    } else {
+        browser.to_vec()
+    };
+    argv.push(url);
+    argv
+}
+
+fn set(assignments: Vec<String>) {
     let Some(state) = state() else {
         notify_err("[live-markdown.nvim] plugin is not configured");
         return;
     };
 
-    match state.start_current() {
-        Ok(url) => notify_info(&format!("[live-markdown.nvim] preview started: {url}")),
-        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    let mut options = Vec::with_capacity(assignments.len());
+    for assignment in assignments {
+        let Some((key, value)) = assignment.split_once('=') else {
+            notify_err(&format!(
+                "[live-markdown.nvim] expected key=value, got {assignment}"
+            ));
+            return;
+        };
+        options.push((key.trim().to_string(), value.trim().to_string()));
     }
+
+    state.spawn_then(
+        state.set_options_current(options),
+        |applied| match applied {
+            Ok(true) => {}
+            Ok(false) => notify_info("[live-markdown.nvim] no active preview session"),
+            Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+        },
+    );
 }
 
-fn show_url(_: ()) {
-    let Some(state) = state() else {
-        notify_err("[live-markdown.nvim] plugin is not configured");
-        return;
+/// The `open_browser` key of `start()`, which overrides the configured policy.
+fn parse_open_browser(
+    opts: Option<&Dictionary>,
+) -> std::result::Result<Option<OpenBrowser>, String> {
+    let Some(value) = opts.and_then(|opts| get_dict_string(opts, &["open_browser", "openBrowser"]))
+    else {
+        return Ok(None);
     };
 
-    match state.show_url_current() {
-        Ok(Some(url)) => notify_info(&format!("[live-markdown.nvim] preview URL: {url}")),
-        Ok(None) => notify_info("[live-markdown.nvim] no active preview for current buffer"),
-        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    OpenBrowser::parse(&value)
+        .map(Some)
+        .ok_or_else(|| format!("invalid open_browser: {value} (always, never or if_no_clients)"))
+}
+
+fn parse_session_options(opts: Option<Dictionary>) -> std::result::Result<SessionOptions, String> {
+    let mut options = SessionOptions::default();
+    let Some(opts) = opts else {
+        return Ok(options);
+    };
+
+    if let Some(theme) = get_dict_string(&opts, &["theme"]) {
+        options.set("theme", &theme)?;
+    }
+    if let Some(auto_scroll) = get_dict_bool(&opts, &["auto_scroll", "autoScroll"]) {
+        options.auto_scroll = Some(auto_scroll);
+    }
+    if let Some(interactive) = get_dict_bool(&opts, &["interactive_tasks", "interactiveTasks"]) {
+        options.interactive_tasks = interactive;
+    }
+    if let Some(follow) = get_dict_bool(&opts, &["follow"]) {
+        options.follow = follow;
+    }
+    if let (Some(top), Some(bottom)) = (
+        get_dict_f64(&opts, &["scroll_comfort_top", "scrollComfortTop"]),
+        get_dict_f64(&opts, &["scroll_comfort_bottom", "scrollComfortBottom"]),
+    ) {
+        options.set("scroll_band", &format!("{top},{bottom}"))?;
+    }
+    if let Some(behavior) = get_dict_string(&opts, &["scroll_behavior", "scrollBehavior"]) {
+        options.set("scroll_behavior", &behavior)?;
+    }
+    if let Some(duration) = get_dict_i64(&opts, &["scroll_duration_ms", "scrollDurationMs"]) {
+        options.set("scroll_duration_ms", &duration.to_string())?;
+    }
+    if let Some(bias) = get_dict_f64(&opts, &["scroll_center_bias", "scrollCenterBias"]) {
+        options.set("scroll_center_bias", &bias.to_string())?;
     }
+    if let Some(anchor) = get_dict_string(&opts, &["scroll_anchor", "scrollAnchor"]) {
+        options.set("scroll_anchor", &anchor)?;
+    }
+    if let Some(asset_root) = get_dict_string(&opts, &["asset_root", "assetRoot"]) {
+        options.set("asset_root", asset_root.trim())?;
+    }
+
+    Ok(options)
 }
 
 fn shutdown(_: ()) {
@@ -247,6 +1983,22 @@ fn shutdown(_: ()) {
     }
 }
 
+fn task_progress(_: ()) -> String {
+    let Some(progress) = state().and_then(|state| state.task_progress_current()) else {
+        return String::new();
+    };
+
+    format_task_progress(progress)
+}
+
+fn format_task_progress(progress: TaskProgress) -> String {
+    if progress.total == 0 {
+        return String::new();
+    }
+
+    format!("{}/{} done", progress.done, progress.total)
+}
+
 fn ensure_callbacks_registered() -> Result<()> {
     if CALLBACKS_REGISTERED.load(Ordering::Acquire) {
         return Ok(());
@@ -260,16 +2012,22 @@ fn ensure_callbacks_registered() -> Result<()> {
 
 fn register_commands() -> Result<()> {
     let stop_opts = CreateCommandOpts::builder()
-        .desc("Stop markdown preview server")
+        .desc("Stop markdown preview server, or the preview of one buffer")
         .force(true)
-        .nargs(CommandNArgs::Zero)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .complete(CommandComplete::CustomList(Function::from_fn(
+            complete_session_bufnrs,
+        )))
         .build();
     api::create_user_command("LiveMarkdownStop", command_stop, &stop_opts)?;
 
     let show_url_opts = CreateCommandOpts::builder()
-        .desc("Show markdown preview URL")
+        .desc("Show markdown preview URL, optionally pinned to one buffer")
         .force(true)
-        .nargs(CommandNArgs::Zero)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .complete(CommandComplete::CustomList(Function::from_fn(
+            complete_session_bufnrs,
+        )))
         .build();
     api::create_user_command("LiveMarkdownShowUrl", command_show_url, &show_url_opts)?;
 
@@ -280,6 +2038,128 @@ fn register_commands() -> Result<()> {
         .build();
     api::create_user_command("LiveMarkdownStart", command_start, &start_opts)?;
 
+    let open_opts = CreateCommandOpts::builder()
+        .desc("Open the markdown link or image under the cursor")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownOpen", command_open, &open_opts)?;
+
+    let info_opts = CreateCommandOpts::builder()
+        .desc("Show preview status in a floating window")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownInfo", command_info, &info_opts)?;
+
+    let split_opts = CreateCommandOpts::builder()
+        .desc("Open the preview in a terminal text browser split")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownSplit", command_split, &split_opts)?;
+
+    let refresh_opts = CreateCommandOpts::builder()
+        .desc("Re-render the current buffer's preview and reload its assets")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownRefresh", command_refresh, &refresh_opts)?;
+
+    let share_opts = CreateCommandOpts::builder()
+        .desc("Create an expiring read-only preview link (default 30m)")
+        .force(true)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .build();
+    api::create_user_command("LiveMarkdownShare", command_share, &share_opts)?;
+
+    let rotate_token_opts = CreateCommandOpts::builder()
+        .desc("Replace the current preview's share link with a new one")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command(
+        "LiveMarkdownRotateToken",
+        command_rotate_token,
+        &rotate_token_opts,
+    )?;
+
+    let clients_opts = CreateCommandOpts::builder()
+        .desc("List browsers connected to the preview server")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownClients", command_clients, &clients_opts)?;
+
+    let set_opts = CreateCommandOpts::builder()
+        .desc("Change options of the running preview (key=value ...)")
+        .force(true)
+        .nargs(CommandNArgs::OneOrMore)
+        .build();
+    api::create_user_command("LiveMarkdownSet", command_set, &set_opts)?;
+
+    let scroll_band_opts = CreateCommandOpts::builder()
+        .desc("Set the preview's auto-scroll band (<top> <bottom>, or none to reset)")
+        .force(true)
+        .nargs(CommandNArgs::Any)
+        .build();
+    api::create_user_command(
+        "LiveMarkdownScrollBand",
+        command_scroll_band,
+        &scroll_band_opts,
+    )?;
+
+    let toc_opts = CreateCommandOpts::builder()
+        .desc("Insert or update the table of contents between toc markers")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownToc", command_toc, &toc_opts)?;
+
+    let bookmarks_opts = CreateCommandOpts::builder()
+        .desc("Jump to a block bookmarked in the preview")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownBookmarks", command_bookmarks, &bookmarks_opts)?;
+
+    let comments_opts = CreateCommandOpts::builder()
+        .desc("List the preview's review comments in the location list")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownComments", command_comments, &comments_opts)?;
+
+    let export_comments_opts = CreateCommandOpts::builder()
+        .desc("Write the preview's review comments to a markdown file")
+        .force(true)
+        .nargs(CommandNArgs::ZeroOrOne)
+        .complete(CommandComplete::File)
+        .build();
+    api::create_user_command(
+        "LiveMarkdownExportComments",
+        command_export_comments,
+        &export_comments_opts,
+    )?;
+
+    let format_table_opts = CreateCommandOpts::builder()
+        .desc("Align the markdown table under the cursor")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command(
+        "LiveMarkdownFormatTable",
+        command_format_table,
+        &format_table_opts,
+    )?;
+
+    let lint_opts = CreateCommandOpts::builder()
+        .desc("Show problems in the current buffer, such as duplicate heading ids")
+        .force(true)
+        .nargs(CommandNArgs::Zero)
+        .build();
+    api::create_user_command("LiveMarkdownLint", command_lint, &lint_opts)?;
+
     Ok(())
 }
 
@@ -287,12 +2167,6 @@ fn register_autocmds() -> Result<()> {
     let augroup = CreateAugroupOpts::builder().clear(true).build();
     let group_id = api::create_augroup("LiveMarkdown", &augroup)?;
 
-    let text_opts = CreateAutocmdOpts::builder()
-        .group(group_id)
-        .callback(autocmd_text_changed)
-        .build();
-    api::create_autocmd(["TextChanged", "TextChangedI"], &text_opts)?;
-
     let write_opts = CreateAutocmdOpts::builder()
         .group(group_id)
         .callback(autocmd_buf_write_post)
@@ -305,12 +2179,43 @@ fn register_autocmds() -> Result<()> {
         .build();
     api::create_autocmd(["CursorMoved", "CursorMovedI"], &cursor_opts)?;
 
+    let scrolled_opts = CreateAutocmdOpts::builder()
+        .group(group_id)
+        .callback(autocmd_win_scrolled)
+        .build();
+    api::create_autocmd(["WinScrolled"], &scrolled_opts)?;
+
     let enter_opts = CreateAutocmdOpts::builder()
         .group(group_id)
         .callback(autocmd_buf_enter)
         .build();
     api::create_autocmd(["BufEnter"], &enter_opts)?;
 
+    let scope_opts = CreateAutocmdOpts::builder()
+        .group(group_id)
+        .callback(autocmd_scope_enter)
+        .build();
+    api::create_autocmd(["WinEnter", "TabEnter"], &scope_opts)?;
+
+    let background_opts = CreateAutocmdOpts::builder()
+        .group(group_id)
+        .patterns(["background"])
+        .callback(autocmd_background_changed)
+        .build();
+    api::create_autocmd(["OptionSet"], &background_opts)?;
+
+    let colorscheme_opts = CreateAutocmdOpts::builder()
+        .group(group_id)
+        .callback(autocmd_colorscheme)
+        .build();
+    api::create_autocmd(["ColorScheme"], &colorscheme_opts)?;
+
+    let rename_opts = CreateAutocmdOpts::builder()
+        .group(group_id)
+        .callback(autocmd_buf_file_post)
+        .build();
+    api::create_autocmd(["BufFilePost"], &rename_opts)?;
+
     let wipeout_opts = CreateAutocmdOpts::builder()
         .group(group_id)
         .callback(autocmd_buf_wipeout)
@@ -326,57 +2231,190 @@ fn register_autocmds() -> Result<()> {
     Ok(())
 }
 
-fn command_stop(_: CommandArgs) {
-    stop(None);
+fn command_stop(args: CommandArgs) {
+    match command_bufnr(&args) {
+        Ok(Some(bufnr)) => stop_session(bufnr),
+        Ok(None) => stop(None),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+fn command_show_url(args: CommandArgs) {
+    match command_bufnr(&args) {
+        Ok(bufnr) => show_url(bufnr),
+        Err(err) => notify_err(&format!("[live-markdown.nvim] {err}")),
+    }
+}
+
+/// The optional buffer number argument of `:LiveMarkdownStop` and friends.
+fn command_bufnr(args: &CommandArgs) -> std::result::Result<Option<i64>, String> {
+    let Some(raw) = args.fargs.first() else {
+        return Ok(None);
+    };
+    parse_bufnr(raw).map(Some)
+}
+
+fn parse_bufnr(raw: &str) -> std::result::Result<i64, String> {
+    raw.trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|bufnr| *bufnr > 0)
+        .ok_or_else(|| format!("invalid buffer number: {raw}"))
 }
 
-fn command_show_url(_: CommandArgs) {
-    show_url(());
+/// Completes the buffer numbers that have a running preview.
+fn complete_session_bufnrs((arg_lead, _, _): (String, String, usize)) -> Vec<String> {
+    let Some(state) = state() else {
+        return Vec::new();
+    };
+
+    state
+        .session_bufnrs()
+        .into_iter()
+        .map(|bufnr| bufnr.to_string())
+        .filter(|bufnr| bufnr.starts_with(arg_lead.trim()))
+        .collect()
 }
 
 fn command_start(_: CommandArgs) {
-    start(());
+    start(None);
+}
+
+fn command_open(_: CommandArgs) {
+    open(());
+}
+
+fn command_info(_: CommandArgs) {
+    info(());
+}
+
+fn command_set(args: CommandArgs) {
+    set(args.fargs);
+}
+
+fn command_scroll_band(args: CommandArgs) {
+    let band = if args.fargs.is_empty() {
+        String::from("default")
+    } else {
+        args.fargs.join(" ")
+    };
+    set(vec![format!("scroll_band={band}")]);
+}
+
+fn command_clients(_: CommandArgs) {
+    show_clients(());
+}
+
+fn command_share(args: CommandArgs) {
+    share(args.fargs.into_iter().next());
+}
+
+fn command_rotate_token(_: CommandArgs) {
+    rotate_token(());
+}
+
+fn command_split(_: CommandArgs) {
+    split(());
+}
+
+fn command_refresh(_: CommandArgs) {
+    refresh(());
+}
+
+fn command_toc(_: CommandArgs) {
+    toc(());
+}
+
+fn command_bookmarks(_: CommandArgs) {
+    // The picker goes through `vim.ui.select`, which plugins like to replace.
+    if let Err(err) = api::command("lua require('live_markdown').pick_bookmark()") {
+        notify_err(&format!("[live-markdown.nvim] {err}"));
+    }
+}
+
+fn command_comments(_: CommandArgs) {
+    show_comments(());
+}
+
+fn command_export_comments(args: CommandArgs) {
+    export_comments(args.fargs.into_iter().next());
+}
+
+fn command_format_table(_: CommandArgs) {
+    format_table(());
+}
+
+fn command_lint(_: CommandArgs) {
+    lint(());
 }
 
-fn autocmd_text_changed(args: AutocmdCallbackArgs) -> bool {
-    if !is_markdown_buffer(&args.buffer) {
+fn autocmd_cursor_moved(args: AutocmdCallbackArgs) -> bool {
+    if !is_previewable_buffer(&args.buffer) {
         return false;
     }
 
     if let Some(state) = state() {
-        state.on_text_changed(args.buffer);
+        state.on_cursor_moved(args.buffer);
     }
 
     false
 }
 
-fn autocmd_cursor_moved(args: AutocmdCallbackArgs) -> bool {
-    if !is_markdown_buffer(&args.buffer) {
+fn autocmd_win_scrolled(_: AutocmdCallbackArgs) -> bool {
+    if let Some(state) = state() {
+        state.on_win_scrolled();
+    }
+
+    false
+}
+
+fn autocmd_background_changed(_: AutocmdCallbackArgs) -> bool {
+    if let Some(state) = state() {
+        state.on_background_changed();
+    }
+
+    false
+}
+
+fn autocmd_colorscheme(_: AutocmdCallbackArgs) -> bool {
+    if let Some(state) = state() {
+        state.on_colorscheme_changed();
+    }
+
+    false
+}
+
+fn autocmd_buf_write_post(args: AutocmdCallbackArgs) -> bool {
+    if !is_previewable_buffer(&args.buffer) {
         return false;
     }
 
     if let Some(state) = state() {
-        state.on_cursor_moved(args.buffer);
+        state.on_buf_write(args.buffer);
+    }
+
+    false
+}
+
+fn autocmd_buf_enter(args: AutocmdCallbackArgs) -> bool {
+    if let Some(state) = state() {
+        state.on_buf_enter(args.buffer);
     }
 
     false
 }
 
-fn autocmd_buf_write_post(args: AutocmdCallbackArgs) -> bool {
-    if !is_markdown_buffer(&args.buffer) {
-        return false;
-    }
-
+fn autocmd_scope_enter(_: AutocmdCallbackArgs) -> bool {
     if let Some(state) = state() {
-        state.on_buf_write(args.buffer);
+        state.on_scope_enter();
     }
 
     false
 }
 
-fn autocmd_buf_enter(args: AutocmdCallbackArgs) -> bool {
+fn autocmd_buf_file_post(args: AutocmdCallbackArgs) -> bool {
     if let Some(state) = state() {
-        state.on_buf_enter(args.buffer);
+        state.on_buf_file_post(args.buffer);
     }
 
     false
@@ -425,8 +2463,9 @@ fn take_state() -> Option<Arc<AppState>> {
     guard.take()
 }
 
-fn parse_server_config(opts: Option<Dictionary>) -> ServerConfig {
-    let mut config = ServerConfig::default();
+/// Reads the options of a `setup()` table whose groups were lifted to the
+/// top level by [`flatten_groups`] over `config`, which holds the defaults.
+fn parse_server_config(mut config: ServerConfig, opts: Option<Dictionary>) -> ServerConfig {
     let Some(opts) = opts else {
         return config;
     };
@@ -475,6 +2514,389 @@ fn parse_server_config(opts: Option<Dictionary>) -> ServerConfig {
         config.scroll_comfort_bottom = scroll_comfort_bottom;
     }
 
+    if let Some(behavior) = get_dict_string(&opts, &["scroll_behavior", "scrollBehavior"])
+        .and_then(|value| ScrollBehavior::parse(&value))
+    {
+        config.scroll_behavior = behavior;
+    }
+
+    if let Some(duration) = get_dict_i64(&opts, &["scroll_duration_ms", "scrollDurationMs"])
+        && (0..=MAX_SCROLL_DURATION_MS as i64).contains(&duration)
+    {
+        config.scroll_duration_ms = duration as u64;
+    }
+
+    if let Some(bias) = get_dict_f64(&opts, &["scroll_center_bias", "scrollCenterBias"])
+        && (0.0..=1.0).contains(&bias)
+    {
+        config.scroll_center_bias = bias;
+    }
+
+    if let Some(anchor) = get_dict_string(&opts, &["scroll_anchor", "scrollAnchor"])
+        .and_then(|value| ScrollAnchor::parse(&value))
+    {
+        config.scroll_anchor = anchor;
+    }
+
+    if let Some(theme) =
+        get_dict_string(&opts, &["theme"]).and_then(|value| ThemeMode::parse(&value))
+    {
+        config.theme = theme;
+    }
+
+    if let Some(diagnostics) = get_dict_bool(&opts, &["diagnostics"]) {
+        config.diagnostics = diagnostics;
+    }
+
+    if let Some(sync) = get_dict_bool(&opts, &["sync_colorscheme", "syncColorscheme"]) {
+        config.sync_colorscheme = sync;
+    }
+
+    if let Some(echo) =
+        get_dict_string(&opts, &["url_echo", "urlEcho"]).and_then(|value| UrlEcho::parse(&value))
+    {
+        config.url_echo = echo;
+    }
+
+    if let Some(open_browser) = get_dict_string(&opts, &["open_browser", "openBrowser"])
+        .and_then(|value| OpenBrowser::parse(&value))
+    {
+        config.open_browser = open_browser;
+    }
+
+    if let Some(scope) = get_dict_string(&opts, &["follow_scope", "followScope"])
+        .and_then(|value| FollowScope::parse(&value))
+    {
+        config.follow_scope = scope;
+    }
+
+    if let Some(kbd) = get_dict_bool(&opts, &["kbd"]) {
+        config.render.kbd = kbd;
+    }
+
+    if let Some(lazy_images) = get_dict_bool(&opts, &["lazy_images", "lazyImages"]) {
+        config.render.lazy_images = lazy_images;
+    }
+
+    if let Some(citations) = get_dict_bool(&opts, &["citations"]) {
+        config.render.citations = citations;
+    }
+
+    if let Some(bibliography) = get_dict_string(&opts, &["bibliography"])
+        && !bibliography.trim().is_empty()
+    {
+        config.render.bibliography = Some(PathBuf::from(bibliography.trim()));
+    }
+
+    if let Some(converters) = opts.get("converters")
+        && let Ok(converters) = Dictionary::from_object(converters.clone())
+    {
+        for (extension, argv) in converters {
+            let Some(argv) = string_list_from_object(argv) else {
+                continue;
+            };
+
+            if let Some(command) = ConverterCommand::from_argv(argv) {
+                let extension = extension
+                    .to_string_lossy()
+                    .trim_start_matches('.')
+                    .to_string();
+                config.render.converters.insert(extension, command);
+            }
+        }
+    }
+
+    if let Some(document_types) = opts
+        .get("document_types")
+        .or_else(|| opts.get("documentTypes"))
+        && let Ok(document_types) = Dictionary::from_object(document_types.clone())
+    {
+        for (extension, content_type) in document_types {
+            let extension = extension
+                .to_string_lossy()
+                .trim_start_matches('.')
+                .to_ascii_lowercase();
+            match String::from_object(content_type) {
+                Ok(content_type) if !content_type.trim().is_empty() => {
+                    config
+                        .document_types
+                        .insert(extension, content_type.trim().to_string());
+                }
+                // `pdf = false` removes a default entry.
+                _ => {
+                    config.document_types.remove(&extension);
+                }
+            }
+        }
+    }
+
+    if let Some(filetypes) = opts
+        .get("filetypes")
+        .and_then(|filetypes| Dictionary::from_object(filetypes.clone()).ok())
+    {
+        for (filetype, backend) in filetypes {
+            if let Ok(backend) = String::from_object(backend)
+                && !backend.trim().is_empty()
+            {
+                config.filetypes.insert(
+                    filetype.to_string_lossy().to_ascii_lowercase(),
+                    backend.trim().to_string(),
+                );
+            }
+        }
+    }
+
+    if let Some(trust_local_svg) = get_dict_bool(&opts, &["trust_local_svg", "trustLocalSvg"]) {
+        config.trust_local_svg = trust_local_svg;
+    }
+
+    if let Some(max_document_bytes) =
+        get_dict_i64(&opts, &["max_document_bytes", "maxDocumentBytes"])
+        && max_document_bytes >= 0
+    {
+        config.max_document_bytes = max_document_bytes as usize;
+    }
+
+    if let Some(record_dir) = get_dict_string(&opts, &["record_dir", "recordDir"])
+        && !record_dir.trim().is_empty()
+    {
+        config.record_dir = Some(PathBuf::from(record_dir.trim()));
+    }
+
+    if let Some(asset_root) = get_dict_string(&opts, &["default_asset_root", "defaultAssetRoot"])
+        && !asset_root.trim().is_empty()
+    {
+        config.default_asset_root = Some(PathBuf::from(asset_root.trim()));
+    }
+
+    if let Some(image_thumbnails) = get_dict_bool(&opts, &["image_thumbnails", "imageThumbnails"]) {
+        config.image_thumbnails = image_thumbnails;
+    }
+
+    if let Some(max_bytes) = get_dict_i64(&opts, &["inline_image_max_bytes", "inlineImageMaxBytes"])
+        && max_bytes >= 0
+    {
+        config.inline_image_max_bytes = max_bytes as u64;
+    }
+
+    if let Some(timeout) = get_dict_i64(&opts, &["converter_timeout_ms", "converterTimeoutMs"])
+        && timeout > 0
+    {
+        config.render.converter_timeout_ms = timeout as u64;
+    }
+
+    if let Some(labels) = opts.get("labels")
+        && let Ok(labels) = Dictionary::from_object(labels.clone())
+    {
+        let labels = labels
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let value = String::from_object(value).ok()?;
+                Some((key.to_string_lossy().into_owned(), value))
+            })
+            .collect();
+        config.render.labels = Labels::new(labels);
+    }
+
+    // `accessibility = false` turns every aid off; a table sets them one by one.
+    if get_dict_bool(&opts, &["accessibility"]) == Some(false) {
+        config.render.accessibility = Accessibility {
+            aria: false,
+            skip_link: false,
+            landmarks: false,
+        };
+    } else if let Some(accessibility) = opts
+        .get("accessibility")
+        .and_then(|accessibility| Dictionary::from_object(accessibility.clone()).ok())
+    {
+        let settings = &mut config.render.accessibility;
+        for (keys, setting) in [
+            (&["aria"][..], &mut settings.aria),
+            (&["skip_link", "skipLink"][..], &mut settings.skip_link),
+            (&["landmarks"][..], &mut settings.landmarks),
+        ] {
+            if let Some(value) = get_dict_bool(&accessibility, keys) {
+                *setting = value;
+            }
+        }
+    }
+
+    if let Some(profile) = get_dict_string(&opts, &["profile"])
+        && let Some(profile) = RenderProfile::parse(&profile)
+    {
+        config.render.profile = Some(profile);
+    }
+
+    if let Some(hardbreaks) = get_dict_bool(&opts, &["hardbreaks"]) {
+        config.render.hardbreaks = Some(hardbreaks);
+    }
+
+    if let Some(figures) = get_dict_bool(&opts, &["figures"]) {
+        config.render.figures = figures;
+    }
+
+    let limits = &mut config.render.limits;
+    for (keys, limit) in [
+        (
+            &["max_input_bytes", "maxInputBytes"],
+            &mut limits.max_input_bytes,
+        ),
+        (&["max_nesting", "maxNesting"], &mut limits.max_nesting),
+        (
+            &["max_output_bytes", "maxOutputBytes"],
+            &mut limits.max_output_bytes,
+        ),
+    ] {
+        if let Some(value) = get_dict_i64(&opts, keys)
+            && value >= 0
+        {
+            *limit = value as usize;
+        }
+    }
+
+    if let Some(slug_style) = get_dict_string(&opts, &["slug_style", "slugStyle"])
+        && let Some(slug_style) = SlugStyle::parse(&slug_style)
+    {
+        config.render.slug_style = Some(slug_style);
+    }
+
+    if let Some(sanitize) = get_dict_bool(&opts, &["sanitize_html", "sanitizeHtml"]) {
+        config.render.sanitize_html = sanitize;
+    }
+
+    if let Some(renderer) = get_dict_string(&opts, &["renderer"])
+        && let Some(renderer) = RendererKind::parse(&renderer)
+    {
+        config.render.renderer = renderer;
+    }
+
+    if let Some(args) = opts
+        .get("pandoc_args")
+        .or_else(|| opts.get("pandocArgs"))
+        .and_then(|args| string_list_from_object(args.clone()))
+    {
+        config.render.pandoc.args = args;
+    }
+
+    if let Some(stop) = get_dict_bool(
+        &opts,
+        &[
+            "stop_on_last_client_disconnect",
+            "stopOnLastClientDisconnect",
+        ],
+    ) {
+        config.stop_on_last_client_disconnect = stop;
+    }
+
+    if let Some(grace) = get_dict_i64(&opts, &["disconnect_grace_ms", "disconnectGraceMs"])
+        && grace >= 0
+    {
+        config.disconnect_grace_ms = grace as u64;
+    }
+
+    if let Some(check_ms) = get_dict_i64(&opts, &["session_check_ms", "sessionCheckMs"])
+        && check_ms >= 0
+    {
+        config.session_check_ms = check_ms as u64;
+    }
+
+    if let Some(watch_ms) = get_dict_i64(&opts, &["asset_watch_ms", "assetWatchMs"])
+        && watch_ms >= 0
+    {
+        config.asset_watch_ms = watch_ms as u64;
+    }
+
+    if let Some(slow_render_ms) = get_dict_i64(&opts, &["slow_render_ms", "slowRenderMs"])
+        && slow_render_ms >= 0
+    {
+        config.slow_render_ms = slow_render_ms as u64;
+    }
+
+    if let Some(browser) = opts
+        .get("text_browser")
+        .or_else(|| opts.get("textBrowser"))
+        .and_then(|browser| {
+            String::from_object(browser.clone())
+                .map(|command| command.split_whitespace().map(String::from).collect())
+                .ok()
+                .or_else(|| string_list_from_object(browser.clone()))
+        })
+        .filter(|argv: &Vec<String>| !argv.is_empty())
+    {
+        config.text_browser = browser;
+    }
+
+    if let Some(openers) = opts
+        .get("browser_openers")
+        .or_else(|| opts.get("browserOpeners"))
+        .and_then(|openers| Array::from_object(openers.clone()).ok())
+    {
+        config.browser_openers = openers
+            .into_iter()
+            .filter_map(|opener| {
+                String::from_object(opener.clone())
+                    .map(|command| command.split_whitespace().map(String::from).collect())
+                    .ok()
+                    .or_else(|| string_list_from_object(opener))
+            })
+            .filter(|argv: &Vec<String>| !argv.is_empty())
+            .collect();
+    }
+
+    for (keys, dir) in [
+        (&["state_dir", "stateDir"], &mut config.state_dir),
+        (&["cache_dir", "cacheDir"], &mut config.cache_dir),
+    ] {
+        if get_dict_bool(&opts, keys) == Some(false) {
+            *dir = None;
+        } else if let Some(path) = get_dict_string(&opts, keys)
+            && !path.trim().is_empty()
+        {
+            *dir = Some(PathBuf::from(path.trim()));
+        }
+    }
+
+    if let Some(spellcheck) = get_dict_bool(&opts, &["spellcheck", "spellCheck"]) {
+        config.spellcheck = spellcheck;
+    }
+
+    if let Some(languages) = opts
+        .get("spell_languages")
+        .or_else(|| opts.get("spellLanguages"))
+        .and_then(|languages| {
+            String::from_object(languages.clone())
+                .map(|language| vec![language])
+                .ok()
+                .or_else(|| string_list_from_object(languages.clone()))
+        })
+    {
+        config.spell_languages = languages
+            .iter()
+            .map(|language| language.trim().to_string())
+            .filter(|language| !language.is_empty())
+            .collect();
+    }
+
+    if let Some(dictionaries) = opts
+        .get("spell_dictionaries")
+        .or_else(|| opts.get("spellDictionaries"))
+        .and_then(|dictionaries| string_list_from_object(dictionaries.clone()))
+    {
+        config.spell_dictionaries = dictionaries
+            .iter()
+            .filter(|path| !path.trim().is_empty())
+            .map(|path| PathBuf::from(path.trim()))
+            .collect();
+    }
+
+    if let Some(words) = opts
+        .get("spell_words")
+        .or_else(|| opts.get("spellWords"))
+        .and_then(|words| string_list_from_object(words.clone()))
+    {
+        config.spell_words = words;
+    }
+
     if config.scroll_comfort_top >= config.scroll_comfort_bottom {
         config.scroll_comfort_top = ServerConfig::default().scroll_comfort_top;
         config.scroll_comfort_bottom = ServerConfig::default().scroll_comfort_bottom;
@@ -483,6 +2905,27 @@ fn parse_server_config(opts: Option<Dictionary>) -> ServerConfig {
     config
 }
 
+/// `live-markdown` under `stdpath("state")` and `stdpath("cache")`, which
+/// follow the XDG variables and `NVIM_APPNAME`.
+fn editor_paths() -> Paths {
+    let stdpath = |what: &str| {
+        api::call_function::<_, String>("stdpath", (what,))
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    Paths::under(stdpath("state").as_deref(), stdpath("cache").as_deref())
+}
+
+fn string_list_from_object(obj: Object) -> Option<Vec<String>> {
+    let list = Array::from_object(obj).ok()?;
+    Some(
+        list.into_iter()
+            .filter_map(|item| String::from_object(item).ok())
+            .collect(),
+    )
+}
+
 fn get_dict_i64(opts: &Dictionary, keys: &[&str]) -> Option<i64> {
     for key in keys {
         if let Some(obj) = opts.get(key)
@@ -497,16 +2940,33 @@ fn get_dict_i64(opts: &Dictionary, keys: &[&str]) -> Option<i64> {
 
 fn get_dict_f64(opts: &Dictionary, keys: &[&str]) -> Option<f64> {
     for key in keys {
-        if let Some(obj) = opts.get(key)
-            && let Ok(value) = f64::from_object(obj.clone())
-        {
+        let Some(obj) = opts.get(key) else {
+            continue;
+        };
+        // Lua hands over whole numbers such as `scroll_center_bias = 1` as integers.
+        if let Ok(value) = f64::from_object(obj.clone()) {
             return Some(value);
         }
+        if let Ok(value) = i64::from_object(obj.clone()) {
+            return Some(value as f64);
+        }
     }
 
     None
 }
 
+fn get_dict_function<A, R>(
+    opts: &Dictionary,
+    key: &str,
+) -> std::result::Result<Option<Function<A, R>>, String> {
+    match opts.get(key) {
+        None => Ok(None),
+        Some(obj) => Function::from_object(obj.clone())
+            .map(Some)
+            .map_err(|_| format!("{key} must be a function")),
+    }
+}
+
 fn get_dict_bool(opts: &Dictionary, keys: &[&str]) -> Option<bool> {
     for key in keys {
         if let Some(obj) = opts.get(key)
@@ -532,37 +2992,37 @@ fn get_dict_string(opts: &Dictionary, keys: &[&str]) -> Option<String> {
 }
 
 fn snapshot_from_buffer(buffer: &api::Buffer) -> std::result::Result<BufferSnapshot, String> {
+    snapshot_with_text(buffer, read_buffer_text(buffer)?)
+}
+
+fn read_buffer_text(buffer: &api::Buffer) -> std::result::Result<String, String> {
+    Ok(read_buffer_lines(buffer, ..)?.join("\n"))
+}
+
+fn read_buffer_lines(
+    buffer: &api::Buffer,
+    rows: impl RangeBounds<usize>,
+) -> std::result::Result<Vec<String>, String> {
+    let lines = buffer
+        .get_lines(rows, false)
+        .map_err(|err| format!("failed to read buffer lines: {err}"))?;
+    Ok(lines
+        .map(|line| line.to_string_lossy().into_owned())
+        .collect())
+}
+
+fn snapshot_with_text(
+    buffer: &api::Buffer,
+    markdown: String,
+) -> std::result::Result<BufferSnapshot, String> {
     let changedtick = u64::from(
         buffer
             .get_changedtick()
             .map_err(|err| format!("failed to get changedtick: {err}"))?,
     );
 
-    let mut markdown = String::new();
-    let lines = buffer
-        .get_lines(.., false)
-        .map_err(|err| format!("failed to read buffer lines: {err}"))?;
-
-    for (idx, line) in lines.enumerate() {
-        if idx > 0 {
-            markdown.push('\n');
-        }
-        markdown.push_str(line.to_string_lossy().as_ref());
-    }
-
     let (cursor_line, cursor_col) = cursor_for_buffer(buffer);
-    let source_path = {
-        let name = buffer
-            .get_name()
-            .map_err(|err| format!("failed to read buffer path: {err}"))?;
-        let path = name.to_string_lossy();
-        let trimmed = path.trim();
-        if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed.to_string())
-        }
-    };
+    let source_path = buffer_source_path(buffer)?;
 
     Ok(BufferSnapshot {
         bufnr: i64::from(buffer.handle()),
@@ -571,9 +3031,23 @@ fn snapshot_from_buffer(buffer: &api::Buffer) -> std::result::Result<BufferSnaps
         cursor_line,
         cursor_col,
         source_path,
+        backend: None,
     })
 }
 
+fn buffer_source_path(buffer: &api::Buffer) -> std::result::Result<Option<String>, String> {
+    let name = buffer
+        .get_name()
+        .map_err(|err| format!("failed to read buffer path: {err}"))?;
+    let path = name.to_string_lossy();
+    let trimmed = path.trim();
+    if trimmed.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(trimmed.to_string()))
+    }
+}
+
 fn cursor_for_buffer(buffer: &api::Buffer) -> (usize, usize) {
     let win = api::get_current_win();
     let Ok(win_buf) = win.get_buf() else {
@@ -587,14 +3061,82 @@ fn cursor_for_buffer(buffer: &api::Buffer) -> (usize, usize) {
     win.get_cursor().unwrap_or((1, 0))
 }
 
-fn is_markdown_buffer(buffer: &api::Buffer) -> bool {
+/// The GUI colors of the highlight groups the preview palette is built from,
+/// following links; groups the colorscheme leaves unset are skipped.
+fn editor_highlights() -> HashMap<String, EditorHighlight> {
+    theme::editor_groups()
+        .into_iter()
+        .filter_map(|group| {
+            let id = api::call_function::<_, i64>("hlID", (group,)).ok()?;
+            let id = api::call_function::<_, i64>("synIDtrans", (id,)).ok()?;
+            let color = |what: &str| {
+                api::call_function::<_, String>("synIDattr", (id, what, "gui"))
+                    .ok()
+                    .filter(|color| color.starts_with('#'))
+            };
+            let highlight = EditorHighlight {
+                fg: color("fg#"),
+                bg: color("bg#"),
+            };
+            (highlight != EditorHighlight::default()).then(|| (group.to_string(), highlight))
+        })
+        .collect()
+}
+
+fn viewport_for_window(win: &api::Window) -> Option<Viewport> {
+    let winid = i64::from(win.handle());
+    let line = |expr: &str| {
+        api::call_function::<_, i64>("line", (expr, winid))
+            .ok()
+            .and_then(|line| usize::try_from(line).ok())
+            .filter(|line| *line > 0)
+    };
+    let (cursor_line, cursor_col) = win.get_cursor().ok()?;
+
+    Some(Viewport {
+        topline: line("w0")?,
+        botline: line("w$")?,
+        cursor_line,
+        cursor_col,
+    })
+}
+
+fn is_previewable_buffer(buffer: &api::Buffer) -> bool {
+    if is_markdown_buffer(buffer) {
+        return true;
+    }
+
+    let Some(state) = state() else {
+        return false;
+    };
+    if buffer_filetype(buffer)
+        .is_some_and(|filetype| state.plugin.backend_for_filetype(&filetype).is_some())
+    {
+        return true;
+    }
+    let Ok(name) = buffer.get_name() else {
+        return false;
+    };
+
+    let path = name.to_string_lossy();
+    Path::new(path.as_ref())
+        .extension()
+        .is_some_and(|ext| state.plugin.has_converter(&ext.to_string_lossy()))
+}
+
+fn buffer_filetype(buffer: &api::Buffer) -> Option<String> {
     let option_opts = OptionOpts::builder().buffer(buffer.clone()).build();
+    api::get_option_value::<String>("filetype", &option_opts)
+        .ok()
+        .filter(|filetype| !filetype.is_empty())
+}
 
-    if let Ok(filetype) = api::get_option_value::<String>("filetype", &option_opts) {
+fn is_markdown_buffer(buffer: &api::Buffer) -> bool {
+    if let Some(filetype) = buffer_filetype(buffer) {
         let filetype = filetype.to_ascii_lowercase();
         if matches!(
             filetype.as_str(),
-            "markdown" | "mdx" | "rmd" | "quarto" | "pandoc"
+            "markdown" | "mdx" | "rmd" | "quarto" | "pandoc" | "djot"
         ) {
             return true;
         }
@@ -612,27 +3154,96 @@ fn is_markdown_buffer(buffer: &api::Buffer) -> bool {
 
     matches!(
         ext.to_string_lossy().to_ascii_lowercase().as_str(),
-        "md" | "markdown" | "mdown" | "mkd" | "mdx" | "qmd"
+        "md" | "markdown" | "mdown" | "mkd" | "mdx" | "qmd" | "dj" | "djot"
     )
 }
 
+/// What to tell the user about a failed plugin call, in their terms.
+fn plugin_error_message(err: &PluginError) -> String {
+    match err {
+        PluginError::Io(_) => err.to_string(),
+        PluginError::ServerBind(_) => format!("{err} (see :checkhealth live_markdown)"),
+        PluginError::SessionNotFound { .. } => {
+            format!("{err}; start one with :LiveMarkdownStart")
+        }
+        PluginError::RenderFailed { .. } => {
+            format!("{err}; the preview shows the converter's output")
+        }
+        PluginError::ConfigInvalid { key, .. } => format!("cannot set {key}: {err}"),
+    }
+}
+
+/// Reports a failed plugin call at the level it deserves: a missing preview
+/// is a hint and a failed render still left something to look at.
+fn report_plugin_error(err: &PluginError) {
+    let message = format!("[live-markdown.nvim] {}", plugin_error_message(err));
+    match err {
+        PluginError::SessionNotFound { .. } => notify_info(&message),
+        PluginError::RenderFailed { .. } => notify_warn(&message),
+        PluginError::Io(_) | PluginError::ServerBind(_) | PluginError::ConfigInvalid { .. } => {
+            notify_err(&message)
+        }
+    }
+}
+
 fn notify_info(message: &str) {
     nvim_oxi::print!("{message}");
 }
 
+fn notify_warn(message: &str) {
+    let _ = api::notify(message, LogLevel::Warn, &Dictionary::new());
+}
+
 fn notify_err(message: &str) {
     api::err_writeln(message);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_server_config;
+    use super::setup_schema::{flatten_groups, validate_setup};
+    use super::{
+        format_connection, format_slow_render, format_task_progress, parse_bufnr,
+        parse_server_config, parse_session_options, parse_share_ttl, plugin_error_message,
+        text_browser_argv,
+    };
+    use crate::plugin::PluginError;
+    use crate::protocol::{ConnectionInfo, ThemeMode};
+    use crate::render::TaskProgress;
     use crate::server::ServerConfig;
     use nvim_oxi::{Dictionary, Object};
+    use std::time::Duration;
+
+    #[test]
+    fn plugin_errors_point_at_their_remedy() {
+        assert_eq!(
+            plugin_error_message(&PluginError::SessionNotFound { bufnr: 4 }),
+            "no active preview for buffer 4; start one with :LiveMarkdownStart"
+        );
+        assert_eq!(
+            plugin_error_message(&PluginError::RenderFailed {
+                bufnr: 4,
+                message: String::from("pandoc exited with 64"),
+            }),
+            "buffer 4 failed to render: pandoc exited with 64; the preview shows the converter's output"
+        );
+        assert_eq!(
+            plugin_error_message(&PluginError::ConfigInvalid {
+                key: String::from("theme"),
+                message: String::from("invalid theme: sepia"),
+            }),
+            "cannot set theme: invalid theme: sepia"
+        );
+        assert!(
+            plugin_error_message(&PluginError::ServerBind(std::io::Error::from(
+                std::io::ErrorKind::AddrInUse
+            )))
+            .starts_with("preview server could not start: ")
+        );
+    }
 
     #[test]
     fn uses_default_config_without_opts() {
-        let parsed = parse_server_config(None);
+        let parsed = parse_server_config(ServerConfig::default(), None);
         let defaults = ServerConfig::default();
 
         assert_eq!(parsed.port, defaults.port);
@@ -652,9 +3263,13 @@ mod tests {
             ("auto_scroll", Object::from(false)),
             ("scroll_comfort_top", Object::from(0.2)),
             ("scrollComfortBottom", Object::from(0.7)),
+            ("citations", Object::from(true)),
+            ("bibliography", Object::from("refs.bib")),
+            ("syncColorscheme", Object::from(true)),
+            ("diagnostics", Object::from(false)),
         ]);
 
-        let parsed = parse_server_config(Some(opts));
+        let parsed = parse_server_config(ServerConfig::default(), Some(opts));
 
         assert_eq!(parsed.port, 6520);
         assert_eq!(parsed.debounce_ms_content, 140);
@@ -663,6 +3278,43 @@ mod tests {
         assert!(!parsed.auto_scroll);
         assert!((parsed.scroll_comfort_top - 0.2).abs() < f64::EPSILON);
         assert!((parsed.scroll_comfort_bottom - 0.7).abs() < f64::EPSILON);
+        assert!(parsed.render.citations);
+        assert!(parsed.sync_colorscheme);
+        assert!(!parsed.diagnostics);
+        assert_eq!(
+            parsed.render.bibliography.as_deref(),
+            Some(std::path::Path::new("refs.bib"))
+        );
+    }
+
+    #[test]
+    fn reads_grouped_setup_options_over_flat_ones() {
+        let opts = Dictionary::from_iter([
+            ("port", Object::from(7000)),
+            ("theme", Object::from("dark")),
+            (
+                "server",
+                Object::from(Dictionary::from_iter([("port", Object::from(7100))])),
+            ),
+            (
+                "preview",
+                Object::from(Dictionary::from_iter([("theme", Object::from("light"))])),
+            ),
+            (
+                "render",
+                Object::from(Dictionary::from_iter([
+                    ("kbd", Object::from(true)),
+                    ("max_nesting", Object::from(12)),
+                ])),
+            ),
+        ]);
+        assert!(validate_setup(&opts).is_empty());
+
+        let parsed = parse_server_config(ServerConfig::default(), Some(flatten_groups(opts)));
+        assert_eq!(parsed.port, 7100);
+        assert_eq!(parsed.theme, ThemeMode::Light);
+        assert!(parsed.render.kbd);
+        assert_eq!(parsed.render.limits.max_nesting, 12);
     }
 
     #[test]
@@ -672,10 +3324,197 @@ mod tests {
             ("scroll_comfort_bottom", Object::from(0.1)),
         ]);
 
-        let parsed = parse_server_config(Some(opts));
+        let parsed = parse_server_config(ServerConfig::default(), Some(opts));
         let defaults = ServerConfig::default();
 
         assert_eq!(parsed.scroll_comfort_top, defaults.scroll_comfort_top);
         assert_eq!(parsed.scroll_comfort_bottom, defaults.scroll_comfort_bottom);
     }
+
+    #[test]
+    fn formats_task_progress_for_statusline() {
+        assert_eq!(
+            format_task_progress(TaskProgress { done: 7, total: 12 }),
+            "7/12 done"
+        );
+        assert_eq!(format_task_progress(TaskProgress::default()), "");
+    }
+
+    #[test]
+    fn text_browsers_without_javascript_open_the_export() {
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "textBrowser",
+                Object::from("lynx -dump"),
+            )])),
+        );
+        assert_eq!(parsed.text_browser, vec!["lynx", "-dump"]);
+
+        assert_eq!(
+            text_browser_argv(&parsed.text_browser, "http://127.0.0.1:6419/", 4),
+            vec!["lynx", "-dump", "http://127.0.0.1:6419/export.html?buf=4"]
+        );
+        assert_eq!(
+            text_browser_argv(
+                &[String::from("/usr/bin/carbonyl")],
+                "http://127.0.0.1:6419/",
+                4
+            ),
+            vec!["/usr/bin/carbonyl", "http://127.0.0.1:6419/"]
+        );
+    }
+
+    #[test]
+    fn document_types_extend_and_remove_defaults() {
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "document_types",
+                Object::from(Dictionary::from_iter([
+                    (".CSV", Object::from("text/csv")),
+                    ("txt", Object::from(false)),
+                ])),
+            )])),
+        );
+
+        assert_eq!(
+            parsed.document_types.get("csv").map(String::as_str),
+            Some("text/csv")
+        );
+        assert_eq!(
+            parsed.document_types.get("pdf").map(String::as_str),
+            Some("application/pdf")
+        );
+        assert!(!parsed.document_types.contains_key("txt"));
+        assert_eq!(parsed.inline_image_max_bytes, 0);
+
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "inlineImageMaxBytes",
+                Object::from(262_144),
+            )])),
+        );
+        assert_eq!(parsed.inline_image_max_bytes, 262_144);
+        assert!(!parsed.image_thumbnails);
+        assert!(!parsed.trust_local_svg);
+
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "trustLocalSvg",
+                Object::from(true),
+            )])),
+        );
+        assert!(parsed.trust_local_svg);
+    }
+
+    #[test]
+    fn accessibility_group_toggles_each_aid() {
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "accessibility",
+                Object::from(Dictionary::from_iter([("skipLink", Object::from(false))])),
+            )])),
+        );
+        assert!(parsed.render.accessibility.aria);
+        assert!(!parsed.render.accessibility.skip_link);
+        assert!(parsed.render.accessibility.landmarks);
+
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([(
+                "accessibility",
+                Object::from(false),
+            )])),
+        );
+        assert!(!parsed.render.accessibility.aria);
+        assert!(!parsed.render.accessibility.landmarks);
+    }
+
+    #[test]
+    fn parses_session_options_for_start() {
+        let options = parse_session_options(Some(Dictionary::from_iter([
+            ("theme", Object::from("dark")),
+            ("autoScroll", Object::from(false)),
+            ("follow", Object::from(false)),
+        ])))
+        .expect("options");
+
+        assert_eq!(options.theme.as_deref(), Some("dark"));
+        assert_eq!(options.auto_scroll, Some(false));
+        assert!(!options.follow);
+        assert!(!options.interactive_tasks);
+        assert!(
+            parse_session_options(Some(Dictionary::from_iter([(
+                "theme",
+                Object::from("sepia")
+            )])))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_share_durations() {
+        assert_eq!(parse_share_ttl("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_share_ttl("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_share_ttl(" 2h "), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_share_ttl("15"), Ok(Duration::from_secs(900)));
+        assert!(parse_share_ttl("0m").is_err());
+        assert!(parse_share_ttl("1d").is_err());
+        assert!(parse_share_ttl("m").is_err());
+    }
+
+    #[test]
+    fn parses_buffer_number_arguments() {
+        assert_eq!(parse_bufnr(" 12 "), Ok(12));
+        assert!(parse_bufnr("0").is_err());
+        assert!(parse_bufnr("%").is_err());
+    }
+
+    #[test]
+    fn formats_connected_clients() {
+        let mut connection = ConnectionInfo {
+            id: 1,
+            bufnr: 4,
+            client: Some(String::from("k2x9")),
+            shared: true,
+            remote: Some(String::from("192.168.1.20:51822")),
+            user_agent: Some(String::from("Mozilla/5.0")),
+            connected_at_ms: 1_000,
+        };
+
+        assert_eq!(
+            format_connection(&connection, 126_000),
+            "  buf 4  192.168.1.20:51822  2m  shared  Mozilla/5.0"
+        );
+
+        connection.shared = false;
+        connection.remote = None;
+        connection.user_agent = None;
+        assert_eq!(
+            format_connection(&connection, 9_500),
+            "  buf 4  unknown  8s"
+        );
+    }
+
+    #[test]
+    fn slow_render_threshold_and_warning() {
+        assert_eq!(
+            parse_server_config(ServerConfig::default(), None).slow_render_ms,
+            200
+        );
+        let parsed = parse_server_config(
+            ServerConfig::default(),
+            Some(Dictionary::from_iter([("slowRenderMs", Object::from(0))])),
+        );
+        assert_eq!(parsed.slow_render_ms, 0);
+
+        assert_eq!(
+            format_slow_render(3, Duration::from_micros(412_900)),
+            "[live-markdown.nvim] rendering buffer 3 took 412 ms; the preview will lag behind edits (see slow_render_ms)"
+        );
+    }
 }
@@ -0,0 +1,587 @@
+use crate::protocol::{
+    FollowScope, MAX_SCROLL_DURATION_MS, OpenBrowser, ScrollAnchor, ScrollBehavior, ThemeMode,
+    UrlEcho,
+};
+use crate::render::{RenderProfile, RendererKind, SlugStyle};
+use crate::server::ServerConfig;
+use nvim_oxi::conversion::FromObject;
+use nvim_oxi::{Array, Dictionary, Object, ObjectKind};
+
+/// Something `setup()` could not use as given. The option keeps its default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupIssue {
+    /// The key as written, with its parent tables: `render.max_nesting`.
+    pub key: String,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    UnknownKey,
+    WrongType,
+    InvalidValue,
+}
+
+impl IssueKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnknownKey => "unknown_key",
+            Self::WrongType => "wrong_type",
+            Self::InvalidValue => "invalid_value",
+        }
+    }
+}
+
+/// What one option accepts.
+#[derive(Clone, Copy)]
+enum Expect {
+    Bool,
+    /// An integer in `min..=max`.
+    Int {
+        min: i64,
+        max: i64,
+    },
+    /// Any number in `min..=max`.
+    Number {
+        min: f64,
+        max: f64,
+    },
+    /// A word `parse` understands; `accepted` lists them for the message.
+    Choice {
+        parse: fn(&str) -> bool,
+        accepted: &'static str,
+    },
+    Text,
+    /// A list of strings.
+    Strings,
+    /// A command line, or a list of words: one string or a list of them.
+    Command,
+    /// A list of commands.
+    Commands,
+    /// A table whose keys are the user's, such as file extensions.
+    Map,
+    /// A table of the listed options.
+    Table(&'static [Key]),
+    Function,
+    /// `false` to turn the option off, or what the inner entry accepts.
+    OrFalse(&'static Expect),
+}
+
+/// An option's spellings, snake_case first, and what it accepts.
+struct Key {
+    names: &'static [&'static str],
+    expect: Expect,
+}
+
+const fn key(names: &'static [&'static str], expect: Expect) -> Key {
+    Key { names, expect }
+}
+
+const MILLIS: Expect = Expect::Int {
+    min: 0,
+    max: i64::MAX,
+};
+const BYTES: Expect = MILLIS;
+const FRACTION: Expect = Expect::Number { min: 0.0, max: 1.0 };
+
+/// The option groups `setup()` takes besides flat keys, with the options each
+/// holds: `setup({ server = { port = 8090 } })` is `setup({ port = 8090 })`.
+const GROUPS: &[(&str, &[Key])] = &[
+    ("server", SERVER_KEYS),
+    ("preview", PREVIEW_KEYS),
+    ("render", RENDER_KEYS),
+];
+
+const SERVER_KEYS: &[Key] = &[
+    key(
+        &["port"],
+        Expect::Int {
+            min: 1,
+            max: u16::MAX as i64,
+        },
+    ),
+    key(
+        &["bind_address", "bindAddress"],
+        Expect::Choice {
+            parse: |value| value == "127.0.0.1" || value == "localhost",
+            accepted: "127.0.0.1, localhost",
+        },
+    ),
+    key(&["debounce_ms_content", "debounceMsContent"], MILLIS),
+    key(&["throttle_ms_cursor", "throttleMsCursor"], MILLIS),
+    key(
+        &[
+            "stop_on_last_client_disconnect",
+            "stopOnLastClientDisconnect",
+        ],
+        Expect::Bool,
+    ),
+    key(&["disconnect_grace_ms", "disconnectGraceMs"], MILLIS),
+    key(&["session_check_ms", "sessionCheckMs"], MILLIS),
+    key(&["asset_watch_ms", "assetWatchMs"], MILLIS),
+    key(&["slow_render_ms", "slowRenderMs"], MILLIS),
+    key(&["state_dir", "stateDir"], Expect::OrFalse(&Expect::Text)),
+    key(&["cache_dir", "cacheDir"], Expect::OrFalse(&Expect::Text)),
+    key(&["record_dir", "recordDir"], Expect::Text),
+    key(&["document_types", "documentTypes"], Expect::Map),
+    key(&["trust_local_svg", "trustLocalSvg"], Expect::Bool),
+    key(&["max_document_bytes", "maxDocumentBytes"], BYTES),
+    key(&["default_asset_root", "defaultAssetRoot"], Expect::Text),
+    key(&["image_thumbnails", "imageThumbnails"], Expect::Bool),
+    key(&["inline_image_max_bytes", "inlineImageMaxBytes"], BYTES),
+];
+
+const PREVIEW_KEYS: &[Key] = &[
+    key(
+        &["theme"],
+        Expect::Choice {
+            parse: |value| ThemeMode::parse(value).is_some(),
+            accepted: "dark, light, auto",
+        },
+    ),
+    key(&["sync_colorscheme", "syncColorscheme"], Expect::Bool),
+    key(&["auto_scroll", "autoScroll"], Expect::Bool),
+    key(&["scroll_comfort_top", "scrollComfortTop"], FRACTION),
+    key(&["scroll_comfort_bottom", "scrollComfortBottom"], FRACTION),
+    key(
+        &["scroll_behavior", "scrollBehavior"],
+        Expect::Choice {
+            parse: |value| ScrollBehavior::parse(value).is_some(),
+            accepted: "smooth, instant",
+        },
+    ),
+    key(
+        &["scroll_duration_ms", "scrollDurationMs"],
+        Expect::Int {
+            min: 0,
+            max: MAX_SCROLL_DURATION_MS as i64,
+        },
+    ),
+    key(&["scroll_center_bias", "scrollCenterBias"], FRACTION),
+    key(
+        &["scroll_anchor", "scrollAnchor"],
+        Expect::Choice {
+            parse: |value| ScrollAnchor::parse(value).is_some(),
+            accepted: "cursor, viewport",
+        },
+    ),
+    key(
+        &["follow_scope", "followScope"],
+        Expect::Choice {
+            parse: |value| FollowScope::parse(value).is_some(),
+            accepted: "global, tabpage, window",
+        },
+    ),
+    key(
+        &["open_browser", "openBrowser"],
+        Expect::Choice {
+            parse: |value| OpenBrowser::parse(value).is_some(),
+            accepted: "always, never, if_no_clients",
+        },
+    ),
+    key(&["browser_openers", "browserOpeners"], Expect::Commands),
+    key(
+        &["url_echo", "urlEcho"],
+        Expect::Choice {
+            parse: |value| UrlEcho::parse(value).is_some(),
+            accepted: "notify, osc8, none",
+        },
+    ),
+    key(&["text_browser", "textBrowser"], Expect::Command),
+    key(&["diagnostics"], Expect::Bool),
+    key(&["spellcheck", "spellCheck"], Expect::Bool),
+    key(&["spell_languages", "spellLanguages"], Expect::Command),
+    key(
+        &["spell_dictionaries", "spellDictionaries"],
+        Expect::Strings,
+    ),
+    key(&["spell_words", "spellWords"], Expect::Strings),
+];
+
+const RENDER_KEYS: &[Key] = &[
+    key(
+        &["profile"],
+        Expect::Choice {
+            parse: |value| RenderProfile::parse(value).is_some(),
+            accepted: "github, commonmark, obsidian, pandoc",
+        },
+    ),
+    key(&["hardbreaks"], Expect::Bool),
+    key(&["figures"], Expect::Bool),
+    key(&["max_input_bytes", "maxInputBytes"], BYTES),
+    key(&["max_nesting", "maxNesting"], BYTES),
+    key(&["max_output_bytes", "maxOutputBytes"], BYTES),
+    key(
+        &["slug_style", "slugStyle"],
+        Expect::Choice {
+            parse: |value| SlugStyle::parse(value).is_some(),
+            accepted: "compact, github, pandoc",
+        },
+    ),
+    key(&["sanitize_html", "sanitizeHtml"], Expect::Bool),
+    key(&["kbd"], Expect::Bool),
+    key(&["lazy_images", "lazyImages"], Expect::Bool),
+    key(&["citations"], Expect::Bool),
+    key(&["bibliography"], Expect::Text),
+    key(
+        &["renderer"],
+        Expect::Choice {
+            parse: |value| RendererKind::parse(value).is_some(),
+            accepted: "builtin, pandoc",
+        },
+    ),
+    key(&["pandoc_args", "pandocArgs"], Expect::Strings),
+    key(&["converters"], Expect::Map),
+    key(
+        &["converter_timeout_ms", "converterTimeoutMs"],
+        Expect::Int {
+            min: 1,
+            max: i64::MAX,
+        },
+    ),
+    key(&["filetypes"], Expect::Map),
+    key(&["labels"], Expect::Map),
+    key(
+        &["accessibility"],
+        Expect::OrFalse(&Expect::Table(ACCESSIBILITY_KEYS)),
+    ),
+];
+
+const ACCESSIBILITY_KEYS: &[Key] = &[
+    key(&["aria"], Expect::Bool),
+    key(&["skip_link", "skipLink"], Expect::Bool),
+    key(&["landmarks"], Expect::Bool),
+];
+
+/// Keys only taken at the top level.
+const TOP_KEYS: &[Key] = &[
+    key(&["server"], Expect::Table(SERVER_KEYS)),
+    key(&["preview"], Expect::Table(PREVIEW_KEYS)),
+    key(&["render"], Expect::Table(RENDER_KEYS)),
+    key(&["on_start"], Expect::Function),
+    key(&["on_stop"], Expect::Function),
+];
+
+/// Lifts the options of the `server`, `preview` and `render` groups to the
+/// top level, where the parser reads them. An option given both ways takes
+/// the grouped value.
+pub fn flatten_groups(opts: Dictionary) -> Dictionary {
+    // Lookups take the first entry with a key, so grouped options go first.
+    let mut flat = Dictionary::new();
+    for (group, _) in GROUPS {
+        if let Some(options) = opts
+            .get(group)
+            .and_then(|options| Dictionary::from_object(options.clone()).ok())
+        {
+            for (name, value) in options {
+                flat.insert(name, value);
+            }
+        }
+    }
+    for (name, value) in opts {
+        flat.insert(name, value);
+    }
+    flat
+}
+
+/// Checks the `setup()` table against the options the plugin knows: unknown
+/// keys (with the closest known one), values of the wrong type and values
+/// outside the range an option accepts.
+pub fn validate_setup(opts: &Dictionary) -> Vec<SetupIssue> {
+    let mut issues = Vec::new();
+    check_table(
+        opts,
+        &[TOP_KEYS, SERVER_KEYS, PREVIEW_KEYS, RENDER_KEYS],
+        "",
+        &mut issues,
+    );
+
+    let defaults = ServerConfig::default();
+    let flat = flatten_groups(opts.clone());
+    let top = number(&flat, &["scroll_comfort_top", "scrollComfortTop"])
+        .filter(|top| (0.0..=1.0).contains(top));
+    let bottom = number(&flat, &["scroll_comfort_bottom", "scrollComfortBottom"])
+        .filter(|bottom| (0.0..=1.0).contains(bottom));
+    if top.is_some() || bottom.is_some() {
+        let top = top.unwrap_or(defaults.scroll_comfort_top);
+        let bottom = bottom.unwrap_or(defaults.scroll_comfort_bottom);
+        if top >= bottom {
+            issues.push(SetupIssue {
+                key: String::from("scroll_comfort_top"),
+                kind: IssueKind::InvalidValue,
+                message: format!(
+                    "scroll_comfort_top ({top}) must be below scroll_comfort_bottom ({bottom})"
+                ),
+            });
+        }
+    }
+
+    issues
+}
+
+fn check_table(
+    table: &Dictionary,
+    key_lists: &[&[Key]],
+    prefix: &str,
+    issues: &mut Vec<SetupIssue>,
+) {
+    let known = || key_lists.iter().flat_map(|keys| keys.iter());
+    for (name, value) in table.iter() {
+        let name = name.to_string_lossy().into_owned();
+        let path = format!("{prefix}{name}");
+        match known().find(|key| key.names.contains(&name.as_str())) {
+            Some(key) => check_value(&path, value, key.expect, issues),
+            None => {
+                let message = match closest_name(&name, known()) {
+                    Some(known) => format!("unknown option {path}; did you mean {prefix}{known}?"),
+                    None => format!("unknown option {path}"),
+                };
+                issues.push(SetupIssue {
+                    key: path,
+                    kind: IssueKind::UnknownKey,
+                    message,
+                });
+            }
+        }
+    }
+}
+
+fn check_value(path: &str, value: &Object, expect: Expect, issues: &mut Vec<SetupIssue>) {
+    let kind = value.kind();
+    let invalid = |message: String| SetupIssue {
+        key: path.to_string(),
+        kind: IssueKind::InvalidValue,
+        message,
+    };
+
+    match expect {
+        Expect::Bool if kind == ObjectKind::Boolean => {}
+        Expect::Int { min, max } if kind == ObjectKind::Integer => {
+            let value = i64::from_object(value.clone()).unwrap_or_default();
+            if !(min..=max).contains(&value) {
+                issues.push(invalid(if max == i64::MAX {
+                    format!("{path} must be at least {min}, got {value}")
+                } else {
+                    format!("{path} must be between {min} and {max}, got {value}")
+                }));
+            }
+        }
+        Expect::Number { min, max } if is_number(kind) => {
+            let value = as_number(value).unwrap_or_default();
+            if !(min..=max).contains(&value) {
+                issues.push(invalid(format!(
+                    "{path} must be between {min} and {max}, got {value}"
+                )));
+            }
+        }
+        Expect::Choice { parse, accepted } if kind == ObjectKind::String => {
+            let value = String::from_object(value.clone()).unwrap_or_default();
+            if !parse(&value) {
+                issues.push(invalid(format!(
+                    "{path} must be one of {accepted}, got \"{value}\""
+                )));
+            }
+        }
+        Expect::Text if kind == ObjectKind::String => {}
+        Expect::Strings if is_list_of(value, |item| item.kind() == ObjectKind::String) => {}
+        Expect::Command if is_command(value) => {}
+        Expect::Commands if is_list_of(value, is_command) => {}
+        Expect::Map if is_table(value) => {}
+        Expect::Table(keys) if is_table(value) => {
+            if let Ok(table) = Dictionary::from_object(value.clone()) {
+                check_table(&table, &[keys], &format!("{path}."), issues);
+            }
+        }
+        Expect::Function if kind == ObjectKind::LuaRef => {}
+        Expect::OrFalse(_) if kind == ObjectKind::Boolean => {
+            if bool::from_object(value.clone()).unwrap_or_default() {
+                issues.push(invalid(format!(
+                    "{path} takes false to turn it off, or {}",
+                    expectation(expect)
+                )));
+            }
+        }
+        Expect::OrFalse(inner) => check_value(path, value, *inner, issues),
+        _ => issues.push(SetupIssue {
+            key: path.to_string(),
+            kind: IssueKind::WrongType,
+            message: format!(
+                "{path} expects {}, got {}",
+                expectation(expect),
+                kind_name(kind)
+            ),
+        }),
+    }
+}
+
+fn expectation(expect: Expect) -> String {
+    match expect {
+        Expect::Bool => String::from("true or false"),
+        Expect::Int { .. } => String::from("an integer"),
+        Expect::Number { .. } => String::from("a number"),
+        Expect::Choice { accepted, .. } => format!("one of {accepted}"),
+        Expect::Text => String::from("a string"),
+        Expect::Strings => String::from("a list of strings"),
+        Expect::Command => String::from("a string or a list of strings"),
+        Expect::Commands => String::from("a list of commands"),
+        Expect::Map | Expect::Table(_) => String::from("a table"),
+        Expect::Function => String::from("a function"),
+        Expect::OrFalse(inner) => format!("false or {}", expectation(*inner)),
+    }
+}
+
+fn kind_name(kind: ObjectKind) -> &'static str {
+    match kind {
+        ObjectKind::Nil => "nil",
+        ObjectKind::Boolean => "a boolean",
+        ObjectKind::Integer | ObjectKind::Float => "a number",
+        ObjectKind::String => "a string",
+        ObjectKind::Array => "a list",
+        ObjectKind::Dictionary => "a table",
+        ObjectKind::LuaRef => "a function",
+        ObjectKind::Buffer | ObjectKind::Window | ObjectKind::TabPage => "a handle",
+    }
+}
+
+fn is_number(kind: ObjectKind) -> bool {
+    matches!(kind, ObjectKind::Integer | ObjectKind::Float)
+}
+
+fn as_number(value: &Object) -> Option<f64> {
+    f64::from_object(value.clone()).ok().or_else(|| {
+        i64::from_object(value.clone())
+            .ok()
+            .map(|value| value as f64)
+    })
+}
+
+fn number(opts: &Dictionary, keys: &[&str]) -> Option<f64> {
+    keys.iter()
+        .find_map(|key| opts.get(key))
+        .and_then(as_number)
+}
+
+/// Lua hands over an empty table as a list, so `{}` passes for a table too.
+fn is_table(value: &Object) -> bool {
+    match value.kind() {
+        ObjectKind::Dictionary => true,
+        ObjectKind::Array => Array::from_object(value.clone()).is_ok_and(|list| list.is_empty()),
+        _ => false,
+    }
+}
+
+fn is_list_of(value: &Object, item: impl Fn(&Object) -> bool) -> bool {
+    match value.kind() {
+        ObjectKind::Array => {
+            Array::from_object(value.clone()).is_ok_and(|list| list.iter().all(item))
+        }
+        ObjectKind::Dictionary => {
+            Dictionary::from_object(value.clone()).is_ok_and(|table| table.is_empty())
+        }
+        _ => false,
+    }
+}
+
+fn is_command(value: &Object) -> bool {
+    value.kind() == ObjectKind::String
+        || is_list_of(value, |item| item.kind() == ObjectKind::String)
+}
+
+/// The known option `name` is most likely a typo of, if any is close enough.
+fn closest_name<'a>(name: &str, keys: impl Iterator<Item = &'a Key>) -> Option<&'static str> {
+    let budget = (name.chars().count() / 4).clamp(1, 3);
+    keys.flat_map(|key| key.names.iter().copied())
+        .map(|known| (edit_distance(name, known), known))
+        .filter(|(distance, _)| *distance <= budget)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut row: Vec<usize> = (0..=right.len()).collect();
+    for (i, left_char) in left.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = diagonal + usize::from(left_char != *right_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[right.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueKind, validate_setup};
+    use nvim_oxi::{Array, Dictionary, Object};
+
+    #[test]
+    fn reports_typos_wrong_types_and_values_out_of_range() {
+        let opts = Dictionary::from_iter([
+            ("debouce_ms_content", Object::from(50)),
+            ("port", Object::from(0)),
+            ("auto_scroll", Object::from("yes")),
+            ("theme", Object::from("sepia")),
+            ("scroll_comfort_top", Object::from(0.8)),
+            (
+                "render",
+                Object::from(Dictionary::from_iter([("max_nestng", Object::from(8))])),
+            ),
+            ("state_dir", Object::from(false)),
+            ("labels", Object::from(Array::new())),
+            ("text_browser", Object::from("lynx -dump")),
+        ]);
+
+        let issues: Vec<(String, IssueKind, String)> = validate_setup(&opts)
+            .into_iter()
+            .map(|issue| (issue.key, issue.kind, issue.message))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    String::from("debouce_ms_content"),
+                    IssueKind::UnknownKey,
+                    String::from(
+                        "unknown option debouce_ms_content; did you mean debounce_ms_content?"
+                    ),
+                ),
+                (
+                    String::from("port"),
+                    IssueKind::InvalidValue,
+                    String::from("port must be between 1 and 65535, got 0"),
+                ),
+                (
+                    String::from("auto_scroll"),
+                    IssueKind::WrongType,
+                    String::from("auto_scroll expects true or false, got a string"),
+                ),
+                (
+                    String::from("theme"),
+                    IssueKind::InvalidValue,
+                    String::from("theme must be one of dark, light, auto, got \"sepia\""),
+                ),
+                (
+                    String::from("render.max_nestng"),
+                    IssueKind::UnknownKey,
+                    String::from(
+                        "unknown option render.max_nestng; did you mean render.max_nesting?"
+                    ),
+                ),
+                (
+                    String::from("scroll_comfort_top"),
+                    IssueKind::InvalidValue,
+                    String::from(
+                        "scroll_comfort_top (0.8) must be below scroll_comfort_bottom (0.65)"
+                    ),
+                ),
+            ]
+        );
+
+        assert!(validate_setup(&Dictionary::new()).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The directory live-markdown's files go in under each base directory.
+const APP_DIR: &str = "live-markdown";
+
+/// Where live-markdown keeps files on disk. Features take their files from
+/// here rather than each picking a location of their own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paths {
+    /// Files kept between runs: recent files, reading positions, logs and
+    /// lockfiles.
+    pub state: Option<PathBuf>,
+    /// Files that can be rebuilt at any time, such as cached renders.
+    pub cache: Option<PathBuf>,
+}
+
+impl Paths {
+    /// The XDG base directories of the process environment; see
+    /// [`Self::xdg_from`].
+    pub fn xdg() -> Self {
+        Self::xdg_from(|name| std::env::var_os(name))
+    }
+
+    /// `$XDG_STATE_HOME/live-markdown` and `$XDG_CACHE_HOME/live-markdown`,
+    /// falling back to `~/.local/state` and `~/.cache` as the spec says, or
+    /// to `%LOCALAPPDATA%\live-markdown` on Windows. A directory stays unset
+    /// when none of its variables hold an absolute path.
+    pub fn xdg_from(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let absolute = |name: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+        };
+        let home = absolute("HOME");
+        let local_app_data = absolute("LOCALAPPDATA");
+        let base = |xdg: &str, home_relative: &str, windows: &str| {
+            absolute(xdg)
+                .map(|dir| dir.join(APP_DIR))
+                .or_else(|| {
+                    home.as_ref()
+                        .map(|home| home.join(home_relative).join(APP_DIR))
+                })
+                .or_else(|| {
+                    local_app_data
+                        .as_ref()
+                        .map(|dir| dir.join(APP_DIR).join(windows))
+                })
+        };
+
+        Self {
+            state: base("XDG_STATE_HOME", ".local/state", "state"),
+            cache: base("XDG_CACHE_HOME", ".cache", "cache"),
+        }
+    }
+
+    /// live-markdown's directories under an application's own state and
+    /// cache directories, such as Neovim's `stdpath()`.
+    pub fn under(state: Option<&Path>, cache: Option<&Path>) -> Self {
+        Self {
+            state: state.map(|dir| dir.join(APP_DIR)),
+            cache: cache.map(|dir| dir.join(APP_DIR)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Paths;
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    fn paths(vars: &[(&str, &str)]) -> Paths {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Paths::xdg_from(|name| vars.get(name).map(OsString::from))
+    }
+
+    #[test]
+    fn resolves_xdg_directories_with_fallbacks() {
+        assert_eq!(
+            paths(&[("XDG_STATE_HOME", "/xdg/state"), ("HOME", "/home/ada")]),
+            Paths {
+                state: Some(PathBuf::from("/xdg/state/live-markdown")),
+                cache: Some(PathBuf::from("/home/ada/.cache/live-markdown")),
+            }
+        );
+        // Relative XDG paths are ignored, as the spec asks.
+        assert_eq!(
+            paths(&[("XDG_CACHE_HOME", "cache"), ("HOME", "/home/ada")]).cache,
+            Some(PathBuf::from("/home/ada/.cache/live-markdown"))
+        );
+        assert_eq!(paths(&[]), Paths::default());
+
+        assert_eq!(
+            Paths::under(Some(Path::new("/state/nvim")), None),
+            Paths {
+                state: Some(PathBuf::from("/state/nvim/live-markdown")),
+                cache: None,
+            }
+        );
+    }
+}
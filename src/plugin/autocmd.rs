@@ -12,7 +12,8 @@ pub struct AutocmdGate {
 #[derive(Debug, Default)]
 struct GateState {
     last_content_emit: Option<(i64, Instant)>,
-    last_cursor_emit: Option<(i64, Instant, usize)>,
+    last_cursor_emit: Option<(i64, Instant, (usize, usize))>,
+    last_viewport_emit: Option<(i64, Instant, (usize, usize))>,
 }
 
 impl AutocmdGate {
@@ -40,13 +41,18 @@ impl AutocmdGate {
         }
     }
 
-    pub async fn allow_cursor_emit(&self, bufnr: i64, line: usize) -> bool {
+    /// Lets a cursor move through unless it repeats the last position or comes
+    /// within the throttle window. Columns count too: they place the cursor
+    /// inside wrapped paragraphs.
+    pub async fn allow_cursor_emit(&self, bufnr: i64, line: usize, col: usize) -> bool {
         let now = Instant::now();
         let mut state = self.state.lock().await;
 
         if state
             .last_cursor_emit
-            .is_some_and(|(last_bufnr, _, last_line)| last_bufnr == bufnr && last_line == line)
+            .is_some_and(|(last_bufnr, _, last_position)| {
+                last_bufnr == bufnr && last_position == (line, col)
+            })
         {
             return false;
         }
@@ -62,7 +68,28 @@ impl AutocmdGate {
             return false;
         }
 
-        state.last_cursor_emit = Some((bufnr, now, line));
+        state.last_cursor_emit = Some((bufnr, now, (line, col)));
+        true
+    }
+
+    /// Throttles window scrolls like cursor moves, keyed on the visible range.
+    pub async fn allow_viewport_emit(&self, bufnr: i64, topline: usize, botline: usize) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().await;
+
+        match state.last_viewport_emit {
+            Some((last_bufnr, _, range)) if last_bufnr == bufnr && range == (topline, botline) => {
+                return false;
+            }
+            Some((last_bufnr, last_emit, _))
+                if last_bufnr == bufnr && now.duration_since(last_emit) < self.cursor_window =>
+            {
+                return false;
+            }
+            _ => {}
+        }
+
+        state.last_viewport_emit = Some((bufnr, now, (topline, botline)));
         true
     }
 
@@ -81,6 +108,13 @@ impl AutocmdGate {
         {
             state.last_cursor_emit = None;
         }
+
+        if state
+            .last_viewport_emit
+            .is_some_and(|(last_bufnr, _, _)| last_bufnr == bufnr)
+        {
+            state.last_viewport_emit = None;
+        }
     }
 }
 
@@ -90,10 +124,22 @@ mod tests {
     use std::time::Duration;
 
     #[tokio::test]
-    async fn cursor_gate_rejects_duplicate_lines() {
-        let gate = AutocmdGate::new(Duration::from_millis(100), Duration::from_millis(20));
+    async fn cursor_gate_rejects_duplicate_positions() {
+        let gate = AutocmdGate::new(Duration::from_millis(100), Duration::from_millis(0));
+
+        assert!(gate.allow_cursor_emit(1, 10, 0).await);
+        assert!(!gate.allow_cursor_emit(1, 10, 0).await);
+        assert!(gate.allow_cursor_emit(1, 10, 42).await);
+    }
+
+    #[tokio::test]
+    async fn viewport_gate_rejects_unchanged_ranges() {
+        let gate = AutocmdGate::new(Duration::from_millis(100), Duration::from_millis(0));
 
-        assert!(gate.allow_cursor_emit(1, 10).await);
-        assert!(!gate.allow_cursor_emit(1, 10).await);
+        assert!(gate.allow_viewport_emit(1, 1, 40).await);
+        assert!(!gate.allow_viewport_emit(1, 1, 40).await);
+        assert!(gate.allow_viewport_emit(1, 5, 44).await);
+        gate.clear_buffer(1).await;
+        assert!(gate.allow_viewport_emit(1, 5, 44).await);
     }
 }
@@ -0,0 +1,223 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An opener still running after this long is taken to have launched the
+/// browser in the foreground rather than to have failed.
+const OPENER_GRACE: Duration = Duration::from_secs(5);
+const OPENER_POLL: Duration = Duration::from_millis(50);
+
+/// Where the editor runs, which decides how a browser can be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Host {
+    Local,
+    /// Linux under WSL: the browser lives on the Windows side.
+    Wsl,
+    /// A remote shell: no browser can be opened, the user has to reach the
+    /// port from their own machine.
+    Ssh,
+}
+
+impl Host {
+    pub fn detect() -> Self {
+        let osrelease = std::fs::read_to_string("/proc/sys/kernel/osrelease").ok();
+        Self::detect_from(|name| std::env::var(name).ok(), osrelease.as_deref())
+    }
+
+    fn detect_from(env: impl Fn(&str) -> Option<String>, osrelease: Option<&str>) -> Self {
+        let set = |name| env(name).is_some_and(|value| !value.is_empty());
+        if set("WSL_DISTRO_NAME")
+            || set("WSL_INTEROP")
+            || osrelease.is_some_and(|release| release.to_ascii_lowercase().contains("microsoft"))
+        {
+            Self::Wsl
+        } else if set("SSH_CONNECTION") || set("SSH_TTY") {
+            Self::Ssh
+        } else {
+            Self::Local
+        }
+    }
+}
+
+/// What to tell a user on a remote host instead of opening a browser.
+pub fn forwarding_hint(url: &str) -> String {
+    let port = url
+        .rsplit_once(':')
+        .and_then(|(_, rest)| rest.split('/').next())
+        .filter(|port| !port.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit()));
+    match port {
+        Some(port) => format!(
+            "preview running at {url}; from your machine run `ssh -L {port}:127.0.0.1:{port} <host>` and open http://127.0.0.1:{port}/"
+        ),
+        None => format!("preview running at {url}; forward its port to open it locally"),
+    }
+}
+
+/// `text` linked to `url` with an OSC 8 escape sequence. Inside tmux the
+/// sequence is wrapped for passthrough, since tmux drops it otherwise.
+pub fn osc8_hyperlink(url: &str, text: &str, tmux: bool) -> String {
+    let link = format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\");
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", link.replace('\x1b', "\x1b\x1b"))
+    } else {
+        link
+    }
+}
+
+/// The commands tried, in order, when no `browser_openers` are configured.
+pub fn default_openers(host: Host) -> Vec<Vec<String>> {
+    let openers: &[&[&str]] = if host == Host::Wsl {
+        &[
+            &["wslview"],
+            &["powershell.exe", "-NoProfile", "-Command", "Start-Process"],
+            &["cmd.exe", "/C", "start", ""],
+            &["xdg-open"],
+        ]
+    } else if cfg!(target_os = "macos") {
+        &[&["open"]]
+    } else if cfg!(windows) {
+        &[&["cmd", "/C", "start", ""], &["explorer"]]
+    } else {
+        &[
+            &["xdg-open"],
+            &["wslview"],
+            &["sensible-browser"],
+            &["gio", "open"],
+        ]
+    };
+
+    openers
+        .iter()
+        .map(|argv| argv.iter().map(|arg| arg.to_string()).collect())
+        .collect()
+}
+
+/// Runs each opener with `url` appended until one starts and exits cleanly.
+/// Blocks while an opener runs, so call it off the editor's main loop.
+pub fn open_url(openers: &[Vec<String>], url: &str) -> Result<(), String> {
+    let mut failures = Vec::new();
+    for argv in openers {
+        let Some((program, args)) = argv.split_first() else {
+            continue;
+        };
+        match run_opener(program, args, url) {
+            Ok(()) => return Ok(()),
+            Err(err) => failures.push(format!("{program}: {err}")),
+        }
+    }
+
+    if failures.is_empty() {
+        Err(String::from("no browser opener configured"))
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+fn run_opener(program: &str, args: &[String], url: &str) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|err| err.to_string())? {
+            Some(status) if status.success() || !trusts_exit_status(program) => return Ok(()),
+            Some(status) => return Err(format!("exited with {status}")),
+            None if started.elapsed() >= OPENER_GRACE => return Ok(()),
+            None => thread::sleep(OPENER_POLL),
+        }
+    }
+}
+
+/// `explorer.exe` exits with 1 even after opening the URL, so trying the
+/// next opener would open it twice.
+fn trusts_exit_status(program: &str) -> bool {
+    !Path::new(program)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("explorer"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Host, default_openers, forwarding_hint, open_url, osc8_hyperlink, trusts_exit_status,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn detects_wsl_and_ssh_hosts() {
+        let host = |vars: &[(&str, &str)], osrelease| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            Host::detect_from(|name| vars.get(name).cloned(), osrelease)
+        };
+
+        assert_eq!(host(&[], None), Host::Local);
+        assert_eq!(host(&[("WSL_DISTRO_NAME", "Ubuntu")], None), Host::Wsl);
+        assert_eq!(
+            host(&[], Some("5.15.153.1-microsoft-standard-WSL2")),
+            Host::Wsl
+        );
+        assert_eq!(
+            host(&[("SSH_CONNECTION", "10.0.0.2 51022 10.0.0.3 22")], None),
+            Host::Ssh
+        );
+        assert_eq!(host(&[("SSH_TTY", "")], None), Host::Local);
+        assert_eq!(default_openers(Host::Wsl)[0], vec!["wslview"]);
+    }
+
+    #[test]
+    fn explorer_exit_status_is_not_a_failure() {
+        assert!(!trusts_exit_status("explorer"));
+        assert!(!trusts_exit_status("/mnt/c/Windows/Explorer.EXE"));
+        assert!(trusts_exit_status("cmd"));
+        assert!(trusts_exit_status("xdg-open"));
+    }
+
+    #[test]
+    fn hyperlinks_are_wrapped_for_tmux() {
+        let url = "http://127.0.0.1:6419/";
+        assert_eq!(
+            osc8_hyperlink(url, "preview", false),
+            "\x1b]8;;http://127.0.0.1:6419/\x1b\\preview\x1b]8;;\x1b\\"
+        );
+        let wrapped = osc8_hyperlink(url, "preview", true);
+        assert!(wrapped.starts_with("\x1bPtmux;\x1b\x1b]8;;"));
+        assert!(wrapped.ends_with("\x1b\x1b]8;;\x1b\x1b\\\x1b\\"));
+    }
+
+    #[test]
+    fn forwarding_hint_names_the_port() {
+        let hint = forwarding_hint("http://127.0.0.1:6420/");
+        assert!(hint.contains("ssh -L 6420:127.0.0.1:6420"));
+    }
+
+    fn argv(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|part| part.to_string()).collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn falls_back_until_an_opener_succeeds() {
+        let openers = vec![
+            argv(&["/nonexistent/live-markdown-opener"]),
+            argv(&["false"]),
+            argv(&["true"]),
+        ];
+        assert_eq!(open_url(&openers, "http://127.0.0.1:6419/"), Ok(()));
+
+        let err = open_url(&openers[..2], "http://127.0.0.1:6419/").expect_err("all fail");
+        assert!(err.contains("/nonexistent/live-markdown-opener"));
+        assert!(err.contains("false: exited with"));
+        assert!(open_url(&[], "http://127.0.0.1:6419/").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::document::LineDelta;
+use std::ops::Range;
+
+/// The rows of one buffer edited since its session's document last caught
+/// up, so an edit sends only what changed instead of the whole buffer.
+#[derive(Debug, Clone, Default)]
+pub struct EditTracker {
+    /// The `changedtick` the document is at; `None` until a full read.
+    base: Option<u64>,
+    /// Rows `first..last_old` of the base, now at `first..last_new`.
+    changed: Option<(usize, usize, usize)>,
+}
+
+/// What a buffer's session needs after its edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PendingEdit {
+    /// The document cannot be patched; send the whole buffer.
+    Full,
+    /// Nothing to send.
+    Unchanged,
+    /// Patch the document at `base` with `delta`.
+    Delta { base: u64, delta: LineDelta },
+}
+
+impl EditTracker {
+    /// Records an `on_lines` change: rows `first..last_old` were replaced by
+    /// the rows now at `first..last_new`.
+    pub fn mark_changed(&mut self, first: usize, last_old: usize, last_new: usize) {
+        if self.base.is_none() {
+            return;
+        }
+        if first > last_old || first > last_new {
+            self.invalidate();
+            return;
+        }
+        self.changed = Some(match self.changed {
+            None => (first, last_old, last_new),
+            Some((start, old_end, new_end)) => {
+                // Widen the region to cover both edits, in the rows of the
+                // base and in the rows now.
+                let end = new_end.max(last_old);
+                (
+                    start.min(first),
+                    old_end + end - new_end,
+                    end - last_old + last_new,
+                )
+            }
+        });
+    }
+
+    /// Forgets the base, as after `:edit` reloads the buffer or the session
+    /// turns out to hold something else.
+    pub fn invalidate(&mut self) {
+        self.base = None;
+        self.changed = None;
+    }
+
+    /// Records that the whole buffer at `changedtick` was sent.
+    pub fn reset(&mut self, changedtick: u64) {
+        self.base = Some(changedtick);
+        self.changed = None;
+    }
+
+    /// Takes the edits since the base, reading the changed rows with
+    /// `read`, and moves the base to `changedtick`.
+    pub fn take<E>(
+        &mut self,
+        changedtick: u64,
+        read: impl FnOnce(Range<usize>) -> Result<Vec<String>, E>,
+    ) -> Result<PendingEdit, E> {
+        let Some(base) = self.base else {
+            return Ok(PendingEdit::Full);
+        };
+        let Some((first, last_old, last_new)) = self.changed else {
+            return Ok(PendingEdit::Unchanged);
+        };
+
+        let lines = read(first..last_new)?;
+        if lines.len() != last_new - first {
+            self.invalidate();
+            return Ok(PendingEdit::Full);
+        }
+        self.reset(changedtick);
+        Ok(PendingEdit::Delta {
+            base,
+            delta: LineDelta {
+                first,
+                last_old,
+                lines,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditTracker, PendingEdit};
+    use crate::document::Document;
+    use std::convert::Infallible;
+
+    #[test]
+    fn merges_edits_into_one_delta_that_patches_the_document() {
+        let mut buffer: Vec<String> = ["# Title", "", "one", "two"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut document = Document::new(&buffer.join("\n"));
+        let mut tracker = EditTracker::default();
+        let read = |buffer: &[String]| {
+            let buffer = buffer.to_vec();
+            move |rows: std::ops::Range<usize>| Ok::<_, Infallible>(buffer[rows].to_vec())
+        };
+
+        assert_eq!(tracker.take(1, read(&buffer)), Ok(PendingEdit::Full));
+        tracker.reset(1);
+        assert_eq!(tracker.take(1, read(&buffer)), Ok(PendingEdit::Unchanged));
+
+        // Edit row 2 and insert a row after it, then delete the title.
+        buffer.splice(2..3, [String::from("ONE"), String::from("one and a half")]);
+        tracker.mark_changed(2, 3, 4);
+        buffer.remove(0);
+        tracker.mark_changed(0, 1, 0);
+
+        let Ok(PendingEdit::Delta { base, delta }) = tracker.take(3, read(&buffer)) else {
+            panic!("expected a delta");
+        };
+        assert_eq!(base, 1);
+        assert_eq!((delta.first, delta.last_old, delta.lines.len()), (0, 3, 3));
+        document.apply(&delta);
+        assert_eq!(document.text(), buffer.join("\n"));
+
+        // The next edit starts from where this one left the document.
+        buffer.push(String::from("three"));
+        tracker.mark_changed(4, 4, 5);
+        let Ok(PendingEdit::Delta { base, delta }) = tracker.take(4, read(&buffer)) else {
+            panic!("expected a delta");
+        };
+        assert_eq!(base, 3);
+        document.apply(&delta);
+        assert_eq!(document.text(), "\nONE\none and a half\ntwo\nthree");
+
+        tracker.invalidate();
+        tracker.mark_changed(0, 1, 1);
+        assert_eq!(tracker.take(5, read(&buffer)), Ok(PendingEdit::Full));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::render::Heading;
+
+/// A problem in the document worth showing as an editor diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    /// Source line, 1-based.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Every check over the document's headings, in source order.
+pub fn lint_headings(headings: &[Heading]) -> Vec<LintIssue> {
+    heading_id_collisions(headings)
+}
+
+/// Headings whose id needed a numeric suffix: links to the suffixed anchor
+/// only work here, since other renderers number duplicates their own way.
+fn heading_id_collisions(headings: &[Heading]) -> Vec<LintIssue> {
+    headings
+        .iter()
+        .filter_map(|heading| {
+            let taken = heading.collides_with.as_deref()?;
+            Some(LintIssue {
+                line: heading.line,
+                message: format!(
+                    "heading id #{taken} is already used; this heading gets #{} instead, which other renderers may not match",
+                    heading.id
+                ),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LintIssue, lint_headings};
+    use crate::render::LiveMarkdownRenderer;
+
+    #[test]
+    fn reports_suffixed_heading_ids() {
+        let headings = LiveMarkdownRenderer::default()
+            .headings("# Notes\n\n## Setup\n\ntext\n\n## Setup\n\n## Setup {#setup-1}\n");
+
+        assert_eq!(
+            lint_headings(&headings),
+            vec![
+                LintIssue {
+                    line: 7,
+                    message: String::from(
+                        "heading id #setup is already used; this heading gets #setup-1 instead, which other renderers may not match"
+                    ),
+                },
+                LintIssue {
+                    line: 9,
+                    message: String::from(
+                        "heading id #setup-1 is already used; this heading gets #setup-1-1 instead, which other renderers may not match"
+                    ),
+                },
+            ]
+        );
+    }
+}
@@ -1,27 +1,86 @@
 pub mod autocmd;
-
-use crate::protocol::SessionEndReason;
-use crate::render::LiveMarkdownRenderer;
-use crate::server::{ServerConfig, ServerController};
-use crate::session::{BufferSnapshot, SessionManager};
+pub mod browser;
+pub mod edits;
+pub mod lint;
+pub mod open;
+pub mod registry;
+pub mod table;
+pub mod toc;
+
+use crate::protocol::{
+    ConnectionsResponse, FollowGroup, RecentFile, SessionEndReason, SessionOptions, SessionSummary,
+    Viewport,
+};
+use crate::recent;
+use crate::render::{Backend, LiveMarkdownRenderer};
+use crate::server::{ServerConfig, ServerController, ServerStatus};
+use crate::session::{
+    BufferEdit, BufferSnapshot, EditOutcome, SessionEndHook, SessionManager,
+    is_supported_image_path,
+};
+use crate::spell::SpellChecker;
+use crate::theme::{self, EditorHighlight};
+use open::LinkTarget;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long browsers wait before their first try to reach a server that
+/// went away with the editor.
+const RESUME_RETRY: Duration = Duration::from_millis(500);
+
+/// Asks the editor which buffers still exist; the answer arrives once the
+/// editor's main loop gets around to it.
+pub type LiveBuffers = Box<dyn Fn() -> oneshot::Receiver<HashSet<i64>> + Send + Sync>;
 
 #[derive(Debug)]
 pub enum PluginError {
     Io(std::io::Error),
+    /// The preview server could not listen on its configured ports.
+    ServerBind(std::io::Error),
+    /// The buffer has no preview session.
+    SessionNotFound {
+        bufnr: i64,
+    },
+    /// The buffer's converter failed; the preview shows its error instead.
+    RenderFailed {
+        bufnr: i64,
+        message: String,
+    },
+    /// A setting was rejected; `message` says why.
+    ConfigInvalid {
+        key: String,
+        message: String,
+    },
 }
 
 impl Display for PluginError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Io(err) => write!(f, "io error: {err}"),
+            Self::ServerBind(err) => write!(f, "preview server could not start: {err}"),
+            Self::SessionNotFound { bufnr } => write!(f, "no active preview for buffer {bufnr}"),
+            Self::RenderFailed { bufnr, message } => {
+                write!(f, "buffer {bufnr} failed to render: {message}")
+            }
+            Self::ConfigInvalid { message, .. } => f.write_str(message),
         }
     }
 }
 
-impl Error for PluginError {}
+impl Error for PluginError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) | Self::ServerBind(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl From<std::io::Error> for PluginError {
     fn from(value: std::io::Error) -> Self {
@@ -29,12 +88,29 @@ impl From<std::io::Error> for PluginError {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenAction {
+    Navigated(String),
+    External(String),
+    Edit(PathBuf),
+    Unresolved(String),
+}
+
 #[derive(Debug, Clone)]
 pub struct LiveMarkdownPlugin {
     renderer: LiveMarkdownRenderer,
     sessions: SessionManager,
     server: ServerController,
     autocmd: autocmd::AutocmdGate,
+    disconnect_grace: Option<Duration>,
+    watching_disconnects: Arc<AtomicBool>,
+    slow_render: Option<Duration>,
+    slow_buffers: Arc<Mutex<HashSet<i64>>>,
+    session_check: Option<Duration>,
+    asset_watch: Option<Duration>,
+    watching_assets: Arc<AtomicBool>,
+    state_dir: Option<PathBuf>,
+    registry: registry::RendererRegistry,
 }
 
 impl Default for LiveMarkdownPlugin {
@@ -45,32 +121,130 @@ impl Default for LiveMarkdownPlugin {
 
 impl LiveMarkdownPlugin {
     pub fn new(config: ServerConfig) -> Self {
-        let sessions = SessionManager::default();
+        let sessions = SessionManager::default().with_max_document_bytes(config.max_document_bytes);
+        let sessions = match &config.record_dir {
+            Some(dir) => sessions.with_recording(dir.clone()),
+            None => sessions,
+        };
+        let sessions = match &config.default_asset_root {
+            Some(root) => sessions.with_default_asset_root(root.clone()),
+            None => sessions,
+        };
+        let spellchecker = config
+            .spellcheck
+            .then(|| {
+                SpellChecker::load(
+                    &config.spell_languages,
+                    &config.spell_dictionaries,
+                    &config.spell_words,
+                )
+            })
+            .flatten();
+        let sessions = match spellchecker {
+            Some(checker) => sessions.with_spellchecker(checker),
+            None => sessions,
+        };
         let server = ServerController::new(config.clone(), sessions.clone());
         let autocmd = autocmd::AutocmdGate::new(
             Duration::from_millis(config.debounce_ms_content),
             Duration::from_millis(config.throttle_ms_cursor),
         );
 
+        let renderer = LiveMarkdownRenderer::new(config.render.clone());
+        let registry = registry::RendererRegistry::new(&config.filetypes, &renderer);
+
         Self {
-            renderer: LiveMarkdownRenderer::default(),
+            renderer,
+            registry,
             sessions,
             server,
             autocmd,
+            disconnect_grace: config
+                .stop_on_last_client_disconnect
+                .then(|| Duration::from_millis(config.disconnect_grace_ms)),
+            watching_disconnects: Arc::new(AtomicBool::new(false)),
+            slow_render: (config.slow_render_ms > 0)
+                .then(|| Duration::from_millis(config.slow_render_ms)),
+            slow_buffers: Arc::new(Mutex::new(HashSet::new())),
+            session_check: (config.session_check_ms > 0)
+                .then(|| Duration::from_millis(config.session_check_ms)),
+            asset_watch: (config.asset_watch_ms > 0)
+                .then(|| Duration::from_millis(config.asset_watch_ms)),
+            watching_assets: Arc::new(AtomicBool::new(false)),
+            state_dir: config.state_dir.clone(),
         }
     }
 
+    /// Runs `hook` with the buffer of every preview session that ends.
+    pub fn with_session_end_hook(mut self, hook: SessionEndHook) -> Self {
+        self.sessions = self.sessions.with_end_hook(hook);
+        self
+    }
+
     pub fn sessions(&self) -> SessionManager {
         self.sessions.clone()
     }
 
+    /// The backend buffers of `filetype` render with, if the filetype is previewable.
+    pub fn backend_for_filetype(&self, filetype: &str) -> Option<Backend> {
+        self.registry.backend_for(filetype).cloned()
+    }
+
+    pub fn has_converter(&self, extension: &str) -> bool {
+        self.renderer.has_converter(extension)
+    }
+
+    pub fn toc_lines(&self, markdown: &str) -> Vec<String> {
+        toc::toc_lines(&self.renderer.headings(markdown))
+    }
+
+    /// Problems in the document to show as editor diagnostics.
+    pub fn lint(&self, markdown: &str) -> Vec<lint::LintIssue> {
+        lint::lint_headings(&self.renderer.headings(markdown))
+    }
+
+    pub fn format_table(&self, markdown: &str, line: usize) -> Option<table::FormattedTable> {
+        table::format_table_at(markdown, line)
+    }
+
     pub async fn has_session(&self, bufnr: i64) -> bool {
         self.sessions.has_session(bufnr).await
     }
 
-    pub async fn start_preview(&self, snapshot: BufferSnapshot) -> Result<String, PluginError> {
-        let addr = self.server.ensure_running().await?;
-        self.sessions.start_session(snapshot, &self.renderer).await;
+    pub async fn start_preview(
+        &self,
+        snapshot: BufferSnapshot,
+        options: SessionOptions,
+    ) -> Result<String, PluginError> {
+        let addr = self
+            .server
+            .ensure_running()
+            .await
+            .map_err(PluginError::ServerBind)?;
+        if let Some(dir) = self.state_dir.clone()
+            && let Some(path) = snapshot
+                .source_path
+                .clone()
+                .filter(|path| !path.trim().is_empty())
+        {
+            // Best effort: a failed write only costs the recent list an entry.
+            tokio::task::spawn_blocking(move || recent::record(&dir, path.trim()));
+        }
+        self.sessions
+            .start_session_with_options(snapshot, options, &self.renderer)
+            .await;
+
+        if let Some(grace) = self.disconnect_grace
+            && !self.watching_disconnects.swap(true, Ordering::AcqRel)
+        {
+            tokio::spawn(self.clone().stop_after_disconnects(grace));
+        }
+
+        if let Some(interval) = self.asset_watch
+            && !self.watching_assets.swap(true, Ordering::AcqRel)
+        {
+            tokio::spawn(self.clone().watch_assets(interval));
+        }
 
         let url = format!("http://{}:{}/", addr.ip(), addr.port(),);
 
@@ -83,6 +257,10 @@ impl LiveMarkdownPlugin {
             .stop_session(bufnr, SessionEndReason::Stopped)
             .await;
         self.autocmd.clear_buffer(bufnr).await;
+        self.slow_buffers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&bufnr);
 
         if self.sessions.session_count().await == 0 {
             self.server.stop().await;
@@ -96,40 +274,279 @@ impl LiveMarkdownPlugin {
         self.server.stop().await;
     }
 
+    pub async fn list_sessions(&self) -> Vec<SessionSummary> {
+        self.sessions.list_sessions().await
+    }
+
+    /// Recently previewed files, newest first.
+    pub fn recent_files(&self) -> Vec<RecentFile> {
+        self.state_dir
+            .as_deref()
+            .map(recent::load)
+            .unwrap_or_default()
+    }
+
+    pub async fn preview_url(&self) -> Option<String> {
+        self.server.preview_url().await
+    }
+
+    pub fn server_status(&self) -> ServerStatus {
+        self.server.status()
+    }
+
+    pub async fn check_port(&self) -> Result<(), PluginError> {
+        self.server
+            .check_port()
+            .await
+            .map_err(PluginError::ServerBind)
+    }
+
+    pub fn connections(&self) -> ConnectionsResponse {
+        self.server.connections()
+    }
+
     pub async fn open_preview(&self, bufnr: i64) -> Result<Option<String>, PluginError> {
         if !self.sessions.has_session(bufnr).await {
-            return Ok(None);
+            return Err(PluginError::SessionNotFound { bufnr });
         }
 
-        self.server.ensure_running().await?;
+        self.server
+            .ensure_running()
+            .await
+            .map_err(PluginError::ServerBind)?;
         Ok(self.server.preview_url().await)
     }
 
-    pub async fn on_text_changed(&self, snapshot: BufferSnapshot) {
-        if self.autocmd.allow_content_emit(snapshot.bufnr).await {
-            let _ = self.sessions.update_content(snapshot, &self.renderer).await;
+    /// Mints a read-only link to `bufnr`'s preview that expires after `ttl`.
+    pub async fn share_preview(
+        &self,
+        bufnr: i64,
+        ttl: Duration,
+    ) -> Result<Option<String>, PluginError> {
+        let Some(token) = self.sessions.create_share(bufnr, ttl).await? else {
+            return Err(PluginError::SessionNotFound { bufnr });
+        };
+
+        self.server
+            .ensure_running()
+            .await
+            .map_err(PluginError::ServerBind)?;
+        Ok(self
+            .server
+            .preview_url()
+            .await
+            .map(|base| format!("{base}share/{token}")))
+    }
+
+    /// Replaces `bufnr`'s share link with a new one and sends its connected
+    /// clients there. `None` when the preview has no live share link.
+    pub async fn rotate_share(&self, bufnr: i64) -> Result<Option<String>, PluginError> {
+        let Some(token) = self.sessions.rotate_share(bufnr).await? else {
+            return Ok(None);
+        };
+
+        self.server
+            .ensure_running()
+            .await
+            .map_err(PluginError::ServerBind)?;
+        let Some(url) = self
+            .server
+            .preview_url()
+            .await
+            .map(|base| format!("{base}share/{token}"))
+        else {
+            return Ok(None);
+        };
+        self.sessions.reconnect(bufnr, &url).await;
+        Ok(Some(url))
+    }
+
+    pub async fn open_link(&self, bufnr: i64, target: LinkTarget) -> OpenAction {
+        match target {
+            LinkTarget::Anchor(anchor) => {
+                if self.sessions.navigate(bufnr, &anchor).await {
+                    OpenAction::Navigated(anchor)
+                } else {
+                    OpenAction::Unresolved(format!("#{anchor} (no active preview for buffer)"))
+                }
+            }
+            LinkTarget::Url(url) => OpenAction::External(url),
+            LinkTarget::Path(raw) => {
+                match self.sessions.resolve_local_link_path(bufnr, &raw).await {
+                    Some(path) if is_supported_image_path(&path) => {
+                        OpenAction::External(path.to_string_lossy().into_owned())
+                    }
+                    Some(path) => OpenAction::Edit(path),
+                    None => OpenAction::Unresolved(raw),
+                }
+            }
         }
     }
 
-    pub async fn on_buf_write(&self, snapshot: BufferSnapshot) {
-        let _ = self
+    /// Returns the render time when the update made the buffer's renders slow.
+    pub async fn on_text_changed(&self, snapshot: BufferSnapshot) -> Option<Duration> {
+        let bufnr = snapshot.bufnr;
+        if !self.autocmd.allow_content_emit(bufnr).await {
+            self.sessions.sync_document(&snapshot).await;
+            return None;
+        }
+        if self.sessions.update_content(snapshot, &self.renderer).await {
+            return self.check_slow_render(bufnr).await;
+        }
+
+        None
+    }
+
+    /// Patches the edited rows into the buffer's document and renders it
+    /// unless the debounce gate holds the render back. Returns the outcome,
+    /// with the render time when the render made the buffer's renders slow.
+    pub async fn on_buffer_edit(&self, edit: BufferEdit) -> (EditOutcome, Option<Duration>) {
+        let bufnr = edit.bufnr;
+        let render = self.autocmd.allow_content_emit(bufnr).await;
+        let outcome = self.sessions.apply_edit(edit, &self.renderer, render).await;
+        let elapsed = match outcome {
+            EditOutcome::Rendered => self.check_slow_render(bufnr).await,
+            _ => None,
+        };
+        (outcome, elapsed)
+    }
+
+    pub async fn on_buf_write(&self, snapshot: BufferSnapshot) -> Option<Duration> {
+        let bufnr = snapshot.bufnr;
+        if self
             .sessions
             .rerender_content(snapshot, &self.renderer)
-            .await;
+            .await
+        {
+            return self.check_slow_render(bufnr).await;
+        }
+
+        None
+    }
+
+    /// Re-renders the buffer on demand, skipping the debounce gate and hash
+    /// check. A converter failure is an error here even though the preview
+    /// shows it too.
+    pub async fn refresh(&self, snapshot: BufferSnapshot) -> Result<(), PluginError> {
+        let bufnr = snapshot.bufnr;
+        if !self.sessions.refresh(snapshot, &self.renderer).await {
+            return Err(PluginError::SessionNotFound { bufnr });
+        }
+        match self.sessions.render_failure(bufnr).await {
+            Some(message) => Err(PluginError::RenderFailed { bufnr, message }),
+            None => Ok(()),
+        }
+    }
+
+    /// Reports a slow render once per slow stretch rather than on every keystroke:
+    /// the buffer has to render under the threshold again before it warns twice.
+    async fn check_slow_render(&self, bufnr: i64) -> Option<Duration> {
+        let threshold = self.slow_render?;
+        let stats = self.sessions.render_stats(bufnr).await?;
+        let elapsed = Duration::from_micros(stats.last_us);
+
+        let mut slow_buffers = self
+            .slow_buffers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if elapsed < threshold {
+            slow_buffers.remove(&bufnr);
+            return None;
+        }
+
+        slow_buffers.insert(bufnr).then_some(elapsed)
     }
 
     pub async fn on_cursor_moved(&self, bufnr: i64, line: usize, col: usize) {
-        if self.autocmd.allow_cursor_emit(bufnr, line).await {
+        if self.autocmd.allow_cursor_emit(bufnr, line, col).await {
             let _ = self.sessions.update_cursor(bufnr, line, col).await;
         }
     }
 
-    pub async fn on_buf_enter(&self, snapshot: BufferSnapshot) {
-        if self.sessions.session_count().await == 0 {
+    pub async fn on_win_scrolled(&self, bufnr: i64, viewport: Viewport) {
+        if self
+            .autocmd
+            .allow_viewport_emit(bufnr, viewport.topline, viewport.botline)
+            .await
+        {
+            let _ = self.sessions.update_viewport(bufnr, viewport).await;
+        }
+    }
+
+    /// Passes a change of the editor's `'background'` on to the previews.
+    pub async fn on_background_changed(&self, background: &str) {
+        if matches!(background, "dark" | "light") {
+            self.sessions.broadcast_theme(background).await;
+        }
+    }
+
+    /// Rebuilds the preview palette from the editor's highlight groups.
+    pub async fn on_colorscheme_changed(&self, highlights: HashMap<String, EditorHighlight>) {
+        self.sessions
+            .set_editor_palette(theme::editor_palette_css(&highlights))
+            .await;
+    }
+
+    /// Applies `key=value` to the session's options and pushes them to the preview.
+    pub async fn set_session_option(
+        &self,
+        bufnr: i64,
+        key: &str,
+        value: &str,
+    ) -> Result<bool, PluginError> {
+        let Some(mut options) = self.sessions.options(bufnr).await else {
+            return Ok(false);
+        };
+
+        options
+            .set(key, value)
+            .map_err(|message| PluginError::ConfigInvalid {
+                key: key.to_string(),
+                message,
+            })?;
+        Ok(self.sessions.set_options(bufnr, options).await)
+    }
+
+    /// Follows the entered buffer, or catches up its own session when the
+    /// preview is not following or already shows it. With a `group`, only the
+    /// preview started in that tabpage or window follows.
+    pub async fn on_buf_enter(&self, snapshot: BufferSnapshot, group: Option<FollowGroup>) {
+        let followed = match group {
+            Some(group) => self.sessions.group_bufnr(group).await,
+            None => self.sessions.active_bufnr().await,
+        };
+        let Some(followed) = followed else {
+            let _ = self.sessions.resume_session(snapshot, &self.renderer).await;
+            return;
+        };
+        if followed == snapshot.bufnr
+            || self
+                .sessions
+                .options(followed)
+                .await
+                .is_some_and(|options| !options.follow)
+        {
+            let _ = self.sessions.resume_session(snapshot, &self.renderer).await;
             return;
         }
 
-        self.sessions.start_session(snapshot, &self.renderer).await;
+        match group {
+            Some(group) => {
+                self.sessions
+                    .follow_in_group(snapshot, group, &self.renderer)
+                    .await;
+            }
+            None => self.sessions.start_session(snapshot, &self.renderer).await,
+        }
+    }
+
+    /// Points commands at the preview of the tabpage or window just entered.
+    pub async fn on_scope_enter(&self, group: FollowGroup) {
+        let _ = self.sessions.focus_group(group).await;
+    }
+
+    pub async fn on_buf_rename(&self, bufnr: i64, source_path: Option<String>) {
+        let _ = self.sessions.update_source_path(bufnr, source_path).await;
     }
 
     pub async fn on_buf_wipeout(&self, bufnr: i64) -> Result<(), PluginError> {
@@ -138,6 +555,10 @@ impl LiveMarkdownPlugin {
             .stop_session(bufnr, SessionEndReason::BufferClosed)
             .await;
         self.autocmd.clear_buffer(bufnr).await;
+        self.slow_buffers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&bufnr);
 
         if self.sessions.session_count().await == 0 {
             self.server.stop().await;
@@ -146,17 +567,71 @@ impl LiveMarkdownPlugin {
         Ok(())
     }
 
+    async fn stop_after_disconnects(self, grace: Duration) {
+        loop {
+            self.sessions.wait_for_disconnect().await;
+            tokio::time::sleep(grace).await;
+
+            for bufnr in self.sessions.abandoned_sessions(grace).await {
+                let _ = self.stop_preview(bufnr).await;
+            }
+        }
+    }
+
+    /// Polls the images shown in previews so edits on disk reach the browser
+    /// without a re-render.
+    async fn watch_assets(self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if self.sessions.session_count().await > 0 {
+                self.sessions.check_assets().await;
+            }
+        }
+    }
+
+    /// Periodically stops previews whose buffer is gone, for when a wipeout
+    /// never reached the plugin (a panicked task, or state replaced by setup).
+    pub async fn supervise_sessions(self, live_buffers: LiveBuffers) {
+        let Some(interval) = self.session_check else {
+            return;
+        };
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if self.sessions.session_count().await == 0 {
+                continue;
+            }
+            let Ok(live) = live_buffers().await else {
+                continue;
+            };
+
+            for bufnr in self.sessions.session_bufnrs().await {
+                if !live.contains(&bufnr) {
+                    let _ = self.on_buf_wipeout(bufnr).await;
+                }
+            }
+        }
+    }
+
+    /// Stops for good as far as this process goes; open browsers keep
+    /// retrying so they pick up the next server that shows their file.
     pub async fn shutdown(&self) {
-        self.sessions.stop_all(SessionEndReason::Stopped).await;
+        self.sessions.going_away(RESUME_RETRY).await;
         self.server.stop().await;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::LiveMarkdownPlugin;
+    use super::open::LinkTarget;
+    use super::{LiveMarkdownPlugin, OpenAction, PluginError};
+    use crate::protocol::{ServerEvent, SessionOptions};
     use crate::server::ServerConfig;
-    use crate::session::BufferSnapshot;
+    use crate::session::{BufferSnapshot, SessionEndHook};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::oneshot;
 
     #[tokio::test]
     async fn start_then_stop_session() {
@@ -169,10 +644,11 @@ mod tests {
             cursor_line: 1,
             cursor_col: 0,
             source_path: None,
+            backend: None,
         };
 
         let started = plugin
-            .start_preview(buffer.clone())
+            .start_preview(buffer.clone(), SessionOptions::default())
             .await
             .expect("start preview");
         assert!(!started.is_empty());
@@ -183,4 +659,275 @@ mod tests {
             .expect("stop preview");
         assert!(stopped);
     }
+
+    #[tokio::test]
+    async fn end_hook_runs_for_stopped_and_closed_sessions() {
+        let ended: Arc<Mutex<Vec<i64>>> = Arc::default();
+        let recorded = Arc::clone(&ended);
+        let plugin = LiveMarkdownPlugin::new(ServerConfig::default()).with_session_end_hook(
+            SessionEndHook::new(move |bufnr, _| recorded.lock().expect("lock").push(bufnr)),
+        );
+        let buffer = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("# hello"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        for bufnr in [5, 6] {
+            plugin
+                .start_preview(buffer(bufnr), SessionOptions::default())
+                .await
+                .expect("start preview");
+        }
+        assert!(ended.lock().expect("lock").is_empty());
+
+        plugin
+            .start_preview(buffer(7), SessionOptions::default())
+            .await
+            .expect("start preview");
+        assert!(plugin.stop_preview(7).await.expect("stop preview"));
+        plugin.on_buf_wipeout(6).await.expect("wipeout");
+        plugin.stop_all_previews().await;
+
+        assert_eq!(*ended.lock().expect("lock"), vec![7, 6, 5]);
+    }
+
+    #[tokio::test]
+    async fn refresh_rerenders_unchanged_buffers() {
+        let plugin = LiveMarkdownPlugin::new(ServerConfig::default());
+        let buffer = BufferSnapshot {
+            bufnr: 6,
+            changedtick: 1,
+            markdown: String::from("# hello"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        plugin
+            .start_preview(buffer.clone(), SessionOptions::default())
+            .await
+            .expect("start preview");
+        let mut rx = plugin.sessions().subscribe(6).await.expect("subscribe");
+
+        assert!(plugin.on_text_changed(buffer.clone()).await.is_none());
+        plugin.refresh(buffer.clone()).await.expect("refresh");
+
+        assert!(matches!(
+            rx.recv().await.expect("refresh event").event,
+            ServerEvent::Refresh { bufnr: 6 }
+        ));
+        assert!(matches!(
+            rx.recv().await.expect("render event").event,
+            ServerEvent::RenderFull { bufnr: 6, .. }
+        ));
+        assert!(matches!(
+            plugin.refresh(BufferSnapshot { bufnr: 7, ..buffer }).await,
+            Err(PluginError::SessionNotFound { bufnr: 7 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reentering_a_buffer_renders_only_changed_content() {
+        let plugin = LiveMarkdownPlugin::new(ServerConfig::default());
+        let buffer = BufferSnapshot {
+            bufnr: 8,
+            changedtick: 1,
+            markdown: String::from("# hello"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        plugin
+            .start_preview(buffer.clone(), SessionOptions::default())
+            .await
+            .expect("start preview");
+        let mut rx = plugin.sessions().subscribe(8).await.expect("subscribe");
+
+        plugin
+            .on_buf_enter(
+                BufferSnapshot {
+                    cursor_line: 3,
+                    ..buffer.clone()
+                },
+                None,
+            )
+            .await;
+        assert!(matches!(
+            rx.recv().await.expect("cursor event").event,
+            ServerEvent::CursorMove { line: 3, .. }
+        ));
+
+        plugin
+            .on_buf_enter(
+                BufferSnapshot {
+                    changedtick: 2,
+                    markdown: String::from("# changed on disk"),
+                    ..buffer
+                },
+                None,
+            )
+            .await;
+        match rx.recv().await.expect("render event").event {
+            ServerEvent::RenderFull { html, .. } => assert!(html.contains("changed on disk")),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn open_link_navigates_anchors_and_resolves_local_files() {
+        let root = std::env::temp_dir().join(format!(
+            "live-markdown-open-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        fs::create_dir_all(&root).expect("create dir");
+        let note = root.join("note.md");
+        let other = root.join("other.md");
+        fs::write(&note, "# note").expect("write note");
+        fs::write(&other, "# other").expect("write other");
+
+        let plugin = LiveMarkdownPlugin::new(ServerConfig::default());
+        plugin
+            .start_preview(
+                BufferSnapshot {
+                    bufnr: 9,
+                    changedtick: 1,
+                    markdown: String::from("[other](other.md)"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(note.to_string_lossy().to_string()),
+                    backend: None,
+                },
+                SessionOptions::default(),
+            )
+            .await
+            .expect("start preview");
+
+        assert_eq!(
+            plugin
+                .open_link(9, LinkTarget::Anchor(String::from("note")))
+                .await,
+            OpenAction::Navigated(String::from("note"))
+        );
+        assert_eq!(
+            plugin
+                .open_link(9, LinkTarget::Path(String::from("other.md")))
+                .await,
+            OpenAction::Edit(other.canonicalize().expect("canonical path"))
+        );
+        assert_eq!(
+            plugin
+                .open_link(9, LinkTarget::Path(String::from("missing.md")))
+                .await,
+            OpenAction::Unresolved(String::from("missing.md"))
+        );
+        assert_eq!(
+            plugin
+                .open_link(9, LinkTarget::Url(String::from("https://example.com")))
+                .await,
+            OpenAction::External(String::from("https://example.com"))
+        );
+
+        plugin.shutdown().await;
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn stops_session_after_last_client_disconnects() {
+        let plugin = LiveMarkdownPlugin::new(ServerConfig {
+            stop_on_last_client_disconnect: true,
+            disconnect_grace_ms: 20,
+            ..ServerConfig::default()
+        });
+
+        plugin
+            .start_preview(
+                BufferSnapshot {
+                    bufnr: 12,
+                    changedtick: 1,
+                    markdown: String::from("# tab"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                SessionOptions::default(),
+            )
+            .await
+            .expect("start preview");
+
+        // A second preview whose browser has not connected yet.
+        plugin
+            .start_preview(
+                BufferSnapshot {
+                    bufnr: 13,
+                    changedtick: 1,
+                    markdown: String::from("# pending"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                SessionOptions::default(),
+            )
+            .await
+            .expect("start preview");
+
+        let sessions = plugin.sessions();
+        let rx = sessions.subscribe(12).await.expect("subscribe");
+        sessions.client_disconnected(12);
+        tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+        assert!(plugin.has_session(12).await);
+
+        drop(rx);
+        sessions.client_disconnected(12);
+        tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+        assert!(!plugin.has_session(12).await);
+        assert!(plugin.has_session(13).await);
+    }
+
+    #[tokio::test]
+    async fn supervisor_stops_sessions_for_wiped_buffers() {
+        let plugin = LiveMarkdownPlugin::new(ServerConfig {
+            session_check_ms: 20,
+            ..ServerConfig::default()
+        });
+        for bufnr in [3, 4] {
+            plugin
+                .start_preview(
+                    BufferSnapshot {
+                        bufnr,
+                        changedtick: 1,
+                        markdown: String::from("# kept"),
+                        cursor_line: 1,
+                        cursor_col: 0,
+                        source_path: None,
+                        backend: None,
+                    },
+                    SessionOptions::default(),
+                )
+                .await
+                .expect("start preview");
+        }
+
+        tokio::spawn(plugin.clone().supervise_sessions(Box::new(|| {
+            let (sender, receiver) = oneshot::channel();
+            let _ = sender.send(HashSet::from([3]));
+            receiver
+        })));
+        tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+
+        assert!(plugin.has_session(3).await);
+        assert!(!plugin.has_session(4).await);
+        plugin.shutdown().await;
+    }
 }
@@ -0,0 +1,197 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Url(String),
+    Anchor(String),
+    Path(String),
+}
+
+impl LinkTarget {
+    fn classify(destination: &str) -> Option<Self> {
+        let destination = destination.trim();
+        if destination.is_empty() {
+            return None;
+        }
+
+        if let Some(anchor) = destination.strip_prefix('#') {
+            return (!anchor.is_empty()).then(|| Self::Anchor(anchor.to_string()));
+        }
+
+        let lower = destination.to_ascii_lowercase();
+        if lower.starts_with("file://") || !has_scheme(destination) {
+            return Some(Self::Path(destination.to_string()));
+        }
+
+        Some(Self::Url(destination.to_string()))
+    }
+}
+
+/// Finds the link, image or autolink that contains the byte column `col`.
+pub fn link_at(line: &str, col: usize) -> Option<LinkTarget> {
+    inline_link_at(line, col)
+        .or_else(|| autolink_at(line, col))
+        .or_else(|| bare_url_at(line, col))
+        .and_then(|destination| LinkTarget::classify(&destination))
+}
+
+fn inline_link_at(line: &str, col: usize) -> Option<String> {
+    let bytes = line.as_bytes();
+    let mut search_from = 0;
+
+    while let Some(offset) = line[search_from..].find("](") {
+        let close_bracket = search_from + offset;
+        let dest_start = close_bracket + 2;
+        let Some(dest_len) = destination_len(&line[dest_start..]) else {
+            search_from = dest_start;
+            continue;
+        };
+        let end = dest_start + dest_len + 1;
+
+        let mut start = matching_open_bracket(bytes, close_bracket).unwrap_or(close_bracket);
+        if start > 0 && bytes[start - 1] == b'!' {
+            start -= 1;
+        }
+
+        if (start..end).contains(&col) {
+            return Some(strip_title(&line[dest_start..dest_start + dest_len]));
+        }
+        search_from = end;
+    }
+
+    None
+}
+
+fn destination_len(rest: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (idx, ch) in rest.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(idx),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn matching_open_bracket(bytes: &[u8], close: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for idx in (0..close).rev() {
+        match bytes[idx] {
+            b']' => depth += 1,
+            b'[' if depth == 0 => return Some(idx),
+            b'[' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn strip_title(destination: &str) -> String {
+    let destination = destination.trim();
+    if let Some(rest) = destination.strip_prefix('<')
+        && let Some(end) = rest.find('>')
+    {
+        return rest[..end].to_string();
+    }
+
+    destination
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn autolink_at(line: &str, col: usize) -> Option<String> {
+    let mut search_from = 0;
+    while let Some(offset) = line[search_from..].find('<') {
+        let start = search_from + offset;
+        let len = line[start + 1..].find('>')?;
+        let end = start + len + 2;
+        let inner = &line[start + 1..end - 1];
+
+        if (start..end).contains(&col) && has_scheme(inner) && !inner.contains(' ') {
+            return Some(inner.to_string());
+        }
+        search_from = start + 1;
+    }
+
+    None
+}
+
+fn bare_url_at(line: &str, col: usize) -> Option<String> {
+    let mut search_from = 0;
+    while let Some(offset) = ["https://", "http://"]
+        .iter()
+        .filter_map(|prefix| line[search_from..].find(prefix))
+        .min()
+    {
+        let start = search_from + offset;
+        let len = line[start..]
+            .find(|ch: char| ch.is_whitespace() || matches!(ch, '<' | '>' | '"' | '`'))
+            .unwrap_or(line.len() - start);
+        let url = line[start..start + len].trim_end_matches(['.', ',', ';', ':', ')', '!', '?']);
+
+        if (start..start + len).contains(&col) {
+            return Some(url.to_string());
+        }
+        search_from = start + len.max(1);
+    }
+
+    None
+}
+
+fn has_scheme(value: &str) -> bool {
+    let Some((scheme, _)) = value.split_once(':') else {
+        return false;
+    };
+
+    scheme.len() > 1
+        && scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkTarget, link_at};
+
+    #[test]
+    fn finds_inline_links_and_images_under_cursor() {
+        let line = "See [the docs](https://example.com/a_(b) \"Title\") and ![logo](img/logo.png).";
+
+        assert_eq!(
+            link_at(line, 6),
+            Some(LinkTarget::Url(String::from("https://example.com/a_(b)")))
+        );
+        assert_eq!(
+            link_at(line, 56),
+            Some(LinkTarget::Path(String::from("img/logo.png")))
+        );
+        assert_eq!(link_at(line, 1), None);
+        assert_eq!(
+            link_at("[up](#install-guide)", 0),
+            Some(LinkTarget::Anchor(String::from("install-guide")))
+        );
+        assert_eq!(
+            link_at("[x](<my file.md> 'a')", 3),
+            Some(LinkTarget::Path(String::from("my file.md")))
+        );
+    }
+
+    #[test]
+    fn finds_autolinks_and_bare_urls() {
+        assert_eq!(
+            link_at("mail <mailto:me@example.com> now", 8),
+            Some(LinkTarget::Url(String::from("mailto:me@example.com")))
+        );
+        assert_eq!(
+            link_at("visit https://example.com/docs.", 12),
+            Some(LinkTarget::Url(String::from("https://example.com/docs")))
+        );
+        assert_eq!(link_at("visit https://example.com", 2), None);
+        assert_eq!(link_at("C:\\notes\\a.md", 3), None);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::render::{Backend, LiveMarkdownRenderer, SourceFormat};
+use std::collections::HashMap;
+
+const DEFAULT_FILETYPES: &[(&str, SourceFormat)] = &[
+    ("markdown", SourceFormat::Markdown),
+    ("rmd", SourceFormat::Markdown),
+    ("quarto", SourceFormat::Markdown),
+    ("pandoc", SourceFormat::Markdown),
+    ("mdx", SourceFormat::Mdx),
+    ("djot", SourceFormat::Djot),
+];
+
+/// Maps Neovim filetypes to the backend their buffers render with. Converters
+/// register under their extension too, so `rst` buffers reach an `rst` converter.
+#[derive(Debug, Clone, Default)]
+pub struct RendererRegistry {
+    backends: HashMap<String, Backend>,
+}
+
+impl RendererRegistry {
+    /// Builds the default table, then applies `overrides` (filetype -> backend
+    /// name). Overrides naming a converter that isn't configured are dropped.
+    pub fn new(overrides: &HashMap<String, String>, renderer: &LiveMarkdownRenderer) -> Self {
+        let mut backends: HashMap<String, Backend> = DEFAULT_FILETYPES
+            .iter()
+            .map(|(filetype, format)| (filetype.to_string(), Backend::Format(*format)))
+            .collect();
+        for extension in renderer.converter_extensions() {
+            backends
+                .entry(extension.to_string())
+                .or_insert_with(|| Backend::Converter(extension.to_string()));
+        }
+
+        for (filetype, name) in overrides {
+            let backend = Backend::parse(name);
+            if let Backend::Converter(converter) = &backend
+                && !renderer.has_converter(converter)
+            {
+                continue;
+            }
+            backends.insert(filetype.to_ascii_lowercase(), backend);
+        }
+
+        Self { backends }
+    }
+
+    pub fn backend_for(&self, filetype: &str) -> Option<&Backend> {
+        self.backends.get(&filetype.to_ascii_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RendererRegistry;
+    use crate::render::{
+        Backend, ConverterCommand, LiveMarkdownRenderer, RenderOptions, SourceFormat,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn maps_filetypes_to_builtin_and_converter_backends() {
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            converters: HashMap::from([(
+                String::from("adoc"),
+                ConverterCommand {
+                    program: String::from("asciidoctor"),
+                    args: Vec::new(),
+                },
+            )]),
+            ..RenderOptions::default()
+        });
+        let overrides = HashMap::from([
+            (String::from("vimwiki"), String::from("markdown")),
+            (String::from("asciidoc"), String::from("adoc")),
+            (String::from("rst"), String::from("rst")),
+        ]);
+        let registry = RendererRegistry::new(&overrides, &renderer);
+
+        assert_eq!(
+            registry.backend_for("Djot"),
+            Some(&Backend::Format(SourceFormat::Djot))
+        );
+        assert_eq!(
+            registry.backend_for("vimwiki"),
+            Some(&Backend::Format(SourceFormat::Markdown))
+        );
+        assert_eq!(
+            registry.backend_for("asciidoc"),
+            Some(&Backend::Converter(String::from("adoc")))
+        );
+        assert_eq!(
+            registry.backend_for("adoc"),
+            Some(&Backend::Converter(String::from("adoc")))
+        );
+        assert_eq!(registry.backend_for("rst"), None);
+        assert_eq!(registry.backend_for("python"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use pulldown_cmark::{Alignment, Event, Options, Parser, Tag, TagEnd};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormattedTable {
+    /// Zero-based, inclusive line range the table occupies.
+    pub start_line: usize,
+    pub end_line: usize,
+    pub lines: Vec<String>,
+}
+
+/// Reflows the table covering the zero-based `line` to aligned pipes.
+pub fn format_table_at(markdown: &str, line: usize) -> Option<FormattedTable> {
+    let mut alignments = Vec::new();
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut table_range = None;
+    let mut table_start = 0;
+
+    for (event, range) in Parser::new_ext(markdown, Options::ENABLE_TABLES).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Table(aligns)) => {
+                let start_line = line_of(markdown, range.start);
+                let end_line = line_of(markdown, range.end.saturating_sub(1));
+                if (start_line..=end_line).contains(&line) {
+                    alignments = aligns;
+                    table_range = Some((start_line, end_line));
+                    table_start = range.start;
+                }
+            }
+            Event::Start(Tag::TableHead | Tag::TableRow) if table_range.is_some() => {
+                rows.push(Vec::new());
+            }
+            Event::Start(Tag::TableCell) if table_range.is_some() => {
+                if let Some(row) = rows.last_mut() {
+                    row.push(markdown[range].trim().to_string());
+                }
+            }
+            Event::End(TagEnd::Table) if table_range.is_some() => break,
+            _ => {}
+        }
+    }
+
+    let (start_line, end_line) = table_range?;
+    // Keep what precedes the table on each line, such as the `> ` of a
+    // quote or a list item's indent.
+    let first_prefix =
+        &markdown[markdown[..table_start].rfind('\n').map_or(0, |at| at + 1)..table_start];
+    let prefixes: Vec<&str> = markdown
+        .lines()
+        .skip(start_line)
+        .take(end_line - start_line + 1)
+        .enumerate()
+        .map(|(index, line)| {
+            if index == 0 {
+                return first_prefix;
+            }
+            let container = line
+                .find(|c: char| !c.is_whitespace() && c != '>')
+                .unwrap_or(line.len())
+                .min(first_prefix.len());
+            &line[..container]
+        })
+        .collect();
+
+    Some(FormattedTable {
+        start_line,
+        end_line,
+        lines: format_rows(&rows, &alignments)
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let prefix = prefixes.get(index).or(prefixes.last()).copied();
+                format!("{}{line}", prefix.unwrap_or_default())
+            })
+            .collect(),
+    })
+}
+
+fn format_rows(rows: &[Vec<String>], alignments: &[Alignment]) -> Vec<String> {
+    let columns = alignments.len();
+    let mut widths = vec![3; columns];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (index, row) in rows.iter().enumerate() {
+        let cells = (0..columns).map(|column| {
+            let cell = row.get(column).map_or("", String::as_str);
+            pad_cell(cell, widths[column], alignments[column])
+        });
+        lines.push(join_row(cells));
+
+        if index == 0 {
+            let delimiters = alignments
+                .iter()
+                .zip(&widths)
+                .map(|(alignment, width)| delimiter_cell(*alignment, *width));
+            lines.push(join_row(delimiters));
+        }
+    }
+
+    lines
+}
+
+fn pad_cell(cell: &str, width: usize, alignment: Alignment) -> String {
+    let padding = width.saturating_sub(cell.chars().count());
+    let (left, right) = match alignment {
+        Alignment::Right => (padding, 0),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+        Alignment::None | Alignment::Left => (0, padding),
+    };
+
+    format!("{}{cell}{}", " ".repeat(left), " ".repeat(right))
+}
+
+fn delimiter_cell(alignment: Alignment, width: usize) -> String {
+    match alignment {
+        Alignment::None => "-".repeat(width),
+        Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+    }
+}
+
+fn join_row(cells: impl Iterator<Item = String>) -> String {
+    let mut line = String::from("|");
+    for cell in cells {
+        line.push(' ');
+        line.push_str(&cell);
+        line.push_str(" |");
+    }
+    line
+}
+
+fn line_of(markdown: &str, offset: usize) -> usize {
+    markdown[..offset.min(markdown.len())]
+        .bytes()
+        .filter(|byte| *byte == b'\n')
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_table_at;
+
+    #[test]
+    fn reflows_table_under_cursor_with_alignments() {
+        let markdown =
+            "intro\n\n|Name|Qty|Note|\n|:-|-:|:-:|\n|apple|3|crisp \\| sweet|\n|kiwi|12\n\nafter";
+
+        let table = format_table_at(markdown, 4).expect("table");
+
+        assert_eq!(table.start_line, 2);
+        assert_eq!(table.end_line, 5);
+        assert_eq!(
+            table.lines,
+            vec![
+                "| Name  | Qty |      Note      |",
+                "| :---- | --: | :------------: |",
+                "| apple |   3 | crisp \\| sweet |",
+                "| kiwi  |  12 |                |",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_quote_and_list_markers_of_nested_tables() {
+        let quoted = "> intro\n>\n> |a|b|\n> |-|-|\n> |1|22|\n";
+        assert_eq!(
+            format_table_at(quoted, 3).expect("table").lines,
+            vec!["> | a   | b   |", "> | --- | --- |", "> | 1   | 22  |"]
+        );
+
+        let listed = "- item\n\n  |a|b|\n  |-|-|\n";
+        assert_eq!(
+            format_table_at(listed, 2).expect("table").lines,
+            vec!["  | a   | b   |", "  | --- | --- |"]
+        );
+    }
+
+    #[test]
+    fn ignores_lines_outside_tables() {
+        let markdown = "| a | b |\n| - | - |\n| 1 | 2 |\n\ntext";
+
+        assert!(format_table_at(markdown, 4).is_none());
+        assert_eq!(
+            format_table_at(markdown, 0).expect("table").lines[1],
+            "| --- | --- |"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::render::Heading;
+
+pub const TOC_START: &str = "<!-- toc -->";
+pub const TOC_STOP: &str = "<!-- tocstop -->";
+
+/// Builds the marker-wrapped table of contents, indented relative to the
+/// shallowest heading.
+pub fn toc_lines(headings: &[Heading]) -> Vec<String> {
+    let base = headings.iter().map(|h| h.level).min().unwrap_or(1);
+
+    let mut lines = Vec::with_capacity(headings.len() + 2);
+    lines.push(String::from(TOC_START));
+    lines.push(String::new());
+    for heading in headings {
+        let indent = "  ".repeat(usize::from(heading.level - base));
+        lines.push(format!(
+            "{indent}- [{}](#{})",
+            escape_link_text(&heading.text),
+            heading.id
+        ));
+    }
+    lines.push(String::new());
+    lines.push(String::from(TOC_STOP));
+    lines
+}
+
+/// Returns the zero-based, inclusive line range of an existing TOC block.
+pub fn toc_range<S: AsRef<str>>(lines: &[S]) -> Option<(usize, usize)> {
+    let start = lines
+        .iter()
+        .position(|line| line.as_ref().trim() == TOC_START)?;
+    let stop = lines[start + 1..]
+        .iter()
+        .position(|line| line.as_ref().trim() == TOC_STOP)?;
+
+    Some((start, start + 1 + stop))
+}
+
+fn escape_link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{toc_lines, toc_range};
+    use crate::render::{LiveMarkdownRenderer, RenderOptions, RenderProfile};
+
+    #[test]
+    fn builds_nested_toc_with_active_slugs() {
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            profile: Some(RenderProfile::Github),
+            ..RenderOptions::default()
+        });
+        let headings =
+            renderer.headings("## Setup & Install\n\n### Use [brackets]\n\n## Setup & Install");
+
+        assert_eq!(
+            toc_lines(&headings),
+            vec![
+                "<!-- toc -->",
+                "",
+                "- [Setup & Install](#setup--install)",
+                "  - [Use \\[brackets\\]](#use-brackets)",
+                "- [Setup & Install](#setup--install-1)",
+                "",
+                "<!-- tocstop -->",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_existing_toc_markers() {
+        let lines = [
+            "# Title",
+            "<!-- toc -->",
+            "- [Old](#old)",
+            " <!-- tocstop -->",
+        ];
+        assert_eq!(toc_range(&lines), Some((1, 3)));
+        assert_eq!(toc_range(&["<!-- toc -->", "no stop"]), None);
+    }
+}
@@ -0,0 +1,122 @@
+/// The process listening on a TCP port, as far as the platform lets us see.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub command: String,
+}
+
+/// Looks up who listens on `port`. Blocks on the filesystem or on `lsof`;
+/// `None` when nobody does or the owner is hidden from this user.
+pub fn port_owner(port: u16) -> Option<PortOwner> {
+    if cfg!(target_os = "linux") {
+        proc_owner(port)
+    } else {
+        lsof_owner(port)
+    }
+}
+
+/// Finds the listening socket's inode in `/proc/net/tcp{,6}`, then the
+/// process holding a descriptor to it.
+fn proc_owner(port: u16) -> Option<PortOwner> {
+    let inodes: Vec<u64> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .into_iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .flat_map(|table| listening_inodes(&table, port))
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let holds_socket = fds.flatten().any(|fd| {
+            std::fs::read_link(fd.path()).is_ok_and(|target| {
+                inodes
+                    .iter()
+                    .any(|inode| target.to_string_lossy() == format!("socket:[{inode}]"))
+            })
+        });
+        if holds_socket {
+            let command = std::fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+            return Some(PortOwner {
+                pid,
+                command: command.trim().to_string(),
+            });
+        }
+    }
+    None
+}
+
+fn lsof_owner(port: u16) -> Option<PortOwner> {
+    let output = std::process::Command::new("lsof")
+        .args(["-nP", &format!("-iTCP:{port}"), "-sTCP:LISTEN", "-Fpc"])
+        .output()
+        .ok()?;
+    parse_lsof(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Inodes of the sockets in a `/proc/net/tcp` table listening on `port`.
+fn listening_inodes(table: &str, port: u16) -> Vec<u64> {
+    const LISTEN: &str = "0A";
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local_port = fields.get(1)?.rsplit_once(':')?.1;
+            if u16::from_str_radix(local_port, 16).ok()? != port || *fields.get(3)? != LISTEN {
+                return None;
+            }
+            fields.get(9)?.parse().ok()
+        })
+        .collect()
+}
+
+/// The first process in `lsof -Fpc` output: a `p<pid>` line followed by its
+/// `c<command>` line.
+fn parse_lsof(output: &str) -> Option<PortOwner> {
+    let mut lines = output.lines();
+    let pid = lines
+        .find_map(|line| line.strip_prefix('p'))?
+        .parse()
+        .ok()?;
+    let command = lines
+        .find_map(|line| line.strip_prefix('c'))
+        .unwrap_or_default()
+        .to_string();
+    Some(PortOwner { pid, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PortOwner, listening_inodes, parse_lsof};
+
+    #[test]
+    fn reads_listeners_from_proc_and_lsof_output() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1913 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 48213 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1913 0100007F:A2C4 01 00000000:00000000 00:00000000 00000000  1000        0 48977 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1207 1 0000000000000000 100 0 0 10 0
+";
+        assert_eq!(listening_inodes(table, 6419), vec![48213]);
+        assert_eq!(listening_inodes(table, 8080), Vec::<u64>::new());
+
+        assert_eq!(
+            parse_lsof("p4242\ncnvim\nf12\n"),
+            Some(PortOwner {
+                pid: 4242,
+                command: String::from("nvim"),
+            })
+        );
+        assert_eq!(parse_lsof(""), None);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+const POSITIONS_FILE: &str = "positions.json";
+const MAX_POSITIONS: usize = 200;
+
+/// Serializes writers within the process: every write stages the same file.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The source line a reader last had at the top of the preview.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct ReadingPosition {
+    path: String,
+    line: usize,
+}
+
+fn load_all(state_dir: &Path) -> Vec<ReadingPosition> {
+    fs::read_to_string(state_dir.join(POSITIONS_FILE))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// The last reading position stored for `path` in `state_dir`, if any.
+pub fn load(state_dir: &Path, path: &str) -> Option<usize> {
+    load_all(state_dir)
+        .into_iter()
+        .find(|entry| entry.path == path)
+        .map(|entry| entry.line)
+}
+
+/// Stores `line` as the reading position of `path`, keeping the most recently
+/// read files past the cap. Like the recent list, the file is replaced
+/// atomically.
+pub fn record(state_dir: &Path, path: &str, line: usize) -> io::Result<()> {
+    let _guard = WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let mut positions = load_all(state_dir);
+    if positions
+        .first()
+        .is_some_and(|entry| entry.path == path && entry.line == line)
+    {
+        return Ok(());
+    }
+    positions.retain(|entry| entry.path != path);
+    positions.insert(
+        0,
+        ReadingPosition {
+            path: path.to_string(),
+            line,
+        },
+    );
+    positions.truncate(MAX_POSITIONS);
+
+    fs::create_dir_all(state_dir)?;
+    let contents = serde_json::to_string(&positions).map_err(io::Error::other)?;
+    let staged = state_dir.join(format!("{POSITIONS_FILE}.{}", std::process::id()));
+    fs::write(&staged, contents)?;
+    fs::rename(staged, state_dir.join(POSITIONS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_POSITIONS, load, record};
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn remembers_the_latest_line_per_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "live-markdown-positions-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        assert_eq!(load(&dir, "/notes/a.md"), None);
+
+        record(&dir, "/notes/a.md", 12).expect("record");
+        record(&dir, "/notes/b.md", 3).expect("record");
+        record(&dir, "/notes/a.md", 40).expect("record");
+        assert_eq!(load(&dir, "/notes/a.md"), Some(40));
+        assert_eq!(load(&dir, "/notes/b.md"), Some(3));
+
+        for index in 0..MAX_POSITIONS {
+            record(&dir, &format!("/notes/{index}.md"), index).expect("record");
+        }
+        assert_eq!(load(&dir, "/notes/b.md"), None);
+        assert_eq!(load(&dir, "/notes/7.md"), Some(7));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
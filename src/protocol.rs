@@ -1,16 +1,357 @@
+use crate::render::{TaskProgress, Truncation};
+use crate::spell::Typo;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct SessionQuery {
     pub buf: i64,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotQuery {
+    pub buf: i64,
+    /// The page's URL fragment, without the `#`.
+    #[serde(default)]
+    pub fragment: Option<String>,
+}
+
+/// Why a session ended. `session_end` events carry it flattened, as
+/// `reason` plus the `message` of an error.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
+#[serde(tag = "reason", rename_all = "snake_case")]
 pub enum SessionEndReason {
     Stopped,
     BufferClosed,
-    Error,
+    Expired,
+    /// The editor quit or `setup()` ran again.
+    ShutDown,
+    Error {
+        message: String,
+    },
+}
+
+impl SessionEndReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::BufferClosed => "buffer_closed",
+            Self::Expired => "expired",
+            Self::ShutDown => "shut_down",
+            Self::Error { .. } => "error",
+        }
+    }
+
+    /// What to tell whoever is looking at the preview.
+    pub fn detail(&self) -> String {
+        match self {
+            Self::Stopped => String::from("the preview was stopped from the editor"),
+            Self::BufferClosed => String::from("its buffer was closed"),
+            Self::Expired => String::from("the share link expired"),
+            Self::ShutDown => String::from("the plugin shut down"),
+            Self::Error { message } => format!("the preview failed: {message}"),
+        }
+    }
+}
+
+/// How the preview moves to follow the cursor.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollBehavior {
+    #[default]
+    Smooth,
+    Instant,
+}
+
+impl ScrollBehavior {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "smooth" => Some(Self::Smooth),
+            "instant" | "auto" => Some(Self::Instant),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Smooth => "smooth",
+            Self::Instant => "instant",
+        }
+    }
+}
+
+/// What the preview keeps in sync with the editor window while scrolling.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollAnchor {
+    /// Keep the cursor's block inside the comfort band.
+    #[default]
+    Cursor,
+    /// Keep the editor's first visible line at the top of the preview.
+    Viewport,
+}
+
+impl ScrollAnchor {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "cursor" => Some(Self::Cursor),
+            "viewport" | "window" => Some(Self::Viewport),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cursor => "cursor",
+            Self::Viewport => "viewport",
+        }
+    }
+}
+
+/// How far a preview's buffer-follow reaches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FollowScope {
+    /// Any buffer entered anywhere takes over the preview.
+    #[default]
+    Global,
+    /// Only buffers entered in the tabpage the preview was started from.
+    Tabpage,
+    /// Only buffers entered in the window the preview was started from.
+    Window,
+}
+
+impl FollowScope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "global" | "editor" => Some(Self::Global),
+            "tabpage" | "tab" => Some(Self::Tabpage),
+            "window" | "win" => Some(Self::Window),
+            _ => None,
+        }
+    }
+}
+
+/// Whether starting a preview opens it in the system browser.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenBrowser {
+    Always,
+    Never,
+    /// Only when no browser is showing the buffer's preview yet.
+    #[default]
+    IfNoClients,
+}
+
+impl OpenBrowser {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "always" | "true" => Some(Self::Always),
+            "never" | "false" => Some(Self::Never),
+            "if_no_clients" => Some(Self::IfNoClients),
+            _ => None,
+        }
+    }
+
+    pub fn should_open(self, clients: usize) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::IfNoClients => clients == 0,
+        }
+    }
+}
+
+/// How a newly started preview's URL is announced in the editor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UrlEcho {
+    /// A plain notification.
+    #[default]
+    Notify,
+    /// The notification plus an OSC 8 hyperlink written to the terminal, so
+    /// terminal emulators make the URL clickable.
+    Osc8,
+    None,
+}
+
+impl UrlEcho {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "notify" => Some(Self::Notify),
+            "osc8" | "hyperlink" => Some(Self::Osc8),
+            "none" | "off" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// The palette previews use unless a session or document picks one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+    /// The browser's `prefers-color-scheme`, until the editor's
+    /// `'background'` changes.
+    Auto,
+}
+
+impl ThemeMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "dark" => Some(Self::Dark),
+            "light" => Some(Self::Light),
+            "auto" | "system" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// The tabpage or window a scoped preview follows buffers in, by handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FollowGroup {
+    Tabpage(i64),
+    Window(i64),
+}
+
+/// The editor window's visible lines and cursor, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub topline: usize,
+    pub botline: usize,
+    pub cursor_line: usize,
+    pub cursor_col: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionOptions {
+    pub theme: Option<String>,
+    pub auto_scroll: Option<bool>,
+    /// Viewport fractions between which the cursor line may move without
+    /// scrolling; `None` keeps the configured band.
+    pub scroll_comfort_top: Option<f64>,
+    pub scroll_comfort_bottom: Option<f64>,
+    pub scroll_behavior: Option<ScrollBehavior>,
+    /// Length of a smooth scroll; 0 eases in proportion to the distance.
+    pub scroll_duration_ms: Option<u64>,
+    /// Pulls the followed line from the comfort band towards the middle of
+    /// the window, from 0 (band) to 1 (centered).
+    pub scroll_center_bias: Option<f64>,
+    pub scroll_anchor: Option<ScrollAnchor>,
+    pub interactive_tasks: bool,
+    pub follow: bool,
+    /// Directory relative assets resolve against while the buffer has no file;
+    /// kept on the server so previews never see local paths.
+    #[serde(skip)]
+    pub asset_root: Option<PathBuf>,
+    /// Where this preview follows buffers; `None` follows them everywhere.
+    #[serde(skip)]
+    pub follow_group: Option<FollowGroup>,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            auto_scroll: None,
+            scroll_comfort_top: None,
+            scroll_comfort_bottom: None,
+            scroll_behavior: None,
+            scroll_duration_ms: None,
+            scroll_center_bias: None,
+            scroll_anchor: None,
+            interactive_tasks: false,
+            follow: true,
+            asset_root: None,
+            follow_group: None,
+        }
+    }
+}
+
+impl SessionOptions {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let flag = || match value.to_ascii_lowercase().as_str() {
+            "true" | "on" | "yes" | "1" => Ok(true),
+            "false" | "off" | "no" | "0" => Ok(false),
+            _ => Err(format!("invalid boolean for {key}: {value}")),
+        };
+
+        match key.replace('-', "_").as_str() {
+            "theme" => {
+                self.theme = match value {
+                    "dark" | "light" | "auto" => Some(value.to_string()),
+                    "default" => None,
+                    _ => return Err(format!("invalid theme: {value}")),
+                };
+            }
+            "auto_scroll" => self.auto_scroll = Some(flag()?),
+            "scroll_band" => {
+                let band = parse_scroll_band(value)?;
+                self.scroll_comfort_top = band.map(|(top, _)| top);
+                self.scroll_comfort_bottom = band.map(|(_, bottom)| bottom);
+            }
+            "scroll_behavior" => {
+                self.scroll_behavior = Some(
+                    ScrollBehavior::parse(value)
+                        .ok_or_else(|| format!("invalid scroll behavior: {value}"))?,
+                );
+            }
+            "scroll_duration" | "scroll_duration_ms" => {
+                let duration = value
+                    .trim_end_matches("ms")
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|ms| *ms <= MAX_SCROLL_DURATION_MS)
+                    .ok_or_else(|| format!("invalid scroll duration: {value}"))?;
+                self.scroll_duration_ms = Some(duration);
+            }
+            "center_bias" | "scroll_center_bias" => {
+                let bias = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|bias| (0.0..=1.0).contains(bias))
+                    .ok_or_else(|| format!("invalid center bias (expected 0..1): {value}"))?;
+                self.scroll_center_bias = Some(bias);
+            }
+            "scroll_anchor" => {
+                self.scroll_anchor = Some(
+                    ScrollAnchor::parse(value)
+                        .ok_or_else(|| format!("invalid scroll anchor: {value}"))?,
+                );
+            }
+            "interactive_tasks" => self.interactive_tasks = flag()?,
+            "follow" => self.follow = flag()?,
+            "asset_root" => {
+                self.asset_root = (!value.is_empty()).then(|| PathBuf::from(value));
+            }
+            _ => return Err(format!("unknown session option: {key}")),
+        }
+
+        Ok(())
+    }
+}
+
+/// Longer animations would still be catching up with the previous cursor move.
+pub const MAX_SCROLL_DURATION_MS: u64 = 2000;
+
+/// Parses `top,bottom` (or `top bottom`) viewport fractions; `default` clears the band.
+fn parse_scroll_band(value: &str) -> Result<Option<(f64, f64)>, String> {
+    if matches!(value.trim(), "" | "default") {
+        return Ok(None);
+    }
+
+    let invalid = || format!("invalid scroll band (expected top,bottom in 0..1): {value}");
+    let mut parts = value
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f64>().map_err(|_| invalid()));
+    let (Some(top), Some(bottom), None) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let (top, bottom) = (top?, bottom?);
+    if !(0.0..1.0).contains(&top) || !(0.0..=1.0).contains(&bottom) || top >= bottom {
+        return Err(invalid());
+    }
+
+    Ok(Some((top, bottom)))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +361,165 @@ pub struct SnapshotResponse {
     pub cursor_line: usize,
     pub cursor_col: usize,
     pub filename: String,
+    pub title: String,
+    pub tasks: TaskProgress,
+    pub options: SessionOptions,
+    /// The session is alive but its buffer is no longer the one being followed.
+    pub paused: bool,
+    pub typos: Vec<Typo>,
+    pub bookmarks: Vec<Bookmark>,
+    pub comments: Vec<Comment>,
+    /// The number of the last event reflected here; later events carry
+    /// larger numbers as their SSE `id`.
+    pub seq: u64,
+    /// Source line of the heading named by the request's `fragment`, so a
+    /// client opening `#some-heading` can move the editor there as well.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fragment_line: Option<usize>,
+    /// Names the session's file across server restarts, where buffer
+    /// numbers change; `/resume?token=` finds its session again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResumeQuery {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub bufnr: i64,
+    pub filename: String,
+    pub clients: usize,
+    pub render: RenderStats,
+    /// Frontmatter `tags` of the document.
+    pub tags: Vec<String>,
+}
+
+/// A previewed file, newest first in `/recent`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecentFile {
+    pub path: String,
+    /// Unix time, in seconds, the file was last previewed.
+    pub opened_at: u64,
+}
+
+/// Where a reader last was in a file, from `/position`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReadingPositionResponse {
+    pub line: Option<usize>,
+}
+
+/// A frontmatter tag and the running previews whose documents carry it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TagEntry {
+    pub tag: String,
+    pub sessions: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionsQuery {
+    pub tag: Option<String>,
+}
+
+/// Render timings for a session, in microseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RenderStats {
+    pub last_us: u64,
+    pub average_us: u64,
+    pub slowest_us: u64,
+    pub renders: u64,
+}
+
+impl RenderStats {
+    pub fn record(&mut self, elapsed: std::time::Duration) {
+        let elapsed_us = elapsed.as_micros().min(u128::from(u64::MAX)) as u64;
+        let total = u128::from(self.average_us) * u128::from(self.renders) + u128::from(elapsed_us);
+
+        self.renders += 1;
+        self.last_us = elapsed_us;
+        self.slowest_us = self.slowest_us.max(elapsed_us);
+        self.average_us = (total / u128::from(self.renders)) as u64;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccessEntry {
+    pub path: String,
+    pub client: Option<String>,
+    pub remote: Option<String>,
+    pub user_agent: Option<String>,
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub bufnr: i64,
+    pub client: Option<String>,
+    pub shared: bool,
+    pub remote: Option<String>,
+    pub user_agent: Option<String>,
+    pub connected_at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionsResponse {
+    pub connections: Vec<ConnectionInfo>,
+    pub recent: Vec<AccessEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PresenceUpdate {
+    pub client: String,
+    pub line: usize,
+}
+
+/// A block of the document a reader marked from the preview.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bookmark {
+    pub line: usize,
+    /// The start of the block's text, to tell bookmarks apart in a picker.
+    pub text: String,
+}
+
+/// Adds the bookmark at `line`, or removes it with `remove`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarkUpdate {
+    pub line: usize,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub remove: bool,
+}
+
+/// A review comment a reader attached to a block of the document.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Comment {
+    pub id: u64,
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CommentAction {
+    Add { line: usize, text: String },
+    Resolve { id: u64 },
+}
+
+/// A client stopping or resuming to follow the editor's cursor.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetachUpdate {
+    pub client: String,
+    pub detached: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ViewerPosition {
+    pub client: String,
+    pub line: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,22 +535,141 @@ pub enum ServerEvent {
         line: usize,
         col: usize,
     },
+    Viewport {
+        bufnr: i64,
+        topline: usize,
+        botline: usize,
+        cursor_line: usize,
+        cursor_col: usize,
+    },
     SessionEnd {
         bufnr: i64,
+        #[serde(flatten)]
         reason: SessionEndReason,
+        /// `reason` in words; missing from recordings made before it existed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
     },
     Heartbeat {
         bufnr: i64,
     },
+    /// A pinned session stopped being the followed buffer; it keeps its last render.
+    SessionPaused {
+        bufnr: i64,
+    },
+    SessionResumed {
+        bufnr: i64,
+    },
+    TitleChanged {
+        bufnr: i64,
+        filename: String,
+        title: String,
+    },
+    Navigate {
+        bufnr: i64,
+        anchor: String,
+    },
+    Refresh {
+        bufnr: i64,
+    },
+    /// The session's share link moved to `url`; the old link no longer
+    /// accepts new connections.
+    Reconnect {
+        bufnr: i64,
+        url: String,
+    },
+    /// The server is going away with the editor or a new `setup()`, and
+    /// may come back. Clients retry after `retry_ms`, doubling the wait
+    /// each time, and find their document again by its resume token.
+    Gone {
+        bufnr: i64,
+        retry_ms: u64,
+    },
+    /// A local image changed on disk; `path` is the image target as written.
+    AssetInvalidated {
+        bufnr: i64,
+        path: String,
+    },
+    OptionsChanged {
+        bufnr: i64,
+        options: SessionOptions,
+    },
+    Sessions {
+        bufnr: i64,
+        sessions: Vec<SessionSummary>,
+    },
+    Presence {
+        bufnr: i64,
+        viewers: Vec<ViewerPosition>,
+    },
+    RenderStats {
+        bufnr: i64,
+        stats: RenderStats,
+    },
+    RenderTruncated {
+        bufnr: i64,
+        reason: Truncation,
+    },
+    /// The editor's `'background'` changed to `theme` (`dark` or `light`).
+    ThemeChanged {
+        bufnr: i64,
+        theme: String,
+    },
+    /// Unknown words in the rendered document; replaces the previous list.
+    Spelling {
+        bufnr: i64,
+        typos: Vec<Typo>,
+    },
+    /// The editor's colorscheme changed; `/theme.css` serves the new palette.
+    PaletteChanged {
+        bufnr: i64,
+    },
+    /// The session's bookmarks, sorted by line; replaces the previous list.
+    Bookmarks {
+        bufnr: i64,
+        bookmarks: Vec<Bookmark>,
+    },
+    /// The session's open review comments; replaces the previous list.
+    Comments {
+        bufnr: i64,
+        comments: Vec<Comment>,
+    },
 }
 
 impl ServerEvent {
+    pub fn session_end(bufnr: i64, reason: SessionEndReason) -> Self {
+        Self::SessionEnd {
+            bufnr,
+            detail: Some(reason.detail()),
+            reason,
+        }
+    }
+
     pub fn event_name(&self) -> &'static str {
         match self {
             Self::RenderFull { .. } => "render_full",
             Self::CursorMove { .. } => "cursor_move",
+            Self::Viewport { .. } => "viewport",
             Self::SessionEnd { .. } => "session_end",
             Self::Heartbeat { .. } => "heartbeat",
+            Self::SessionPaused { .. } => "session_paused",
+            Self::SessionResumed { .. } => "session_resumed",
+            Self::TitleChanged { .. } => "title_changed",
+            Self::Navigate { .. } => "navigate",
+            Self::Refresh { .. } => "refresh",
+            Self::Reconnect { .. } => "reconnect",
+            Self::Gone { .. } => "gone",
+            Self::AssetInvalidated { .. } => "asset_invalidated",
+            Self::OptionsChanged { .. } => "options_changed",
+            Self::Sessions { .. } => "sessions",
+            Self::Presence { .. } => "presence",
+            Self::RenderStats { .. } => "render_stats",
+            Self::RenderTruncated { .. } => "render_truncated",
+            Self::Spelling { .. } => "spelling",
+            Self::ThemeChanged { .. } => "theme_changed",
+            Self::PaletteChanged { .. } => "palette_changed",
+            Self::Bookmarks { .. } => "bookmarks",
+            Self::Comments { .. } => "comments",
         }
     }
 
@@ -58,8 +677,161 @@ impl ServerEvent {
         match self {
             Self::RenderFull { bufnr, .. } => *bufnr,
             Self::CursorMove { bufnr, .. } => *bufnr,
+            Self::Viewport { bufnr, .. } => *bufnr,
             Self::SessionEnd { bufnr, .. } => *bufnr,
             Self::Heartbeat { bufnr } => *bufnr,
+            Self::SessionPaused { bufnr } => *bufnr,
+            Self::SessionResumed { bufnr } => *bufnr,
+            Self::TitleChanged { bufnr, .. } => *bufnr,
+            Self::Navigate { bufnr, .. } => *bufnr,
+            Self::Refresh { bufnr } => *bufnr,
+            Self::Reconnect { bufnr, .. } => *bufnr,
+            Self::Gone { bufnr, .. } => *bufnr,
+            Self::AssetInvalidated { bufnr, .. } => *bufnr,
+            Self::OptionsChanged { bufnr, .. } => *bufnr,
+            Self::Sessions { bufnr, .. } => *bufnr,
+            Self::Presence { bufnr, .. } => *bufnr,
+            Self::RenderStats { bufnr, .. } => *bufnr,
+            Self::RenderTruncated { bufnr, .. } => *bufnr,
+            Self::Spelling { bufnr, .. } => *bufnr,
+            Self::ThemeChanged { bufnr, .. } => *bufnr,
+            Self::PaletteChanged { bufnr } => *bufnr,
+            Self::Bookmarks { bufnr, .. } => *bufnr,
+            Self::Comments { bufnr, .. } => *bufnr,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        OpenBrowser, RenderStats, ScrollAnchor, ScrollBehavior, ServerEvent, SessionEndReason,
+        SessionOptions,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn session_end_events_say_why_in_words() {
+        let end = ServerEvent::session_end(
+            4,
+            SessionEndReason::Error {
+                message: String::from("connection reset"),
+            },
+        );
+        let json = serde_json::to_value(&end).expect("serialize");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "session_end",
+                "bufnr": 4,
+                "reason": "error",
+                "message": "connection reset",
+                "detail": "the preview failed: connection reset",
+            })
+        );
+
+        let recorded: ServerEvent =
+            serde_json::from_str(r#"{"type":"session_end","bufnr":2,"reason":"buffer_closed"}"#)
+                .expect("deserialize");
+        assert!(matches!(
+            recorded,
+            ServerEvent::SessionEnd {
+                reason: SessionEndReason::BufferClosed,
+                detail: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn session_options_parse_key_value_updates() {
+        let mut options = SessionOptions::default();
+
+        options.set("theme", "light").expect("theme");
+        options.set("auto-scroll", "off").expect("auto_scroll");
+        options.set("follow", "false").expect("follow");
+
+        assert_eq!(options.theme.as_deref(), Some("light"));
+        assert_eq!(options.auto_scroll, Some(false));
+        assert!(!options.follow);
+        assert!(options.set("theme", "neon").is_err());
+        options.set("theme", "auto").expect("auto theme");
+        assert_eq!(options.theme.as_deref(), Some("auto"));
+        options.set("theme", "default").expect("default theme");
+        assert_eq!(options.theme, None);
+        assert!(options.set("colour", "red").is_err());
+    }
+
+    #[test]
+    fn scroll_band_sets_both_edges_or_resets_them() {
+        let mut options = SessionOptions::default();
+
+        options
+            .set("scroll_band", "0.1,0.5")
+            .expect("comma separated");
+        assert_eq!(options.scroll_comfort_top, Some(0.1));
+        assert_eq!(options.scroll_comfort_bottom, Some(0.5));
+        options
+            .set("scroll-band", "0.3 0.9")
+            .expect("space separated");
+        assert_eq!(options.scroll_comfort_top, Some(0.3));
+
+        assert!(options.set("scroll_band", "0.7,0.2").is_err());
+        assert!(options.set("scroll_band", "0.2").is_err());
+        assert!(options.set("scroll_band", "0.2,1.5").is_err());
+        assert_eq!(options.scroll_comfort_bottom, Some(0.9));
+
+        options.set("scroll_band", "default").expect("reset");
+        assert_eq!(options.scroll_comfort_top, None);
+        assert_eq!(options.scroll_comfort_bottom, None);
+    }
+
+    #[test]
+    fn open_browser_policy_respects_connected_clients() {
+        let policy = OpenBrowser::parse("if_no_clients").expect("valid policy");
+        assert!(policy.should_open(0));
+        assert!(!policy.should_open(2));
+        assert!(OpenBrowser::Always.should_open(2));
+        assert!(!OpenBrowser::Never.should_open(0));
+        assert_eq!(OpenBrowser::parse("sometimes"), None);
+    }
+
+    #[test]
+    fn scroll_animation_options_are_validated() {
+        let mut options = SessionOptions::default();
+
+        options.set("scroll_behavior", "instant").expect("behavior");
+        options.set("scroll_duration", "250ms").expect("duration");
+        options.set("center-bias", "0.5").expect("bias");
+        assert_eq!(options.scroll_behavior, Some(ScrollBehavior::Instant));
+        assert_eq!(options.scroll_duration_ms, Some(250));
+        assert_eq!(options.scroll_center_bias, Some(0.5));
+
+        assert!(options.set("scroll_behavior", "bouncy").is_err());
+        assert!(options.set("scroll_duration", "9000").is_err());
+        assert!(options.set("center_bias", "2").is_err());
+
+        options.set("scroll_anchor", "viewport").expect("anchor");
+        assert_eq!(options.scroll_anchor, Some(ScrollAnchor::Viewport));
+        assert!(options.set("scroll_anchor", "page").is_err());
+    }
+
+    #[test]
+    fn render_stats_track_last_average_and_slowest() {
+        let mut stats = RenderStats::default();
+
+        stats.record(Duration::from_micros(300));
+        stats.record(Duration::from_micros(900));
+        stats.record(Duration::from_micros(600));
+
+        assert_eq!(
+            stats,
+            RenderStats {
+                last_us: 600,
+                average_us: 600,
+                slowest_us: 900,
+                renders: 3,
+            }
+        );
+    }
+}
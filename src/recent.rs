@@ -0,0 +1,83 @@
+use crate::protocol::RecentFile;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RECENT_FILE: &str = "recent.json";
+const MAX_RECENT: usize = 20;
+
+/// Recently previewed files in `state_dir`, newest first. A missing or
+/// unreadable list is empty.
+pub fn load(state_dir: &Path) -> Vec<RecentFile> {
+    fs::read_to_string(state_dir.join(RECENT_FILE))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Moves `path` to the front of the list in `state_dir`, dropping the oldest
+/// entries past the cap. The list is replaced atomically so a concurrent
+/// reader never sees half of it.
+pub fn record(state_dir: &Path, path: &str) -> io::Result<()> {
+    let opened_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    let mut recent = load(state_dir);
+    recent.retain(|entry| entry.path != path);
+    recent.insert(
+        0,
+        RecentFile {
+            path: path.to_string(),
+            opened_at,
+        },
+    );
+    recent.truncate(MAX_RECENT);
+
+    fs::create_dir_all(state_dir)?;
+    let contents = serde_json::to_string_pretty(&recent).map_err(io::Error::other)?;
+    let staged = state_dir.join(format!("{RECENT_FILE}.{}", std::process::id()));
+    fs::write(&staged, contents)?;
+    fs::rename(staged, state_dir.join(RECENT_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_RECENT, load, record};
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn keeps_the_newest_distinct_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "live-markdown-recent-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        assert!(load(&dir).is_empty());
+
+        for index in 0..MAX_RECENT + 2 {
+            record(&dir, &format!("/notes/{index}.md")).expect("record");
+        }
+        record(&dir, "/notes/5.md").expect("record");
+
+        let recent = load(&dir);
+        assert_eq!(recent.len(), MAX_RECENT);
+        assert_eq!(recent[0].path, "/notes/5.md");
+        assert_eq!(recent[1].path, format!("/notes/{}.md", MAX_RECENT + 1));
+        assert!(recent.iter().all(|entry| entry.path != "/notes/0.md"));
+        assert_eq!(
+            recent
+                .iter()
+                .filter(|entry| entry.path == "/notes/5.md")
+                .count(),
+            1
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
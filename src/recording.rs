@@ -0,0 +1,138 @@
+use crate::protocol::ServerEvent;
+use crate::session::{SequencedEvent, SessionManager};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// One line of a recording; `at_ms` counts from the start of the recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub at_ms: u64,
+    pub event: ServerEvent,
+}
+
+/// Appends `initial` and every later event from `events` to a new JSONL file
+/// in `dir`, until the session's channel closes.
+pub fn spawn_recorder(
+    dir: &Path,
+    bufnr: i64,
+    initial: ServerEvent,
+    mut events: broadcast::Receiver<SequencedEvent>,
+) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let started_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    let path = dir.join(format!("session-{bufnr}-{started_ms}.jsonl"));
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    let started = Instant::now();
+    write_event(&mut file, started, initial)?;
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(sequenced) => sequenced.event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if write_event(&mut file, started, event).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(path)
+}
+
+fn write_event(file: &mut File, started: Instant, event: ServerEvent) -> io::Result<()> {
+    let recorded = RecordedEvent {
+        at_ms: started.elapsed().as_millis() as u64,
+        event,
+    };
+    let mut line = serde_json::to_string(&recorded).map_err(io::Error::other)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
+
+pub fn read_recording(path: &Path) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {err}", index + 1),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Sends recorded events to the session they belong to, keeping their
+/// original spacing.
+pub async fn replay(sessions: &SessionManager, events: &[RecordedEvent]) {
+    let mut previous_ms = 0;
+    for recorded in events {
+        let delay = recorded.at_ms.saturating_sub(previous_ms);
+        previous_ms = recorded.at_ms;
+        if delay > 0 {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+        sessions.replay_event(recorded.event.clone()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_recording, spawn_recorder};
+    use crate::protocol::ServerEvent;
+    use crate::session::SequencedEvent;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn records_events_until_the_session_closes() {
+        let dir = std::env::temp_dir().join(format!("live-markdown-rec-{}", std::process::id()));
+        let (sender, receiver) = broadcast::channel(8);
+        let initial = ServerEvent::RenderFull {
+            bufnr: 3,
+            html: String::from("<p>a</p>"),
+            cursor_line: 1,
+        };
+
+        let path = spawn_recorder(&dir, 3, initial, receiver).expect("recorder starts");
+        sender
+            .send(SequencedEvent {
+                seq: 1,
+                event: ServerEvent::CursorMove {
+                    bufnr: 3,
+                    line: 4,
+                    col: 0,
+                },
+            })
+            .expect("recorder subscribed");
+        drop(sender);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let events = read_recording(&path).expect("readable recording");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0].event,
+            ServerEvent::RenderFull { bufnr: 3, .. }
+        ));
+        assert!(matches!(
+            events[1].event,
+            ServerEvent::CursorMove { line: 4, .. }
+        ));
+        assert!(events[0].at_ms <= events[1].at_ms);
+    }
+}
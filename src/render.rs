@@ -1,89 +1,1043 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::ansi::{self, AnsiStyle};
+use crate::attributes::{self, Attributes};
+use crate::citation::{self, Bibliography, CITATION_URL_PREFIX};
+use crate::convert::ExternalConverter;
+use crate::djot::DjotRenderer;
+use crate::frontmatter::Frontmatter;
+use crate::sanitize::sanitize_html;
+use crate::session::has_url_scheme;
 use pulldown_cmark::{
-    BlockQuoteKind, CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag,
-    TagEnd,
+    BlockQuoteKind, BrokenLink, CodeBlockKind, CowStr, Event, HeadingLevel, LinkType,
+    MetadataBlockKind, Options, Parser, Tag, TagEnd, TextMergeWithOffset,
 };
+use serde::{Deserialize, Serialize};
+
+pub use crate::convert::ConverterCommand;
+pub use crate::frontmatter::DocumentOverrides;
+pub use crate::labels::Labels;
+
+/// Settings for [`LiveMarkdownRenderer`]; `Default` matches the plugin defaults.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Syntax extensions to enable; overrides the profile's set when given.
+    pub extensions: Option<Extensions>,
+    /// Heading id style; overrides the profile's style when given.
+    pub slug_style: Option<SlugStyle>,
+    /// Render raw HTML through the sanitizer instead of escaping it.
+    pub sanitize_html: bool,
+    pub citations: bool,
+    pub bibliography: Option<PathBuf>,
+    pub converters: HashMap<String, ConverterCommand>,
+    pub converter_timeout_ms: u64,
+    pub renderer: RendererKind,
+    pub pandoc: ConverterCommand,
+    pub profile: Option<RenderProfile>,
+    pub labels: Labels,
+    pub kbd: bool,
+    /// Mark images `loading="lazy" decoding="async"` so image-heavy documents
+    /// stay responsive; the preview loads the ones near the cursor eagerly.
+    pub lazy_images: bool,
+    /// Render a paragraph holding nothing but an image as a `<figure>`, with
+    /// the image's title, or else its alt text, as the `<figcaption>`.
+    pub figures: bool,
+    pub hardbreaks: Option<bool>,
+    pub limits: RenderLimits,
+    pub accessibility: Accessibility,
+}
+
+/// Assistive technology support in the rendered document and the preview page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accessibility {
+    /// ARIA roles on alerts and tables, and header scopes on table cells.
+    pub aria: bool,
+    /// A "skip to content" link ahead of the preview's header.
+    pub skip_link: bool,
+    /// Landmark roles and labels on the preview's header, outline and document.
+    pub landmarks: bool,
+}
+
+impl Default for Accessibility {
+    fn default() -> Self {
+        Self {
+            aria: true,
+            skip_link: true,
+            landmarks: true,
+        }
+    }
+}
+
+/// Bounds that keep pathological Markdown from exhausting memory or time;
+/// a limit of 0 disables that check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderLimits {
+    /// Source beyond this many bytes is cut at the preceding line break.
+    pub max_input_bytes: usize,
+    /// Blocks and inlines nested deeper than this are dropped.
+    pub max_nesting: usize,
+    /// Rendering stops once the HTML grows past this many bytes.
+    pub max_output_bytes: usize,
+}
+
+impl Default for RenderLimits {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: 8 * 1024 * 1024,
+            max_nesting: 64,
+            max_output_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Which [`RenderLimits`] entry cut a render short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    InputBytes,
+    Nesting,
+    OutputBytes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderProfile {
+    Github,
+    Commonmark,
+    Obsidian,
+    Pandoc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlugStyle {
+    #[default]
+    Compact,
+    Github,
+    Pandoc,
+}
+
+impl SlugStyle {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "compact" => Some(Self::Compact),
+            "github" | "gfm" => Some(Self::Github),
+            "pandoc" => Some(Self::Pandoc),
+            _ => None,
+        }
+    }
+}
+
+/// Markdown syntax extensions, independent of the underlying parser's flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extensions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    pub alerts: bool,
+    pub math: bool,
+    pub wikilinks: bool,
+    pub heading_attributes: bool,
+    pub smart_punctuation: bool,
+    pub definition_lists: bool,
+    pub superscript: bool,
+    pub subscript: bool,
+    pub metadata_blocks: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            alerts: true,
+            math: true,
+            wikilinks: true,
+            heading_attributes: true,
+            smart_punctuation: true,
+            definition_lists: true,
+            superscript: true,
+            subscript: true,
+            metadata_blocks: true,
+        }
+    }
+}
+
+impl Extensions {
+    fn options(self) -> Options {
+        let flags = [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.tasklists, Options::ENABLE_TASKLISTS),
+            (self.alerts, Options::ENABLE_GFM),
+            (self.math, Options::ENABLE_MATH),
+            (self.wikilinks, Options::ENABLE_WIKILINKS),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.definition_lists, Options::ENABLE_DEFINITION_LIST),
+            (self.superscript, Options::ENABLE_SUPERSCRIPT),
+            (self.subscript, Options::ENABLE_SUBSCRIPT),
+            (
+                self.metadata_blocks,
+                Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+                    | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS,
+            ),
+        ];
+        flags
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(Options::empty(), |options, (_, flag)| options | flag)
+    }
+}
+
+impl RenderProfile {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "github" | "gfm" => Some(Self::Github),
+            "commonmark" => Some(Self::Commonmark),
+            "obsidian" => Some(Self::Obsidian),
+            "pandoc" => Some(Self::Pandoc),
+            _ => None,
+        }
+    }
+
+    fn options(self) -> Options {
+        let github = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_GFM
+            | Options::ENABLE_MATH
+            | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+
+        match self {
+            Self::Github => github,
+            Self::Commonmark => Options::empty(),
+            Self::Obsidian => github | Options::ENABLE_WIKILINKS,
+            Self::Pandoc => {
+                Options::ENABLE_TABLES
+                    | Options::ENABLE_FOOTNOTES
+                    | Options::ENABLE_STRIKETHROUGH
+                    | Options::ENABLE_TASKLISTS
+                    | Options::ENABLE_SMART_PUNCTUATION
+                    | Options::ENABLE_HEADING_ATTRIBUTES
+                    | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+                    | Options::ENABLE_MATH
+                    | Options::ENABLE_DEFINITION_LIST
+                    | Options::ENABLE_SUPERSCRIPT
+                    | Options::ENABLE_SUBSCRIPT
+            }
+        }
+    }
+
+    fn slug_style(self) -> SlugStyle {
+        match self {
+            Self::Github | Self::Commonmark | Self::Obsidian => SlugStyle::Github,
+            Self::Pandoc => SlugStyle::Pandoc,
+        }
+    }
+
+    fn resolves_wiki_links_to_notes(self) -> bool {
+        self == Self::Obsidian
+    }
+
+    fn uses_hardbreaks(self) -> bool {
+        self == Self::Obsidian
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RendererKind {
+    #[default]
+    Builtin,
+    Pandoc,
+}
+
+impl RendererKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "builtin" => Some(Self::Builtin),
+            "pandoc" => Some(Self::Pandoc),
+            _ => None,
+        }
+    }
+}
+
+pub fn default_pandoc_command() -> ConverterCommand {
+    ConverterCommand {
+        program: String::from("pandoc"),
+        args: vec![
+            String::from("--from=commonmark_x+sourcepos"),
+            String::from("--to=html5"),
+        ],
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            extensions: None,
+            slug_style: None,
+            sanitize_html: false,
+            citations: false,
+            bibliography: None,
+            converters: HashMap::new(),
+            converter_timeout_ms: 5_000,
+            renderer: RendererKind::Builtin,
+            pandoc: default_pandoc_command(),
+            profile: None,
+            labels: Labels::default(),
+            kbd: false,
+            lazy_images: true,
+            figures: false,
+            hardbreaks: None,
+            limits: RenderLimits::default(),
+            accessibility: Accessibility::default(),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct LiveMarkdownRenderer {
     options: Options,
+    settings: RenderOptions,
+    bibliography: Arc<Bibliography>,
+    converters: Arc<HashMap<String, ExternalConverter>>,
+    pandoc: Option<ExternalConverter>,
+    slug_style: SlugStyle,
+    hardbreaks: bool,
 }
 
 impl Default for LiveMarkdownRenderer {
     fn default() -> Self {
-        let mut options = Options::empty();
-        options.insert(Options::all());
-        Self { options }
+        Self::new(RenderOptions::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SourceFormat {
+    #[default]
+    Markdown,
+    Mdx,
+    Djot,
+}
+
+impl SourceFormat {
+    pub fn from_source_path(path: Option<&str>) -> Self {
+        let extension = path
+            .map(Path::new)
+            .and_then(|path| path.extension())
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("mdx") => Self::Mdx,
+            Some("dj" | "djot") => Self::Djot,
+            _ => Self::Markdown,
+        }
+    }
+}
+
+/// The renderer a document goes through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    Format(SourceFormat),
+    /// An external converter, by the extension it is configured for.
+    Converter(String),
+}
+
+impl Backend {
+    /// Reads a builtin format name, or else the name of a converter.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Self::Format(SourceFormat::Markdown),
+            "mdx" => Self::Format(SourceFormat::Mdx),
+            "djot" | "dj" => Self::Format(SourceFormat::Djot),
+            other => Self::Converter(other.to_string()),
+        }
     }
 }
 
+pub trait RenderBackend: std::fmt::Debug + Send + Sync {
+    fn render_document(&self, source: &str) -> RenderOutput;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    pub id: String,
+    /// Source line of the heading, 1-based.
+    pub line: usize,
+    /// The id this heading would have had if an earlier heading had not
+    /// already taken it; other renderers may number duplicates differently.
+    pub collides_with: Option<String>,
+}
+
+/// A finished render. Converter and Djot backends only fill in `html`.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOutput {
+    pub html: String,
+    pub tasks: TaskProgress,
+    pub overrides: DocumentOverrides,
+    /// Headings in document order, with the ids used in `html`.
+    pub outline: Vec<Heading>,
+    pub links: Vec<Link>,
+    pub stats: DocumentStats,
+    /// Set when a [`RenderLimits`] entry cut the render short.
+    pub truncated: Option<Truncation>,
+    /// The converter's error, when `html` shows it in place of the document
+    /// or the built-in renderer stood in for a failed pandoc.
+    pub failure: Option<String>,
+}
+
+/// Counters gathered while rendering, alongside the HTML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentStats {
+    pub lines: usize,
+    pub words: usize,
+    pub headings: usize,
+    pub tasks: TaskProgress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// A fragment pointing into the same document.
+    Anchor,
+    /// A local file, such as another note.
+    File,
+    /// A local or embedded image.
+    Asset,
+    External,
+}
+
+/// A link or image found while rendering, as written in the source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub target: String,
+    pub text: String,
+    pub line: usize,
+    pub image: bool,
+}
+
+impl Link {
+    fn new(target: String, line: usize, image: bool) -> Self {
+        Self {
+            kind: link_kind(&target, image),
+            target,
+            text: String::new(),
+            line,
+            image,
+        }
+    }
+}
+
+fn link_kind(target: &str, image: bool) -> LinkKind {
+    let trimmed = target.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    if trimmed.starts_with('#') {
+        LinkKind::Anchor
+    } else if lower.starts_with("data:") {
+        LinkKind::Asset
+    } else if trimmed.starts_with("//") || (has_url_scheme(trimmed) && !lower.starts_with("file:"))
+    {
+        LinkKind::External
+    } else if image {
+        LinkKind::Asset
+    } else {
+        LinkKind::File
+    }
+}
+
+/// Scratch buffers reused across [`LiveMarkdownRenderer::render_with`] calls,
+/// so repeated renders of similar documents stop reallocating.
+#[derive(Debug, Default)]
+pub struct RenderContext {
+    output: String,
+    line_starts: Vec<usize>,
+    headings: Vec<Heading>,
+    links: Vec<Link>,
+}
+
+/// A render borrowed from a [`RenderContext`]; valid until the context is reused.
+#[derive(Debug)]
+pub struct RenderedView<'a> {
+    pub html: &'a str,
+    pub outline: &'a [Heading],
+    pub links: &'a [Link],
+    pub stats: DocumentStats,
+    pub truncated: Option<Truncation>,
+    pub overrides: DocumentOverrides,
+}
+
+#[derive(Debug, Default)]
+struct RenderState {
+    heading_index: usize,
+    skip_until: Option<TagEnd>,
+    cited_keys: Vec<String>,
+    /// The image whose alt text is being written, if any.
+    image: Option<ImageFrame>,
+    in_table_head: bool,
+    /// Task counts of every list in document order, from a pre-pass.
+    list_tasks: Vec<TaskProgress>,
+    list_index: usize,
+    tasks: TaskProgress,
+    ansi: Option<AnsiStyle>,
+    diff: Option<String>,
+    in_code_block: bool,
+    pending_text: String,
+    aria: bool,
+    lazy_images: bool,
+    attribute_blocks: bool,
+    /// Attributes for the block whose opening tag is written next.
+    block_attributes: Option<Attributes>,
+    /// Last source line of the quote or list item being opened, when it
+    /// spans more than one.
+    container_end_line: Option<usize>,
+    /// The open paragraph ends in an attribute block still to strip.
+    trailing_attributes: bool,
+    image_start: usize,
+    image_attributes: Option<Attributes>,
+    /// Caption, as HTML, of the figure the open paragraph is rendered as.
+    figure_caption: Option<String>,
+}
+
+#[derive(Debug)]
+struct ImageFrame {
+    src: String,
+    title: Option<String>,
+    /// Where the alt attribute's value starts in the output.
+    alt_start: usize,
+    /// Images nested in the alt text, whose ends are not this image's.
+    nested: usize,
+}
+
 impl LiveMarkdownRenderer {
+    pub fn new(settings: RenderOptions) -> Self {
+        let options = match (settings.extensions, settings.profile) {
+            (Some(extensions), _) => extensions.options(),
+            (None, Some(profile)) => profile.options(),
+            (None, None) => Options::all(),
+        };
+        let hardbreaks = settings
+            .hardbreaks
+            .unwrap_or_else(|| settings.profile.is_some_and(RenderProfile::uses_hardbreaks));
+        let slug_style = settings
+            .slug_style
+            .or_else(|| settings.profile.map(RenderProfile::slug_style))
+            .unwrap_or_default();
+
+        let bibliography = match settings.bibliography.as_deref() {
+            Some(path) if settings.citations => Bibliography::load(path),
+            _ => Bibliography::default(),
+        };
+
+        let timeout = Duration::from_millis(settings.converter_timeout_ms);
+        let converters = settings
+            .converters
+            .iter()
+            .map(|(extension, command)| {
+                let extension = extension.to_ascii_lowercase();
+                let converter = ExternalConverter::new(&extension, command.clone(), timeout);
+                (extension, converter)
+            })
+            .collect();
+        let pandoc = (settings.renderer == RendererKind::Pandoc)
+            .then(|| ExternalConverter::new("pandoc", settings.pandoc.clone(), timeout));
+
+        Self {
+            options,
+            settings,
+            bibliography: Arc::new(bibliography),
+            converters: Arc::new(converters),
+            pandoc,
+            slug_style,
+            hardbreaks,
+        }
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.settings.labels
+    }
+
+    pub fn has_converter(&self, extension: &str) -> bool {
+        self.converters
+            .contains_key(&extension.to_ascii_lowercase())
+    }
+
+    pub fn converter_extensions(&self) -> impl Iterator<Item = &str> {
+        self.converters.keys().map(String::as_str)
+    }
+
+    pub fn render_source(&self, source: &str, source_path: Option<&str>) -> RenderOutput {
+        self.render_backend(&self.backend_for_path(source_path), source)
+    }
+
+    /// The backend a file is rendered with when nothing else picks one: a
+    /// converter registered for its extension, else the format the extension names.
+    pub fn backend_for_path(&self, source_path: Option<&str>) -> Backend {
+        let extension = source_path
+            .and_then(|path| Path::new(path).extension())
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension {
+            Some(extension) if self.converters.contains_key(&extension) => {
+                Backend::Converter(extension)
+            }
+            _ => Backend::Format(SourceFormat::from_source_path(source_path)),
+        }
+    }
+
+    /// Renders with `backend`; a converter that isn't configured falls back to Markdown.
+    pub fn render_backend(&self, backend: &Backend, source: &str) -> RenderOutput {
+        let format = match backend {
+            Backend::Converter(name) => match self.converters.get(name) {
+                Some(converter) => return converter.render_document(source),
+                None => SourceFormat::Markdown,
+            },
+            Backend::Format(format) => *format,
+        };
+
+        if format == SourceFormat::Markdown
+            && let Some(pandoc) = &self.pandoc
+        {
+            return match pandoc.convert_to_html(source) {
+                Ok(body) => with_document_overrides(pandoc.document(&body), source),
+                Err(err) => RenderOutput {
+                    failure: Some(err),
+                    ..self.render_document_as(source, format)
+                },
+            };
+        }
+
+        self.render_document_as(source, format)
+    }
+
+    /// Headings in document order, with the same ids the preview assigns.
+    pub fn headings(&self, markdown: &str) -> Vec<Heading> {
+        collect_headings(markdown, self.options, self.slug_style)
+    }
+
     pub fn render(&self, markdown: &str) -> String {
-        let mut output = String::with_capacity(markdown.len().saturating_mul(2) + 128);
+        self.render_document(markdown).html
+    }
+
+    pub fn render_document(&self, markdown: &str) -> RenderOutput {
+        self.render_markdown(markdown, false)
+    }
+
+    pub fn render_document_as(&self, source: &str, format: SourceFormat) -> RenderOutput {
+        match format {
+            SourceFormat::Markdown => self.render_markdown(source, false),
+            SourceFormat::Mdx => self.render_markdown(source, true),
+            SourceFormat::Djot => DjotRenderer.render_document(source),
+        }
+    }
+
+    /// Renders Markdown into `ctx`, returning the HTML together with the
+    /// heading outline and document stats from the same pass.
+    pub fn render_with<'a>(&self, ctx: &'a mut RenderContext, markdown: &str) -> RenderedView<'a> {
+        let (stats, truncated) = self.render_markdown_into(ctx, markdown, false);
+        let overrides = apply_document_overrides(&mut ctx.output, markdown);
+        RenderedView {
+            html: &ctx.output,
+            outline: &ctx.headings,
+            links: &ctx.links,
+            stats,
+            truncated,
+            overrides,
+        }
+    }
+
+    fn render_markdown(&self, markdown: &str, mdx: bool) -> RenderOutput {
+        let mut ctx = RenderContext::default();
+        let (stats, truncated) = self.render_markdown_into(&mut ctx, markdown, mdx);
+        let overrides = apply_document_overrides(&mut ctx.output, markdown);
+        RenderOutput {
+            html: ctx.output,
+            tasks: stats.tasks,
+            overrides,
+            outline: ctx.headings,
+            links: ctx.links,
+            stats,
+            truncated,
+            failure: None,
+        }
+    }
+
+    fn render_markdown_into(
+        &self,
+        ctx: &mut RenderContext,
+        markdown: &str,
+        mdx: bool,
+    ) -> (DocumentStats, Option<Truncation>) {
+        let limits = self.settings.limits;
+        let mut truncated = None;
+        let markdown = match truncate_input(markdown, limits.max_input_bytes) {
+            Some(cut) => {
+                truncated = Some(Truncation::InputBytes);
+                cut
+            }
+            None => markdown,
+        };
+
+        let RenderContext {
+            output,
+            line_starts,
+            headings,
+            links,
+        } = ctx;
+        output.clear();
+        links.clear();
+        output.reserve(markdown.len().saturating_mul(2) + 128);
         output.push_str("<article id=\"md-root\">");
 
-        let line_starts = line_start_indices(markdown);
-        let heading_ids = collect_heading_ids(markdown, self.options);
-        let parser = Parser::new_ext(markdown, self.options).into_offset_iter();
+        fill_line_start_indices(line_starts, markdown);
+        collect_headings_into(headings, markdown, self.options, self.slug_style);
+        let outline = headings.as_slice();
+        let note_wiki_links = self
+            .settings
+            .profile
+            .is_some_and(RenderProfile::resolves_wiki_links_to_notes);
+        let citations = self.settings.citations;
+        let parser = Parser::new_with_broken_link_callback(
+            markdown,
+            self.options,
+            Some(|link: BrokenLink<'_>| citation_link(citations, link.reference.as_ref())),
+        )
+        .into_offset_iter();
+        let mut parser = TextMergeWithOffset::new(parser).peekable();
 
         let mut last_line = 1usize;
-        let mut heading_index = 0usize;
-        let mut image_titles: Vec<Option<String>> = Vec::new();
-        let mut in_table_head = false;
+        let mut words = 0usize;
+        let mut open_links: Vec<Option<usize>> = Vec::new();
+        let mut depth = 0usize;
+        let mut clipped_at: Option<usize> = None;
+        let mut state = RenderState {
+            aria: self.settings.accessibility.aria,
+            lazy_images: self.settings.lazy_images,
+            attribute_blocks: self.options.contains(Options::ENABLE_HEADING_ATTRIBUTES),
+            list_tasks: count_list_tasks(markdown, self.options),
+            ..RenderState::default()
+        };
+
+        while let Some((mut event, range)) = parser.next() {
+            if self.settings.kbd
+                && let Event::Start(Tag::Link {
+                    link_type: LinkType::WikiLink { has_pothole: false },
+                    dest_url,
+                    ..
+                }) = &event
+                && is_key_name(dest_url.as_ref())
+            {
+                // `[[Ctrl]]` parses as a wikilink; hand it back as text.
+                let shorthand = format!("[[{dest_url}]]");
+                for (inner, _) in parser.by_ref() {
+                    if matches!(inner, Event::End(TagEnd::Link)) {
+                        break;
+                    }
+                }
+                event = Event::Text(shorthand.into());
+            }
 
-        for (event, range) in parser {
-            let mut line = line_for_offset(range.start, &line_starts);
+            if limits.max_output_bytes > 0 && output.len() > limits.max_output_bytes {
+                truncated = Some(Truncation::OutputBytes);
+                break;
+            }
+
+            match &event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if clipped_at.is_none() && limits.max_nesting > 0 && depth > limits.max_nesting {
+                truncated.get_or_insert(Truncation::Nesting);
+                clipped_at = Some(depth);
+            }
+            if let Some(clip_depth) = clipped_at {
+                if matches!(event, Event::End(_)) && depth < clip_depth {
+                    clipped_at = None;
+                }
+                // Keep heading ids and list task counts aligned.
+                match event {
+                    Event::Start(Tag::Heading { .. }) => state.heading_index += 1,
+                    Event::Start(Tag::List(_)) => state.list_index += 1,
+                    _ => {}
+                }
+                continue;
+            }
+
+            let mut line = line_for_offset(range.start, line_starts);
             if line < last_line {
                 line = last_line;
             } else {
                 last_line = line;
             }
 
-            if !image_titles.is_empty() {
-                render_image_alt_event(&mut output, &mut image_titles, event);
+            if state.attribute_blocks
+                && state.skip_until.is_none()
+                && state.image.as_ref().is_none_or(|image| image.nested == 0)
+            {
+                take_attribute_block(
+                    &mut event,
+                    &markdown[range.clone()],
+                    &mut parser,
+                    &mut state,
+                );
+            }
+
+            match &event {
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    ..
+                }) => {
+                    if is_citation_link(*link_type, dest_url.as_ref()) {
+                        open_links.push(None);
+                    } else {
+                        let target =
+                            if note_wiki_links && matches!(link_type, LinkType::WikiLink { .. }) {
+                                note_link_target(dest_url.as_ref())
+                            } else {
+                                dest_url.to_string()
+                            };
+                        open_links.push(Some(links.len()));
+                        links.push(Link::new(target, line, false));
+                    }
+                }
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    open_links.push(Some(links.len()));
+                    links.push(Link::new(dest_url.to_string(), line, true));
+                }
+                Event::End(TagEnd::Link | TagEnd::Image) => {
+                    open_links.pop();
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if matches!(event, Event::Text(_)) {
+                        words += text.split_whitespace().count();
+                    }
+                    for index in open_links.iter().flatten() {
+                        links[*index].text.push_str(text.as_ref());
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(end) = state.skip_until {
+                if matches!(event, Event::End(tag) if tag == end) {
+                    state.skip_until = None;
+                }
                 continue;
             }
 
+            if state.image.is_some() {
+                render_image_alt_event(output, &mut state, event);
+                if state.image.is_none()
+                    && let Some(attributes) = state.image_attributes.take()
+                {
+                    insert_attributes(output, state.image_start, &attributes);
+                }
+                continue;
+            }
+
+            if self.settings.kbd && !state.in_code_block {
+                if let Event::Text(text) = &event {
+                    state.pending_text.push_str(text.as_ref());
+                    continue;
+                }
+                render_kbd_text(output, &mut state.pending_text);
+            }
+
+            if self.settings.figures && matches!(event, Event::Start(Tag::Paragraph)) {
+                state.figure_caption = figure_caption(markdown, &range, parser.peek());
+            }
+
+            // Containers also carry their last line, so a cursor past the
+            // end of a nested quote or item maps back to the enclosing one.
+            if matches!(event, Event::Start(Tag::BlockQuote(_) | Tag::Item)) {
+                let end = range.start + markdown[range.clone()].trim_end().len();
+                let end_line = line_for_offset(end.saturating_sub(1), line_starts);
+                state.container_end_line = (end_line > line).then_some(end_line);
+            }
+
+            let tag_start = output.len();
+            if matches!(event, Event::Start(Tag::Image { .. })) {
+                state.image_start = tag_start;
+            }
+
             match event {
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    ..
+                }) if is_citation_link(link_type, dest_url.as_ref()) => {
+                    let group = &dest_url[CITATION_URL_PREFIX.len()..];
+                    render_citation(output, group, &self.bibliography, &mut state);
+                    state.skip_until = Some(TagEnd::Link);
+                }
+                Event::Start(Tag::Paragraph)
+                    if mdx && mdx_esm_keyword(&markdown[range.clone()]).is_some() =>
+                {
+                    let source = &markdown[range.clone()];
+                    let keyword = mdx_esm_keyword(source).unwrap_or("import");
+                    render_mdx_block(output, "esm", keyword, source, line);
+                    state.skip_until = Some(TagEnd::Paragraph);
+                }
+                Event::Start(Tag::HtmlBlock) if mdx && is_jsx_block(&markdown[range.clone()]) => {
+                    let source = &markdown[range.clone()];
+                    render_mdx_block(output, "jsx", &jsx_block_name(source), source, line);
+                    state.skip_until = Some(TagEnd::HtmlBlock);
+                }
+                Event::Start(Tag::HtmlBlock) if self.settings.sanitize_html => {
+                    output.push_str("<div data-line=\"");
+                    output.push_str(&line.to_string());
+                    output.push_str("\" class=\"html-block\">");
+                    output.push_str(&sanitize_html(&markdown[range.clone()]));
+                    output.push_str("</div>");
+                    state.skip_until = Some(TagEnd::HtmlBlock);
+                }
+                Event::InlineHtml(raw) if mdx && is_jsx_block(raw.as_ref()) => {
+                    output.push_str("<code class=\"mdx-inline\">");
+                    push_escaped_html(output, raw.as_ref());
+                    output.push_str("</code>");
+                }
+                Event::Start(Tag::Link {
+                    link_type: link_type @ LinkType::WikiLink { .. },
+                    dest_url,
+                    title,
+                    id,
+                }) if note_wiki_links => {
+                    let tag = Tag::Link {
+                        link_type,
+                        dest_url: note_link_target(dest_url.as_ref()).into(),
+                        title,
+                        id,
+                    };
+                    render_start_tag(
+                        output,
+                        tag,
+                        line,
+                        outline,
+                        &self.settings.labels,
+                        &mut state,
+                    )
+                }
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+                    if is_ansi_code_block(lang.as_ref(), &markdown[range.clone()]) =>
+                {
+                    state.ansi = Some(AnsiStyle::default());
+                    render_start_tag(
+                        output,
+                        Tag::CodeBlock(CodeBlockKind::Fenced(lang)),
+                        line,
+                        outline,
+                        &self.settings.labels,
+                        &mut state,
+                    )
+                }
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+                    if is_diff_code_block(lang.as_ref()) =>
+                {
+                    state.diff = Some(String::new());
+                    render_start_tag(
+                        output,
+                        Tag::CodeBlock(CodeBlockKind::Fenced(lang)),
+                        line,
+                        outline,
+                        &self.settings.labels,
+                        &mut state,
+                    )
+                }
+                Event::Start(Tag::Paragraph) if state.figure_caption.is_some() => {
+                    output.push_str("<figure data-line=\"");
+                    output.push_str(&line.to_string());
+                    output.push_str("\" class=\"image-figure\">");
+                }
+                Event::End(TagEnd::Paragraph) if state.figure_caption.is_some() => {
+                    let caption = state.figure_caption.take().unwrap_or_default();
+                    if !caption.is_empty() {
+                        output.push_str("<figcaption>");
+                        output.push_str(&caption);
+                        output.push_str("</figcaption>");
+                    }
+                    output.push_str("</figure>");
+                }
+                Event::Start(Tag::Paragraph) if range.len() > WRAP_HINT_BYTES => {
+                    open_paragraph_with_columns(output, markdown, line_starts, line, range.end);
+                }
                 Event::Start(tag) => render_start_tag(
-                    &mut output,
+                    output,
                     tag,
                     line,
-                    &heading_ids,
-                    &mut heading_index,
-                    &mut image_titles,
-                    &mut in_table_head,
+                    outline,
+                    &self.settings.labels,
+                    &mut state,
                 ),
-                Event::End(tag) => render_end_tag(&mut output, tag, &mut in_table_head),
-                Event::Text(text) => push_escaped_html(&mut output, text.as_ref()),
+                Event::End(tag) => render_end_tag(output, tag, &mut state),
+                Event::Text(text) => {
+                    if let Some(diff) = state.diff.as_mut() {
+                        diff.push_str(text.as_ref());
+                    } else if let Some(style) = state.ansi.as_mut() {
+                        ansi::push_ansi_html(output, text.as_ref(), style);
+                    } else {
+                        push_escaped_html(output, text.as_ref());
+                    }
+                }
                 Event::Code(text) => {
                     output.push_str("<code>");
-                    push_escaped_html(&mut output, text.as_ref());
+                    push_escaped_html(output, text.as_ref());
                     output.push_str("</code>");
                 }
                 Event::InlineMath(math) => {
                     output.push_str("<span class=\"math-inline\">");
-                    push_escaped_html(&mut output, math.as_ref());
+                    push_escaped_html(output, math.as_ref());
                     output.push_str("</span>");
                 }
                 Event::DisplayMath(math) => {
                     output.push_str("<div class=\"math-display\">");
-                    push_escaped_html(&mut output, math.as_ref());
+                    push_escaped_html(output, math.as_ref());
                     output.push_str("</div>");
                 }
+                Event::InlineHtml(raw) if self.settings.kbd && is_kbd_tag(raw.as_ref()) => {
+                    output.push_str(if raw.starts_with("</") {
+                        "</kbd>"
+                    } else {
+                        "<kbd>"
+                    });
+                }
+                Event::InlineHtml(raw) if self.settings.sanitize_html => {
+                    output.push_str(&sanitize_html(raw.as_ref()))
+                }
                 Event::Html(raw) | Event::InlineHtml(raw) => {
-                    push_escaped_html(&mut output, raw.as_ref())
+                    push_escaped_html(output, raw.as_ref())
                 }
                 Event::FootnoteReference(label) => {
-                    output.push_str("<sup>");
-                    push_escaped_html(&mut output, label.as_ref());
-                    output.push_str("</sup>");
+                    output.push_str("<sup class=\"footnote-ref\"><a href=\"#fn-");
+                    push_escaped_attr(output, label.as_ref());
+                    output.push_str("\">");
+                    push_escaped_html(output, label.as_ref());
+                    output.push_str("</a></sup>");
                 }
+                Event::SoftBreak if self.hardbreaks => output.push_str("<br />\n"),
                 Event::SoftBreak => output.push('\n'),
                 Event::HardBreak => output.push_str("<br />\n"),
                 Event::Rule => output.push_str("<hr />"),
                 Event::TaskListMarker(checked) => {
+                    record_task_marker(&mut state, checked);
                     if checked {
                         output.push_str("<input type=\"checkbox\" checked disabled /> ");
                     } else {
@@ -91,29 +1045,284 @@ impl LiveMarkdownRenderer {
                     }
                 }
             }
+
+            if let Some(attributes) = state.block_attributes.take() {
+                insert_attributes(output, tag_start, &attributes);
+            }
+        }
+
+        render_kbd_text(output, &mut state.pending_text);
+
+        if !state.cited_keys.is_empty() {
+            render_references(
+                output,
+                &state.cited_keys,
+                &self.bibliography,
+                &self.settings.labels,
+            );
         }
 
         output.push_str("</article>");
-        output
+        let stats = DocumentStats {
+            lines: line_starts.len(),
+            words,
+            headings: headings.len(),
+            tasks: state.tasks,
+        };
+        (stats, truncated)
+    }
+}
+
+/// Cuts `markdown` at the last line break within `max_bytes`, or returns
+/// `None` when it already fits.
+fn truncate_input(markdown: &str, max_bytes: usize) -> Option<&str> {
+    if max_bytes == 0 || markdown.len() <= max_bytes {
+        return None;
+    }
+
+    let mut end = max_bytes;
+    while !markdown.is_char_boundary(end) {
+        end -= 1;
     }
+    let end = markdown[..end]
+        .rfind('\n')
+        .map_or(end, |newline| newline + 1);
+    Some(&markdown[..end])
+}
+
+fn with_document_overrides(mut output: RenderOutput, markdown: &str) -> RenderOutput {
+    output.overrides = apply_document_overrides(&mut output.html, markdown);
+    output
+}
+
+fn apply_document_overrides(html: &mut String, markdown: &str) -> DocumentOverrides {
+    const ROOT_OPEN: &str = "<article id=\"md-root\"";
+
+    let Some(overrides) = Frontmatter::parse(markdown).map(|frontmatter| frontmatter.overrides())
+    else {
+        return DocumentOverrides::default();
+    };
+
+    if html.starts_with(ROOT_OPEN) {
+        let mut attrs = String::new();
+        if let Some(theme) = &overrides.theme {
+            attrs.push_str(" data-theme=\"");
+            push_escaped_attr(&mut attrs, theme);
+            attrs.push('"');
+        }
+        if let Some(toc) = overrides.toc {
+            attrs.push_str(&format!(" data-toc=\"{toc}\""));
+        }
+        if let Some(max_width) = overrides.max_width {
+            attrs.push_str(&format!(" data-max-width=\"{max_width}\""));
+        }
+        html.insert_str(ROOT_OPEN.len(), &attrs);
+    }
+
+    overrides
+}
+
+impl RenderBackend for LiveMarkdownRenderer {
+    fn render_document(&self, source: &str) -> RenderOutput {
+        LiveMarkdownRenderer::render_document(self, source)
+    }
+}
+
+fn citation_link(enabled: bool, reference: &str) -> Option<(CowStr<'static>, CowStr<'static>)> {
+    if !enabled || citation::parse_citation_group(reference).is_empty() {
+        return None;
+    }
+
+    let dest = format!("{CITATION_URL_PREFIX}{reference}");
+    Some((CowStr::from(dest), CowStr::from("")))
+}
+
+fn is_citation_link(link_type: LinkType, dest_url: &str) -> bool {
+    matches!(
+        link_type,
+        LinkType::ShortcutUnknown | LinkType::CollapsedUnknown | LinkType::ReferenceUnknown
+    ) && dest_url.starts_with(CITATION_URL_PREFIX)
+}
+
+fn render_citation(
+    out: &mut String,
+    group: &str,
+    bibliography: &Bibliography,
+    state: &mut RenderState,
+) {
+    let items = citation::parse_citation_group(group);
+    let keys = items
+        .iter()
+        .map(|item| item.key.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    out.push_str("<span class=\"citation\" data-cites=\"");
+    push_escaped_attr(out, &keys);
+    out.push_str("\">(");
+
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            out.push_str("; ");
+        }
+        if !item.prefix.is_empty() {
+            push_escaped_html(out, &item.prefix);
+            out.push(' ');
+        }
+
+        match bibliography.get(&item.key) {
+            Some(entry) => {
+                out.push_str("<a href=\"#ref-");
+                push_escaped_attr(out, &item.key);
+                out.push_str("\">");
+                if !item.suppress_author {
+                    push_escaped_html(out, &entry.author_label());
+                    out.push(' ');
+                }
+                push_escaped_html(out, entry.year.as_deref().unwrap_or("n.d."));
+                out.push_str("</a>");
+            }
+            None => {
+                out.push_str("<strong class=\"citation-missing\">?");
+                push_escaped_html(out, &item.key);
+                out.push_str("</strong>");
+            }
+        }
+
+        if !item.suffix.is_empty() {
+            out.push_str(", ");
+            push_escaped_html(out, &item.suffix);
+        }
+
+        if !state.cited_keys.contains(&item.key) {
+            state.cited_keys.push(item.key.clone());
+        }
+    }
+
+    out.push_str(")</span>");
+}
+
+fn render_references(
+    out: &mut String,
+    cited_keys: &[String],
+    bibliography: &Bibliography,
+    labels: &Labels,
+) {
+    let mut entries = cited_keys
+        .iter()
+        .filter_map(|key| bibliography.get(key))
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return;
+    }
+
+    entries.sort_by(|a, b| {
+        a.authors
+            .first()
+            .cmp(&b.authors.first())
+            .then_with(|| a.year.cmp(&b.year))
+    });
+
+    out.push_str("<section class=\"references\" id=\"refs\"><h2>");
+    push_escaped_html(out, labels.get("references"));
+    out.push_str("</h2>");
+    for entry in entries {
+        out.push_str("<div class=\"csl-entry\" id=\"ref-");
+        push_escaped_attr(out, &entry.key);
+        out.push_str("\">");
+        push_escaped_html(out, &entry.authors.join(", "));
+        if !entry.authors.is_empty() {
+            out.push_str(". ");
+        }
+        push_escaped_html(out, entry.year.as_deref().unwrap_or("n.d."));
+        out.push_str(". ");
+        if let Some(title) = entry.title.as_deref() {
+            out.push_str("<em>");
+            push_escaped_html(out, title);
+            out.push_str("</em>. ");
+        }
+        if let Some(container) = entry.container.as_deref() {
+            push_escaped_html(out, container);
+            out.push_str(". ");
+        }
+        if let Some(url) = entry.url.as_deref() {
+            out.push_str("<a href=\"");
+            push_escaped_attr(out, &sanitize_url(url));
+            out.push_str("\">");
+            push_escaped_html(out, url);
+            out.push_str("</a>");
+        }
+        out.push_str("</div>");
+    }
+    out.push_str("</section>");
+}
+
+fn mdx_esm_keyword(source: &str) -> Option<&'static str> {
+    let first_line = source.trim_start().lines().next()?;
+    if first_line.starts_with("import ") || first_line.starts_with("import{") {
+        Some("import")
+    } else if first_line.starts_with("export ") {
+        Some("export")
+    } else {
+        None
+    }
+}
+
+fn is_jsx_block(source: &str) -> bool {
+    let trimmed = source.trim_start();
+    let Some(rest) = trimmed.strip_prefix('<') else {
+        return false;
+    };
+
+    rest.starts_with('>')
+        || rest.starts_with("/>")
+        || rest.starts_with(|ch: char| ch.is_ascii_uppercase())
+        || rest
+            .strip_prefix('/')
+            .is_some_and(|rest| rest.starts_with(|ch: char| ch.is_ascii_uppercase()))
+}
+
+fn jsx_block_name(source: &str) -> String {
+    let rest = source.trim_start().trim_start_matches(['<', '/']);
+    let name: String = rest
+        .chars()
+        .take_while(|ch| ch.is_alphanumeric() || matches!(ch, '.' | '_' | '-'))
+        .collect();
+
+    if name.is_empty() {
+        String::from("Fragment")
+    } else {
+        format!("<{name}>")
+    }
+}
+
+fn render_mdx_block(out: &mut String, kind: &str, label: &str, source: &str, line: usize) {
+    out.push_str("<div data-line=\"");
+    out.push_str(&line.to_string());
+    out.push_str("\" class=\"mdx-block mdx-");
+    out.push_str(kind);
+    out.push_str("\"><p class=\"mdx-block-label\">");
+    push_escaped_html(out, label);
+    out.push_str("</p><pre><code>");
+    push_escaped_html(out, source.trim_end());
+    out.push_str("</code></pre></div>");
 }
 
 fn render_start_tag(
     out: &mut String,
     tag: Tag<'_>,
     line: usize,
-    heading_ids: &[String],
-    heading_index: &mut usize,
-    image_titles: &mut Vec<Option<String>>,
-    in_table_head: &mut bool,
+    headings: &[Heading],
+    labels: &Labels,
+    state: &mut RenderState,
 ) {
     match tag {
         Tag::Paragraph => open_block_tag(out, "p", line),
         Tag::Heading {
             level,
             id: _,
-            classes: _,
-            attrs: _,
+            classes,
+            attrs,
         } => {
             let level = heading_level_number(level);
             out.push_str("<h");
@@ -121,31 +1330,45 @@ fn render_start_tag(
             out.push_str(" data-line=\"");
             out.push_str(&line.to_string());
             out.push('"');
-            if let Some(heading_id) = heading_ids.get(*heading_index) {
+            if let Some(heading) = headings.get(state.heading_index) {
                 out.push_str(" id=\"");
-                push_escaped_attr(out, heading_id);
+                push_escaped_attr(out, &heading.id);
                 out.push('"');
             }
+            let mut extra = Attributes {
+                classes: classes.iter().map(|class| class.to_string()).collect(),
+                ..Attributes::default()
+            };
+            for (key, value) in &attrs {
+                extra.push_pair(key, value.as_deref().unwrap_or_default());
+            }
+            push_attributes(out, &extra);
             out.push('>');
-            *heading_index = heading_index.saturating_add(1);
+            state.heading_index = state.heading_index.saturating_add(1);
         }
         Tag::BlockQuote(kind) => {
             out.push_str("<blockquote data-line=\"");
             out.push_str(&line.to_string());
             out.push('"');
+            push_line_end(out, state.container_end_line.take());
             if let Some(kind) = kind {
                 let kind_name = block_quote_kind_name(kind);
                 out.push_str(" data-alert=\"");
                 out.push_str(kind_name);
                 out.push_str("\" class=\"markdown-alert markdown-alert-");
                 out.push_str(kind_name);
-                out.push_str("\">");
-                render_alert_title(out, kind);
+                out.push('"');
+                if state.aria {
+                    out.push_str(" role=\"note\"");
+                }
+                out.push('>');
+                render_alert_title(out, kind, labels);
             } else {
                 out.push('>');
             }
         }
         Tag::CodeBlock(kind) => {
+            state.in_code_block = true;
             out.push_str("<pre data-line=\"");
             out.push_str(&line.to_string());
             out.push_str("\"><code");
@@ -154,6 +1377,12 @@ fn render_start_tag(
                 if !trimmed.is_empty() {
                     out.push_str(" class=\"language-");
                     push_escaped_attr(out, trimmed);
+                    if state.ansi.is_some() {
+                        out.push_str(" ansi");
+                    }
+                    if state.diff.is_some() {
+                        out.push_str(" diff");
+                    }
                     out.push('"');
                 }
             }
@@ -163,15 +1392,35 @@ fn render_start_tag(
             if let Some(start) = start {
                 out.push_str("<ol start=\"");
                 out.push_str(&start.to_string());
-                out.push_str("\">");
+                out.push('"');
             } else {
-                out.push_str("<ul>");
+                out.push_str("<ul");
             }
+            let progress = state
+                .list_tasks
+                .get(state.list_index)
+                .copied()
+                .unwrap_or_default();
+            state.list_index += 1;
+            if progress.total > 0 {
+                out.push_str(" class=\"contains-task-list\" data-tasks-done=\"");
+                out.push_str(&progress.done.to_string());
+                out.push_str("\" data-tasks-total=\"");
+                out.push_str(&progress.total.to_string());
+                out.push('"');
+            }
+            out.push('>');
         }
         Tag::DefinitionList => out.push_str("<dl>"),
         Tag::DefinitionListTitle => open_block_tag(out, "dt", line),
         Tag::DefinitionListDefinition => open_block_tag(out, "dd", line),
-        Tag::Item => open_block_tag(out, "li", line),
+        Tag::Item => {
+            out.push_str("<li data-line=\"");
+            out.push_str(&line.to_string());
+            out.push('"');
+            push_line_end(out, state.container_end_line.take());
+            out.push('>');
+        }
         Tag::Emphasis => out.push_str("<em>"),
         Tag::Superscript => out.push_str("<sup>"),
         Tag::Subscript => out.push_str("<sub>"),
@@ -202,11 +1451,12 @@ fn render_start_tag(
             out.push_str("<img src=\"");
             push_escaped_attr(out, &sanitize_image_url(dest_url.as_ref()));
             out.push_str("\" alt=\"");
-            if title.is_empty() {
-                image_titles.push(None);
-            } else {
-                image_titles.push(Some(title.to_string()));
-            }
+            state.image = Some(ImageFrame {
+                src: dest_url.to_string(),
+                title: (!title.is_empty()).then(|| title.to_string()),
+                alt_start: out.len(),
+                nested: 0,
+            });
         }
         Tag::HtmlBlock => {
             out.push_str("<pre data-line=\"");
@@ -216,7 +1466,9 @@ fn render_start_tag(
         Tag::FootnoteDefinition(label) => {
             out.push_str("<section data-line=\"");
             out.push_str(&line.to_string());
-            out.push_str("\" class=\"footnote\" data-footnote=\"");
+            out.push_str("\" class=\"footnote\" id=\"fn-");
+            push_escaped_attr(out, label.as_ref());
+            out.push_str("\" data-footnote=\"");
             push_escaped_attr(out, label.as_ref());
             out.push_str("\">");
         }
@@ -227,14 +1479,21 @@ fn render_start_tag(
             out.push_str(metadata_block_kind_name(kind));
             out.push_str("\">");
         }
+        Tag::Table(_alignments) if state.aria => {
+            out.push_str("<table data-line=\"");
+            out.push_str(&line.to_string());
+            out.push_str("\" role=\"table\">");
+        }
         Tag::Table(_alignments) => open_block_tag(out, "table", line),
         Tag::TableHead => {
-            *in_table_head = true;
+            state.in_table_head = true;
             out.push_str("<thead>");
         }
         Tag::TableRow => out.push_str("<tr>"),
         Tag::TableCell => {
-            if *in_table_head {
+            if state.in_table_head && state.aria {
+                out.push_str("<th scope=\"col\">");
+            } else if state.in_table_head {
                 out.push_str("<th>");
             } else {
                 out.push_str("<td>");
@@ -243,7 +1502,7 @@ fn render_start_tag(
     }
 }
 
-fn render_end_tag(out: &mut String, tag: TagEnd, in_table_head: &mut bool) {
+fn render_end_tag(out: &mut String, tag: TagEnd, state: &mut RenderState) {
     match tag {
         TagEnd::Paragraph => out.push_str("</p>"),
         TagEnd::Heading(level) => {
@@ -253,10 +1512,16 @@ fn render_end_tag(out: &mut String, tag: TagEnd, in_table_head: &mut bool) {
             out.push('>');
         }
         TagEnd::BlockQuote(_) => out.push_str("</blockquote>"),
-        TagEnd::CodeBlock => out.push_str("</code></pre>"),
+        TagEnd::CodeBlock => {
+            state.in_code_block = false;
+            state.ansi = None;
+            if let Some(diff) = state.diff.take() {
+                render_diff_lines(out, &diff);
+            }
+            out.push_str("</code></pre>");
+        }
         TagEnd::HtmlBlock => out.push_str("</pre>"),
-        TagEnd::List(true) => out.push_str("</ol>"),
-        TagEnd::List(false) => out.push_str("</ul>"),
+        TagEnd::List(ordered) => out.push_str(if ordered { "</ol>" } else { "</ul>" }),
         TagEnd::Item => out.push_str("</li>"),
         TagEnd::FootnoteDefinition => out.push_str("</section>"),
         TagEnd::DefinitionList => out.push_str("</dl>"),
@@ -264,12 +1529,12 @@ fn render_end_tag(out: &mut String, tag: TagEnd, in_table_head: &mut bool) {
         TagEnd::DefinitionListDefinition => out.push_str("</dd>"),
         TagEnd::Table => out.push_str("</table>"),
         TagEnd::TableHead => {
-            *in_table_head = false;
+            state.in_table_head = false;
             out.push_str("</thead>");
         }
         TagEnd::TableRow => out.push_str("</tr>"),
         TagEnd::TableCell => {
-            if *in_table_head {
+            if state.in_table_head {
                 out.push_str("</th>");
             } else {
                 out.push_str("</td>");
@@ -286,32 +1551,325 @@ fn render_end_tag(out: &mut String, tag: TagEnd, in_table_head: &mut bool) {
     }
 }
 
-fn render_image_alt_event(
-    out: &mut String,
-    image_titles: &mut Vec<Option<String>>,
-    event: Event<'_>,
-) {
-    match event {
-        Event::End(TagEnd::Image) => {
-            out.push('"');
-            if let Some(Some(title)) = image_titles.pop() {
-                out.push_str(" title=\"");
-                push_escaped_attr(out, &title);
-                out.push('"');
-            }
-            out.push_str(" />");
+fn record_task_marker(state: &mut RenderState, checked: bool) {
+    state.tasks.total += 1;
+    if checked {
+        state.tasks.done += 1;
+    }
+}
+
+/// Picks up pandoc-style `{#id .class key=val}` blocks: at the end of a
+/// fenced code info string, right after an image, or at the end of a
+/// paragraph. The block is removed from the event and kept in `state` for the
+/// tag it belongs to.
+fn take_attribute_block<'a, I>(
+    event: &mut Event<'a>,
+    source: &str,
+    parser: &mut std::iter::Peekable<I>,
+    state: &mut RenderState,
+) where
+    I: Iterator<Item = (Event<'a>, std::ops::Range<usize>)>,
+{
+    match event {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+            if let Some((lang, attributes)) = attributes::split_info_string(info) {
+                *info = CowStr::from(lang);
+                state.block_attributes = Some(attributes);
+            }
+        }
+        Event::Start(Tag::Paragraph) => {
+            // A block glued to an image or link belongs to it, and prose
+            // blocks need an id or class so text like `{x=1}` stays put.
+            state.trailing_attributes = false;
+            if let Some((before, attributes)) = attributes::split_trailing(source)
+                && !before.ends_with([')', ']'])
+                && (attributes.id.is_some() || !attributes.classes.is_empty())
+            {
+                state.trailing_attributes = true;
+                state.block_attributes = Some(attributes);
+            }
+        }
+        Event::Text(text)
+            if state.trailing_attributes
+                && matches!(parser.peek(), Some((Event::End(TagEnd::Paragraph), _))) =>
+        {
+            state.trailing_attributes = false;
+            if let Some((before, _)) = attributes::split_trailing(text) {
+                let before = before.to_string();
+                *text = CowStr::from(before);
+            }
+        }
+        Event::End(TagEnd::Image) => {
+            if let Some((Event::Text(next), _)) = parser.peek_mut()
+                && let Some((attributes, rest)) = attributes::split_leading(next)
+            {
+                let rest = rest.to_string();
+                *next = CowStr::from(rest);
+                state.image_attributes = Some(attributes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds `attributes` right after the tag name of the opening tag at `start`.
+fn insert_attributes(out: &mut String, start: usize, attributes: &Attributes) {
+    let Some(name_end) = out[start..].find([' ', '>']).map(|offset| start + offset) else {
+        return;
+    };
+    let mut html = String::new();
+    push_attributes(&mut html, attributes);
+    out.insert_str(name_end, &html);
+}
+
+fn push_attributes(out: &mut String, attributes: &Attributes) {
+    if let Some(id) = &attributes.id {
+        out.push_str(" id=\"");
+        push_escaped_attr(out, id);
+        out.push('"');
+    }
+    if !attributes.classes.is_empty() {
+        out.push_str(" class=\"");
+        push_escaped_attr(out, &attributes.classes.join(" "));
+        out.push('"');
+    }
+    for (name, value) in &attributes.pairs {
+        out.push(' ');
+        out.push_str(name);
+        out.push_str("=\"");
+        push_escaped_attr(out, value);
+        out.push('"');
+    }
+}
+
+/// Writes the events inside an image's brackets as its plain-text alt: markup
+/// and HTML tags are dropped and line breaks become spaces.
+fn render_image_alt_event(out: &mut String, state: &mut RenderState, event: Event<'_>) {
+    let Some(image) = state.image.as_mut() else {
+        return;
+    };
+    match event {
+        Event::Start(Tag::Image { .. }) => image.nested += 1,
+        Event::End(TagEnd::Image) if image.nested > 0 => image.nested -= 1,
+        Event::End(TagEnd::Image) => {
+            let Some(image) = state.image.take() else {
+                return;
+            };
+            let alt = out[image.alt_start..].trim_end().len();
+            out.truncate(image.alt_start + alt);
+            if let Some(caption) = state.figure_caption.as_mut()
+                && caption.is_empty()
+            {
+                caption.push_str(&out[image.alt_start..]);
+            }
+            if alt == 0 {
+                push_escaped_attr(out, &fallback_alt(&image));
+            }
+            out.push('"');
+            if let Some(title) = &image.title {
+                out.push_str(" title=\"");
+                push_escaped_attr(out, title);
+                out.push('"');
+            }
+            if state.lazy_images {
+                out.push_str(" loading=\"lazy\" decoding=\"async\"");
+            }
+            out.push_str(" />");
+        }
+        Event::Text(text)
+        | Event::Code(text)
+        | Event::InlineMath(text)
+        | Event::DisplayMath(text)
+        | Event::FootnoteReference(text) => {
+            let mut word = [0u8; 4];
+            for ch in text.chars() {
+                if !ch.is_whitespace() {
+                    push_escaped_attr(out, ch.encode_utf8(&mut word));
+                } else if out.len() > image.alt_start && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+        }
+        Event::SoftBreak | Event::HardBreak
+            if out.len() > image.alt_start && !out.ends_with(' ') =>
+        {
+            out.push(' ');
+        }
+        _ => {}
+    }
+}
+
+/// When the paragraph at `paragraph` is exactly one image, its caption so
+/// far: the escaped title, or empty until the alt text is known.
+fn figure_caption(
+    markdown: &str,
+    paragraph: &std::ops::Range<usize>,
+    next: Option<&(Event<'_>, std::ops::Range<usize>)>,
+) -> Option<String> {
+    match next {
+        Some((Event::Start(Tag::Image { title, .. }), image))
+            if markdown[paragraph.clone()].trim() == markdown[image.clone()].trim() =>
+        {
+            let mut caption = String::new();
+            push_escaped_html(&mut caption, title.trim());
+            Some(caption)
+        }
+        _ => None,
+    }
+}
+
+/// The alt for an image written without one: its title, or else its file
+/// name with separators turned into spaces.
+fn fallback_alt(image: &ImageFrame) -> String {
+    if let Some(title) = image
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+    {
+        return title.to_string();
+    }
+    if image.src.trim_start().starts_with("data:") {
+        return String::new();
+    }
+    let path = image.src.split(['?', '#']).next().unwrap_or_default();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.split(['-', '_', ' ', '+'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_ansi_code_block(lang: &str, source: &str) -> bool {
+    match lang
+        .split_whitespace()
+        .next()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("ansi") => true,
+        Some("console") => ansi::contains_ansi(source),
+        _ => false,
+    }
+}
+
+fn is_kbd_tag(raw: &str) -> bool {
+    matches!(raw.trim().to_ascii_lowercase().as_str(), "<kbd>" | "</kbd>")
+}
+
+/// Whether a `[[...]]` target names a key rather than a note: a single
+/// character, a function key or a common key name.
+fn is_key_name(name: &str) -> bool {
+    const KEYS: &[&str] = &[
+        "alt",
+        "backspace",
+        "capslock",
+        "cmd",
+        "command",
+        "control",
+        "ctrl",
+        "del",
+        "delete",
+        "down",
+        "end",
+        "enter",
+        "esc",
+        "escape",
+        "fn",
+        "home",
+        "ins",
+        "insert",
+        "leader",
+        "left",
+        "meta",
+        "opt",
+        "option",
+        "pagedown",
+        "pageup",
+        "pgdn",
+        "pgup",
+        "return",
+        "right",
+        "shift",
+        "space",
+        "super",
+        "tab",
+        "up",
+        "win",
+    ];
+    let name = name.trim();
+    let lower = name.to_ascii_lowercase();
+    name.chars().count() == 1
+        || KEYS.contains(&lower.as_str())
+        || lower
+            .strip_prefix('f')
+            .and_then(|number| number.parse::<u8>().ok())
+            .is_some_and(|number| (1..=24).contains(&number))
+}
+
+fn render_kbd_text(out: &mut String, text: &mut String) {
+    let mut rest = text.as_str();
+    while let Some(open) = rest.find("[[") {
+        let inner_start = open + 2;
+        let Some(inner_len) = rest[inner_start..].find("]]") else {
+            break;
+        };
+        let key = &rest[inner_start..inner_start + inner_len];
+        if key.trim().is_empty() || key.len() > 32 || key.contains(['[', ']', '\n']) {
+            push_escaped_html(out, &rest[..inner_start]);
+            rest = &rest[inner_start..];
+            continue;
         }
-        Event::Text(text)
-        | Event::Code(text)
-        | Event::Html(text)
-        | Event::InlineHtml(text)
-        | Event::InlineMath(text)
-        | Event::DisplayMath(text)
-        | Event::FootnoteReference(text) => {
-            push_escaped_attr(out, text.as_ref());
+
+        push_escaped_html(out, &rest[..open]);
+        out.push_str("<kbd>");
+        push_escaped_html(out, key.trim());
+        out.push_str("</kbd>");
+        rest = &rest[inner_start + inner_len + 2..];
+    }
+
+    push_escaped_html(out, rest);
+    text.clear();
+}
+
+fn is_diff_code_block(lang: &str) -> bool {
+    matches!(
+        lang.split_whitespace()
+            .next()
+            .map(str::to_ascii_lowercase)
+            .as_deref(),
+        Some("diff" | "patch" | "udiff")
+    )
+}
+
+fn render_diff_lines(out: &mut String, source: &str) {
+    for line in source.split_inclusive('\n') {
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let kind = if content.starts_with("+++")
+            || content.starts_with("---")
+            || content.starts_with("diff ")
+            || content.starts_with("index ")
+        {
+            "diff-meta"
+        } else if content.starts_with("@@") {
+            "diff-hunk"
+        } else if content.starts_with('+') {
+            "diff-add"
+        } else if content.starts_with('-') {
+            "diff-remove"
+        } else {
+            "diff-context"
+        };
+
+        out.push_str("<span class=\"diff-line ");
+        out.push_str(kind);
+        out.push_str("\">");
+        push_escaped_html(out, content);
+        out.push_str("</span>");
+        if line.len() != content.len() {
+            out.push('\n');
         }
-        Event::SoftBreak | Event::HardBreak => out.push(' '),
-        _ => {}
     }
 }
 
@@ -323,15 +1881,58 @@ fn open_block_tag(out: &mut String, tag: &str, line: usize) {
     out.push_str("\">");
 }
 
-fn line_start_indices(markdown: &str) -> Vec<usize> {
-    let mut starts = Vec::with_capacity(markdown.lines().count() + 1);
+/// Writes the last source line of a multi-line container.
+fn push_line_end(out: &mut String, end_line: Option<usize>) {
+    if let Some(end_line) = end_line {
+        out.push_str(" data-line-end=\"");
+        out.push_str(&end_line.to_string());
+        out.push('"');
+    }
+}
+
+/// Paragraphs with longer sources likely wrap in the preview, so they carry
+/// the byte width of each source line for placing the cursor column.
+const WRAP_HINT_BYTES: usize = 80;
+
+/// Opens a paragraph with `data-cols`, the byte lengths of the source lines it
+/// spans, so the preview can tell where a cursor column falls inside it.
+fn open_paragraph_with_columns(
+    out: &mut String,
+    markdown: &str,
+    line_starts: &[usize],
+    line: usize,
+    end: usize,
+) {
+    let last_line = line_for_offset(end.saturating_sub(1), line_starts).max(line);
+    out.push_str("<p data-line=\"");
+    out.push_str(&line.to_string());
+    out.push_str("\" data-cols=\"");
+    for current in line..=last_line {
+        let start = line_starts[current - 1];
+        let stop = line_starts
+            .get(current)
+            .map_or(markdown.len(), |next| next - 1);
+        if current > line {
+            out.push(',');
+        }
+        out.push_str(
+            &markdown[start..stop]
+                .trim_end_matches('\r')
+                .len()
+                .to_string(),
+        );
+    }
+    out.push_str("\">");
+}
+
+fn fill_line_start_indices(starts: &mut Vec<usize>, markdown: &str) {
+    starts.clear();
     starts.push(0);
     for (idx, byte) in markdown.bytes().enumerate() {
         if byte == b'\n' {
             starts.push(idx + 1);
         }
     }
-    starts
 }
 
 fn line_for_offset(offset: usize, starts: &[usize]) -> usize {
@@ -342,121 +1943,156 @@ fn line_for_offset(offset: usize, starts: &[usize]) -> usize {
     }
 }
 
-fn collect_heading_ids(markdown: &str, options: Options) -> Vec<String> {
-    let mut ids = Vec::new();
-    let mut used_ids = HashSet::new();
-    let mut next_suffixes: HashMap<String, usize> = HashMap::new();
-    let mut heading_aliases = collect_internal_heading_aliases(markdown, options);
-
-    let mut heading_text: Option<String> = None;
-    let mut explicit_heading_id: Option<String> = None;
+fn collect_headings(markdown: &str, options: Options, slug_style: SlugStyle) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    collect_headings_into(&mut headings, markdown, options, slug_style);
+    headings
+}
 
+/// Counts the tasks directly inside each list, in the order the lists open,
+/// so the opening tag can carry the progress without patching it later.
+fn count_list_tasks(markdown: &str, options: Options) -> Vec<TaskProgress> {
+    let mut counts = Vec::new();
+    let mut open = Vec::new();
     for event in Parser::new_ext(markdown, options) {
         match event {
-            Event::Start(Tag::Heading {
-                level: _,
-                id,
-                classes: _,
-                attrs: _,
-            }) => {
-                heading_text = Some(String::new());
-                explicit_heading_id = normalize_heading_id(id.as_deref());
-            }
-            Event::End(TagEnd::Heading(_)) => {
-                let text = heading_text.take().unwrap_or_default();
-                let base = if let Some(explicit) = explicit_heading_id.take() {
-                    explicit
-                } else if let Some(alias) = take_heading_alias(&mut heading_aliases, &text) {
-                    alias
-                } else {
-                    slugify_heading(&text)
-                };
-                let unique = unique_heading_id(base, &mut used_ids, &mut next_suffixes);
-                ids.push(unique);
-            }
-            Event::Text(text)
-            | Event::Code(text)
-            | Event::Html(text)
-            | Event::InlineHtml(text)
-            | Event::InlineMath(text)
-            | Event::DisplayMath(text) => {
-                if let Some(current) = heading_text.as_mut() {
-                    current.push_str(text.as_ref());
-                }
+            Event::Start(Tag::List(_)) => {
+                open.push(counts.len());
+                counts.push(TaskProgress::default());
             }
-            Event::FootnoteReference(text) => {
-                if let Some(current) = heading_text.as_mut() {
-                    current.push_str(text.as_ref());
-                }
+            Event::End(TagEnd::List(_)) => {
+                open.pop();
             }
-            Event::SoftBreak | Event::HardBreak => {
-                if let Some(current) = heading_text.as_mut() {
-                    if !current.ends_with(' ') {
-                        current.push(' ');
+            Event::TaskListMarker(checked) => {
+                if let Some(&index) = open.last() {
+                    counts[index].total += 1;
+                    if checked {
+                        counts[index].done += 1;
                     }
                 }
             }
             _ => {}
         }
     }
-
-    ids
+    counts
 }
 
-fn collect_internal_heading_aliases(
+/// Gathers heading text and internal-link aliases in one parse, then assigns
+/// ids once every alias in the document is known.
+fn collect_headings_into(
+    headings: &mut Vec<Heading>,
     markdown: &str,
     options: Options,
-) -> HashMap<String, VecDeque<String>> {
-    let mut aliases: HashMap<String, VecDeque<String>> = HashMap::new();
+    slug_style: SlugStyle,
+) {
+    headings.clear();
+    let mut drafts: Vec<HeadingDraft> = Vec::new();
+    let mut heading_aliases: HashMap<String, VecDeque<String>> = HashMap::new();
 
-    let mut active_fragment: Option<String> = None;
-    let mut active_text = String::new();
+    let mut heading: Option<HeadingDraft> = None;
+    let mut link: Option<(String, String)> = None;
+    let (mut scanned, mut line) = (0usize, 1usize);
 
-    for event in Parser::new_ext(markdown, options) {
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
         match event {
+            Event::Start(Tag::Heading {
+                level,
+                id,
+                classes: _,
+                attrs: _,
+            }) => {
+                if range.start > scanned {
+                    line += markdown.as_bytes()[scanned..range.start]
+                        .iter()
+                        .filter(|byte| **byte == b'\n')
+                        .count();
+                    scanned = range.start;
+                }
+                heading = Some(HeadingDraft {
+                    line,
+                    level: heading_level_number(level),
+                    text: String::new(),
+                    explicit_id: normalize_heading_id(id.as_deref()),
+                });
+            }
+            Event::End(TagEnd::Heading(_)) => drafts.extend(heading.take()),
             Event::Start(Tag::Link {
                 link_type: _,
                 dest_url,
                 title: _,
                 id: _,
             }) => {
-                active_fragment = internal_fragment_id(dest_url.as_ref());
-                active_text.clear();
+                link = internal_fragment_id(dest_url.as_ref())
+                    .map(|fragment| (fragment, String::new()));
             }
             Event::End(TagEnd::Link) => {
-                if let Some(fragment) = active_fragment.take() {
-                    let key = normalize_heading_lookup_text(&active_text);
+                if let Some((fragment, text)) = link.take() {
+                    let key = normalize_heading_lookup_text(&text);
                     if !key.is_empty() {
-                        aliases.entry(key).or_default().push_back(fragment);
+                        heading_aliases.entry(key).or_default().push_back(fragment);
                     }
                 }
-                active_text.clear();
             }
             Event::Text(text)
             | Event::Code(text)
             | Event::Html(text)
             | Event::InlineHtml(text)
             | Event::InlineMath(text)
-            | Event::DisplayMath(text) => {
-                if active_fragment.is_some() {
-                    active_text.push_str(text.as_ref());
+            | Event::DisplayMath(text)
+            | Event::FootnoteReference(text) => {
+                if let Some(current) = heading.as_mut() {
+                    current.text.push_str(text.as_ref());
                 }
-            }
-            Event::FootnoteReference(text) => {
-                if active_fragment.is_some() {
-                    active_text.push_str(text.as_ref());
+                if let Some((_, current)) = link.as_mut() {
+                    current.push_str(text.as_ref());
                 }
             }
             Event::SoftBreak | Event::HardBreak => {
-                if active_fragment.is_some() && !active_text.ends_with(' ') {
-                    active_text.push(' ');
+                if let Some(current) = heading.as_mut() {
+                    push_word_break(&mut current.text);
+                }
+                if let Some((_, current)) = link.as_mut() {
+                    push_word_break(current);
                 }
             }
             _ => {}
         }
     }
 
-    aliases
+    let mut used_ids = HashSet::new();
+    let mut next_suffixes: HashMap<String, usize> = HashMap::new();
+    for draft in drafts {
+        let base = draft
+            .explicit_id
+            .or_else(|| take_heading_alias(&mut heading_aliases, &draft.text))
+            .unwrap_or_else(|| match slug_style {
+                SlugStyle::Compact => slugify_heading(&draft.text),
+                SlugStyle::Github => github_slug(&draft.text),
+                SlugStyle::Pandoc => pandoc_slug(&draft.text),
+            });
+        let id = unique_heading_id(base.clone(), &mut used_ids, &mut next_suffixes);
+        headings.push(Heading {
+            level: draft.level,
+            text: draft.text.trim().to_string(),
+            line: draft.line,
+            collides_with: (id != base).then_some(base),
+            id,
+        });
+    }
+}
+
+#[derive(Debug)]
+struct HeadingDraft {
+    line: usize,
+    level: u8,
+    text: String,
+    explicit_id: Option<String>,
+}
+
+fn push_word_break(text: &mut String) {
+    if !text.ends_with(' ') {
+        text.push(' ');
+    }
 }
 
 fn take_heading_alias(
@@ -540,6 +2176,58 @@ fn slugify_heading(text: &str) -> String {
     }
 }
 
+fn github_slug(text: &str) -> String {
+    let slug: String = text
+        .trim()
+        .chars()
+        .filter(|ch| ch.is_alphanumeric() || matches!(ch, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .map(|ch| if ch == ' ' { '-' } else { ch })
+        .collect();
+
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug
+    }
+}
+
+fn pandoc_slug(text: &str) -> String {
+    let slug: String = text
+        .chars()
+        .skip_while(|ch| !ch.is_alphabetic())
+        .filter(|ch| ch.is_alphanumeric() || ch.is_whitespace() || matches!(ch, '_' | '-' | '.'))
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug
+    }
+}
+
+fn note_link_target(dest: &str) -> String {
+    let (page, fragment) = match dest.split_once('#') {
+        Some((page, fragment)) => (page, Some(fragment)),
+        None => (dest, None),
+    };
+
+    let mut target = page.trim().to_string();
+    if !target.is_empty() && Path::new(&target).extension().is_none() {
+        target.push_str(".md");
+    }
+    if let Some(fragment) = fragment {
+        target.push('#');
+        target.push_str(&github_slug(fragment));
+    }
+
+    target
+}
+
 fn unique_heading_id(
     base: String,
     used_ids: &mut HashSet<String>,
@@ -571,16 +2259,6 @@ fn block_quote_kind_name(kind: BlockQuoteKind) -> &'static str {
     }
 }
 
-fn block_quote_kind_title(kind: BlockQuoteKind) -> &'static str {
-    match kind {
-        BlockQuoteKind::Note => "Note",
-        BlockQuoteKind::Tip => "Tip",
-        BlockQuoteKind::Important => "Important",
-        BlockQuoteKind::Warning => "Warning",
-        BlockQuoteKind::Caution => "Caution",
-    }
-}
-
 fn block_quote_kind_icon_path(kind: BlockQuoteKind) -> &'static str {
     match kind {
         BlockQuoteKind::Note => {
@@ -601,11 +2279,11 @@ fn block_quote_kind_icon_path(kind: BlockQuoteKind) -> &'static str {
     }
 }
 
-fn render_alert_title(out: &mut String, kind: BlockQuoteKind) {
+fn render_alert_title(out: &mut String, kind: BlockQuoteKind, labels: &Labels) {
     out.push_str("<p class=\"markdown-alert-title\"><svg class=\"octicon markdown-alert-icon\" viewBox=\"0 0 16 16\" width=\"16\" height=\"16\" aria-hidden=\"true\"><path d=\"");
     out.push_str(block_quote_kind_icon_path(kind));
     out.push_str("\"></path></svg>");
-    out.push_str(block_quote_kind_title(kind));
+    push_escaped_html(out, labels.get(block_quote_kind_name(kind)));
     out.push_str("</p>");
 }
 
@@ -616,40 +2294,84 @@ fn metadata_block_kind_name(kind: MetadataBlockKind) -> &'static str {
     }
 }
 
-fn sanitize_url(url: &str) -> String {
-    let trimmed = url.trim();
-    if trimmed.is_empty() {
-        return String::from("#");
-    }
+/// `data-` attributes the renderer writes and the preview page acts on, so
+/// author markup may not set them.
+const RESERVED_DATA_ATTRIBUTES: &[&str] = &[
+    "data-cols",
+    "data-footnote",
+    "data-line",
+    "data-line-end",
+    "data-preview",
+    "data-preview-html",
+    "data-render-anyway",
+    "data-resolve-comment",
+    "data-tasks-done",
+    "data-tasks-total",
+];
+
+pub(crate) fn is_reserved_data_attribute(name: &str) -> bool {
+    RESERVED_DATA_ATTRIBUTES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
 
-    let lower = trimmed.to_ascii_lowercase();
-    if lower.starts_with("javascript:")
-        || lower.starts_with("data:")
-        || lower.starts_with("vbscript:")
-    {
+/// Schemes links may use; relative links and fragments need none.
+const LINK_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+pub(crate) fn sanitize_url(url: &str) -> String {
+    let trimmed = url.trim();
+    if trimmed.is_empty() || !is_allowed_link(trimmed) {
         return String::from("#");
     }
 
     trimmed.to_string()
 }
 
-fn sanitize_image_url(url: &str) -> String {
+pub(crate) fn sanitize_image_url(url: &str) -> String {
     let trimmed = url.trim();
     if trimmed.is_empty() {
         return String::from("#");
     }
 
-    let lower = trimmed.to_ascii_lowercase();
-    if lower.starts_with("javascript:") || lower.starts_with("vbscript:") {
-        return String::from("#");
-    }
-    if lower.starts_with("data:") && !lower.starts_with("data:image/") {
+    let allowed = match url_scheme(trimmed).as_deref() {
+        None | Some("http" | "https") => true,
+        Some("data") => compact_url(trimmed)
+            .to_ascii_lowercase()
+            .starts_with("data:image/"),
+        Some(_) => false,
+    };
+    if !allowed {
         return String::from("#");
     }
 
     trimmed.to_string()
 }
 
+/// Whether `url` is relative or uses one of [`LINK_SCHEMES`]. Expects
+/// character references to be decoded already.
+pub(crate) fn is_allowed_link(url: &str) -> bool {
+    url_scheme(url).is_none_or(|scheme| LINK_SCHEMES.contains(&scheme.as_str()))
+}
+
+/// The lowercased scheme of `url` as a browser reads it, or `None` when it
+/// is relative.
+fn url_scheme(url: &str) -> Option<String> {
+    let compact = compact_url(url);
+    let (scheme, _) = compact.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|ch| ch.is_ascii_alphabetic())
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'));
+    valid.then(|| scheme.to_ascii_lowercase())
+}
+
+/// `url` without the whitespace and control characters browsers skip, so
+/// `java\tscript:` is seen for what it is.
+fn compact_url(url: &str) -> String {
+    url.chars()
+        .filter(|ch| !ch.is_ascii_control() && !ch.is_ascii_whitespace())
+        .collect()
+}
+
 fn heading_level_number(level: HeadingLevel) -> u8 {
     match level {
         HeadingLevel::H1 => 1,
@@ -661,7 +2383,7 @@ fn heading_level_number(level: HeadingLevel) -> u8 {
     }
 }
 
-fn push_escaped_html(out: &mut String, text: &str) {
+pub(crate) fn push_escaped_html(out: &mut String, text: &str) {
     for ch in text.chars() {
         match ch {
             '&' => out.push_str("&amp;"),
@@ -674,13 +2396,18 @@ fn push_escaped_html(out: &mut String, text: &str) {
     }
 }
 
-fn push_escaped_attr(out: &mut String, text: &str) {
+pub(crate) fn push_escaped_attr(out: &mut String, text: &str) {
     push_escaped_html(out, text);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::LiveMarkdownRenderer;
+    use super::{
+        Accessibility, ConverterCommand, DocumentStats, Extensions, Labels, Link, LinkKind,
+        LiveMarkdownRenderer, RenderContext, RenderLimits, RenderOptions, RenderProfile,
+        RendererKind, SlugStyle, SourceFormat, TaskProgress, Truncation,
+    };
+    use std::collections::HashMap;
 
     #[test]
     fn renders_common_markdown_blocks() {
@@ -715,6 +2442,27 @@ mod tests {
         assert!(html.contains("</svg>Caution</p>"));
     }
 
+    #[test]
+    fn aria_roles_mark_alerts_and_tables_unless_disabled() {
+        let markdown = "> [!NOTE]\n> body\n\n| a | b |\n|---|---|\n| 1 | 2 |";
+        let html = LiveMarkdownRenderer::default().render(markdown);
+        assert!(html.contains("class=\"markdown-alert markdown-alert-note\" role=\"note\">"));
+        assert!(html.contains("<table data-line=\"4\" role=\"table\">"));
+        assert!(html.contains("<th scope=\"col\">a</th>"));
+        assert!(html.contains("<td>1</td>"));
+
+        let html = LiveMarkdownRenderer::new(RenderOptions {
+            accessibility: Accessibility {
+                aria: false,
+                ..Accessibility::default()
+            },
+            ..RenderOptions::default()
+        })
+        .render(markdown);
+        assert!(!html.contains("role="));
+        assert!(!html.contains("scope="));
+    }
+
     #[test]
     fn keeps_regular_block_quotes_without_alert_chrome() {
         let renderer = LiveMarkdownRenderer::default();
@@ -746,6 +2494,118 @@ mod tests {
         assert!(html.contains("src=\"#\""));
     }
 
+    #[test]
+    fn renders_attribute_blocks_on_blocks_and_images() {
+        let markdown = "# Intro {.lead data-toc=skip}\n\nA callout paragraph. {#callout .note}\n\n![chart](chart.png){.wide width=50% onload=x} after\n\n```rust {#main .numbered}\nfn main() {}\n```\n\n```{.python}\npass\n```\n\nThe set {x=1} stays.\n";
+        let html = LiveMarkdownRenderer::new(RenderOptions {
+            lazy_images: false,
+            ..RenderOptions::default()
+        })
+        .render(markdown);
+
+        assert!(html.contains(
+            "<h1 data-line=\"1\" id=\"intro\" class=\"lead\" data-toc=\"skip\">Intro</h1>"
+        ));
+        assert!(
+            html.contains(
+                "<p id=\"callout\" class=\"note\" data-line=\"3\">A callout paragraph.</p>"
+            )
+        );
+        assert!(html.contains(
+            "<img class=\"wide\" width=\"50%\" data-onload=\"x\" src=\"chart.png\" alt=\"chart\" /> after"
+        ));
+        assert!(html.contains(
+            "<pre id=\"main\" class=\"numbered\" data-line=\"7\"><code class=\"language-rust\">"
+        ));
+        assert!(html.contains("<pre data-line=\"11\"><code class=\"language-python\">"));
+        assert!(html.contains("<p data-line=\"15\">The set {x=1} stays.</p>"));
+
+        let commonmark = LiveMarkdownRenderer::new(RenderOptions {
+            profile: Some(RenderProfile::Commonmark),
+            ..RenderOptions::default()
+        });
+        assert!(
+            commonmark
+                .render("Plain. {.note}")
+                .contains("<p data-line=\"1\">Plain. {.note}</p>")
+        );
+    }
+
+    #[test]
+    fn writes_plain_text_image_alts() {
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            lazy_images: false,
+            ..RenderOptions::default()
+        });
+        let html = renderer.render(
+            "![A *bold*\n`chart` <b>now</b>](a.png) ![](img/sales_by-region.png) ![](b.png \"Sales\")",
+        );
+        assert!(html.contains("<img src=\"a.png\" alt=\"A bold chart now\" />"));
+        assert!(html.contains("<img src=\"img/sales_by-region.png\" alt=\"sales by region\" />"));
+        assert!(html.contains("<img src=\"b.png\" alt=\"Sales\" title=\"Sales\" />"));
+    }
+
+    #[test]
+    fn marks_where_nested_quotes_and_items_end() {
+        let html = LiveMarkdownRenderer::default().render(
+            "## 2.0\n\n- Breaking\n  > [!WARNING]\n  > Config moved.\n  >\n  > > Old keys\n  > > still load.\n- Fixed\n\n  > quoted\n\nafter\n",
+        );
+
+        assert!(html.contains("<li data-line=\"3\" data-line-end=\"8\">"));
+        assert!(
+            html.contains("<blockquote data-line=\"4\" data-line-end=\"8\" data-alert=\"warning\"")
+        );
+        assert!(html.contains("<p data-line=\"5\">Config moved.</p>"));
+        assert!(
+            html.contains("<blockquote data-line=\"7\" data-line-end=\"8\"><p data-line=\"7\">")
+        );
+        assert!(html.contains("<li data-line=\"9\" data-line-end=\"11\">"));
+        assert!(html.contains("<blockquote data-line=\"11\"><p data-line=\"11\">quoted</p>"));
+        assert!(html.contains("<p data-line=\"13\">after</p>"));
+    }
+
+    #[test]
+    fn renders_standalone_images_as_figures() {
+        let markdown = "![Chart](chart.png \"Quarterly sales\")\n\n![Inline](x.png \"T\") in text\n\n![Q&A *board*](board.png)\n\n![](bare.png)\n";
+        let plain = LiveMarkdownRenderer::new(RenderOptions {
+            lazy_images: false,
+            ..RenderOptions::default()
+        });
+        assert!(!plain.render(markdown).contains("<figure"));
+
+        let figures = LiveMarkdownRenderer::new(RenderOptions {
+            lazy_images: false,
+            figures: true,
+            ..RenderOptions::default()
+        })
+        .render(markdown);
+        assert!(figures.contains(
+            "<figure data-line=\"1\" class=\"image-figure\"><img src=\"chart.png\" alt=\"Chart\" title=\"Quarterly sales\" /><figcaption>Quarterly sales</figcaption></figure>"
+        ));
+        assert!(figures.contains("<p data-line=\"3\"><img src=\"x.png\""));
+        assert!(figures.contains(
+            "<img src=\"board.png\" alt=\"Q&amp;A board\" /><figcaption>Q&amp;A board</figcaption></figure>"
+        ));
+        assert!(figures.contains(
+            "<figure data-line=\"7\" class=\"image-figure\"><img src=\"bare.png\" alt=\"bare\" /></figure>"
+        ));
+    }
+
+    #[test]
+    fn marks_images_lazy_unless_disabled() {
+        let markdown = "![chart](chart.png \"Q3\")";
+        assert_eq!(
+            LiveMarkdownRenderer::default().render(markdown),
+            "<article id=\"md-root\"><p data-line=\"1\"><img src=\"chart.png\" alt=\"chart\" title=\"Q3\" loading=\"lazy\" decoding=\"async\" /></p></article>"
+        );
+
+        let eager = LiveMarkdownRenderer::new(RenderOptions {
+            lazy_images: false,
+            ..RenderOptions::default()
+        });
+        assert!(!eager.render(markdown).contains("loading="));
+    }
+
     #[test]
     fn keeps_data_line_markers_monotonic() {
         let renderer = LiveMarkdownRenderer::default();
@@ -811,4 +2671,474 @@ mod tests {
             "<h2 data-line=\"5\" id=\"autoescape\">Automatic Escaping for Special Characters</h2>"
         ));
     }
+
+    #[test]
+    fn infers_heading_ids_from_links_after_the_heading() {
+        let renderer = LiveMarkdownRenderer::default();
+        let markdown = "## Inline HTML\n\nSee [Inline HTML](#html) again.";
+        let html = renderer.render(markdown);
+
+        assert!(html.contains("<h2 data-line=\"1\" id=\"html\">Inline HTML</h2>"));
+    }
+
+    #[test]
+    fn collects_links_and_images_with_kinds_and_lines() {
+        let renderer = LiveMarkdownRenderer::default();
+        let markdown = "# Top\n\nSee [top](#top) and [`notes`](notes.md).\n\n![logo](img/logo.png) [site](https://example.com)\n\n[![badge](https://example.com/b.svg)](file:///tmp/x.md)";
+        let links = renderer.render_document(markdown).links;
+
+        let link = |kind, target: &str, text: &str, line, image| Link {
+            kind,
+            target: target.to_string(),
+            text: text.to_string(),
+            line,
+            image,
+        };
+        assert_eq!(
+            links,
+            [
+                link(LinkKind::Anchor, "#top", "top", 3, false),
+                link(LinkKind::File, "notes.md", "notes", 3, false),
+                link(LinkKind::Asset, "img/logo.png", "logo", 5, true),
+                link(LinkKind::External, "https://example.com", "site", 5, false),
+                link(LinkKind::File, "file:///tmp/x.md", "badge", 7, false),
+                link(
+                    LinkKind::External,
+                    "https://example.com/b.svg",
+                    "badge",
+                    7,
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn enforces_render_limits_and_reports_the_truncation() {
+        let limited = |limits| {
+            LiveMarkdownRenderer::new(RenderOptions {
+                limits,
+                ..RenderOptions::default()
+            })
+        };
+
+        let rendered = limited(RenderLimits {
+            max_input_bytes: 12,
+            ..RenderLimits::default()
+        })
+        .render_document("first\n\nsecond line");
+        assert_eq!(rendered.truncated, Some(Truncation::InputBytes));
+        assert!(rendered.html.contains("first"));
+        assert!(!rendered.html.contains("second"));
+
+        let nested = format!("{} deep\n\n# After", ">".repeat(200));
+        let rendered = limited(RenderLimits {
+            max_nesting: 8,
+            ..RenderLimits::default()
+        })
+        .render_document(&nested);
+        assert_eq!(rendered.truncated, Some(Truncation::Nesting));
+        assert!(!rendered.html.contains("deep"));
+        assert_eq!(rendered.html.matches("<blockquote").count(), 8);
+        assert!(rendered.html.contains("id=\"after\">After</h1>"));
+
+        let rendered = limited(RenderLimits {
+            max_output_bytes: 256,
+            ..RenderLimits::default()
+        })
+        .render_document(&"para\n\n".repeat(1000));
+        assert_eq!(rendered.truncated, Some(Truncation::OutputBytes));
+        assert!(rendered.html.len() < 1024);
+        assert!(rendered.html.ends_with("</article>"));
+
+        let rendered = LiveMarkdownRenderer::default().render_document(&nested);
+        assert_eq!(rendered.truncated, Some(Truncation::Nesting));
+        assert_eq!(
+            LiveMarkdownRenderer::default()
+                .render_document("> fine")
+                .truncated,
+            None
+        );
+    }
+
+    #[test]
+    fn summarizes_task_list_progress_per_list_and_overall() {
+        let renderer = LiveMarkdownRenderer::default();
+        let markdown =
+            "- [x] one\n- [ ] two\n  - [x] nested\n\n1. [x] first\n2. [x] second\n\n- plain";
+        let rendered = renderer.render_document(markdown);
+
+        assert_eq!(rendered.tasks, TaskProgress { done: 4, total: 5 });
+        assert!(rendered.html.contains(
+            "<ul class=\"contains-task-list\" data-tasks-done=\"1\" data-tasks-total=\"2\">"
+        ));
+        assert!(rendered.html.contains(
+            "<ul class=\"contains-task-list\" data-tasks-done=\"1\" data-tasks-total=\"1\">"
+        ));
+        assert!(rendered.html.contains(
+            "<ol start=\"1\" class=\"contains-task-list\" data-tasks-done=\"2\" data-tasks-total=\"2\">"
+        ));
+        assert!(
+            rendered
+                .html
+                .contains("<ul><li data-line=\"8\">plain</li></ul>")
+        );
+    }
+
+    #[test]
+    fn long_paragraphs_carry_source_line_widths() {
+        let renderer = LiveMarkdownRenderer::default();
+        let long = "word ".repeat(20);
+        let markdown = format!(
+            "short\n\n{}\nand more\r\n\n> {}",
+            long.trim_end(),
+            long.trim_end()
+        );
+        let rendered = renderer.render_document(&markdown);
+
+        assert!(rendered.html.contains("<p data-line=\"1\">short</p>"));
+        assert!(
+            rendered
+                .html
+                .contains("<p data-line=\"3\" data-cols=\"99,8\">")
+        );
+        assert!(
+            rendered
+                .html
+                .contains("<p data-line=\"6\" data-cols=\"101\">")
+        );
+    }
+
+    #[test]
+    fn render_with_reuses_context_and_reports_outline_and_stats() {
+        let renderer = LiveMarkdownRenderer::default();
+        let mut ctx = RenderContext::default();
+        let markdown = "# Intro\n\nSome words here.\n\n## Tasks\n\n- [x] done\n- [ ] todo";
+
+        let view = renderer.render_with(&mut ctx, markdown);
+        assert_eq!(view.html, renderer.render(markdown));
+        let ids: Vec<_> = view.outline.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, ["intro", "tasks"]);
+        assert_eq!(
+            view.stats,
+            DocumentStats {
+                lines: 8,
+                words: 7,
+                headings: 2,
+                tasks: TaskProgress { done: 1, total: 2 },
+            }
+        );
+
+        let view = renderer.render_with(&mut ctx, "plain");
+        assert_eq!(view.html, renderer.render("plain"));
+        assert!(view.outline.is_empty());
+    }
+
+    #[test]
+    fn renders_citations_and_references_when_enabled() {
+        let bib_path = std::env::temp_dir().join(format!(
+            "live-markdown.nvim-citations-{}.bib",
+            std::process::id()
+        ));
+        std::fs::write(
+            &bib_path,
+            "@book{doe99, author = {Doe, Jane}, title = {Things}, year = {1999}}",
+        )
+        .expect("write bibliography");
+
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            citations: true,
+            bibliography: Some(bib_path.clone()),
+            ..RenderOptions::default()
+        });
+        let html = renderer.render("As shown [see @doe99, p. 4; @missing].");
+
+        assert!(html.contains("<span class=\"citation\" data-cites=\"doe99 missing\">(see <a href=\"#ref-doe99\">Doe 1999</a>, p. 4; <strong class=\"citation-missing\">?missing</strong>)</span>"));
+        assert!(html.contains(
+            "<div class=\"csl-entry\" id=\"ref-doe99\">Doe. 1999. <em>Things</em>. </div>"
+        ));
+
+        let plain = LiveMarkdownRenderer::default().render("As shown [@doe99].");
+        assert!(plain.contains("[@doe99]"));
+        assert!(!plain.contains("citation"));
+
+        let _ = std::fs::remove_file(bib_path);
+    }
+
+    #[test]
+    fn renders_mdx_esm_and_jsx_blocks_as_cards() {
+        let renderer = LiveMarkdownRenderer::default();
+        let markdown = "import { Chart } from './chart'\nexport const meta = {}\n\n# Title\n\n<Chart data={points} />\n\nText with <Badge /> inline.";
+        let html = renderer
+            .render_document_as(markdown, SourceFormat::Mdx)
+            .html;
+
+        assert!(html.contains("<div data-line=\"1\" class=\"mdx-block mdx-esm\"><p class=\"mdx-block-label\">import</p><pre><code>import { Chart } from &#39;./chart&#39;\nexport const meta = {}</code></pre></div>"));
+        assert!(html.contains("<h1 data-line=\"4\" id=\"title\">Title</h1>"));
+        assert!(html.contains("<div data-line=\"6\" class=\"mdx-block mdx-jsx\"><p class=\"mdx-block-label\">&lt;Chart&gt;</p>"));
+        assert!(html.contains("<code class=\"mdx-inline\">&lt;Badge /&gt;</code>"));
+
+        let plain = renderer.render(markdown);
+        assert!(!plain.contains("mdx-block"));
+    }
+
+    #[test]
+    fn detects_source_format_from_source_path() {
+        assert_eq!(
+            SourceFormat::from_source_path(Some("/notes/page.MDX")),
+            SourceFormat::Mdx
+        );
+        assert_eq!(
+            SourceFormat::from_source_path(Some("/notes/page.md")),
+            SourceFormat::Markdown
+        );
+        assert_eq!(SourceFormat::from_source_path(None), SourceFormat::Markdown);
+    }
+
+    #[test]
+    fn routes_configured_extensions_through_external_converters() {
+        let mut options = RenderOptions::default();
+        options.converters.insert(
+            String::from("adoc"),
+            ConverterCommand::from_argv(vec![String::from("cat")]).expect("command"),
+        );
+        let renderer = LiveMarkdownRenderer::new(options);
+
+        assert!(renderer.has_converter("ADOC"));
+        assert!(!renderer.has_converter("rst"));
+
+        let converted = renderer.render_source("<p>raw</p>", Some("/docs/guide.adoc"));
+        assert_eq!(
+            converted.html,
+            "<article id=\"md-root\" data-format=\"adoc\"><p>raw</p></article>"
+        );
+
+        let markdown = renderer.render_source("# Hi", Some("/docs/guide.md"));
+        assert!(
+            markdown
+                .html
+                .contains("<h1 data-line=\"1\" id=\"hi\">Hi</h1>")
+        );
+    }
+
+    #[test]
+    fn pandoc_renderer_keeps_line_sync_and_falls_back_on_failure() {
+        let shell = |script: &str| ConverterCommand {
+            program: String::from("sh"),
+            args: vec![String::from("-c"), script.to_string()],
+        };
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            renderer: RendererKind::Pandoc,
+            pandoc: shell("cat >/dev/null; printf '<dl data-pos=\"2:1-3:4\"><dt>Term</dt></dl>'"),
+            ..RenderOptions::default()
+        });
+        assert_eq!(
+            renderer
+                .render_source("\nTerm\n: Def", Some("/notes/a.md"))
+                .html,
+            "<article id=\"md-root\" data-format=\"pandoc\"><dl data-line=\"2\"><dt>Term</dt></dl></article>"
+        );
+
+        let broken = LiveMarkdownRenderer::new(RenderOptions {
+            renderer: RendererKind::Pandoc,
+            pandoc: shell("exit 1"),
+            ..RenderOptions::default()
+        });
+        let fallback = broken.render_source("# Hi", None);
+        assert!(
+            fallback
+                .html
+                .contains("<h1 data-line=\"1\" id=\"hi\">Hi</h1>")
+        );
+        assert!(fallback.failure.is_some_and(|err| err.contains("exited")));
+        assert_eq!(RendererKind::parse(" Pandoc "), Some(RendererKind::Pandoc));
+        assert_eq!(RendererKind::parse("other"), None);
+    }
+
+    #[test]
+    fn render_profiles_bundle_extensions_slugs_and_wiki_links() {
+        let markdown =
+            "# 1. Intro: Why?\n\n> [!NOTE]\n> Hi\n\n[[Other Page#Some Heading|other]] ~~gone~~";
+        let with_profile = |profile| {
+            LiveMarkdownRenderer::new(RenderOptions {
+                profile: Some(profile),
+                ..RenderOptions::default()
+            })
+            .render(markdown)
+        };
+
+        let github = with_profile(RenderProfile::Github);
+        assert!(github.contains("id=\"1-intro-why\""));
+        assert!(github.contains("markdown-alert-note"));
+        assert!(github.contains("<del>gone</del>"));
+        assert!(github.contains("[[Other Page#Some Heading|other]]"));
+
+        let obsidian = with_profile(RenderProfile::Obsidian);
+        assert!(obsidian.contains("<a href=\"Other Page.md#some-heading\">other</a>"));
+
+        let commonmark = with_profile(RenderProfile::Commonmark);
+        assert!(!commonmark.contains("markdown-alert"));
+        assert!(commonmark.contains("~~gone~~"));
+
+        let pandoc = with_profile(RenderProfile::Pandoc);
+        assert!(pandoc.contains("id=\"intro-why\""));
+        assert!(!pandoc.contains("markdown-alert"));
+
+        assert_eq!(RenderProfile::parse("GFM"), Some(RenderProfile::Github));
+        assert_eq!(RenderProfile::parse("mystery"), None);
+    }
+
+    #[test]
+    fn explicit_extensions_and_slug_style_override_the_profile() {
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            profile: Some(RenderProfile::Github),
+            extensions: Some(Extensions {
+                tables: false,
+                ..Extensions::default()
+            }),
+            slug_style: Some(SlugStyle::Pandoc),
+            ..RenderOptions::default()
+        });
+        let rendered = renderer.render_document("# 1. Intro\n\n| a |\n|---|\n| b |");
+
+        assert!(!rendered.html.contains("<table"));
+        assert_eq!(rendered.outline.len(), 1);
+        assert_eq!(rendered.outline[0].id, "intro");
+        assert_eq!(rendered.stats.headings, 1);
+    }
+
+    #[test]
+    fn sanitizes_raw_html_only_when_enabled() {
+        let markdown =
+            "<div onclick=\"x()\"><b>hi</b><script>x()</script></div>\n\ninline <em>ok</em>";
+
+        let escaped = LiveMarkdownRenderer::default().render(markdown);
+        assert!(escaped.contains("<pre data-line=\"1\" class=\"html-block\">&lt;div"));
+
+        let sanitized = LiveMarkdownRenderer::new(RenderOptions {
+            sanitize_html: true,
+            ..RenderOptions::default()
+        })
+        .render(markdown);
+        assert!(
+            sanitized.contains("<div data-line=\"1\" class=\"html-block\"><div><b>hi</b></div>")
+        );
+        assert!(!sanitized.contains("script"));
+        assert!(sanitized.contains("inline <em>ok</em>"));
+    }
+
+    #[test]
+    fn exposes_frontmatter_overrides_on_the_preview_root() {
+        let rendered = LiveMarkdownRenderer::default().render_document(
+            "---\nlive_markdown: { theme: light, toc: true, max_width: 900 }\n---\n# Deck",
+        );
+
+        assert!(rendered.html.starts_with(
+            "<article id=\"md-root\" data-theme=\"light\" data-toc=\"true\" data-max-width=\"900\">"
+        ));
+        assert_eq!(rendered.overrides.toc, Some(true));
+
+        let plain = LiveMarkdownRenderer::default().render_document("# Deck");
+        assert!(plain.html.starts_with("<article id=\"md-root\">"));
+    }
+
+    #[test]
+    fn localizes_alert_titles_from_configured_labels() {
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            labels: Labels::new(HashMap::from([(
+                String::from("note"),
+                String::from("Hinweis"),
+            )])),
+            ..RenderOptions::default()
+        });
+
+        let html = renderer.render("> [!NOTE]\n> a\n\n> [!TIP]\n> b");
+        assert!(html.contains("</svg>Hinweis</p>"));
+        assert!(html.contains("</svg>Tip</p>"));
+    }
+
+    #[test]
+    fn renders_ansi_escapes_in_ansi_and_console_blocks() {
+        let renderer = LiveMarkdownRenderer::default();
+        let html = renderer.render(
+            "```console\n$ cargo test\n\\e[32mok\\e[0m\n```\n\n```console\n$ ls\n```\n\n```sh\necho \\e[31m\n```",
+        );
+
+        assert!(html.contains("<code class=\"language-console ansi\">$ cargo test\n<span class=\"ansi-fg-2\">ok</span>\n</code>"));
+        assert!(html.contains("<code class=\"language-console\">$ ls\n</code>"));
+        assert!(html.contains("<code class=\"language-sh\">echo \\e[31m\n</code>"));
+    }
+
+    #[test]
+    fn renders_diff_blocks_with_line_classes() {
+        let html = LiveMarkdownRenderer::default()
+            .render("```diff\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-old\n+new <b>\n same\n```");
+
+        assert!(html.contains("<code class=\"language-diff diff\"><span class=\"diff-line diff-meta\">--- a/x</span>\n<span class=\"diff-line diff-meta\">+++ b/x</span>\n<span class=\"diff-line diff-hunk\">@@ -1 +1 @@</span>\n<span class=\"diff-line diff-remove\">-old</span>\n<span class=\"diff-line diff-add\">+new &lt;b&gt;</span>\n<span class=\"diff-line diff-context\"> same</span>\n</code>"));
+    }
+
+    #[test]
+    fn renders_kbd_shorthand_only_when_enabled() {
+        let markdown = "Press [[Ctrl]]+[[K]] or <kbd>Esc</kbd>, not `[[code]]` or [[]].";
+        let renderer = LiveMarkdownRenderer::new(RenderOptions {
+            kbd: true,
+            ..RenderOptions::default()
+        });
+
+        assert_eq!(
+            renderer.render(markdown),
+            "<article id=\"md-root\"><p data-line=\"1\">Press <kbd>Ctrl</kbd>+<kbd>K</kbd> or <kbd>Esc</kbd>, not <code>[[code]]</code> or [[]].</p></article>"
+        );
+        // Wikilinks to notes still link.
+        let html = renderer.render("See [[Setup notes]] and [[F5]].");
+        assert!(
+            html.contains("<a href=\"Setup notes\">Setup notes</a>"),
+            "{html}"
+        );
+        assert!(html.contains("<kbd>F5</kbd>"), "{html}");
+
+        let plain = LiveMarkdownRenderer::default().render(markdown);
+        assert!(!plain.contains("<kbd>"));
+    }
+
+    #[test]
+    fn hardbreaks_turn_single_newlines_into_line_breaks() {
+        let markdown = "first\nsecond";
+        let hard = LiveMarkdownRenderer::new(RenderOptions {
+            hardbreaks: Some(true),
+            ..RenderOptions::default()
+        });
+        assert!(hard.render(markdown).contains("first<br />\nsecond"));
+        assert!(
+            LiveMarkdownRenderer::default()
+                .render(markdown)
+                .contains("first\nsecond")
+        );
+
+        let obsidian = LiveMarkdownRenderer::new(RenderOptions {
+            profile: Some(RenderProfile::Obsidian),
+            ..RenderOptions::default()
+        });
+        assert!(obsidian.render(markdown).contains("<br />"));
+
+        let obsidian_soft = LiveMarkdownRenderer::new(RenderOptions {
+            profile: Some(RenderProfile::Obsidian),
+            hardbreaks: Some(false),
+            ..RenderOptions::default()
+        });
+        assert!(!obsidian_soft.render(markdown).contains("<br />"));
+    }
+
+    #[test]
+    fn links_footnotes_and_headings_inside_task_lists() {
+        let html = LiveMarkdownRenderer::default().render(
+            "- [ ] see[^1] and [setup](#setup)\n- [x] done\n\n## Setup\n\nInstall first.\n\n[^1]: Footnote *body*.",
+        );
+
+        assert!(html.contains(
+            "<ul class=\"contains-task-list\" data-tasks-done=\"1\" data-tasks-total=\"2\"><li data-line=\"1\"><input type=\"checkbox\" disabled /> see<sup class=\"footnote-ref\"><a href=\"#fn-1\">1</a></sup> and <a href=\"#setup\">setup</a></li>"
+        ));
+        assert!(html.contains("<h2 data-line=\"4\" id=\"setup\">Setup</h2>"));
+        assert!(html.contains("class=\"footnote\" id=\"fn-1\" data-footnote=\"1\""));
+    }
 }
@@ -0,0 +1,81 @@
+use crate::protocol::Comment;
+use std::path::{Path, PathBuf};
+
+/// The open comments as a markdown review: one section per commented line,
+/// quoting the source line the comments are attached to.
+pub fn review_markdown(filename: &str, source: &str, comments: &[Comment]) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut out = format!("# Review of {filename}\n\n");
+    out.push_str(&match comments.len() {
+        1 => String::from("1 comment.\n"),
+        count => format!("{count} comments.\n"),
+    });
+
+    let mut previous_line = None;
+    for comment in comments {
+        if previous_line != Some(comment.line) {
+            previous_line = Some(comment.line);
+            out.push_str(&format!("\n## Line {}\n\n", comment.line));
+            if let Some(quoted) = lines
+                .get(comment.line.saturating_sub(1))
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+            {
+                out.push_str("> ");
+                out.push_str(quoted);
+                out.push_str("\n\n");
+            }
+        }
+        out.push_str("- ");
+        out.push_str(&comment.text.trim().replace('\n', "\n  "));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Where a buffer's review goes when no path is given: next to the file, as
+/// `<name>.review.md`.
+pub fn default_review_path(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".review.md");
+    source.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_review_path, review_markdown};
+    use crate::protocol::Comment;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn groups_comments_under_their_source_line() {
+        let comment = |id, line, text: &str| Comment {
+            id,
+            line,
+            text: text.to_string(),
+        };
+        let review = review_markdown(
+            "spec.md",
+            "# Spec\n\nThe server *must* retry.\n",
+            &[
+                comment(2, 1, "Title?"),
+                comment(1, 3, "How often?"),
+                comment(3, 3, "Backoff\nplease"),
+                comment(4, 9, "past the end"),
+            ],
+        );
+
+        assert_eq!(
+            review,
+            "# Review of spec.md\n\n4 comments.\n\
+             \n## Line 1\n\n> # Spec\n\n- Title?\n\
+             \n## Line 3\n\n> The server *must* retry.\n\n- How often?\n- Backoff\n  please\n\
+             \n## Line 9\n\n- past the end\n"
+        );
+        assert_eq!(
+            default_review_path(Path::new("/notes/spec.md")),
+            PathBuf::from("/notes/spec.md.review.md")
+        );
+    }
+}
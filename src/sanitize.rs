@@ -0,0 +1,698 @@
+use crate::render::{
+    is_allowed_link, is_reserved_data_attribute, push_escaped_attr, sanitize_image_url,
+    sanitize_url,
+};
+
+const DROPPED_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "template", "frame", "frameset",
+    "applet",
+];
+const DROPPED_TAGS: &[&str] = &["base", "meta", "link", "form", "html", "head", "body"];
+const SVG_DROPPED_WITH_CONTENT: &[&str] = &[
+    "script",
+    "foreignobject",
+    "iframe",
+    "object",
+    "embed",
+    "handler",
+    "listener",
+];
+// Animation values can retarget `href` at runtime.
+const SVG_ANIMATION_ATTRIBUTES: &[&str] = &["to", "from", "values", "by"];
+const URL_ATTRIBUTES: &[&str] = &["href", "xlink:href", "cite"];
+const IMAGE_URL_ATTRIBUTES: &[&str] = &["src", "srcset", "poster"];
+// Anything else is dropped, along with every `on*` handler; `data-*` and
+// `aria-*` attributes are kept too, except the renderer's own `data-*`.
+const HTML_ATTRIBUTES: &[&str] = &[
+    "abbr",
+    "align",
+    "alt",
+    "autoplay",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "checked",
+    "cite",
+    "class",
+    "color",
+    "cols",
+    "colspan",
+    "controls",
+    "datetime",
+    "decoding",
+    "default",
+    "dir",
+    "disabled",
+    "face",
+    "headers",
+    "height",
+    "hidden",
+    "href",
+    "hreflang",
+    "id",
+    "kind",
+    "label",
+    "lang",
+    "loading",
+    "loop",
+    "media",
+    "muted",
+    "name",
+    "nowrap",
+    "open",
+    "playsinline",
+    "poster",
+    "preload",
+    "rel",
+    "reversed",
+    "role",
+    "rows",
+    "rowspan",
+    "scope",
+    "size",
+    "sizes",
+    "span",
+    "src",
+    "srclang",
+    "srcset",
+    "start",
+    "style",
+    "summary",
+    "tabindex",
+    "target",
+    "title",
+    "translate",
+    "type",
+    "valign",
+    "value",
+    "width",
+];
+const SVG_ATTRIBUTES: &[&str] = &[
+    "accumulate",
+    "additive",
+    "alignment-baseline",
+    "amplitude",
+    "attributename",
+    "attributetype",
+    "azimuth",
+    "basefrequency",
+    "baseline-shift",
+    "begin",
+    "bias",
+    "by",
+    "calcmode",
+    "class",
+    "clip",
+    "clip-path",
+    "clip-rule",
+    "clippathunits",
+    "color",
+    "color-interpolation",
+    "color-interpolation-filters",
+    "color-rendering",
+    "cursor",
+    "cx",
+    "cy",
+    "d",
+    "diffuseconstant",
+    "direction",
+    "display",
+    "divisor",
+    "dominant-baseline",
+    "dur",
+    "dx",
+    "dy",
+    "edgemode",
+    "elevation",
+    "end",
+    "exponent",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "filter",
+    "filterunits",
+    "flood-color",
+    "flood-opacity",
+    "font-family",
+    "font-size",
+    "font-size-adjust",
+    "font-stretch",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "fr",
+    "from",
+    "fx",
+    "fy",
+    "gradienttransform",
+    "gradientunits",
+    "height",
+    "href",
+    "id",
+    "image-rendering",
+    "in",
+    "in2",
+    "intercept",
+    "isolation",
+    "k",
+    "k1",
+    "k2",
+    "k3",
+    "k4",
+    "kernelmatrix",
+    "kernelunitlength",
+    "keypoints",
+    "keysplines",
+    "keytimes",
+    "lang",
+    "lengthadjust",
+    "letter-spacing",
+    "lighting-color",
+    "limitingconeangle",
+    "marker-end",
+    "marker-mid",
+    "marker-start",
+    "markerheight",
+    "markerunits",
+    "markerwidth",
+    "mask",
+    "maskcontentunits",
+    "maskunits",
+    "max",
+    "media",
+    "min",
+    "mix-blend-mode",
+    "mode",
+    "numoctaves",
+    "offset",
+    "opacity",
+    "operator",
+    "order",
+    "orient",
+    "overflow",
+    "paint-order",
+    "path",
+    "pathlength",
+    "patterncontentunits",
+    "patterntransform",
+    "patternunits",
+    "pointer-events",
+    "points",
+    "pointsatx",
+    "pointsaty",
+    "pointsatz",
+    "preservealpha",
+    "preserveaspectratio",
+    "primitiveunits",
+    "r",
+    "radius",
+    "refx",
+    "refy",
+    "repeatcount",
+    "repeatdur",
+    "restart",
+    "result",
+    "role",
+    "rotate",
+    "rx",
+    "ry",
+    "scale",
+    "seed",
+    "shape-rendering",
+    "side",
+    "spacing",
+    "specularconstant",
+    "specularexponent",
+    "spreadmethod",
+    "startoffset",
+    "stddeviation",
+    "stitchtiles",
+    "stop-color",
+    "stop-opacity",
+    "stroke",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-miterlimit",
+    "stroke-opacity",
+    "stroke-width",
+    "style",
+    "surfacescale",
+    "systemlanguage",
+    "tabindex",
+    "tablevalues",
+    "targetx",
+    "targety",
+    "text-anchor",
+    "text-decoration",
+    "text-rendering",
+    "textlength",
+    "to",
+    "transform",
+    "transform-origin",
+    "type",
+    "values",
+    "vector-effect",
+    "version",
+    "viewbox",
+    "visibility",
+    "white-space",
+    "width",
+    "word-spacing",
+    "writing-mode",
+    "x",
+    "x1",
+    "x2",
+    "xchannelselector",
+    "xlink:href",
+    "xlink:title",
+    "xml:space",
+    "xmlns",
+    "xmlns:xlink",
+    "y",
+    "y1",
+    "y2",
+    "ychannelselector",
+    "z",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Markup {
+    Html,
+    Svg,
+}
+
+pub fn sanitize_html(input: &str) -> String {
+    sanitize_markup(input, Markup::Html)
+}
+
+/// Strips scripts, `foreignObject` and event handlers from an SVG document
+/// while keeping its (case-sensitive) element and attribute names intact.
+pub fn sanitize_svg(input: &str) -> String {
+    sanitize_markup(input, Markup::Svg)
+}
+
+fn sanitize_markup(input: &str, markup: Markup) -> String {
+    let (dropped_with_content, dropped_tags) = match markup {
+        Markup::Html => (DROPPED_WITH_CONTENT, DROPPED_TAGS),
+        Markup::Svg => (SVG_DROPPED_WITH_CONTENT, &[][..]),
+    };
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+
+        if markup == Markup::Svg && rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").map_or(rest.len(), |end| end + 3);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let Some(tag) = parse_tag(rest) else {
+            out.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[tag.len..];
+
+        if dropped_with_content.contains(&tag.name.as_str()) {
+            if !tag.closing && !tag.self_closing {
+                rest = skip_past_closing_tag(rest, &tag.name);
+            }
+            continue;
+        }
+
+        if dropped_tags.contains(&tag.name.as_str()) {
+            continue;
+        }
+
+        push_tag(&mut out, &tag, markup);
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[derive(Debug)]
+struct ParsedTag {
+    name: String,
+    raw_name: String,
+    closing: bool,
+    self_closing: bool,
+    attrs: Vec<(String, Option<String>)>,
+    len: usize,
+}
+
+fn parse_tag(input: &str) -> Option<ParsedTag> {
+    let bytes = input.as_bytes();
+    let mut index = 1;
+    let closing = bytes.get(index) == Some(&b'/');
+    if closing {
+        index += 1;
+    }
+
+    let name_start = index;
+    while index < bytes.len()
+        && (bytes[index].is_ascii_alphanumeric() || matches!(bytes[index], b'-' | b':'))
+    {
+        index += 1;
+    }
+    if index == name_start || !bytes[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let raw_name = input[name_start..index].to_string();
+    let name = raw_name.to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+
+        match bytes.get(index)? {
+            b'>' => {
+                index += 1;
+                break;
+            }
+            b'/' => {
+                self_closing = true;
+                index += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let attr_start = index;
+        while index < bytes.len()
+            && !bytes[index].is_ascii_whitespace()
+            && !matches!(bytes[index], b'=' | b'>' | b'/')
+        {
+            index += 1;
+        }
+        if index == attr_start {
+            index += 1;
+            continue;
+        }
+        let attr_name = input[attr_start..index].to_string();
+
+        while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+
+        if bytes.get(index) != Some(&b'=') {
+            attrs.push((attr_name, None));
+            continue;
+        }
+        index += 1;
+        while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+
+        let value = match bytes.get(index)? {
+            quote @ (b'"' | b'\'') => {
+                let value_start = index + 1;
+                let value_len = input[value_start..].find(*quote as char)?;
+                index = value_start + value_len + 1;
+                &input[value_start..value_start + value_len]
+            }
+            _ => {
+                let value_start = index;
+                while index < bytes.len()
+                    && !bytes[index].is_ascii_whitespace()
+                    && bytes[index] != b'>'
+                {
+                    index += 1;
+                }
+                &input[value_start..index]
+            }
+        };
+        attrs.push((attr_name, Some(decode_char_refs(value))));
+    }
+
+    Some(ParsedTag {
+        name,
+        raw_name,
+        closing,
+        self_closing,
+        attrs,
+        len: index,
+    })
+}
+
+fn skip_past_closing_tag<'a>(input: &'a str, name: &str) -> &'a str {
+    let lower = input.to_ascii_lowercase();
+    let needle = format!("</{name}");
+    let Some(start) = lower.find(&needle) else {
+        return "";
+    };
+
+    match input[start..].find('>') {
+        Some(end) => &input[start + end + 1..],
+        None => "",
+    }
+}
+
+fn push_tag(out: &mut String, tag: &ParsedTag, markup: Markup) {
+    let tag_name = match markup {
+        Markup::Html => &tag.name,
+        Markup::Svg => &tag.raw_name,
+    };
+    out.push('<');
+    if tag.closing {
+        out.push('/');
+        out.push_str(tag_name);
+        out.push('>');
+        return;
+    }
+
+    out.push_str(tag_name);
+    for (raw_name, value) in &tag.attrs {
+        let lower = raw_name.to_ascii_lowercase();
+        let name = match markup {
+            Markup::Html => lower.as_str(),
+            Markup::Svg => raw_name.as_str(),
+        };
+        if !is_allowed_attribute(&lower, markup) {
+            continue;
+        }
+        if markup == Markup::Svg
+            && SVG_ANIMATION_ATTRIBUTES.contains(&lower.as_str())
+            && value
+                .as_ref()
+                .is_some_and(|value| !value.split(';').all(is_allowed_link))
+        {
+            continue;
+        }
+
+        out.push(' ');
+        out.push_str(name);
+        let Some(value) = value else {
+            continue;
+        };
+
+        let value = if URL_ATTRIBUTES.contains(&lower.as_str()) {
+            sanitize_url(value)
+        } else if IMAGE_URL_ATTRIBUTES.contains(&lower.as_str()) {
+            sanitize_image_url(value)
+        } else {
+            value.clone()
+        };
+
+        out.push_str("=\"");
+        push_escaped_attr(out, &value);
+        out.push('"');
+    }
+
+    if tag.self_closing {
+        out.push_str(" /");
+    }
+    out.push('>');
+}
+
+fn is_allowed_attribute(lower: &str, markup: Markup) -> bool {
+    if lower.starts_with("on") {
+        return false;
+    }
+    if lower.starts_with("data-") {
+        return !is_reserved_data_attribute(lower);
+    }
+    if lower.starts_with("aria-") {
+        return true;
+    }
+    match markup {
+        Markup::Html => HTML_ATTRIBUTES.contains(&lower),
+        Markup::Svg => SVG_ATTRIBUTES.contains(&lower),
+    }
+}
+
+/// Decodes the character references of an attribute value, so URL checks see
+/// what the browser will. Unknown named references are kept as written; they
+/// are escaped on output, so the browser shows them literally.
+fn decode_char_refs(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        match decode_char_ref(rest) {
+            Some((ch, len)) => {
+                out.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The character `input` starts with a reference to, and the reference's length.
+fn decode_char_ref(input: &str) -> Option<(char, usize)> {
+    let body = &input[1..];
+    if let Some(number) = body.strip_prefix('#') {
+        let (digits, radix, prefix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (number, 10, 1),
+        };
+        let len = digits
+            .find(|ch: char| !ch.is_digit(radix))
+            .unwrap_or(digits.len());
+        if len == 0 {
+            return None;
+        }
+        let code = digits[..len].chars().fold(0u32, |code, digit| {
+            code.saturating_mul(radix)
+                .saturating_add(digit.to_digit(radix).unwrap_or(0))
+        });
+        let ch = char::from_u32(code)
+            .filter(|&ch| ch != '\0')
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        let semicolon = usize::from(digits[len..].starts_with(';'));
+        return Some((ch, 1 + prefix + len + semicolon));
+    }
+
+    let (name, _) = body.split_once(';')?;
+    let ch = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "Tab" => '\t',
+        "NewLine" => '\n',
+        "colon" => ':',
+        "sol" => '/',
+        "lpar" => '(',
+        "rpar" => ')',
+        "copy" => '\u{a9}',
+        "reg" => '\u{ae}',
+        "hellip" => '\u{2026}',
+        "ndash" => '\u{2013}',
+        "mdash" => '\u{2014}',
+        _ => return None,
+    };
+    Some((ch, name.len() + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize_html, sanitize_svg};
+
+    #[test]
+    fn strips_scripts_event_handlers_and_dangerous_urls() {
+        let html = sanitize_html(
+            "<p onclick=\"x()\" class='lead'>Hi <a href=\"javascript:alert(1)\">x</a></p><script>alert(1)</script><img src=x onerror=alert(1) /><!-- note -->",
+        );
+
+        assert_eq!(
+            html,
+            "<p class=\"lead\">Hi <a href=\"#\">x</a></p><img src=\"x\" />"
+        );
+    }
+
+    #[test]
+    fn checks_urls_after_decoding_character_references() {
+        for href in [
+            "&#106;avascript:alert(1)",
+            "&#x6A;avascript:alert(1)",
+            "&#106avascript:alert(1)",
+            "&#0000000000106;avascript:alert(1)",
+            "java&#9;script:alert(1)",
+            "java&Tab;script:alert(1)",
+            "javascript&colon;alert(1)",
+            " \u{1}javascript:alert(1)",
+            "vbscript:x",
+            "data:text/html,x",
+        ] {
+            let html = sanitize_html(&format!("<a href=\"{href}\">x</a>"));
+            assert_eq!(html, "<a href=\"#\">x</a>", "{href}");
+        }
+
+        assert_eq!(
+            sanitize_html("<a href=\"/a?b=1&amp;c=2\" title=\"&lt;&#34;&unknown;\">x</a>"),
+            "<a href=\"/a?b=1&amp;c=2\" title=\"&lt;&quot;&amp;unknown;\">x</a>"
+        );
+        assert_eq!(
+            sanitize_html("<a href=\"mailto:a@b.c\" style=\"color: red\" formaction=\"x\">x</a>"),
+            "<a href=\"mailto:a@b.c\" style=\"color: red\">x</a>"
+        );
+    }
+
+    #[test]
+    fn drops_encoded_scripts_from_svg_animations() {
+        let svg = sanitize_svg(
+            "<svg><a><set attributeName=\"href\" to=\"&#106;avascript:x\"/><animate attributeName=\"href\" values=\"#a;java&#x09;script:x\"/><animate attributeName=\"x\" values=\"0;10\"/></a></svg>",
+        );
+
+        assert_eq!(
+            svg,
+            "<svg><a><set attributeName=\"href\" /><animate attributeName=\"href\" /><animate attributeName=\"x\" values=\"0;10\" /></a></svg>"
+        );
+    }
+
+    #[test]
+    fn drops_data_attributes_the_renderer_owns() {
+        assert_eq!(
+            sanitize_html(
+                "<span data-preview-html=\"<img src=x onerror=alert(1)>\" data-line=\"3\" data-resolve-comment=\"1\" data-kind=\"note\" aria-label=\"x\">x</span>"
+            ),
+            "<span data-kind=\"note\" aria-label=\"x\">x</span>"
+        );
+    }
+
+    #[test]
+    fn keeps_text_with_stray_angle_brackets() {
+        assert_eq!(sanitize_html("1 < 2 <b>ok</b>"), "1 &lt; 2 <b>ok</b>");
+    }
+
+    #[test]
+    fn sanitizes_svg_without_lowercasing_it() {
+        let svg = sanitize_svg(
+            "<?xml version=\"1.0\"?><svg viewBox=\"0 0 10 10\" onload=\"alert(1)\"><style><![CDATA[a > b { fill: red }]]></style><linearGradient id=\"g\"/><script>alert(1)</script><foreignObject><div>x</div></foreignObject><a xlink:href=\"javascript:alert(1)\"><rect width=\"10\" onClick=\"x()\"/><set attributeName=\"href\" to=\"java script:x\"/></a></svg>",
+        );
+
+        assert_eq!(
+            svg,
+            "<svg viewBox=\"0 0 10 10\"><style><![CDATA[a > b { fill: red }]]></style><linearGradient id=\"g\" /><a xlink:href=\"#\"><rect width=\"10\" /><set attributeName=\"href\" /></a></svg>"
+        );
+    }
+}
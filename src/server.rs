@@ -1,23 +1,50 @@
-use crate::protocol::{ServerEvent, SessionQuery};
-use crate::session::SessionManager;
+use crate::access::{AccessLog, Peer};
+use crate::asset_links::{AssetLinkMode, AssetLinks};
+use crate::data_uri::{data_uri, rewrite_image_sources};
+use crate::port_owner::{PortOwner, port_owner};
+use crate::positions;
+use crate::protocol::{
+    BookmarkUpdate, CommentAction, ConnectionsResponse, DetachUpdate, FollowScope, OpenBrowser,
+    PresenceUpdate, ReadingPositionResponse, RecentFile, ResumeQuery, ScrollAnchor, ScrollBehavior,
+    ServerEvent, SessionEndReason, SessionQuery, SessionsQuery, SnapshotQuery, SnapshotResponse,
+    ThemeMode, UrlEcho,
+};
+use crate::recent;
+use crate::render::{
+    Accessibility, LiveMarkdownRenderer, RenderOptions, RenderProfile, RendererKind,
+    push_escaped_html,
+};
+use crate::sanitize::sanitize_svg;
+use crate::session::{SequencedEvent, SessionManager};
+use crate::theme;
+use crate::thumbnail::{
+    THUMBNAIL_MIN_BYTES, ThumbnailCache, ThumbnailKey, downscale, thumbnail_width,
+};
 use async_stream::stream;
-use axum::extract::{Query, State};
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::extract::{ConnectInfo, Extension, Path as UrlPath, Query, Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header::USER_AGENT};
+use axum::middleware::{self, Next};
 use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{Html, IntoResponse, Response};
-use axum::{Json, Router, routing::get};
+use axum::{
+    Json, Router,
+    routing::{get, post},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, UNIX_EPOCH};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tokio::net::TcpListener;
-use tokio::sync::{Mutex, oneshot};
+use tokio::sync::{Mutex, broadcast, oneshot, watch};
 use tokio::task::JoinHandle;
 
 const PORT_FALLBACK_ATTEMPTS: u16 = 12;
 const PREVIEW_HTML: &str = include_str!("assets/preview.html");
+const FAVICON_SVG: &str = include_str!("assets/favicon.svg");
 
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
@@ -28,6 +55,75 @@ pub struct ServerConfig {
     pub auto_scroll: bool,
     pub scroll_comfort_top: f64,
     pub scroll_comfort_bottom: f64,
+    pub scroll_behavior: ScrollBehavior,
+    /// Length of a smooth scroll; 0 eases in proportion to the distance.
+    pub scroll_duration_ms: u64,
+    /// How far the followed line is pulled towards the window's middle (0..1).
+    pub scroll_center_bias: f64,
+    pub scroll_anchor: ScrollAnchor,
+    /// Whether previews follow buffers entered anywhere, or only in the
+    /// tabpage or window they were started from.
+    pub follow_scope: FollowScope,
+    /// Whether `:LiveMarkdownStart` opens the preview in the system browser.
+    pub open_browser: OpenBrowser,
+    /// Commands tried in order to open the preview, each given the URL as its
+    /// last argument; empty uses the platform's usual openers.
+    pub browser_openers: Vec<Vec<String>>,
+    /// How the URL of a newly started preview is announced.
+    pub url_echo: UrlEcho,
+    /// The palette of previews whose session and document pick none.
+    pub theme: ThemeMode,
+    /// Builds the preview palette from the editor's highlight groups and
+    /// serves it from `/theme.css`.
+    pub sync_colorscheme: bool,
+    /// Report problems such as duplicate heading ids as editor diagnostics
+    /// when a previewed buffer is started or written.
+    pub diagnostics: bool,
+    pub render: RenderOptions,
+    pub text_browser: Vec<String>,
+    pub stop_on_last_client_disconnect: bool,
+    pub disconnect_grace_ms: u64,
+    /// Warn when a render takes at least this long; 0 disables the warning.
+    pub slow_render_ms: u64,
+    /// How often previews are checked against the buffers that still exist;
+    /// 0 disables the check.
+    pub session_check_ms: u64,
+    /// How often local images shown in previews are checked for changes on
+    /// disk; 0 disables the check.
+    pub asset_watch_ms: u64,
+    /// Non-image files the preview may serve, by lowercase extension.
+    pub document_types: HashMap<String, String>,
+    /// Local images up to this size are embedded as data URIs in exports;
+    /// 0 links them instead, unless an export asks for `assets=data`.
+    pub inline_image_max_bytes: u64,
+    /// Serve downscaled copies of large images when the preview asks for a width.
+    pub image_thumbnails: bool,
+    /// Serve local SVGs as they are instead of sanitizing and sandboxing them.
+    pub trust_local_svg: bool,
+    /// Append every session's events to a JSONL file in this directory.
+    pub record_dir: Option<PathBuf>,
+    /// Larger buffers render only their first part until the preview asks
+    /// for the rest; 0 disables the cap.
+    pub max_document_bytes: usize,
+    /// Relative assets of buffers without a file resolve against this directory.
+    pub default_asset_root: Option<PathBuf>,
+    /// Extra filetype -> backend entries (`markdown`, `mdx`, `djot` or a
+    /// converter's extension) for picking a buffer's renderer.
+    pub filetypes: HashMap<String, String>,
+    /// Underline words missing from the spelling dictionaries in the preview.
+    pub spellcheck: bool,
+    /// Dictionaries looked up by name under `/usr/share/dict`,
+    /// `/usr/share/hunspell` and `/usr/share/myspell`.
+    pub spell_languages: Vec<String>,
+    /// Extra word lists or Hunspell `.dic` files, by path.
+    pub spell_dictionaries: Vec<PathBuf>,
+    /// Words always accepted, such as names and jargon.
+    pub spell_words: Vec<String>,
+    /// Where state kept between editor sessions lives, such as the recently
+    /// previewed files and reading positions; `None` keeps nothing.
+    pub state_dir: Option<PathBuf>,
+    /// Where files that can be rebuilt at any time live; `None` keeps nothing.
+    pub cache_dir: Option<PathBuf>,
 }
 
 impl Default for ServerConfig {
@@ -40,32 +136,196 @@ impl Default for ServerConfig {
             auto_scroll: true,
             scroll_comfort_top: 0.25,
             scroll_comfort_bottom: 0.65,
+            scroll_behavior: ScrollBehavior::Smooth,
+            scroll_duration_ms: 0,
+            scroll_center_bias: 0.0,
+            scroll_anchor: ScrollAnchor::Cursor,
+            follow_scope: FollowScope::Global,
+            open_browser: OpenBrowser::IfNoClients,
+            browser_openers: Vec::new(),
+            url_echo: UrlEcho::Notify,
+            theme: ThemeMode::Dark,
+            sync_colorscheme: false,
+            diagnostics: true,
+            render: RenderOptions::default(),
+            text_browser: vec![String::from("w3m")],
+            stop_on_last_client_disconnect: false,
+            disconnect_grace_ms: 3000,
+            slow_render_ms: 200,
+            session_check_ms: 5000,
+            asset_watch_ms: 1000,
+            document_types: HashMap::from([
+                (String::from("pdf"), String::from("application/pdf")),
+                (
+                    String::from("txt"),
+                    String::from("text/plain; charset=utf-8"),
+                ),
+            ]),
+            inline_image_max_bytes: 0,
+            image_thumbnails: false,
+            trust_local_svg: false,
+            record_dir: None,
+            max_document_bytes: 1024 * 1024,
+            default_asset_root: None,
+            filetypes: HashMap::new(),
+            spellcheck: false,
+            spell_languages: vec![String::from("words")],
+            spell_dictionaries: Vec::new(),
+            spell_words: Vec::new(),
+            state_dir: None,
+            cache_dir: None,
         }
     }
 }
 
-#[derive(Debug)]
-struct RuntimeState {
-    addr: Option<SocketAddr>,
-    shutdown: Option<oneshot::Sender<()>>,
-    task: Option<JoinHandle<()>>,
+impl ServerConfig {
+    /// Applies the `LIVE_MARKDOWN_*` variables of the process environment;
+    /// see [`Self::apply_env_vars`].
+    pub fn apply_env(&mut self) -> Vec<String> {
+        self.apply_env_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Applies the `LIVE_MARKDOWN_*` variables `var` knows, for setups such as
+    /// containers that configure the server outside Lua and the command line.
+    /// Returns a message for each variable that was set to something unusable,
+    /// whose option keeps its value, and a warning when the server will listen
+    /// beyond loopback. Empty variables count as unset.
+    pub fn apply_env_vars(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut read = |name: &str, accepted: &str, apply: &mut dyn FnMut(&str) -> bool| {
+            let Some(value) = var(name).filter(|value| !value.trim().is_empty()) else {
+                return;
+            };
+            if !apply(value.trim()) {
+                problems.push(format!("{name} must be {accepted}, got \"{value}\""));
+            }
+        };
+
+        read(
+            "LIVE_MARKDOWN_PORT",
+            "a port between 1 and 65535",
+            &mut |value| match value.parse::<u16>() {
+                Ok(port) if port > 0 => {
+                    self.port = port;
+                    true
+                }
+                _ => false,
+            },
+        );
+        // Unlike setup(), any address goes: a container has to listen beyond
+        // its own loopback for the host to reach it. Only share links answer
+        // other machines, but those can still read whatever is shared.
+        let mut exposed = None;
+        read(
+            "LIVE_MARKDOWN_BIND",
+            "an IP address or localhost",
+            &mut |value| {
+                if value == "localhost" {
+                    self.bind_address = String::from("127.0.0.1");
+                } else if let Ok(ip) = value.parse::<std::net::IpAddr>() {
+                    self.bind_address = value.to_string();
+                    if !ip.is_loopback() {
+                        exposed = Some(value.to_string());
+                    }
+                } else {
+                    return false;
+                }
+                true
+            },
+        );
+        read("LIVE_MARKDOWN_THEME", "dark, light or auto", &mut |value| {
+            ThemeMode::parse(value)
+                .map(|theme| self.theme = theme)
+                .is_some()
+        });
+        read(
+            "LIVE_MARKDOWN_OPEN_BROWSER",
+            "always, never or if_no_clients",
+            &mut |value| {
+                OpenBrowser::parse(value)
+                    .map(|open| self.open_browser = open)
+                    .is_some()
+            },
+        );
+        read(
+            "LIVE_MARKDOWN_RENDERER",
+            "builtin or pandoc",
+            &mut |value| {
+                RendererKind::parse(value)
+                    .map(|renderer| self.render.renderer = renderer)
+                    .is_some()
+            },
+        );
+        read(
+            "LIVE_MARKDOWN_PROFILE",
+            "github, commonmark, obsidian or pandoc",
+            &mut |value| {
+                RenderProfile::parse(value)
+                    .map(|profile| self.render.profile = Some(profile))
+                    .is_some()
+            },
+        );
+        read("LIVE_MARKDOWN_STATE_DIR", "a directory", &mut |value| {
+            self.state_dir = Some(PathBuf::from(value));
+            true
+        });
+        read("LIVE_MARKDOWN_CACHE_DIR", "a directory", &mut |value| {
+            self.cache_dir = Some(PathBuf::from(value));
+            true
+        });
+        read("LIVE_MARKDOWN_RECORD_DIR", "a directory", &mut |value| {
+            self.record_dir = Some(PathBuf::from(value));
+            true
+        });
+        if let Some(address) = exposed {
+            problems.push(format!(
+                "LIVE_MARKDOWN_BIND={address} listens beyond loopback: anyone who can reach the port can open share links, while previews and control routes stay local"
+            ));
+        }
+
+        problems
+    }
 }
 
-impl RuntimeState {
-    fn empty() -> Self {
-        Self {
-            addr: None,
-            shutdown: None,
-            task: None,
+/// Where the preview server is in its lifecycle. Every change is published,
+/// so callers can wait for a transition instead of polling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServerStatus {
+    Starting,
+    Running(SocketAddr),
+    Stopping,
+    #[default]
+    Stopped,
+}
+
+impl ServerStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::Running(_) => "running",
+            Self::Stopping => "stopping",
+            Self::Stopped => "stopped",
         }
     }
 }
 
+#[derive(Debug, Default)]
+struct RuntimeState {
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<()>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ServerController {
     config: ServerConfig,
     sessions: SessionManager,
+    access: AccessLog,
+    thumbnails: ThumbnailCache,
+    /// Held for a whole start or stop, so the two never interleave.
     runtime: Arc<Mutex<RuntimeState>>,
+    status: watch::Sender<ServerStatus>,
+    /// Every request is taken to come from here instead of its real peer.
+    peer_override: Option<SocketAddr>,
 }
 
 impl ServerController {
@@ -73,25 +333,60 @@ impl ServerController {
         Self {
             config,
             sessions,
-            runtime: Arc::new(Mutex::new(RuntimeState::empty())),
+            access: AccessLog::default(),
+            thumbnails: ThumbnailCache::default(),
+            runtime: Arc::default(),
+            status: watch::Sender::default(),
+            peer_override: None,
         }
     }
 
+    /// Makes every request look as if it came from `peer`, so tests can
+    /// stand in for a browser on another machine.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn with_peer_override(mut self, peer: SocketAddr) -> Self {
+        self.peer_override = Some(peer);
+        self
+    }
+
     pub async fn ensure_running(&self) -> Result<SocketAddr, std::io::Error> {
         let mut runtime = self.runtime.lock().await;
-        if let Some(addr) = runtime.addr {
+        if let ServerStatus::Running(addr) = self.status()
+            && runtime
+                .task
+                .as_ref()
+                .is_some_and(|task| !task.is_finished())
+        {
             return Ok(addr);
         }
 
-        let (listener, addr) = bind_listener(&self.config).await?;
+        // A server that died on its own leaves nothing to shut down.
+        *runtime = RuntimeState::default();
+        self.status.send_replace(ServerStatus::Starting);
+        let (listener, addr) = match bind_listener(&self.config).await {
+            Ok(bound) => bound,
+            Err(err) => {
+                self.status.send_replace(ServerStatus::Stopped);
+                return Err(err);
+            }
+        };
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
         let state = HttpState {
             sessions: self.sessions.clone(),
             config: self.config.clone(),
+            access: self.access.clone(),
+            thumbnails: self.thumbnails.clone(),
+            renderer: LiveMarkdownRenderer::new(self.config.render.clone()),
         };
-        let app = build_router(state);
+        let mut router = build_router(state);
+        if let Some(peer) = self.peer_override {
+            router = router.layer(Extension(ConnectInfo(peer)));
+        }
+        let app = router.into_make_service_with_connect_info::<SocketAddr>();
 
+        let status = self.status.clone();
+        let sessions = self.sessions.clone();
         let task = tokio::spawn(async move {
             let server = axum::serve(listener, app)
                 .with_graceful_shutdown(async {
@@ -99,53 +394,152 @@ impl ServerController {
                 })
                 .await;
 
+            // Previews cannot outlive the server that streams them.
             if let Err(err) = server {
                 eprintln!("live-markdown.nvim server stopped with error: {err}");
+                sessions
+                    .stop_all(SessionEndReason::Error {
+                        message: err.to_string(),
+                    })
+                    .await;
             }
+            status.send_if_modified(|status| {
+                let running = matches!(status, ServerStatus::Running(_));
+                if running {
+                    *status = ServerStatus::Stopped;
+                }
+                running
+            });
         });
 
-        runtime.addr = Some(addr);
         runtime.shutdown = Some(shutdown_tx);
         runtime.task = Some(task);
+        self.status.send_replace(ServerStatus::Running(addr));
 
         Ok(addr)
     }
 
+    /// Shuts the server down and waits for it to finish; a start asked for
+    /// meanwhile binds only after.
     pub async fn stop(&self) {
-        let (shutdown, task) = {
-            let mut runtime = self.runtime.lock().await;
-            runtime.addr = None;
-            (runtime.shutdown.take(), runtime.task.take())
+        let mut runtime = self.runtime.lock().await;
+        let RuntimeState { shutdown, task } = std::mem::take(&mut *runtime);
+        let Some(task) = task else {
+            self.status.send_replace(ServerStatus::Stopped);
+            return;
         };
 
+        self.status.send_replace(ServerStatus::Stopping);
         if let Some(tx) = shutdown {
             let _ = tx.send(());
         }
-        if let Some(task) = task {
-            let _ = task.await;
+        let _ = task.await;
+        self.status.send_replace(ServerStatus::Stopped);
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        *self.status.borrow()
+    }
+
+    /// Binds the configured ports as a start would and lets go again, to
+    /// report why a start would fail.
+    pub async fn check_port(&self) -> Result<(), std::io::Error> {
+        let _runtime = self.runtime.lock().await;
+        if matches!(self.status(), ServerStatus::Running(_)) {
+            return Ok(());
         }
+        bind_listener(&self.config).await.map(drop)
+    }
+
+    /// Follows every status change, for waiting on a start or stop.
+    pub fn watch_status(&self) -> watch::Receiver<ServerStatus> {
+        self.status.subscribe()
     }
 
     pub async fn bound_addr(&self) -> Option<SocketAddr> {
-        self.runtime.lock().await.addr
+        match self.status() {
+            ServerStatus::Running(addr) => Some(addr),
+            _ => None,
+        }
     }
 
     pub async fn preview_url(&self) -> Option<String> {
         let addr = self.bound_addr().await?;
         Some(format!("http://{}:{}/", addr.ip(), addr.port()))
     }
+
+    pub fn connections(&self) -> ConnectionsResponse {
+        ConnectionsResponse {
+            connections: self.access.connections(),
+            recent: self.access.recent(),
+        }
+    }
 }
 
 #[derive(Clone)]
 struct HttpState {
     sessions: SessionManager,
     config: ServerConfig,
+    access: AccessLog,
+    thumbnails: ThumbnailCache,
+    renderer: LiveMarkdownRenderer,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct AssetQuery {
     buf: i64,
     path: String,
+    w: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EventsQuery {
+    buf: i64,
+    client: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ShareEventsQuery {
+    client: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ShareSnapshotQuery {
+    #[serde(default)]
+    fragment: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ShareAssetQuery {
+    path: String,
+    w: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExportQuery {
+    buf: Option<i64>,
+    /// How local images are linked; see [`export_asset_links`].
+    assets: Option<AssetLinkMode>,
+    /// Directory the export will be saved in, for `assets=relative`.
+    base: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ShellQuery {
+    print: Option<String>,
+}
+
+impl ShellQuery {
+    fn print(&self) -> bool {
+        self.print
+            .as_deref()
+            .is_some_and(|value| matches!(value, "1" | "true" | "yes"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SplitQuery {
+    bufs: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -154,17 +548,92 @@ struct ActiveResponse {
 }
 
 fn build_router(state: HttpState) -> Router {
-    Router::new()
+    // Everything that lists, controls or reads outside one shared document
+    // answers local clients only; share links carry their own token instead.
+    let local = Router::new()
         .route("/", get(preview_shell))
         .route("/snapshot", get(snapshot))
         .route("/active", get(active))
+        .route("/resume", get(resume))
+        .route("/sessions", get(sessions))
+        .route("/tags", get(tags))
+        .route("/recent", get(recent_files))
+        .route("/connections", get(connections))
+        .route("/links", get(links))
+        .route("/bookmarks", get(bookmarks).post(update_bookmark))
+        .route("/comments", get(comments).post(update_comments))
         .route("/asset", get(asset))
         .route("/events", get(events))
+        .route("/presence", post(presence))
+        .route("/position", get(reading_position))
+        .route("/detach", post(detach))
+        .route("/render-anyway", post(render_anyway))
+        .route("/export.html", get(export_html))
+        .route("/split", get(split_view))
+        .route_layer(middleware::from_fn(local_only));
+
+    Router::new()
+        .merge(local)
+        .route("/favicon.ico", get(favicon))
+        .route("/favicon.svg", get(favicon))
+        .route("/theme.css", get(editor_theme))
+        .route("/share/{token}", get(share_shell))
+        .route("/share/{token}/active", get(share_active))
+        .route("/share/{token}/snapshot", get(share_snapshot))
+        .route("/share/{token}/asset", get(share_asset))
+        .route("/share/{token}/events", get(share_events))
+        .route("/share/{token}/presence", post(share_presence))
+        .route("/share/{token}/detach", post(share_detach))
+        .route("/share/{token}/comments", get(share_comments))
+        .layer(middleware::from_fn_with_state(state.clone(), record_access))
         .with_state(state)
 }
 
-async fn preview_shell(State(state): State<HttpState>) -> impl IntoResponse {
+/// Turns away requests from other machines, which a server bound beyond
+/// loopback receives; they may only use share links.
+async fn local_only(request: Request, next: Next) -> Response {
+    let local = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(addr)| addr.ip().to_canonical().is_loopback());
+    if !local {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            "only share links are served to other machines",
+        );
+    }
+    next.run(request).await
+}
+
+async fn preview_shell(
+    State(state): State<HttpState>,
+    Query(query): Query<ShellQuery>,
+) -> impl IntoResponse {
+    let print = query.print();
     let html = PREVIEW_HTML
+        .replace("__THEME_PALETTES__", &theme::palette_css())
+        .replace(
+            "__ROOT_THEME__",
+            &root_theme_attr(state.config.theme, print),
+        )
+        .replace(
+            "__DEFAULT_THEME__",
+            theme::default_theme_attr(state.config.theme).unwrap_or("dark"),
+        )
+        .replace(
+            "__EDITOR_THEME__",
+            if state.config.sync_colorscheme && !print {
+                "<link rel=\"stylesheet\" id=\"editor-theme\" href=\"/theme.css\" />"
+            } else {
+                ""
+            },
+        )
+        .replace("__PRINT_MODE__", if print { "true" } else { "false" })
+        .replace(
+            "__ACCESSIBILITY__",
+            &accessibility_json(state.config.render.accessibility),
+        )
+        .replace("__BODY_CLASS__", if print { "print-mode" } else { "" })
         .replace(
             "__AUTO_SCROLL__",
             if state.config.auto_scroll {
@@ -180,41 +649,439 @@ async fn preview_shell(State(state): State<HttpState>) -> impl IntoResponse {
         .replace(
             "__SCROLL_BOTTOM__",
             &format!("{:.2}", state.config.scroll_comfort_bottom),
+        )
+        .replace("__SCROLL_BEHAVIOR__", state.config.scroll_behavior.as_str())
+        .replace(
+            "__SCROLL_DURATION__",
+            &state.config.scroll_duration_ms.to_string(),
+        )
+        .replace(
+            "__CENTER_BIAS__",
+            &format!("{:.2}", state.config.scroll_center_bias),
+        )
+        .replace("__SCROLL_ANCHOR__", state.config.scroll_anchor.as_str())
+        .replace("__LABELS__", &state.config.render.labels.to_json())
+        .replace(
+            "__IMAGE_THUMBNAILS__",
+            if state.config.image_thumbnails {
+                "true"
+            } else {
+                "false"
+            },
+        )
+        .replace(
+            "__DOCUMENT_EXTENSIONS__",
+            &document_extensions_json(&state.config.document_types),
         );
 
     let mut headers = HeaderMap::new();
     headers.insert(
         "content-security-policy",
         HeaderValue::from_static(
-            "default-src 'none'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; connect-src 'self'; img-src 'self' https: http: data:;",
+            "default-src 'none'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; connect-src 'self'; img-src 'self' https: http: data:; object-src 'self';",
         ),
     );
 
     (headers, Html(html))
 }
 
-async fn snapshot(State(state): State<HttpState>, Query(query): Query<SessionQuery>) -> Response {
-    match state.sessions.snapshot(query.buf).await {
-        Some(snapshot) => Json(snapshot).into_response(),
+/// The `data-theme` attribute the page starts with, so it shows the right
+/// palette before the first snapshot arrives.
+fn root_theme_attr(mode: ThemeMode, print: bool) -> String {
+    let theme = if print {
+        Some("light")
+    } else {
+        theme::default_theme_attr(mode)
+    };
+    theme.map_or_else(String::new, |theme| format!(" data-theme=\"{theme}\""))
+}
+
+fn accessibility_json(accessibility: Accessibility) -> String {
+    format!(
+        "{{\"skip_link\":{},\"landmarks\":{}}}",
+        accessibility.skip_link, accessibility.landmarks
+    )
+}
+
+fn document_extensions_json(document_types: &HashMap<String, String>) -> String {
+    let mut extensions: Vec<&String> = document_types.keys().collect();
+    extensions.sort();
+    serde_json::to_string(&extensions).unwrap_or_else(|_| String::from("[]"))
+}
+
+async fn favicon() -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("image/svg+xml"));
+    headers.insert(
+        "cache-control",
+        HeaderValue::from_static("public, max-age=86400"),
+    );
+
+    (headers, FAVICON_SVG)
+}
+
+async fn editor_theme(State(state): State<HttpState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("text/css"));
+    headers.insert("cache-control", HeaderValue::from_static("no-cache"));
+
+    (headers, state.sessions.editor_palette().await)
+}
+
+async fn snapshot(State(state): State<HttpState>, Query(query): Query<SnapshotQuery>) -> Response {
+    let Some(mut snapshot) = state.sessions.snapshot(query.buf).await else {
+        return json_error(StatusCode::NOT_FOUND, "preview session not found");
+    };
+    if let Some(fragment) = query.fragment.filter(|fragment| !fragment.is_empty()) {
+        snapshot.fragment_line = state.sessions.heading_line(query.buf, &fragment).await;
+    }
+    Json(snapshot).into_response()
+}
+
+async fn links(State(state): State<HttpState>, Query(query): Query<SessionQuery>) -> Response {
+    match state.sessions.links(query.buf).await {
+        Some(links) => Json(links).into_response(),
         None => json_error(StatusCode::NOT_FOUND, "preview session not found"),
     }
 }
 
+async fn bookmarks(State(state): State<HttpState>, Query(query): Query<SessionQuery>) -> Response {
+    match state.sessions.bookmarks(query.buf).await {
+        Some(bookmarks) => Json(bookmarks).into_response(),
+        None => json_error(StatusCode::NOT_FOUND, "preview session not found"),
+    }
+}
+
+async fn update_bookmark(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    Json(update): Json<BookmarkUpdate>,
+) -> Response {
+    if !state.sessions.has_session(query.buf).await {
+        return json_error(StatusCode::NOT_FOUND, "preview session not found");
+    }
+    match state.sessions.update_bookmark(query.buf, update).await {
+        Some(bookmarks) => Json(bookmarks).into_response(),
+        None => json_error(StatusCode::BAD_REQUEST, "invalid bookmark"),
+    }
+}
+
+async fn comments(State(state): State<HttpState>, Query(query): Query<SessionQuery>) -> Response {
+    match state.sessions.comments(query.buf).await {
+        Some(comments) => Json(comments).into_response(),
+        None => json_error(StatusCode::NOT_FOUND, "preview session not found"),
+    }
+}
+
+async fn update_comments(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    Json(action): Json<CommentAction>,
+) -> Response {
+    if !state.sessions.has_session(query.buf).await {
+        return json_error(StatusCode::NOT_FOUND, "preview session not found");
+    }
+    match state.sessions.update_comments(query.buf, action).await {
+        Some(comments) => Json(comments).into_response(),
+        None => json_error(StatusCode::BAD_REQUEST, "invalid comment"),
+    }
+}
+
 async fn active(State(state): State<HttpState>) -> Response {
     let bufnr = state.sessions.active_bufnr().await;
     Json(ActiveResponse { bufnr }).into_response()
 }
 
+async fn resume(State(state): State<HttpState>, Query(query): Query<ResumeQuery>) -> Response {
+    match state.sessions.resume(&query.token).await {
+        Some(bufnr) => Json(ActiveResponse { bufnr: Some(bufnr) }).into_response(),
+        None => json_error(
+            StatusCode::NOT_FOUND,
+            "no preview session for this document",
+        ),
+    }
+}
+
+async fn sessions(State(state): State<HttpState>, Query(query): Query<SessionsQuery>) -> Response {
+    match query.tag.filter(|tag| !tag.trim().is_empty()) {
+        Some(tag) => Json(state.sessions.list_tagged_sessions(&tag).await).into_response(),
+        None => Json(state.sessions.list_sessions().await).into_response(),
+    }
+}
+
+async fn tags(State(state): State<HttpState>) -> Response {
+    Json(state.sessions.tag_index().await).into_response()
+}
+
+async fn recent_files(State(state): State<HttpState>) -> Response {
+    let Some(dir) = state.config.state_dir.clone() else {
+        return Json(Vec::<RecentFile>::new()).into_response();
+    };
+    let recent = tokio::task::spawn_blocking(move || recent::load(&dir))
+        .await
+        .unwrap_or_default();
+    Json(recent).into_response()
+}
+
+async fn connections(State(state): State<HttpState>) -> Response {
+    Json(ConnectionsResponse {
+        connections: state.access.connections(),
+        recent: state.access.recent(),
+    })
+    .into_response()
+}
+
+async fn record_access(
+    State(state): State<HttpState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = Peer {
+        remote: request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.to_string()),
+        user_agent: request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
+    let client = request.uri().query().and_then(client_from_query);
+    state.access.record(request.uri().path(), client, &peer);
+
+    request.extensions_mut().insert(peer);
+    next.run(request).await
+}
+
+fn client_from_query(query: &str) -> Option<String> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("client="))
+        .filter(|client| !client.is_empty())
+        .map(str::to_string)
+}
+
+async fn export_html(State(state): State<HttpState>, Query(query): Query<ExportQuery>) -> Response {
+    let bufnr = match query.buf {
+        Some(bufnr) => Some(bufnr),
+        None => state.sessions.active_bufnr().await,
+    };
+    let Some(mut snapshot) = (match bufnr {
+        Some(bufnr) => state.sessions.snapshot(bufnr).await,
+        None => None,
+    }) else {
+        return json_error(StatusCode::NOT_FOUND, "preview session not found");
+    };
+    let links = export_asset_links(&state.config, &query, snapshot.bufnr);
+    snapshot.html = rewrite_local_images(&state, &snapshot, &links).await;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "content-security-policy",
+        HeaderValue::from_static(
+            "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' https: http: data:;",
+        ),
+    );
+
+    (headers, Html(export_document(&snapshot))).into_response()
+}
+
+/// An explicit `assets=` wins; otherwise images are embedded when
+/// `inline_image_max_bytes` allows it and served by this server when not.
+fn export_asset_links(config: &ServerConfig, query: &ExportQuery, bufnr: i64) -> AssetLinks {
+    match query.assets {
+        Some(AssetLinkMode::Server) => AssetLinks::Server { bufnr },
+        Some(AssetLinkMode::Relative) => AssetLinks::OnDisk {
+            base: query
+                .base
+                .as_deref()
+                .map(str::trim)
+                .filter(|base| !base.is_empty())
+                .map(PathBuf::from),
+        },
+        Some(AssetLinkMode::Data) => AssetLinks::DataUri {
+            max_bytes: config.inline_image_max_bytes,
+        },
+        None if config.inline_image_max_bytes > 0 => AssetLinks::DataUri {
+            max_bytes: config.inline_image_max_bytes,
+        },
+        None => AssetLinks::Server { bufnr },
+    }
+}
+
+/// Points the snapshot's local images where `links` says, embedding them
+/// for `DataUri`. Remote images and sources outside the buffer's directory
+/// are left as they are.
+async fn rewrite_local_images(
+    state: &HttpState,
+    snapshot: &SnapshotResponse,
+    links: &AssetLinks,
+) -> String {
+    let mut sources = Vec::new();
+    rewrite_image_sources(&snapshot.html, |src| {
+        sources.push(src.to_string());
+        None
+    });
+
+    let mut rewritten = HashMap::new();
+    for src in sources {
+        if rewritten.contains_key(&src) {
+            continue;
+        }
+        let Some(path) = state
+            .sessions
+            .resolve_local_asset_path(snapshot.bufnr, &src)
+            .await
+        else {
+            continue;
+        };
+        if let AssetLinks::DataUri { max_bytes } = links
+            && let Some(embedded) = embedded_image(state, &path, *max_bytes).await
+        {
+            rewritten.insert(src, embedded);
+            continue;
+        }
+        let link = links.link(&src, &path);
+        rewritten.insert(src, link);
+    }
+
+    rewrite_image_sources(&snapshot.html, |src| rewritten.get(src).cloned())
+}
+
+/// `path` as a `data:` URI, if it is no larger than `max_bytes` (0 for any
+/// size). Untrusted SVGs are sanitized first.
+async fn embedded_image(state: &HttpState, path: &Path, max_bytes: u64) -> Option<String> {
+    let small_enough = tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|metadata| max_bytes == 0 || metadata.len() <= max_bytes);
+    if !small_enough {
+        return None;
+    }
+    let mut bytes = tokio::fs::read(path).await.ok()?;
+    let content_type = image_content_type(path, &bytes);
+    if content_type == "image/svg+xml" && !state.config.trust_local_svg {
+        bytes = sanitize_svg(&String::from_utf8_lossy(&bytes)).into_bytes();
+    }
+    Some(data_uri(content_type, &bytes))
+}
+
+async fn split_view(Query(query): Query<SplitQuery>) -> Response {
+    let bufnrs = parse_split_buffers(&query.bufs);
+    if bufnrs.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "expected bufs=<bufnr>,<bufnr>");
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "content-security-policy",
+        HeaderValue::from_static(
+            "default-src 'none'; frame-src 'self'; style-src 'unsafe-inline';",
+        ),
+    );
+
+    (headers, Html(split_document(&bufnrs))).into_response()
+}
+
+fn parse_split_buffers(raw: &str) -> Vec<i64> {
+    const MAX_PANES: usize = 4;
+
+    let mut bufnrs = Vec::new();
+    for bufnr in raw
+        .split(',')
+        .filter_map(|value| value.trim().parse::<i64>().ok())
+    {
+        if !bufnrs.contains(&bufnr) && bufnrs.len() < MAX_PANES {
+            bufnrs.push(bufnr);
+        }
+    }
+    bufnrs
+}
+
+/// Each pane is a preview shell pinned to one buffer, so every pane keeps its
+/// own snapshot and event stream.
+fn split_document(bufnrs: &[i64]) -> String {
+    let mut out = String::from(
+        "<!doctype html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\" />\n<title>Split \u{2014} live-markdown</title>\n<style>html,body{margin:0;height:100%;background:#0d1117;}body{display:flex;gap:2px;}iframe{flex:1 1 0;min-width:0;height:100%;border:0;background:#0d1117;}</style>\n</head>\n<body>\n",
+    );
+    for bufnr in bufnrs {
+        out.push_str(&format!(
+            "<iframe src=\"/?buf={bufnr}\" title=\"buffer {bufnr}\"></iframe>\n"
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn export_document(snapshot: &SnapshotResponse) -> String {
+    let stylesheet = PREVIEW_HTML
+        .split_once("<style>")
+        .and_then(|(_, rest)| rest.split_once("</style>"))
+        .map_or("", |(css, _)| css)
+        .replace("__THEME_PALETTES__", &theme::palette_css());
+
+    let mut out = String::with_capacity(snapshot.html.len() + stylesheet.len() + 512);
+    out.push_str("<!doctype html>\n<html lang=\"en\"");
+    if let Some(theme) = article_theme(&snapshot.html).or(snapshot.options.theme.as_deref()) {
+        out.push_str(" data-theme=\"");
+        out.push_str(theme);
+        out.push('"');
+    }
+    out.push_str(">\n<head>\n<meta charset=\"utf-8\" />\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n<title>");
+    push_escaped_html(&mut out, &snapshot.title);
+    out.push_str("</title>\n<style>");
+    out.push_str(&stylesheet);
+    out.push_str("</style>\n</head>\n<body>\n<main class=\"shell\">\n<header class=\"preview-header\"><p class=\"filename-line\">");
+    push_escaped_html(&mut out, &snapshot.filename);
+    out.push_str("</p></header>\n<section id=\"preview-root\">");
+    out.push_str(&snapshot.html);
+    out.push_str("</section>\n</main>\n</body>\n</html>\n");
+    out
+}
+
+fn article_theme(html: &str) -> Option<&'static str> {
+    let open_tag = &html[..html.find('>')?];
+    if open_tag.contains("data-theme=\"light\"") {
+        Some("light")
+    } else if open_tag.contains("data-theme=\"dark\"") {
+        Some("dark")
+    } else if open_tag.contains("data-theme=\"auto\"") {
+        Some("auto")
+    } else {
+        None
+    }
+}
+
+/// Images are always served; other files only when their extension is listed
+/// in `document_types`, which also supplies the content type.
+async fn resolve_asset(
+    state: &HttpState,
+    bufnr: i64,
+    raw_path: &str,
+) -> Option<(PathBuf, Option<String>)> {
+    if let Some(path) = state
+        .sessions
+        .resolve_local_asset_path(bufnr, raw_path)
+        .await
+    {
+        return Some((path, None));
+    }
+
+    let path = state
+        .sessions
+        .resolve_local_file_path(bufnr, raw_path)
+        .await?;
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let content_type = state.config.document_types.get(&extension)?.clone();
+    Some((path, Some(content_type)))
+}
+
 async fn asset(
     State(state): State<HttpState>,
     request_headers: HeaderMap,
     Query(query): Query<AssetQuery>,
 ) -> Response {
-    let Some(path) = state
-        .sessions
-        .resolve_local_asset_path(query.buf, &query.path)
-        .await
-    else {
+    let Some((path, document_type)) = resolve_asset(&state, query.buf, &query.path).await else {
         return StatusCode::NOT_FOUND.into_response();
     };
 
@@ -228,22 +1095,47 @@ async fn asset(
         }
     };
 
-    let etag = build_asset_etag(&metadata);
-    if let Some(ref value) = etag {
-        if if_none_match_matches(&request_headers, value) {
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                "cache-control",
-                HeaderValue::from_static("private, max-age=60"),
-            );
-            if let Ok(header_value) = HeaderValue::from_str(value) {
-                headers.insert("etag", header_value);
-            }
-            return (StatusCode::NOT_MODIFIED, headers).into_response();
+    let thumbnail_width = query
+        .w
+        .filter(|_| {
+            state.config.image_thumbnails
+                && document_type.is_none()
+                && metadata.len() >= THUMBNAIL_MIN_BYTES
+        })
+        .and_then(thumbnail_width);
+    let etag = build_asset_etag(&metadata).map(|etag| match thumbnail_width {
+        Some(width) => format!("{}-w{width}\"", etag.trim_end_matches('"')),
+        None => etag,
+    });
+    if let Some(ref value) = etag
+        && if_none_match_matches(&request_headers, value)
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cache-control",
+            HeaderValue::from_static("private, max-age=60"),
+        );
+        if let Ok(header_value) = HeaderValue::from_str(value) {
+            headers.insert("etag", header_value);
         }
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    let thumbnail_key = thumbnail_width
+        .zip(etag.clone())
+        .map(|(width, etag)| ThumbnailKey {
+            path: path.clone(),
+            etag,
+            width,
+        });
+    if let Some(Some(thumbnail)) = thumbnail_key
+        .as_ref()
+        .and_then(|key| state.thumbnails.get(key))
+    {
+        return thumbnail_response(thumbnail.bytes.to_vec(), thumbnail.content_type, etag);
     }
 
-    let bytes = match tokio::fs::read(&path).await {
+    let mut bytes = match tokio::fs::read(&path).await {
         Ok(bytes) => bytes,
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
             return StatusCode::NOT_FOUND.into_response();
@@ -253,33 +1145,113 @@ async fn asset(
         }
     };
 
+    if let Some(key) = thumbnail_key
+        && state.thumbnails.get(&key).is_none()
+    {
+        let (source, width) = (bytes.clone(), key.width);
+        let thumbnail = tokio::task::spawn_blocking(move || downscale(&source, width))
+            .await
+            .ok()
+            .flatten();
+        state.thumbnails.insert(key, thumbnail.clone());
+        if let Some(thumbnail) = thumbnail {
+            return thumbnail_response(thumbnail.bytes.to_vec(), thumbnail.content_type, etag);
+        }
+    }
+
+    let content_type = document_type
+        .and_then(|value| HeaderValue::from_str(&value).ok())
+        .unwrap_or_else(|| HeaderValue::from_static(image_content_type(&path, &bytes)));
     let mut headers = HeaderMap::new();
+    if content_type == "image/svg+xml" && !state.config.trust_local_svg {
+        bytes = sanitize_svg(&String::from_utf8_lossy(&bytes)).into_bytes();
+        headers.insert(
+            "content-security-policy",
+            HeaderValue::from_static(
+                "default-src 'none'; style-src 'unsafe-inline'; img-src data:; sandbox",
+            ),
+        );
+    }
+    headers.insert("content-type", content_type);
     headers.insert(
-        "content-type",
-        HeaderValue::from_static(image_content_type(&path)),
+        "x-content-type-options",
+        HeaderValue::from_static("nosniff"),
     );
     headers.insert(
         "cache-control",
         HeaderValue::from_static("private, max-age=60"),
     );
-    if let Some(value) = etag {
-        if let Ok(header_value) = HeaderValue::from_str(&value) {
-            headers.insert("etag", header_value);
-        }
+    if let Some(value) = etag
+        && let Ok(header_value) = HeaderValue::from_str(&value)
+    {
+        headers.insert("etag", header_value);
+    }
+
+    (headers, bytes).into_response()
+}
+
+fn thumbnail_response(
+    bytes: Vec<u8>,
+    content_type: &'static str,
+    etag: Option<String>,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static(content_type));
+    headers.insert(
+        "cache-control",
+        HeaderValue::from_static("private, max-age=60"),
+    );
+    if let Some(value) = etag
+        && let Ok(header_value) = HeaderValue::from_str(&value)
+    {
+        headers.insert("etag", header_value);
     }
 
     (headers, bytes).into_response()
 }
 
-async fn events(State(state): State<HttpState>, Query(query): Query<SessionQuery>) -> Response {
-    let Some(mut rx) = state.sessions.subscribe(query.buf).await else {
+async fn events(
+    State(state): State<HttpState>,
+    Extension(peer): Extension<Peer>,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    event_stream(state, &peer, query.buf, query.client, None).await
+}
+
+async fn event_stream(
+    state: HttpState,
+    peer: &Peer,
+    bufnr: i64,
+    client: Option<String>,
+    share_expiry: Option<Instant>,
+) -> Response {
+    let Some(mut rx) = state.sessions.subscribe(bufnr).await else {
         return json_error(StatusCode::NOT_FOUND, "preview session not found");
     };
 
-    let bufnr = query.buf;
+    let connection = state
+        .access
+        .connect(bufnr, client.clone(), share_expiry.is_some(), peer);
+    let sessions = state.sessions.clone();
+    let follower = client.clone();
+    let guard = ClientGuard {
+        sessions: state.sessions.clone(),
+        access: state.access.clone(),
+        connection,
+        bufnr,
+        viewer: client.map(|client| (bufnr, client)),
+    };
     let stream = stream! {
+        let _guard = guard;
         let mut heartbeat_interval = tokio::time::interval(Duration::from_secs(15));
         heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let expiry = async {
+            match share_expiry {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(expiry);
 
         loop {
             tokio::select! {
@@ -287,11 +1259,28 @@ async fn events(State(state): State<HttpState>, Query(query): Query<SessionQuery
                     let heartbeat = ServerEvent::Heartbeat { bufnr };
                     yield Ok::<Event, Infallible>(sse_event(&heartbeat));
                 }
+                _ = &mut expiry => {
+                    let end = ServerEvent::session_end(bufnr, SessionEndReason::Expired);
+                    yield Ok::<Event, Infallible>(sse_event(&end));
+                    break;
+                }
                 recv = rx.recv() => {
-                    match recv {
-                        Ok(payload) => yield Ok::<Event, Infallible>(sse_event(&payload)),
+                    let first = match recv {
+                        Ok(sequenced) => sequenced,
                         Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
                         Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    };
+                    for sequenced in coalesce_cursor_moves(first, &mut rx) {
+                        let payload = &sequenced.event;
+                        if share_expiry.is_some() && !share_visible(payload) {
+                            continue;
+                        }
+                        if matches!(payload, ServerEvent::CursorMove { .. } | ServerEvent::Viewport { .. })
+                            && detached(&sessions, bufnr, follower.as_deref()).await
+                        {
+                            continue;
+                        }
+                        yield Ok::<Event, Infallible>(sse_event(payload).id(sequenced.seq.to_string()));
                     }
                 }
             }
@@ -307,6 +1296,238 @@ async fn events(State(state): State<HttpState>, Query(query): Query<SessionQuery
         .into_response()
 }
 
+/// `first` and the events already queued behind it, keeping only the last
+/// of each run of cursor moves: a burst from fast scrolling costs one write.
+fn coalesce_cursor_moves(
+    first: SequencedEvent,
+    rx: &mut broadcast::Receiver<SequencedEvent>,
+) -> Vec<SequencedEvent> {
+    let mut batch = vec![first];
+    while batch
+        .last()
+        .is_some_and(|last| matches!(last.event, ServerEvent::CursorMove { .. }))
+    {
+        let Ok(next) = rx.try_recv() else {
+            break;
+        };
+        if matches!(next.event, ServerEvent::CursorMove { .. }) {
+            batch.pop();
+        }
+        batch.push(next);
+    }
+    batch
+}
+
+/// Whether `client` asked to stop following the editor's cursor.
+async fn detached(sessions: &SessionManager, bufnr: i64, client: Option<&str>) -> bool {
+    match client {
+        Some(client) => sessions.is_detached(bufnr, client).await,
+        None => false,
+    }
+}
+
+/// Share viewers only see their own document, not the list of open buffers.
+fn share_visible(event: &ServerEvent) -> bool {
+    !matches!(event, ServerEvent::Sessions { .. })
+}
+
+async fn share_shell(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    query: Query<ShellQuery>,
+) -> Response {
+    if state.sessions.resolve_share(&token).await.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    preview_shell(State(state), query).await.into_response()
+}
+
+async fn share_active(State(state): State<HttpState>, UrlPath(token): UrlPath<String>) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((bufnr, _)) => Json(ActiveResponse { bufnr: Some(bufnr) }).into_response(),
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+async fn share_snapshot(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    Query(query): Query<ShareSnapshotQuery>,
+) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((buf, _)) => {
+            let query = SnapshotQuery {
+                buf,
+                fragment: query.fragment,
+            };
+            snapshot(State(state), Query(query)).await
+        }
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+async fn share_asset(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    request_headers: HeaderMap,
+    Query(query): Query<ShareAssetQuery>,
+) -> Response {
+    let Some((buf, _)) = state.sessions.resolve_share(&token).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let query = AssetQuery {
+        buf,
+        path: query.path,
+        w: query.w,
+    };
+    asset(State(state), request_headers, Query(query)).await
+}
+
+async fn share_events(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    Extension(peer): Extension<Peer>,
+    Query(query): Query<ShareEventsQuery>,
+) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((bufnr, expires_at)) => {
+            event_stream(state, &peer, bufnr, query.client, Some(expires_at)).await
+        }
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+async fn presence(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    Json(update): Json<PresenceUpdate>,
+) -> Response {
+    let response = update_presence(&state, query.buf, &update).await;
+    if response.status() == StatusCode::NO_CONTENT
+        && let Some(dir) = state.config.state_dir.clone()
+        && let Some(path) = state.sessions.source_path(query.buf).await
+    {
+        let line = update.line;
+        tokio::task::spawn_blocking(move || positions::record(&dir, &path.to_string_lossy(), line));
+    }
+    response
+}
+
+async fn update_presence(state: &HttpState, bufnr: i64, update: &PresenceUpdate) -> Response {
+    if state
+        .sessions
+        .update_presence(bufnr, &update.client, update.line)
+        .await
+    {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        json_error(StatusCode::NOT_FOUND, "preview session not found")
+    }
+}
+
+/// The reading position kept for the session's file, so a preview reopened
+/// after a restart can return to it.
+async fn reading_position(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+) -> Response {
+    let (Some(dir), Some(path)) = (
+        state.config.state_dir.clone(),
+        state.sessions.source_path(query.buf).await,
+    ) else {
+        return Json(ReadingPositionResponse::default()).into_response();
+    };
+    let line = tokio::task::spawn_blocking(move || positions::load(&dir, &path.to_string_lossy()))
+        .await
+        .unwrap_or_default();
+    Json(ReadingPositionResponse { line }).into_response()
+}
+
+async fn detach(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    Json(update): Json<DetachUpdate>,
+) -> Response {
+    if state
+        .sessions
+        .set_detached(query.buf, &update.client, update.detached)
+        .await
+    {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        json_error(StatusCode::NOT_FOUND, "preview session not found")
+    }
+}
+
+async fn render_anyway(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+) -> Response {
+    if state
+        .sessions
+        .render_anyway(query.buf, &state.renderer)
+        .await
+    {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        json_error(StatusCode::NOT_FOUND, "preview session not found")
+    }
+}
+
+async fn share_presence(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    Json(update): Json<PresenceUpdate>,
+) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((buf, _)) => update_presence(&state, buf, &update).await,
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+async fn share_detach(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+    Json(update): Json<DetachUpdate>,
+) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((buf, _)) => detach(State(state), Query(SessionQuery { buf }), Json(update)).await,
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+async fn share_comments(
+    State(state): State<HttpState>,
+    UrlPath(token): UrlPath<String>,
+) -> Response {
+    match state.sessions.resolve_share(&token).await {
+        Some((buf, _)) => comments(State(state), Query(SessionQuery { buf })).await,
+        None => json_error(StatusCode::NOT_FOUND, "share link expired"),
+    }
+}
+
+struct ClientGuard {
+    sessions: SessionManager,
+    access: AccessLog,
+    connection: u64,
+    bufnr: i64,
+    viewer: Option<(i64, String)>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.sessions.client_disconnected(self.bufnr);
+        self.access.disconnect(self.connection);
+
+        if let Some((bufnr, client)) = self.viewer.take()
+            && let Ok(handle) = tokio::runtime::Handle::try_current()
+        {
+            let sessions = self.sessions.clone();
+            handle.spawn(async move { sessions.remove_viewer(bufnr, &client).await });
+        }
+    }
+}
+
 fn sse_event(payload: &ServerEvent) -> Event {
     let data = serde_json::to_string(payload).unwrap_or_else(|_| {
         String::from("{\"type\":\"error\",\"message\":\"serialization_error\"}")
@@ -324,9 +1545,9 @@ fn json_error(status: StatusCode, message: &str) -> Response {
     (status, Json(ErrorBody { error: message })).into_response()
 }
 
-fn image_content_type(path: &Path) -> &'static str {
+fn image_content_type(path: &Path, bytes: &[u8]) -> &'static str {
     let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
-        return "application/octet-stream";
+        return sniffed_content_type(bytes);
     };
 
     match ext.to_ascii_lowercase().as_str() {
@@ -339,10 +1560,16 @@ fn image_content_type(path: &Path) -> &'static str {
         "ico" => "image/x-icon",
         "avif" => "image/avif",
         "tif" | "tiff" => "image/tiff",
-        _ => "application/octet-stream",
+        _ => sniffed_content_type(bytes),
     }
 }
 
+fn sniffed_content_type(bytes: &[u8]) -> &'static str {
+    infer::get(bytes)
+        .filter(|kind| matches!(kind.matcher_type(), infer::MatcherType::Image))
+        .map_or("application/octet-stream", |kind| kind.mime_type())
+}
+
 fn build_asset_etag(metadata: &std::fs::Metadata) -> Option<String> {
     let modified = metadata.modified().ok()?;
     let modified_secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
@@ -386,13 +1613,142 @@ async fn bind_listener(config: &ServerConfig) -> Result<(TcpListener, SocketAddr
         }
     }
 
-    Err(last_error.unwrap_or_else(|| std::io::Error::other("failed to bind preview server")))
+    let Some(err) = last_error else {
+        return Err(std::io::Error::other("failed to bind preview server"));
+    };
+    let owner = if err.kind() == ErrorKind::AddrInUse {
+        tokio::task::spawn_blocking(move || port_owner(start_port))
+            .await
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+    Err(std::io::Error::new(
+        err.kind(),
+        bind_failure(config, end_port, &err, owner.as_ref()),
+    ))
+}
+
+/// Says why no port could be bound and what to change, naming the process
+/// on the configured port when it is taken.
+fn bind_failure(
+    config: &ServerConfig,
+    end_port: u16,
+    err: &std::io::Error,
+    owner: Option<&PortOwner>,
+) -> String {
+    let address = &config.bind_address;
+    let port = config.port;
+    match err.kind() {
+        ErrorKind::AddrInUse => {
+            let holder = match owner {
+                Some(owner) => format!(
+                    "; port {port} is held by {} (pid {})",
+                    owner.command, owner.pid
+                ),
+                None => String::new(),
+            };
+            format!(
+                "ports {port}-{end_port} on {address} are all in use{holder}. Stop the process holding the port, or set `port` in setup() to a free one (0 lets the system pick)"
+            )
+        }
+        ErrorKind::PermissionDenied => format!(
+            "not allowed to listen on {address}:{port} ({err}). Ports below 1024 need extra privileges; set `port` in setup() to 1024 or above"
+        ),
+        ErrorKind::AddrNotAvailable => format!(
+            "cannot listen on {address} ({err}). Set `bind_address` in setup() to an address of this machine, such as 127.0.0.1"
+        ),
+        _ => format!("failed to listen on {address}:{port}: {err}"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ServerConfig, if_none_match_matches};
+    use super::{
+        ServerConfig, client_from_query, coalesce_cursor_moves, document_extensions_json,
+        export_document, if_none_match_matches, image_content_type, parse_split_buffers,
+        share_visible, split_document,
+    };
+    use crate::protocol::{
+        ServerEvent, SessionEndReason, SessionOptions, SnapshotResponse, ThemeMode,
+    };
+    use crate::render::{RendererKind, TaskProgress};
+    use crate::session::SequencedEvent;
     use axum::http::{HeaderMap, HeaderValue};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn bursts_of_cursor_moves_keep_the_latest() {
+        let (sender, mut rx) = broadcast::channel(16);
+        let cursor = |seq, line| SequencedEvent {
+            seq,
+            event: ServerEvent::CursorMove {
+                bufnr: 1,
+                line,
+                col: 0,
+            },
+        };
+        for seq in 2..=4 {
+            sender.send(cursor(seq, seq as usize * 10)).expect("send");
+        }
+        sender
+            .send(SequencedEvent {
+                seq: 5,
+                event: ServerEvent::Refresh { bufnr: 1 },
+            })
+            .expect("send");
+        sender.send(cursor(6, 60)).expect("send");
+        sender.send(cursor(7, 70)).expect("send");
+
+        sender
+            .send(SequencedEvent {
+                seq: 8,
+                event: ServerEvent::Refresh { bufnr: 1 },
+            })
+            .expect("send");
+
+        let seqs =
+            |batch: Vec<SequencedEvent>| batch.iter().map(|event| event.seq).collect::<Vec<_>>();
+        assert_eq!(
+            seqs(coalesce_cursor_moves(cursor(1, 10), &mut rx)),
+            vec![4, 5]
+        );
+        let next = rx.try_recv().expect("queued");
+        assert_eq!(seqs(coalesce_cursor_moves(next, &mut rx)), vec![7, 8]);
+        let next = SequencedEvent {
+            seq: 9,
+            event: ServerEvent::Refresh { bufnr: 1 },
+        };
+        assert_eq!(seqs(coalesce_cursor_moves(next, &mut rx)), vec![9]);
+    }
+
+    #[test]
+    fn environment_variables_override_defaults() {
+        let env = HashMap::from([
+            ("LIVE_MARKDOWN_PORT", "8080"),
+            ("LIVE_MARKDOWN_BIND", "0.0.0.0"),
+            ("LIVE_MARKDOWN_THEME", "light"),
+            ("LIVE_MARKDOWN_RENDERER", "pandocc"),
+            ("LIVE_MARKDOWN_STATE_DIR", ""),
+        ]);
+        let mut cfg = ServerConfig::default();
+        let problems = cfg.apply_env_vars(|name| env.get(name).map(|value| value.to_string()));
+
+        assert_eq!(cfg.port, 8080);
+        assert_eq!(cfg.bind_address, "0.0.0.0");
+        assert_eq!(cfg.theme, ThemeMode::Light);
+        assert_eq!(cfg.render.renderer, RendererKind::Builtin);
+        assert_eq!(cfg.state_dir, None);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(
+            problems[0],
+            "LIVE_MARKDOWN_RENDERER must be builtin or pandoc, got \"pandocc\""
+        );
+        assert!(problems[1].starts_with("LIVE_MARKDOWN_BIND=0.0.0.0 listens beyond loopback"));
+    }
 
     #[test]
     fn config_defaults_match_spec() {
@@ -404,6 +1760,10 @@ mod tests {
         assert!(cfg.auto_scroll);
         assert!((cfg.scroll_comfort_top - 0.25).abs() < f64::EPSILON);
         assert!((cfg.scroll_comfort_bottom - 0.65).abs() < f64::EPSILON);
+        assert_eq!(
+            document_extensions_json(&cfg.document_types),
+            "[\"pdf\",\"txt\"]"
+        );
     }
 
     #[test]
@@ -427,4 +1787,88 @@ mod tests {
         headers.insert("if-none-match", HeaderValue::from_static("*"));
         assert!(if_none_match_matches(&headers, "W/\"whatever\""));
     }
+
+    #[test]
+    fn export_document_inlines_theme_without_scripts() {
+        let snapshot = SnapshotResponse {
+            bufnr: 2,
+            html: String::from(
+                "<article id=\"md-root\" data-theme=\"light\"><p data-line=\"1\">Hi</p></article>",
+            ),
+            cursor_line: 1,
+            cursor_col: 0,
+            filename: String::from("<notes>.md"),
+            title: String::from("notes.md \u{2014} live-markdown"),
+            tasks: TaskProgress::default(),
+            options: SessionOptions::default(),
+            paused: false,
+            typos: Vec::new(),
+            bookmarks: Vec::new(),
+            comments: Vec::new(),
+            seq: 0,
+            fragment_line: None,
+            resume: None,
+        };
+
+        let html = export_document(&snapshot);
+
+        assert!(html.starts_with("<!doctype html>\n<html lang=\"en\" data-theme=\"light\">"));
+        assert!(html.contains("--accent: #fd8000;"));
+        assert!(html.contains("@media (prefers-color-scheme: light)"));
+        assert!(!html.contains("__THEME_PALETTES__"));
+        assert!(html.contains("<p class=\"filename-line\">&lt;notes&gt;.md</p>"));
+        assert!(html.contains("<p data-line=\"1\">Hi</p>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("EventSource"));
+    }
+
+    #[test]
+    fn split_view_pins_one_pane_per_buffer() {
+        assert_eq!(parse_split_buffers("2, 5,x,2,7,8,9"), vec![2, 5, 7, 8]);
+        assert!(parse_split_buffers("").is_empty());
+
+        let html = split_document(&[2, 5]);
+        assert!(html.contains("<iframe src=\"/?buf=2\""));
+        assert!(html.contains("<iframe src=\"/?buf=5\""));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn share_streams_hide_the_session_list() {
+        assert!(!share_visible(&ServerEvent::Sessions {
+            bufnr: 1,
+            sessions: Vec::new(),
+        }));
+        assert!(share_visible(&ServerEvent::session_end(
+            1,
+            SessionEndReason::Expired
+        )));
+        assert!(share_visible(&ServerEvent::Heartbeat { bufnr: 1 }));
+    }
+
+    #[test]
+    fn access_log_picks_up_client_ids_from_queries() {
+        assert_eq!(
+            client_from_query("buf=3&client=k2x9").as_deref(),
+            Some("k2x9")
+        );
+        assert_eq!(client_from_query("buf=3&client="), None);
+        assert_eq!(client_from_query("buf=3"), None);
+    }
+
+    #[test]
+    fn content_type_falls_back_to_magic_bytes() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        assert_eq!(
+            image_content_type(Path::new("a.svg"), b"<svg/>"),
+            "image/svg+xml"
+        );
+        assert_eq!(image_content_type(Path::new("figure"), png), "image/png");
+        assert_eq!(image_content_type(Path::new("f.3a9c1e"), png), "image/png");
+        assert_eq!(
+            image_content_type(Path::new("notes"), b"plain text"),
+            "application/octet-stream"
+        );
+    }
 }
@@ -1,11 +1,32 @@
-use crate::protocol::{ServerEvent, SessionEndReason, SnapshotResponse};
-use crate::render::LiveMarkdownRenderer;
+use crate::document::{Document, LineDelta};
+use crate::frontmatter::{Frontmatter, preview_disabled};
+use crate::protocol::{
+    Bookmark, BookmarkUpdate, Comment, CommentAction, FollowGroup, RenderStats, ServerEvent,
+    SessionEndReason, SessionOptions, SessionSummary, SnapshotResponse, TagEntry, ViewerPosition,
+    Viewport,
+};
+use crate::recording;
+use crate::render::{
+    Backend, Heading, Labels, Link, LinkKind, LiveMarkdownRenderer, RenderOutput, TaskProgress,
+    Truncation, push_escaped_html,
+};
+use crate::spell::{SpellChecker, Typo};
+use crate::token::{constant_time_eq, is_well_formed, random_token};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Notify, RwLock, broadcast, watch};
 
 const EVENT_CHANNEL_CAPACITY: usize = 256;
+const PRESENCE_TTL: Duration = Duration::from_secs(60);
+const MAX_CLIENT_ID_LEN: usize = 64;
+const MAX_BOOKMARKS: usize = 200;
+const MAX_BOOKMARK_TEXT: usize = 80;
+const MAX_COMMENTS: usize = 500;
+const MAX_COMMENT_TEXT: usize = 2000;
 
 #[derive(Debug, Clone)]
 pub struct BufferSnapshot {
@@ -15,6 +36,78 @@ pub struct BufferSnapshot {
     pub cursor_line: usize,
     pub cursor_col: usize,
     pub source_path: Option<String>,
+    /// Renderer picked for the buffer's filetype; `None` goes by the file extension.
+    pub backend: Option<Backend>,
+}
+
+/// Rows of a buffer edited since its session's document was at
+/// `base_changedtick`, with the state a snapshot carries besides the text.
+#[derive(Debug, Clone)]
+pub struct BufferEdit {
+    pub bufnr: i64,
+    pub base_changedtick: u64,
+    pub changedtick: u64,
+    pub delta: LineDelta,
+    pub cursor_line: usize,
+    pub cursor_col: usize,
+    pub source_path: Option<String>,
+    pub backend: Option<Backend>,
+}
+
+/// What became of a [`BufferEdit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOutcome {
+    NoSession,
+    /// The document is not at the edit's base; it needs the whole buffer.
+    OutOfSync,
+    Applied,
+    Rendered,
+}
+
+/// An event with its place in the session's stream. Numbers only grow, so
+/// a client can drop events older than the snapshot it loaded.
+#[derive(Debug, Clone)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: ServerEvent,
+}
+
+/// Numbers every event of a session as it is broadcast.
+#[derive(Debug)]
+struct EventSender {
+    sender: broadcast::Sender<SequencedEvent>,
+    seq: AtomicU64,
+}
+
+impl EventSender {
+    fn new() -> Self {
+        let (sender, _receiver) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            sender,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Sends `event` with the next number; the receivers it reached.
+    fn send(&self, event: ServerEvent) -> usize {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sender
+            .send(SequencedEvent { seq, event })
+            .unwrap_or_default()
+    }
+
+    /// The number of the latest event sent, 0 before the first.
+    fn last_seq(&self) -> u64 {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.sender.subscribe()
+    }
+
+    fn receiver_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
 }
 
 #[derive(Debug)]
@@ -25,156 +118,848 @@ struct Session {
     cursor_line: usize,
     cursor_col: usize,
     html: String,
+    tasks: TaskProgress,
+    links: Vec<Link>,
+    /// Heading ids with their source lines, for resolving URL fragments.
+    heading_lines: Vec<(String, usize)>,
+    truncated: Option<Truncation>,
+    /// The converter's error from the last render, if it failed.
+    render_failure: Option<String>,
+    render_stats: RenderStats,
+    options: SessionOptions,
+    pinned: bool,
+    paused: bool,
+    viewers: HashMap<String, (usize, Instant)>,
+    /// Clients that stopped following the editor's cursor.
+    detached: HashSet<String>,
     source_path: Option<PathBuf>,
-    broadcaster: broadcast::Sender<ServerEvent>,
+    broadcaster: EventSender,
+    recorded: bool,
+    /// The buffer's full content, patched line by line on every edit; what
+    /// renders read.
+    document: Document,
+    /// The `changedtick` `document` is at, which the next edit starts from.
+    document_tick: u64,
+    /// The snapshot, less the text kept in `document`, while only its head
+    /// is rendered.
+    clipped: Option<BufferSnapshot>,
+    render_anyway: bool,
+    preview_disabled: bool,
+    /// Last seen modification time of each local image the document shows.
+    asset_mtimes: HashMap<PathBuf, SystemTime>,
+    typos: Vec<Typo>,
+    tags: Vec<String>,
+    /// Bookmarked blocks by their first source line.
+    bookmarks: BTreeMap<usize, String>,
+    /// Open review comments, sorted by line and then by age.
+    comments: Vec<Comment>,
+    next_comment_id: u64,
 }
 
 impl Session {
-    fn new(snapshot: &BufferSnapshot, html: String, content_hash: u64) -> Self {
-        let (broadcaster, _receiver) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    fn new(
+        snapshot: &BufferSnapshot,
+        rendered: RenderOutput,
+        render_time: Duration,
+        content_hash: u64,
+        options: SessionOptions,
+    ) -> Self {
+        let broadcaster = EventSender::new();
+        let mut render_stats = RenderStats::default();
+        render_stats.record(render_time);
         Self {
             bufnr: snapshot.bufnr,
             changedtick: snapshot.changedtick,
             content_hash,
             cursor_line: snapshot.cursor_line,
             cursor_col: snapshot.cursor_col,
-            html,
+            html: rendered.html,
+            tasks: rendered.tasks,
+            links: rendered.links,
+            heading_lines: heading_lines(&rendered.outline),
+            truncated: rendered.truncated,
+            render_failure: rendered.failure,
+            render_stats,
+            options,
+            pinned: false,
+            paused: false,
+            viewers: HashMap::new(),
+            detached: HashSet::new(),
             source_path: snapshot_source_path(snapshot.source_path.as_deref()),
             broadcaster,
+            recorded: false,
+            document: Document::new(&snapshot.markdown),
+            document_tick: snapshot.changedtick,
+            clipped: None,
+            render_anyway: false,
+            preview_disabled: preview_disabled(&snapshot.markdown),
+            asset_mtimes: HashMap::new(),
+            typos: Vec::new(),
+            tags: document_tags(&snapshot.markdown),
+            bookmarks: BTreeMap::new(),
+            comments: Vec::new(),
+            next_comment_id: 1,
         }
     }
 
-    fn apply_snapshot(&mut self, snapshot: &BufferSnapshot, html: String, content_hash: u64) {
+    /// Local image targets as written in the document, one per distinct path.
+    fn local_image_targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self
+            .links
+            .iter()
+            .filter(|link| link.kind == LinkKind::Asset)
+            .filter(|link| parse_local_asset_reference(&link.target).is_some())
+            .map(|link| link.target.clone())
+            .collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    /// Returns whether the document's tags changed, which the session list shows.
+    fn apply_snapshot(
+        &mut self,
+        snapshot: &BufferSnapshot,
+        rendered: RenderOutput,
+        render_time: Duration,
+        content_hash: u64,
+    ) -> bool {
+        let previous_filename = self.filename();
+
         self.changedtick = snapshot.changedtick;
         self.content_hash = content_hash;
         self.cursor_line = snapshot.cursor_line;
         self.cursor_col = snapshot.cursor_col;
-        self.html = html;
+        self.html = rendered.html;
+        self.tasks = rendered.tasks;
+        self.links = rendered.links;
+        self.heading_lines = heading_lines(&rendered.outline);
+        self.truncated = rendered.truncated;
+        self.render_failure = rendered.failure;
+        self.render_stats.record(render_time);
         self.source_path = snapshot_source_path(snapshot.source_path.as_deref());
+        self.preview_disabled = preview_disabled(&snapshot.markdown);
+        let tags = document_tags(&snapshot.markdown);
+        let tags_changed = tags != self.tags;
+        self.tags = tags;
+
+        if self.filename() != previous_filename {
+            self.send_title_changed();
+        }
+        tags_changed
     }
 
-    fn snapshot_response(&self) -> SnapshotResponse {
-        let filename = self
-            .source_path
+    /// Takes the whole buffer of `snapshot` as the document, unless edits
+    /// already brought it further.
+    fn replace_document(&mut self, snapshot: &BufferSnapshot) {
+        if snapshot.changedtick >= self.document_tick {
+            self.document = Document::new(&snapshot.markdown);
+            self.document_tick = snapshot.changedtick;
+        }
+    }
+
+    /// The document as a snapshot, with the rest of `edit`'s state.
+    fn document_snapshot(&self, edit: BufferEdit) -> BufferSnapshot {
+        BufferSnapshot {
+            bufnr: edit.bufnr,
+            changedtick: self.document_tick,
+            markdown: self.document.text(),
+            cursor_line: edit.cursor_line,
+            cursor_col: edit.cursor_col,
+            source_path: edit.source_path,
+            backend: edit.backend,
+        }
+    }
+
+    /// Subscribed preview clients, not counting an event recorder.
+    fn client_count(&self) -> usize {
+        self.broadcaster
+            .receiver_count()
+            .saturating_sub(usize::from(self.recorded))
+    }
+
+    fn filename(&self) -> String {
+        self.source_path
             .as_ref()
             .and_then(|p| p.file_name())
             .map(|name| name.to_string_lossy().to_string())
-            .unwrap_or_else(|| String::from("buffer"));
+            .unwrap_or_else(|| String::from("buffer"))
+    }
+
+    fn send_title_changed(&self) {
+        let filename = self.filename();
+        let _ = self.broadcaster.send(ServerEvent::TitleChanged {
+            bufnr: self.bufnr,
+            title: page_title(&filename),
+            filename,
+        });
+    }
+
+    fn send_render_stats(&self) {
+        let _ = self.broadcaster.send(ServerEvent::RenderStats {
+            bufnr: self.bufnr,
+            stats: self.render_stats,
+        });
+    }
+
+    /// Stores the spelling annotations, announcing them only when they changed.
+    fn set_typos(&mut self, typos: Option<Vec<Typo>>) {
+        let Some(typos) = typos else {
+            return;
+        };
+        if typos == self.typos {
+            return;
+        }
+        self.typos = typos;
+        let _ = self.broadcaster.send(ServerEvent::Spelling {
+            bufnr: self.bufnr,
+            typos: self.typos.clone(),
+        });
+    }
+
+    fn send_truncation(&self) {
+        if let Some(reason) = self.truncated {
+            let _ = self.broadcaster.send(ServerEvent::RenderTruncated {
+                bufnr: self.bufnr,
+                reason,
+            });
+        }
+    }
+
+    fn send_options_changed(&self) {
+        let _ = self.broadcaster.send(ServerEvent::OptionsChanged {
+            bufnr: self.bufnr,
+            options: self.options.clone(),
+        });
+    }
+
+    fn send_presence(&self) {
+        let mut viewers: Vec<ViewerPosition> = self
+            .viewers
+            .iter()
+            .map(|(client, (line, _))| ViewerPosition {
+                client: client.clone(),
+                line: *line,
+            })
+            .collect();
+        viewers.sort_by(|a, b| a.client.cmp(&b.client));
+
+        let _ = self.broadcaster.send(ServerEvent::Presence {
+            bufnr: self.bufnr,
+            viewers,
+        });
+    }
+
+    fn snapshot_response(&self) -> SnapshotResponse {
+        let filename = self.filename();
 
         SnapshotResponse {
             bufnr: self.bufnr,
             html: self.html.clone(),
             cursor_line: self.cursor_line,
             cursor_col: self.cursor_col,
+            title: page_title(&filename),
             filename,
+            tasks: self.tasks,
+            options: self.options.clone(),
+            paused: self.paused,
+            typos: self.typos.clone(),
+            bookmarks: self.bookmark_list(),
+            comments: self.comments.clone(),
+            seq: self.broadcaster.last_seq(),
+            fragment_line: None,
+            resume: self.source_path.as_deref().map(resume_token),
+        }
+    }
+
+    fn bookmark_list(&self) -> Vec<Bookmark> {
+        self.bookmarks
+            .iter()
+            .map(|(line, text)| Bookmark {
+                line: *line,
+                text: text.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Share {
+    bufnr: i64,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    sessions: HashMap<i64, Session>,
+    active: Option<i64>,
+    shares: HashMap<String, Share>,
+    editor_palette: String,
+}
+
+impl SessionState {
+    /// The session currently following buffers in `group`.
+    fn group_bufnr(&self, group: FollowGroup) -> Option<i64> {
+        self.sessions
+            .values()
+            .find(|session| session.options.follow_group == Some(group) && !session.paused)
+            .map(|session| session.bufnr)
+    }
+
+    fn summaries(&self) -> Vec<SessionSummary> {
+        let mut sessions: Vec<SessionSummary> = self
+            .sessions
+            .values()
+            .map(|session| SessionSummary {
+                bufnr: session.bufnr,
+                filename: session.filename(),
+                clients: session.client_count(),
+                render: session.render_stats,
+                tags: session.tags.clone(),
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.bufnr);
+        sessions
+    }
+
+    /// Every tag of the running documents with the sessions carrying it, by tag.
+    fn tag_index(&self) -> Vec<TagEntry> {
+        let mut index: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+        for session in self.sessions.values() {
+            for tag in &session.tags {
+                index.entry(tag).or_default().push(session.bufnr);
+            }
         }
+        index
+            .into_iter()
+            .map(|(tag, mut sessions)| {
+                sessions.sort_unstable();
+                TagEntry {
+                    tag: tag.to_string(),
+                    sessions,
+                }
+            })
+            .collect()
+    }
+
+    fn send_sessions(&self) {
+        let sessions = self.summaries();
+        for session in self.sessions.values() {
+            let _ = session.broadcaster.send(ServerEvent::Sessions {
+                bufnr: session.bufnr,
+                sessions: sessions.clone(),
+            });
+        }
+    }
+}
+
+type EndHookFn = dyn Fn(i64, &SessionEndReason) + Send + Sync;
+
+/// Called with the buffer of every session that is stopped, and why.
+#[derive(Clone)]
+pub struct SessionEndHook(Arc<EndHookFn>);
+
+impl SessionEndHook {
+    pub fn new(hook: impl Fn(i64, &SessionEndReason) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl std::fmt::Debug for SessionEndHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionEndHook").finish_non_exhaustive()
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct SessionManager {
-    active: Arc<RwLock<Option<Session>>>,
+    state: Arc<RwLock<SessionState>>,
+    disconnects: Arc<Notify>,
+    /// When each buffer's preview last lost a client.
+    lost_clients: Arc<Mutex<HashMap<i64, Instant>>>,
+    record_dir: Option<PathBuf>,
+    max_document_bytes: usize,
+    default_asset_root: Option<PathBuf>,
+    spell: Option<Arc<SpellChecker>>,
+    on_end: Option<SessionEndHook>,
+    /// The buffers with a session, republished whenever one starts or ends.
+    bufnrs: watch::Sender<HashSet<i64>>,
 }
 
 impl SessionManager {
+    /// Records the events of every session started from now on into a JSONL
+    /// file in `dir`.
+    pub fn with_recording(mut self, dir: PathBuf) -> Self {
+        self.record_dir = Some(dir);
+        self
+    }
+
+    /// Renders only the first `max_bytes` of larger buffers until the preview
+    /// asks to render them anyway; 0 renders everything.
+    pub fn with_max_document_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_document_bytes = max_bytes;
+        self
+    }
+
+    /// Resolves relative assets of buffers without a file against `root`,
+    /// unless the session sets its own `asset_root`.
+    pub fn with_default_asset_root(mut self, root: PathBuf) -> Self {
+        self.default_asset_root = Some(root);
+        self
+    }
+
+    /// Checks the spelling of every rendered document and sends the unknown
+    /// words to its preview.
+    pub fn with_spellchecker(mut self, checker: SpellChecker) -> Self {
+        self.spell = Some(Arc::new(checker));
+        self
+    }
+
+    /// Runs `hook` whenever a session is stopped or closed with its buffer.
+    /// Handing the preview over to the next followed buffer does not count:
+    /// the page stays open.
+    pub fn with_end_hook(mut self, hook: SessionEndHook) -> Self {
+        self.on_end = Some(hook);
+        self
+    }
+
+    /// Follows the set of buffers with a session without taking the state
+    /// lock, for callers that cannot wait on it.
+    pub fn watch_bufnrs(&self) -> watch::Receiver<HashSet<i64>> {
+        self.bufnrs.subscribe()
+    }
+
+    fn publish_bufnrs(&self, state: &SessionState) {
+        self.bufnrs
+            .send_replace(state.sessions.keys().copied().collect());
+    }
+
+    fn ended(&self, bufnr: i64, reason: &SessionEndReason) {
+        if let Some(SessionEndHook(hook)) = &self.on_end {
+            hook(bufnr, reason);
+        }
+    }
+
+    /// The typos in the rendered part of `snapshot`; `None` without a checker.
+    fn check_spelling(&self, snapshot: &BufferSnapshot, limit: Option<usize>) -> Option<Vec<Typo>> {
+        let checker = self.spell.as_ref()?;
+        let checks_prose = match &snapshot.backend {
+            Some(Backend::Converter(_)) => false,
+            Some(Backend::Format(_)) | None => true,
+        };
+        if !checks_prose || preview_disabled(&snapshot.markdown) {
+            return Some(Vec::new());
+        }
+
+        let source = match limit.filter(|limit| snapshot.markdown.len() > *limit) {
+            Some(limit) => document_head(&snapshot.markdown, limit),
+            None => &snapshot.markdown,
+        };
+        Some(checker.check(source))
+    }
+
+    async fn document_limit(&self, bufnr: i64) -> Option<usize> {
+        if self.max_document_bytes == 0 {
+            return None;
+        }
+        let state = self.state.read().await;
+        let forced = state
+            .sessions
+            .get(&bufnr)
+            .is_some_and(|session| session.render_anyway);
+        (!forced).then_some(self.max_document_bytes)
+    }
+
+    /// Follows a buffer switch: the buffer becomes the active session, keeping
+    /// the options of the session it replaces. The previously active session is
+    /// dropped unless it was started explicitly.
     pub async fn start_session(&self, snapshot: BufferSnapshot, renderer: &LiveMarkdownRenderer) {
-        let rendered_html = renderer.render(&snapshot.markdown);
-        let new_hash = content_hash(&snapshot.markdown);
+        let options = {
+            let state = self.state.read().await;
+            state
+                .active
+                .and_then(|bufnr| state.sessions.get(&bufnr))
+                .map(|session| session.options.clone())
+                .unwrap_or_default()
+        };
 
-        let mut active = self.active.write().await;
-        if let Some(session) = active.as_mut()
-            && session.bufnr == snapshot.bufnr
-        {
-            session.apply_snapshot(&snapshot, rendered_html.clone(), new_hash);
+        self.activate(snapshot, options, false, renderer).await;
+    }
+
+    /// Follows a buffer switch inside `group`, handing over from the session
+    /// following that tabpage or window. Returns false when no preview was
+    /// started there.
+    pub async fn follow_in_group(
+        &self,
+        snapshot: BufferSnapshot,
+        group: FollowGroup,
+        renderer: &LiveMarkdownRenderer,
+    ) -> bool {
+        let options = {
+            let state = self.state.read().await;
+            let Some(bufnr) = state.group_bufnr(group) else {
+                return false;
+            };
+            state.sessions[&bufnr].options.clone()
+        };
+
+        self.activate(snapshot, options, false, renderer).await;
+        true
+    }
+
+    /// The session following buffers in `group`, if a preview was started there.
+    pub async fn group_bufnr(&self, group: FollowGroup) -> Option<i64> {
+        self.state.read().await.group_bufnr(group)
+    }
+
+    /// Makes the session following `group` the active one, so commands act on
+    /// the preview of the tabpage or window the user moved to.
+    pub async fn focus_group(&self, group: FollowGroup) -> bool {
+        let mut state = self.state.write().await;
+        let Some(bufnr) = state.group_bufnr(group) else {
+            return false;
+        };
+        state.active = Some(bufnr);
+        true
+    }
+
+    /// Starts a session that stays alive until it is stopped, even when the
+    /// preview follows other buffers.
+    pub async fn start_session_with_options(
+        &self,
+        snapshot: BufferSnapshot,
+        options: SessionOptions,
+        renderer: &LiveMarkdownRenderer,
+    ) {
+        self.activate(snapshot, options, true, renderer).await;
+    }
+
+    async fn activate(
+        &self,
+        snapshot: BufferSnapshot,
+        options: SessionOptions,
+        pinned: bool,
+        renderer: &LiveMarkdownRenderer,
+    ) {
+        let limit = self.document_limit(snapshot.bufnr).await;
+        let (rendered, render_time) = render_off_thread(renderer, &snapshot, limit).await;
+        let rendered_html = rendered.html.clone();
+        let typos = self.check_spelling(&snapshot, limit);
+        let new_hash = content_hash(&snapshot.markdown);
+        let bufnr = snapshot.bufnr;
+
+        let mut state = self.state.write().await;
+        let mut changed = false;
+        // A scoped preview hands over from the session following its own
+        // tabpage or window, leaving other groups' previews alone.
+        let previous = match options.follow_group {
+            Some(group) => state.group_bufnr(group),
+            None => state.active,
+        };
+        if let Some(session) = state.sessions.get_mut(&bufnr) {
+            session.replace_document(&snapshot);
+            changed |= session.apply_snapshot(&snapshot, rendered, render_time, new_hash);
+            session.clipped = clipped_snapshot(&snapshot, limit);
+            session.pinned |= pinned;
+            if session.paused {
+                session.paused = false;
+                let _ = session
+                    .broadcaster
+                    .send(ServerEvent::SessionResumed { bufnr });
+            }
+            if session.options != options {
+                session.options = options;
+                session.send_options_changed();
+            }
             let _ = session.broadcaster.send(ServerEvent::RenderFull {
-                bufnr: snapshot.bufnr,
+                bufnr,
                 html: rendered_html,
                 cursor_line: snapshot.cursor_line,
             });
-            return;
+            session.send_render_stats();
+            session.send_truncation();
+            session.set_typos(typos);
+        } else {
+            let mut session = Session::new(&snapshot, rendered, render_time, new_hash, options);
+            session.pinned = pinned;
+            session.clipped = clipped_snapshot(&snapshot, limit);
+            let initial = ServerEvent::RenderFull {
+                bufnr,
+                html: rendered_html,
+                cursor_line: snapshot.cursor_line,
+            };
+            if let Some(dir) = &self.record_dir {
+                let events = session.broadcaster.subscribe();
+                match recording::spawn_recorder(dir, bufnr, initial.clone(), events) {
+                    Ok(_) => session.recorded = true,
+                    Err(err) => {
+                        eprintln!("live-markdown.nvim could not record session {bufnr}: {err}")
+                    }
+                }
+            }
+            let _ = session.broadcaster.send(initial);
+            session.send_truncation();
+            session.set_typos(typos);
+            state.sessions.insert(bufnr, session);
+            changed = true;
         }
 
-        let session = Session::new(&snapshot, rendered_html.clone(), new_hash);
-        let _ = session.broadcaster.send(ServerEvent::RenderFull {
-            bufnr: snapshot.bufnr,
-            html: rendered_html,
-            cursor_line: snapshot.cursor_line,
-        });
-        *active = Some(session);
+        state.active = Some(bufnr);
+        if let Some(previous) = previous
+            && previous != bufnr
+            && let Some(session) = state.sessions.get_mut(&previous)
+        {
+            if session.pinned {
+                session.paused = true;
+                let _ = session
+                    .broadcaster
+                    .send(ServerEvent::SessionPaused { bufnr: previous });
+            } else {
+                state.sessions.remove(&previous);
+                state.shares.retain(|_, share| share.bufnr != previous);
+                changed = true;
+            }
+        }
+
+        if changed {
+            state.send_sessions();
+            self.publish_bufnrs(&state);
+        }
     }
 
     pub async fn stop_session(&self, bufnr: i64, reason: SessionEndReason) -> bool {
-        let mut active = self.active.write().await;
-        let Some(session) = active.take() else {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.remove(&bufnr) else {
             return false;
         };
 
-        if session.bufnr != bufnr {
-            *active = Some(session);
-            return false;
+        if state.active == Some(bufnr) {
+            state.active = None;
         }
+        state.shares.retain(|_, share| share.bufnr != bufnr);
 
         let _ = session
             .broadcaster
-            .send(ServerEvent::SessionEnd { bufnr, reason });
+            .send(ServerEvent::session_end(bufnr, reason.clone()));
+        state.send_sessions();
+        self.publish_bufnrs(&state);
+        self.ended(bufnr, &reason);
 
         true
     }
 
     pub async fn stop_all(&self, reason: SessionEndReason) {
-        let mut active = self.active.write().await;
-        if let Some(session) = active.take() {
-            let _ = session.broadcaster.send(ServerEvent::SessionEnd {
-                bufnr: session.bufnr,
-                reason,
-            });
+        self.end_all(&reason, |bufnr| {
+            ServerEvent::session_end(bufnr, reason.clone())
+        })
+        .await;
+    }
+
+    /// Ends every session telling its browsers to wait for the server to
+    /// come back, starting `retry` from now.
+    pub async fn going_away(&self, retry: Duration) {
+        let retry_ms = u64::try_from(retry.as_millis()).unwrap_or(u64::MAX);
+        self.end_all(&SessionEndReason::ShutDown, |bufnr| ServerEvent::Gone {
+            bufnr,
+            retry_ms,
+        })
+        .await;
+    }
+
+    async fn end_all(&self, reason: &SessionEndReason, last_event: impl Fn(i64) -> ServerEvent) {
+        let mut state = self.state.write().await;
+        state.active = None;
+        state.shares.clear();
+        for (bufnr, session) in state.sessions.drain() {
+            let _ = session.broadcaster.send(last_event(bufnr));
+            self.ended(bufnr, reason);
+        }
+        self.publish_bufnrs(&state);
+    }
+
+    /// The session showing the file `token` was handed out for.
+    pub async fn resume(&self, token: &str) -> Option<i64> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .values()
+            .find(|session| {
+                session.source_path.as_deref().map(resume_token).as_deref() == Some(token)
+            })
+            .map(|session| session.bufnr)
+    }
+
+    /// Mints a read-only token for `bufnr` that stops resolving after `ttl`,
+    /// or `None` without a session. Shared sessions are pinned so the link
+    /// survives buffer follows. Fails when the system has no randomness.
+    pub async fn create_share(&self, bufnr: i64, ttl: Duration) -> std::io::Result<Option<String>> {
+        let token = random_token()?;
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return Ok(None);
+        };
+        session.pinned = true;
+
+        state.shares.insert(
+            token.clone(),
+            Share {
+                bufnr,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+        Ok(Some(token))
+    }
+
+    /// Replaces every live share token of `bufnr` with one new token that
+    /// expires when the latest of them would have. Returns `None` when the
+    /// buffer has no live share.
+    pub async fn rotate_share(&self, bufnr: i64) -> std::io::Result<Option<String>> {
+        let token = random_token()?;
+        let mut state = self.state.write().await;
+        let now = Instant::now();
+        let mut expires_at = None;
+        state.shares.retain(|_, share| {
+            if share.bufnr != bufnr {
+                return true;
+            }
+            if share.expires_at > now {
+                expires_at = expires_at.max(Some(share.expires_at));
+            }
+            false
+        });
+
+        let Some(expires_at) = expires_at else {
+            return Ok(None);
+        };
+        state
+            .shares
+            .insert(token.clone(), Share { bufnr, expires_at });
+        Ok(Some(token))
+    }
+
+    /// Returns the buffer and expiry behind a live share token. Every live
+    /// token is compared in constant time, so response times do not help
+    /// guess one.
+    pub async fn resolve_share(&self, token: &str) -> Option<(i64, Instant)> {
+        if !is_well_formed(token) {
+            return None;
+        }
+        let mut state = self.state.write().await;
+        let now = Instant::now();
+        state.shares.retain(|_, share| share.expires_at > now);
+
+        let mut found = None;
+        for (live, share) in &state.shares {
+            if constant_time_eq(live.as_bytes(), token.as_bytes()) {
+                found = Some((share.bufnr, share.expires_at));
+            }
         }
+        found
     }
 
     pub async fn update_content(
         &self,
         snapshot: BufferSnapshot,
         renderer: &LiveMarkdownRenderer,
+    ) -> bool {
+        self.render_update(snapshot, renderer, true).await
+    }
+
+    /// Patches `edit` into its session's document and, when `render` is set,
+    /// renders the document as [`Self::update_content`] would a snapshot.
+    pub async fn apply_edit(
+        &self,
+        edit: BufferEdit,
+        renderer: &LiveMarkdownRenderer,
+        render: bool,
+    ) -> EditOutcome {
+        let snapshot = {
+            let mut state = self.state.write().await;
+            let Some(session) = state.sessions.get_mut(&edit.bufnr) else {
+                return EditOutcome::NoSession;
+            };
+            if session.document_tick != edit.base_changedtick {
+                return EditOutcome::OutOfSync;
+            }
+            session.document.apply(&edit.delta);
+            session.document_tick = edit.changedtick;
+            if !render {
+                return EditOutcome::Applied;
+            }
+            session.document_snapshot(edit)
+        };
+
+        if self.render_update(snapshot, renderer, false).await {
+            EditOutcome::Rendered
+        } else {
+            EditOutcome::Applied
+        }
+    }
+
+    /// Keeps the document of `snapshot`'s session current without rendering,
+    /// for updates that are throttled.
+    pub async fn sync_document(&self, snapshot: &BufferSnapshot) {
+        let mut state = self.state.write().await;
+        if let Some(session) = state.sessions.get_mut(&snapshot.bufnr) {
+            session.replace_document(snapshot);
+        }
+    }
+
+    /// Renders `snapshot` unless it shows what the preview already does.
+    /// A snapshot read from the document leaves the document alone.
+    async fn render_update(
+        &self,
+        snapshot: BufferSnapshot,
+        renderer: &LiveMarkdownRenderer,
+        from_buffer: bool,
     ) -> bool {
         let new_hash = content_hash(&snapshot.markdown);
 
         {
-            let active = self.active.read().await;
-            let Some(session) = active.as_ref() else {
+            let state = self.state.read().await;
+            let Some(session) = state.sessions.get(&snapshot.bufnr) else {
                 return false;
             };
 
-            if session.bufnr != snapshot.bufnr {
+            if session.changedtick == snapshot.changedtick && session.content_hash == new_hash {
                 return false;
             }
-
-            if session.changedtick == snapshot.changedtick && session.content_hash == new_hash {
+            if session.preview_disabled && preview_disabled(&snapshot.markdown) {
                 return false;
             }
         }
 
-        let rendered_html = renderer.render(&snapshot.markdown);
+        let limit = self.document_limit(snapshot.bufnr).await;
+        let (rendered, render_time) = render_off_thread(renderer, &snapshot, limit).await;
+        let rendered_html = rendered.html.clone();
+        let typos = self.check_spelling(&snapshot, limit);
 
-        let mut active = self.active.write().await;
-        let Some(session) = active.as_mut() else {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&snapshot.bufnr) else {
             return false;
         };
 
-        if session.bufnr != snapshot.bufnr {
-            return false;
-        }
-
         if session.changedtick == snapshot.changedtick && session.content_hash == new_hash {
             return false;
         }
 
-        session.apply_snapshot(&snapshot, rendered_html.clone(), new_hash);
+        if from_buffer {
+            session.replace_document(&snapshot);
+        }
+        let tags_changed = session.apply_snapshot(&snapshot, rendered, render_time, new_hash);
+        session.clipped = clipped_snapshot(&snapshot, limit);
 
         let _ = session.broadcaster.send(ServerEvent::RenderFull {
             bufnr: snapshot.bufnr,
             html: rendered_html,
             cursor_line: snapshot.cursor_line,
         });
+        session.send_render_stats();
+        session.send_truncation();
+        session.set_typos(typos);
+        if tags_changed {
+            state.send_sessions();
+        }
 
         true
     }
@@ -184,39 +969,144 @@ impl SessionManager {
         snapshot: BufferSnapshot,
         renderer: &LiveMarkdownRenderer,
     ) -> bool {
-        let rendered_html = renderer.render(&snapshot.markdown);
-        let new_hash = content_hash(&snapshot.markdown);
+        self.rerender(snapshot, renderer, true).await
+    }
 
-        let mut active = self.active.write().await;
-        let Some(session) = active.as_mut() else {
-            return false;
-        };
+    async fn rerender(
+        &self,
+        snapshot: BufferSnapshot,
+        renderer: &LiveMarkdownRenderer,
+        from_buffer: bool,
+    ) -> bool {
+        let limit = self.document_limit(snapshot.bufnr).await;
+        let (rendered, render_time) = render_off_thread(renderer, &snapshot, limit).await;
+        let rendered_html = rendered.html.clone();
+        let typos = self.check_spelling(&snapshot, limit);
+        let new_hash = content_hash(&snapshot.markdown);
 
-        if session.bufnr != snapshot.bufnr {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&snapshot.bufnr) else {
             return false;
         };
 
-        session.apply_snapshot(&snapshot, rendered_html.clone(), new_hash);
+        if from_buffer {
+            session.replace_document(&snapshot);
+        }
+        let tags_changed = session.apply_snapshot(&snapshot, rendered, render_time, new_hash);
+        session.clipped = clipped_snapshot(&snapshot, limit);
 
         let _ = session.broadcaster.send(ServerEvent::RenderFull {
             bufnr: snapshot.bufnr,
             html: rendered_html,
             cursor_line: snapshot.cursor_line,
         });
+        session.send_render_stats();
+        session.send_truncation();
+        session.set_typos(typos);
+        if tags_changed {
+            state.send_sessions();
+        }
 
         true
     }
 
-    pub async fn update_cursor(&self, bufnr: i64, line: usize, col: usize) -> bool {
-        let mut active = self.active.write().await;
-        let Some(session) = active.as_mut() else {
-            return false;
-        };
+    /// Re-renders `snapshot` regardless of its hash and tells the preview to
+    /// reload local assets, which may have changed on disk.
+    pub async fn refresh(&self, snapshot: BufferSnapshot, renderer: &LiveMarkdownRenderer) -> bool {
+        {
+            let state = self.state.read().await;
+            let Some(session) = state.sessions.get(&snapshot.bufnr) else {
+                return false;
+            };
+            let _ = session.broadcaster.send(ServerEvent::Refresh {
+                bufnr: snapshot.bufnr,
+            });
+        }
+
+        self.rerender_content(snapshot, renderer).await
+    }
+
+    /// Why the buffer's converter failed on its last render, if it did.
+    pub async fn render_failure(&self, bufnr: i64) -> Option<String> {
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr)?.render_failure.clone()
+    }
 
-        if session.bufnr != bufnr {
+    /// Points the session at the buffer's new file after a rename, so local
+    /// assets resolve against the new directory and the title follows.
+    pub async fn update_source_path(&self, bufnr: i64, source_path: Option<String>) -> bool {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
             return false;
+        };
+
+        let previous_filename = session.filename();
+        session.source_path = snapshot_source_path(source_path.as_deref());
+        if session.filename() != previous_filename {
+            session.send_title_changed();
+        }
+
+        true
+    }
+
+    /// The file `bufnr` is showing, if it has one.
+    pub async fn source_path(&self, bufnr: i64) -> Option<PathBuf> {
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr)?.source_path.clone()
+    }
+
+    /// Lifts `max_document_bytes` for `bufnr` and renders the whole buffer.
+    pub async fn render_anyway(&self, bufnr: i64, renderer: &LiveMarkdownRenderer) -> bool {
+        let snapshot = {
+            let mut state = self.state.write().await;
+            let Some(session) = state.sessions.get_mut(&bufnr) else {
+                return false;
+            };
+            session.render_anyway = true;
+            session.clipped.take().map(|snapshot| BufferSnapshot {
+                changedtick: session.document_tick,
+                markdown: session.document.text(),
+                ..snapshot
+            })
+        };
+
+        match snapshot {
+            Some(snapshot) => self.rerender(snapshot, renderer, false).await,
+            None => true,
+        }
+    }
+
+    /// Catches an existing session up with its buffer on re-entry: the
+    /// content is rendered again only when it changed while the buffer was
+    /// not being watched, otherwise just the cursor moves.
+    pub async fn resume_session(
+        &self,
+        snapshot: BufferSnapshot,
+        renderer: &LiveMarkdownRenderer,
+    ) -> bool {
+        let stale = {
+            let state = self.state.read().await;
+            let Some(session) = state.sessions.get(&snapshot.bufnr) else {
+                return false;
+            };
+            session.content_hash != content_hash(&snapshot.markdown)
+        };
+
+        if stale {
+            return self.rerender_content(snapshot, renderer).await;
         }
 
+        self.update_cursor(snapshot.bufnr, snapshot.cursor_line, snapshot.cursor_col)
+            .await;
+        true
+    }
+
+    pub async fn update_cursor(&self, bufnr: i64, line: usize, col: usize) -> bool {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return false;
+        };
+
         if session.cursor_line == line && session.cursor_col == col {
             return false;
         }
@@ -230,73 +1120,658 @@ impl SessionManager {
         true
     }
 
+    /// Broadcasts the editor window's visible range along with its cursor, so
+    /// previews anchored to the viewport can mirror what the editor shows.
+    pub async fn update_viewport(&self, bufnr: i64, viewport: Viewport) -> bool {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return false;
+        };
+
+        session.cursor_line = viewport.cursor_line;
+        session.cursor_col = viewport.cursor_col;
+        let _ = session.broadcaster.send(ServerEvent::Viewport {
+            bufnr,
+            topline: viewport.topline,
+            botline: viewport.botline,
+            cursor_line: viewport.cursor_line,
+            cursor_col: viewport.cursor_col,
+        });
+        true
+    }
+
+    /// Tells every preview the editor's `'background'` is now `theme`.
+    pub async fn broadcast_theme(&self, theme: &str) {
+        let state = self.state.read().await;
+        for session in state.sessions.values() {
+            let _ = session.broadcaster.send(ServerEvent::ThemeChanged {
+                bufnr: session.bufnr,
+                theme: theme.to_string(),
+            });
+        }
+    }
+
+    /// Replaces the palette generated from the editor's colorscheme and
+    /// tells every preview to reload it.
+    pub async fn set_editor_palette(&self, css: String) {
+        let mut state = self.state.write().await;
+        if state.editor_palette == css {
+            return;
+        }
+        state.editor_palette = css;
+        for session in state.sessions.values() {
+            let _ = session.broadcaster.send(ServerEvent::PaletteChanged {
+                bufnr: session.bufnr,
+            });
+        }
+    }
+
+    pub async fn editor_palette(&self) -> String {
+        self.state.read().await.editor_palette.clone()
+    }
+
+    /// Re-sends a recorded event to its session; full renders also replace
+    /// the HTML served to newly opened previews.
+    pub async fn replay_event(&self, event: ServerEvent) -> bool {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&event.bufnr()) else {
+            return false;
+        };
+
+        match &event {
+            ServerEvent::RenderFull {
+                html, cursor_line, ..
+            } => {
+                session.html = html.clone();
+                session.cursor_line = *cursor_line;
+            }
+            ServerEvent::CursorMove { line, col, .. } => {
+                session.cursor_line = *line;
+                session.cursor_col = *col;
+            }
+            ServerEvent::Viewport {
+                cursor_line,
+                cursor_col,
+                ..
+            } => {
+                session.cursor_line = *cursor_line;
+                session.cursor_col = *cursor_col;
+            }
+            ServerEvent::SessionPaused { .. } => session.paused = true,
+            ServerEvent::SessionResumed { .. } => session.paused = false,
+            ServerEvent::Spelling { typos, .. } => session.typos = typos.clone(),
+            ServerEvent::Comments { comments, .. } => {
+                session.comments = comments.clone();
+                let last_id = comments.iter().map(|comment| comment.id).max();
+                session.next_comment_id = session.next_comment_id.max(last_id.unwrap_or(0) + 1);
+            }
+            ServerEvent::Bookmarks { bookmarks, .. } => {
+                session.bookmarks = bookmarks
+                    .iter()
+                    .map(|bookmark| (bookmark.line, bookmark.text.clone()))
+                    .collect();
+            }
+            _ => {}
+        }
+
+        let _ = session.broadcaster.send(event);
+        true
+    }
+
     pub async fn has_session(&self, bufnr: i64) -> bool {
-        let active = self.active.read().await;
-        active
-            .as_ref()
-            .is_some_and(|session| session.bufnr == bufnr)
+        let state = self.state.read().await;
+        state.sessions.contains_key(&bufnr)
     }
 
     pub async fn snapshot(&self, bufnr: i64) -> Option<SnapshotResponse> {
-        let active = self.active.read().await;
-        let session = active.as_ref()?;
-        if session.bufnr != bufnr {
-            return None;
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr).map(Session::snapshot_response)
+    }
+
+    /// Source line of the heading whose id is `fragment`.
+    pub async fn heading_line(&self, bufnr: i64, fragment: &str) -> Option<usize> {
+        let state = self.state.read().await;
+        let session = state.sessions.get(&bufnr)?;
+        session
+            .heading_lines
+            .iter()
+            .find(|(id, _)| id == fragment)
+            .map(|(_, line)| *line)
+    }
+
+    pub async fn task_progress(&self, bufnr: i64) -> Option<TaskProgress> {
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr).map(|session| session.tasks)
+    }
+
+    pub async fn links(&self, bufnr: i64) -> Option<Vec<Link>> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .map(|session| session.links.clone())
+    }
+
+    pub async fn render_stats(&self, bufnr: i64) -> Option<RenderStats> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .map(|session| session.render_stats)
+    }
+
+    pub async fn options(&self, bufnr: i64) -> Option<SessionOptions> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .map(|session| session.options.clone())
+    }
+
+    pub async fn set_options(&self, bufnr: i64, options: SessionOptions) -> bool {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return false;
+        };
+
+        if session.options != options {
+            session.options = options;
+            session.send_options_changed();
         }
+        true
+    }
+
+    pub async fn navigate(&self, bufnr: i64, anchor: &str) -> bool {
+        let state = self.state.read().await;
+        let Some(session) = state.sessions.get(&bufnr) else {
+            return false;
+        };
 
-        Some(session.snapshot_response())
+        let _ = session.broadcaster.send(ServerEvent::Navigate {
+            bufnr,
+            anchor: anchor.to_string(),
+        });
+        true
     }
 
-    pub async fn resolve_local_asset_path(&self, bufnr: i64, raw_path: &str) -> Option<PathBuf> {
-        let active = self.active.read().await;
-        let session = active.as_ref()?;
-        if session.bufnr != bufnr {
-            return None;
+    /// Points the session's connected clients at its new share link.
+    pub async fn reconnect(&self, bufnr: i64, url: &str) -> bool {
+        let state = self.state.read().await;
+        let Some(session) = state.sessions.get(&bufnr) else {
+            return false;
+        };
+
+        let _ = session.broadcaster.send(ServerEvent::Reconnect {
+            bufnr,
+            url: url.to_string(),
+        });
+        true
+    }
+
+    /// Records where a preview client is reading and tells every client.
+    /// Viewers that have not reported within a minute are dropped.
+    pub async fn update_presence(&self, bufnr: i64, client: &str, line: usize) -> bool {
+        if client.is_empty() || client.len() > MAX_CLIENT_ID_LEN {
+            return false;
         }
 
-        let source_file = session.source_path.as_ref()?;
-        let source_dir = source_file.parent()?.canonicalize().ok()?;
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return false;
+        };
 
-        let reference = parse_local_asset_reference(raw_path)?;
-        let candidate = if reference.is_absolute() {
-            reference
-        } else {
-            source_dir.join(reference)
+        let now = Instant::now();
+        let before = session.viewers.len();
+        session
+            .viewers
+            .retain(|_, (_, seen)| now.duration_since(*seen) < PRESENCE_TTL);
+        let previous = session
+            .viewers
+            .insert(client.to_string(), (line, now))
+            .map(|(line, _)| line);
+
+        if previous != Some(line) || session.viewers.len() != before {
+            session.send_presence();
+        }
+        true
+    }
+
+    pub async fn remove_viewer(&self, bufnr: i64, client: &str) {
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return;
         };
+        session.detached.remove(client);
+        if session.viewers.remove(client).is_some() {
+            session.send_presence();
+        }
+    }
 
-        let resolved = candidate.canonicalize().ok()?;
-        if !resolved.starts_with(&source_dir) {
-            return None;
+    /// Adds or removes a bookmark and sends the new list to every client;
+    /// `None` without a session or when the list is full.
+    pub async fn update_bookmark(
+        &self,
+        bufnr: i64,
+        update: BookmarkUpdate,
+    ) -> Option<Vec<Bookmark>> {
+        let mut state = self.state.write().await;
+        let session = state.sessions.get_mut(&bufnr)?;
+
+        if update.remove {
+            session.bookmarks.remove(&update.line);
+        } else {
+            if update.line == 0
+                || (session.bookmarks.len() >= MAX_BOOKMARKS
+                    && !session.bookmarks.contains_key(&update.line))
+            {
+                return None;
+            }
+            let text: String = update
+                .text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .take(MAX_BOOKMARK_TEXT)
+                .collect();
+            session.bookmarks.insert(update.line, text);
         }
-        if !resolved.is_file() {
-            return None;
+
+        let bookmarks = session.bookmark_list();
+        let _ = session.broadcaster.send(ServerEvent::Bookmarks {
+            bufnr,
+            bookmarks: bookmarks.clone(),
+        });
+        Some(bookmarks)
+    }
+
+    pub async fn bookmarks(&self, bufnr: i64) -> Option<Vec<Bookmark>> {
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr).map(Session::bookmark_list)
+    }
+
+    /// Adds or resolves a review comment and sends the open comments to every
+    /// client; `None` without a session, for an unknown comment or an empty
+    /// or excess one.
+    pub async fn update_comments(&self, bufnr: i64, action: CommentAction) -> Option<Vec<Comment>> {
+        let mut state = self.state.write().await;
+        let session = state.sessions.get_mut(&bufnr)?;
+
+        match action {
+            CommentAction::Add { line, text } => {
+                let text: String = text.trim().chars().take(MAX_COMMENT_TEXT).collect();
+                if line == 0 || text.is_empty() || session.comments.len() >= MAX_COMMENTS {
+                    return None;
+                }
+                let id = session.next_comment_id;
+                session.next_comment_id += 1;
+                let at = session
+                    .comments
+                    .partition_point(|comment| comment.line <= line);
+                session.comments.insert(at, Comment { id, line, text });
+            }
+            CommentAction::Resolve { id } => {
+                let at = session
+                    .comments
+                    .iter()
+                    .position(|comment| comment.id == id)?;
+                session.comments.remove(at);
+            }
+        }
+
+        let _ = session.broadcaster.send(ServerEvent::Comments {
+            bufnr,
+            comments: session.comments.clone(),
+        });
+        Some(session.comments.clone())
+    }
+
+    pub async fn comments(&self, bufnr: i64) -> Option<Vec<Comment>> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .map(|session| session.comments.clone())
+    }
+
+    /// Stops or resumes sending cursor and viewport moves to one client, so
+    /// it stays where its reader left it while the others keep following.
+    pub async fn set_detached(&self, bufnr: i64, client: &str, detached: bool) -> bool {
+        if client.is_empty() || client.len() > MAX_CLIENT_ID_LEN {
+            return false;
+        }
+
+        let mut state = self.state.write().await;
+        let Some(session) = state.sessions.get_mut(&bufnr) else {
+            return false;
+        };
+        if detached {
+            session.detached.insert(client.to_string());
+        } else {
+            session.detached.remove(client);
         }
-        if !is_supported_image_path(&resolved) {
+        true
+    }
+
+    pub async fn is_detached(&self, bufnr: i64, client: &str) -> bool {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .is_some_and(|session| session.detached.contains(client))
+    }
+
+    pub async fn resolve_local_asset_path(&self, bufnr: i64, raw_path: &str) -> Option<PathBuf> {
+        self.resolve_local_file_path(bufnr, raw_path)
+            .await
+            .filter(|path| is_supported_image_path(path))
+    }
+
+    /// Resolves any existing file inside the buffer's directory; callers decide
+    /// which kinds of file they are willing to serve.
+    pub async fn resolve_local_file_path(&self, bufnr: i64, raw_path: &str) -> Option<PathBuf> {
+        let source_dir = self.source_dir(bufnr).await?;
+        let resolved = resolve_against(&source_dir, raw_path)?;
+        if !resolved.starts_with(&source_dir) {
             return None;
         }
 
         Some(resolved)
     }
 
-    pub async fn subscribe(&self, bufnr: i64) -> Option<broadcast::Receiver<ServerEvent>> {
-        let active = self.active.read().await;
-        let session = active.as_ref()?;
-        if session.bufnr != bufnr {
-            return None;
+    /// Resolves a link destination relative to the buffer's directory. Unlike
+    /// asset paths, links may point at any existing file, including ones
+    /// outside the source directory.
+    pub async fn resolve_local_link_path(&self, bufnr: i64, raw_path: &str) -> Option<PathBuf> {
+        let source_dir = self.source_dir(bufnr).await?;
+        resolve_against(&source_dir, raw_path)
+    }
+
+    /// Stats the local images each document shows and tells its preview
+    /// about the ones modified since the last check. Images seen for the
+    /// first time only record their modification time.
+    pub async fn check_assets(&self) -> usize {
+        let watched: Vec<(i64, PathBuf, Vec<String>)> = {
+            let state = self.state.read().await;
+            state
+                .sessions
+                .values()
+                .filter_map(|session| {
+                    let targets = session.local_image_targets();
+                    if targets.is_empty() {
+                        return None;
+                    }
+                    let dir = self.session_source_dir(session)?;
+                    Some((session.bufnr, dir, targets))
+                })
+                .collect()
+        };
+
+        let mut invalidated = 0;
+        for (bufnr, source_dir, targets) in watched {
+            let mut seen = Vec::with_capacity(targets.len());
+            for target in targets {
+                let Some(path) = resolve_against(&source_dir, &target)
+                    .filter(|path| path.starts_with(&source_dir))
+                else {
+                    continue;
+                };
+                let Ok(modified) = std::fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                    continue;
+                };
+                seen.push((target, path, modified));
+            }
+
+            let mut state = self.state.write().await;
+            let Some(session) = state.sessions.get_mut(&bufnr) else {
+                continue;
+            };
+            let previous = std::mem::take(&mut session.asset_mtimes);
+            for (target, path, modified) in seen {
+                if previous.get(&path).is_some_and(|last| *last != modified) {
+                    let _ = session.broadcaster.send(ServerEvent::AssetInvalidated {
+                        bufnr,
+                        path: target,
+                    });
+                    invalidated += 1;
+                }
+                session.asset_mtimes.insert(path, modified);
+            }
         }
 
-        Some(session.broadcaster.subscribe())
+        invalidated
+    }
+
+    /// The buffer's directory, or the asset root for buffers without a file.
+    async fn source_dir(&self, bufnr: i64) -> Option<PathBuf> {
+        let state = self.state.read().await;
+        let session = state.sessions.get(&bufnr)?;
+        self.session_source_dir(session)
+    }
+
+    fn session_source_dir(&self, session: &Session) -> Option<PathBuf> {
+        let dir = match &session.source_path {
+            Some(path) => path.parent()?.to_path_buf(),
+            None => session
+                .options
+                .asset_root
+                .clone()
+                .or_else(|| self.default_asset_root.clone())?,
+        };
+        dir.canonicalize().ok()
+    }
+
+    pub async fn subscribe(&self, bufnr: i64) -> Option<broadcast::Receiver<SequencedEvent>> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .get(&bufnr)
+            .map(|session| session.broadcaster.subscribe())
     }
 
     pub async fn session_count(&self) -> usize {
-        let active = self.active.read().await;
-        if active.is_some() { 1 } else { 0 }
+        self.state.read().await.sessions.len()
+    }
+
+    pub async fn session_bufnrs(&self) -> Vec<i64> {
+        self.state.read().await.sessions.keys().copied().collect()
+    }
+
+    pub async fn list_sessions(&self) -> Vec<SessionSummary> {
+        self.state.read().await.summaries()
+    }
+
+    /// Running sessions whose document is tagged `tag`.
+    pub async fn list_tagged_sessions(&self, tag: &str) -> Vec<SessionSummary> {
+        let tag = tag.trim().trim_start_matches('#');
+        let mut sessions = self.list_sessions().await;
+        sessions.retain(|session| session.tags.iter().any(|known| known == tag));
+        sessions
+    }
+
+    pub async fn tag_index(&self) -> Vec<TagEntry> {
+        self.state.read().await.tag_index()
+    }
+
+    /// Notes that a preview client of `bufnr` went away, for
+    /// [`Self::abandoned_sessions`].
+    pub fn client_disconnected(&self, bufnr: i64) {
+        self.lost_clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(bufnr, Instant::now());
+        self.disconnects.notify_one();
+    }
+
+    pub async fn wait_for_disconnect(&self) {
+        self.disconnects.notified().await;
+    }
+
+    /// Sessions that lost their last client at least `grace` ago and have
+    /// had none since. Sessions no browser ever connected to are left alone.
+    pub async fn abandoned_sessions(&self, grace: Duration) -> Vec<i64> {
+        let state = self.state.read().await;
+        let mut lost = self
+            .lost_clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut abandoned = Vec::new();
+        lost.retain(|bufnr, lost_at| {
+            let Some(session) = state.sessions.get(bufnr) else {
+                return false;
+            };
+            if session.client_count() > 0 {
+                return false;
+            }
+            if lost_at.elapsed() < grace {
+                return true;
+            }
+            abandoned.push(*bufnr);
+            false
+        });
+        abandoned
+    }
+
+    /// Preview clients subscribed to `bufnr`'s session; 0 without a session.
+    pub async fn client_count(&self, bufnr: i64) -> usize {
+        let state = self.state.read().await;
+        state.sessions.get(&bufnr).map_or(0, Session::client_count)
     }
 
     pub async fn active_bufnr(&self) -> Option<i64> {
-        let active = self.active.read().await;
-        active.as_ref().map(|session| session.bufnr)
+        self.state.read().await.active
+    }
+}
+
+/// Runs [`render_snapshot`] on the blocking pool, since an external converter
+/// may take up to its timeout and must not stall the server's workers.
+async fn render_off_thread(
+    renderer: &LiveMarkdownRenderer,
+    snapshot: &BufferSnapshot,
+    limit: Option<usize>,
+) -> (RenderOutput, Duration) {
+    let renderer = renderer.clone();
+    let snapshot = snapshot.clone();
+    match tokio::task::spawn_blocking(move || render_snapshot(&renderer, &snapshot, limit)).await {
+        Ok(rendered) => rendered,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Renders `snapshot`, or only the lines within `limit` bytes behind a banner
+/// offering to render the rest.
+fn render_snapshot(
+    renderer: &LiveMarkdownRenderer,
+    snapshot: &BufferSnapshot,
+    limit: Option<usize>,
+) -> (RenderOutput, Duration) {
+    let started = Instant::now();
+    if preview_disabled(&snapshot.markdown) {
+        return (disabled_output(renderer.labels()), started.elapsed());
+    }
+
+    let Some(limit) = limit.filter(|limit| snapshot.markdown.len() > *limit) else {
+        let rendered = render_source(renderer, snapshot, &snapshot.markdown);
+        return (rendered, started.elapsed());
+    };
+
+    let head = document_head(&snapshot.markdown, limit);
+    let mut rendered = render_source(renderer, snapshot, head);
+    insert_clipped_banner(
+        &mut rendered.html,
+        head.len(),
+        snapshot.markdown.len(),
+        renderer.labels(),
+    );
+    (rendered, started.elapsed())
+}
+
+fn disabled_output(labels: &Labels) -> RenderOutput {
+    let mut html = String::from("<article id=\"md-root\"><div class=\"document-disabled\">");
+    push_escaped_html(&mut html, labels.get("preview_disabled"));
+    html.push_str("</div></article>");
+    RenderOutput {
+        html,
+        ..RenderOutput::default()
+    }
+}
+
+fn render_source(
+    renderer: &LiveMarkdownRenderer,
+    snapshot: &BufferSnapshot,
+    source: &str,
+) -> RenderOutput {
+    match &snapshot.backend {
+        Some(backend) => renderer.render_backend(backend, source),
+        None => renderer.render_source(source, snapshot.source_path.as_deref()),
+    }
+}
+
+fn heading_lines(outline: &[Heading]) -> Vec<(String, usize)> {
+    outline
+        .iter()
+        .map(|heading| (heading.id.clone(), heading.line))
+        .collect()
+}
+
+fn document_tags(markdown: &str) -> Vec<String> {
+    Frontmatter::parse(markdown)
+        .map(|frontmatter| frontmatter.tags())
+        .unwrap_or_default()
+}
+
+fn clipped_snapshot(snapshot: &BufferSnapshot, limit: Option<usize>) -> Option<BufferSnapshot> {
+    limit
+        .is_some_and(|limit| snapshot.markdown.len() > limit)
+        .then(|| BufferSnapshot {
+            bufnr: snapshot.bufnr,
+            changedtick: snapshot.changedtick,
+            markdown: String::new(),
+            cursor_line: snapshot.cursor_line,
+            cursor_col: snapshot.cursor_col,
+            source_path: snapshot.source_path.clone(),
+            backend: snapshot.backend.clone(),
+        })
+}
+
+fn document_head(markdown: &str, limit: usize) -> &str {
+    let mut end = limit.min(markdown.len());
+    while !markdown.is_char_boundary(end) {
+        end -= 1;
     }
+    markdown[..end]
+        .rfind('\n')
+        .map_or(&markdown[..end], |newline| &markdown[..=newline])
+}
+
+fn insert_clipped_banner(html: &mut String, shown: usize, total: usize, labels: &Labels) {
+    let message = labels
+        .get("document_clipped")
+        .replace("{shown}", &format_kib(shown))
+        .replace("{total}", &format_kib(total));
+    let mut banner = String::from("<div class=\"document-clipped\"><span>");
+    push_escaped_html(&mut banner, &message);
+    banner.push_str("</span> <button type=\"button\" data-render-anyway>");
+    push_escaped_html(&mut banner, labels.get("render_anyway"));
+    banner.push_str("</button></div>");
+
+    let insert_at = html.find('>').map_or(0, |end| end + 1);
+    html.insert_str(insert_at, &banner);
+}
+
+fn format_kib(bytes: usize) -> String {
+    format!("{} KB", bytes.div_ceil(1024))
+}
+
+pub fn page_title(filename: &str) -> String {
+    format!("{filename} \u{2014} live-markdown")
+}
+
+/// Stable across processes for the same path, unlike a bufnr.
+fn resume_token(path: &Path) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
 }
 
 fn content_hash(input: &str) -> u64 {
@@ -314,6 +1789,18 @@ fn snapshot_source_path(path: Option<&str>) -> Option<PathBuf> {
     }
 }
 
+fn resolve_against(source_dir: &Path, raw_path: &str) -> Option<PathBuf> {
+    let reference = parse_local_asset_reference(raw_path)?;
+    let candidate = if reference.is_absolute() {
+        reference
+    } else {
+        source_dir.join(reference)
+    };
+
+    let resolved = candidate.canonicalize().ok()?;
+    resolved.is_file().then_some(resolved)
+}
+
 fn parse_local_asset_reference(raw_path: &str) -> Option<PathBuf> {
     let trimmed = raw_path.trim();
     if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//") {
@@ -344,7 +1831,7 @@ fn parse_local_asset_reference(raw_path: &str) -> Option<PathBuf> {
     Some(PathBuf::from(decoded))
 }
 
-fn has_url_scheme(value: &str) -> bool {
+pub(crate) fn has_url_scheme(value: &str) -> bool {
     if value.len() >= 3 {
         let bytes = value.as_bytes();
         if bytes[0].is_ascii_alphabetic()
@@ -410,7 +1897,19 @@ fn decode_hex_nibble(value: u8) -> Option<u8> {
     }
 }
 
-fn is_supported_image_path(path: &Path) -> bool {
+/// Accepts known image extensions, falling back to the file's magic bytes for
+/// extensionless or hashed asset names.
+pub(crate) fn is_supported_image_path(path: &Path) -> bool {
+    has_image_extension(path) || sniff_image_type(path).is_some()
+}
+
+/// Content type of an image detected from its leading bytes.
+pub(crate) fn sniff_image_type(path: &Path) -> Option<&'static str> {
+    let kind = infer::get_from_path(path).ok()??;
+    matches!(kind.matcher_type(), infer::MatcherType::Image).then(|| kind.mime_type())
+}
+
+fn has_image_extension(path: &Path) -> bool {
     let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
         return false;
     };
@@ -434,12 +1933,18 @@ fn is_supported_image_path(path: &Path) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::{BufferSnapshot, SessionManager};
-    use crate::protocol::{ServerEvent, SessionEndReason};
-    use crate::render::LiveMarkdownRenderer;
+    use super::{BufferEdit, BufferSnapshot, EditOutcome, SequencedEvent, SessionManager};
+    use crate::document::LineDelta;
+    use crate::protocol::{
+        Bookmark, BookmarkUpdate, CommentAction, FollowGroup, ServerEvent, SessionEndReason,
+        SessionOptions, Viewport,
+    };
+    use crate::render::{Backend, LiveMarkdownRenderer, SourceFormat};
+    use crate::spell::{SpellChecker, Typo};
+    use std::collections::HashSet;
     use std::fs;
     use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     fn temp_test_dir(name: &str) -> PathBuf {
         let nanos = SystemTime::now()
@@ -463,6 +1968,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -480,6 +1986,7 @@ mod tests {
                     cursor_line: 2,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -493,19 +2000,81 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn starting_new_buffer_replaces_previous_session() {
+    async fn resolves_url_fragments_to_heading_lines() {
         let sessions = SessionManager::default();
         let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |changedtick, markdown: &str| BufferSnapshot {
+            bufnr: 6,
+            changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
 
         sessions
-            .start_session(
-                BufferSnapshot {
-                    bufnr: 11,
-                    changedtick: 1,
-                    markdown: String::from("# first"),
-                    cursor_line: 1,
-                    cursor_col: 0,
+            .start_session(snapshot(1, "# Notes\n\n## Setup\n"), &renderer)
+            .await;
+        assert_eq!(sessions.heading_line(6, "setup").await, Some(3));
+        assert_eq!(sessions.heading_line(6, "missing").await, None);
+
+        sessions
+            .update_content(snapshot(2, "# Notes\n\nintro\n\n## Setup\n"), &renderer)
+            .await;
+        assert_eq!(sessions.heading_line(6, "setup").await, Some(5));
+        assert_eq!(sessions.heading_line(7, "setup").await, None);
+    }
+
+    #[tokio::test]
+    async fn publishes_the_buffers_with_a_session() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let bufnrs = sessions.watch_bufnrs();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("# doc"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session_with_options(snapshot(1), SessionOptions::default(), &renderer)
+            .await;
+        sessions
+            .start_session_with_options(snapshot(2), SessionOptions::default(), &renderer)
+            .await;
+        assert_eq!(*bufnrs.borrow(), HashSet::from([1, 2]));
+
+        sessions.stop_session(1, SessionEndReason::Stopped).await;
+        assert_eq!(*bufnrs.borrow(), HashSet::from([2]));
+
+        sessions.start_session(snapshot(3), &renderer).await;
+        sessions.start_session(snapshot(4), &renderer).await;
+        assert_eq!(*bufnrs.borrow(), HashSet::from([2, 4]));
+
+        sessions.stop_all(SessionEndReason::Stopped).await;
+        assert!(bufnrs.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn starting_new_buffer_replaces_previous_session() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 11,
+                    changedtick: 1,
+                    markdown: String::from("# first"),
+                    cursor_line: 1,
+                    cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -520,6 +2089,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -546,6 +2116,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -556,6 +2127,50 @@ mod tests {
         assert!(!sessions.update_cursor(2, 2, 3).await);
     }
 
+    #[tokio::test]
+    async fn viewport_updates_carry_topline_and_cursor() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 2,
+                    changedtick: 1,
+                    markdown: String::from("line"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        let mut rx = sessions.subscribe(2).await.expect("valid subscription");
+        let viewport = Viewport {
+            topline: 30,
+            botline: 70,
+            cursor_line: 42,
+            cursor_col: 5,
+        };
+
+        assert!(sessions.update_viewport(2, viewport).await);
+        assert!(!sessions.update_viewport(9, viewport).await);
+        assert!(matches!(
+            rx.recv().await.expect("viewport event").event,
+            ServerEvent::Viewport {
+                topline: 30,
+                botline: 70,
+                cursor_line: 42,
+                ..
+            }
+        ));
+        assert_eq!(
+            sessions.snapshot(2).await.expect("snapshot").cursor_line,
+            42
+        );
+    }
+
     #[tokio::test]
     async fn subscription_requires_active_session() {
         let sessions = SessionManager::default();
@@ -570,6 +2185,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -578,9 +2194,14 @@ mod tests {
         let mut rx = sessions.subscribe(3).await.expect("valid subscription");
 
         assert!(sessions.subscribe(99).await.is_none());
+        let listed = sessions.list_sessions().await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].bufnr, 3);
+        assert_eq!(listed[0].filename, "buffer");
+        assert_eq!(listed[0].clients, 1);
         assert!(sessions.update_cursor(3, 4, 0).await);
 
-        let event = rx.recv().await.expect("event");
+        let event = rx.recv().await.expect("event").event;
         match event {
             ServerEvent::CursorMove { bufnr, line, .. } => {
                 assert_eq!(bufnr, 3);
@@ -598,6 +2219,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -626,6 +2248,7 @@ mod tests {
                     cursor_line: 1,
                     cursor_col: 0,
                     source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
@@ -643,13 +2266,14 @@ mod tests {
                         cursor_line: 1,
                         cursor_col: 0,
                         source_path: None,
+                        backend: None,
                     },
                     &renderer,
                 )
                 .await
         );
 
-        let event = rx.recv().await.expect("render event");
+        let event = rx.recv().await.expect("render event").event;
         match event {
             ServerEvent::RenderFull {
                 bufnr, cursor_line, ..
@@ -662,58 +2286,1209 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn resolves_image_asset_paths_from_buffer_directory() {
+    async fn snapshot_backend_overrides_the_extension() {
         let sessions = SessionManager::default();
         let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr, backend| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("_hi_"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: Some(String::from("/tmp/notes/page.txt")),
+            backend,
+        };
 
-        let root = temp_test_dir("assets");
-        let image_dir = root.join("images");
-        fs::create_dir_all(&image_dir).expect("create image dir");
+        for (bufnr, backend) in [(1, None), (2, Some(Backend::Format(SourceFormat::Djot)))] {
+            sessions
+                .start_session_with_options(
+                    snapshot(bufnr, backend),
+                    SessionOptions::default(),
+                    &renderer,
+                )
+                .await;
+        }
 
-        let markdown_path = root.join("note.md");
-        fs::write(&markdown_path, "# note").expect("write markdown file");
+        let markdown = sessions.snapshot(1).await.expect("snapshot").html;
+        let djot = sessions.snapshot(2).await.expect("snapshot").html;
+        assert!(!markdown.contains("data-format=\"djot\""));
+        assert!(djot.contains("data-format=\"djot\""));
+    }
 
-        let image_path = image_dir.join("diagram.png");
-        fs::write(&image_path, [137u8, 80, 78, 71]).expect("write image file");
+    #[tokio::test]
+    async fn documents_that_disable_the_preview_show_a_note() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |changedtick, markdown: &str| BufferSnapshot {
+            bufnr: 21,
+            changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session(
+                snapshot(1, "<!-- live-markdown: off -->\n# Generated"),
+                &renderer,
+            )
+            .await;
+        let html = sessions.snapshot(21).await.expect("snapshot").html;
+        assert!(html.contains("class=\"document-disabled\""));
+        assert!(!html.contains("Generated"));
+
+        assert!(
+            !sessions
+                .update_content(
+                    snapshot(2, "<!-- live-markdown: off -->\n# Still generated"),
+                    &renderer
+                )
+                .await
+        );
+        assert!(
+            sessions
+                .update_content(snapshot(3, "# Generated"), &renderer)
+                .await
+        );
+        let html = sessions.snapshot(21).await.expect("snapshot").html;
+        assert!(html.contains("Generated</h1>"));
+    }
+
+    #[tokio::test]
+    async fn clips_large_documents_until_rendered_anyway() {
+        let sessions = SessionManager::default().with_max_document_bytes(32);
+        let renderer = LiveMarkdownRenderer::default();
+        let markdown = format!("# Head\n\n{}tail marker\n", "line\n".repeat(20));
+        let snapshot = BufferSnapshot {
+            bufnr: 6,
+            changedtick: 1,
+            markdown: markdown.clone(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions.start_session(snapshot, &renderer).await;
+        let clipped = sessions.snapshot(6).await.expect("snapshot").html;
+        assert!(clipped.contains("Showing the first 1 KB of 1 KB"));
+        assert!(clipped.contains("data-render-anyway"));
+        assert!(!clipped.contains("tail marker"));
+
+        assert!(sessions.render_anyway(6, &renderer).await);
+        let full = sessions.snapshot(6).await.expect("snapshot").html;
+        assert!(full.contains("tail marker"));
+        assert!(!full.contains("data-render-anyway"));
+
+        let edited = BufferSnapshot {
+            bufnr: 6,
+            changedtick: 2,
+            markdown: format!("{markdown}more\n"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        assert!(sessions.update_content(edited, &renderer).await);
+        let html = sessions.snapshot(6).await.expect("snapshot").html;
+        assert!(html.contains("tail marker"));
+        assert!(!sessions.render_anyway(9, &renderer).await);
+    }
+
+    #[tokio::test]
+    async fn edits_patch_the_document_they_start_from() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let edit = |base_changedtick, changedtick, first, last_old, line: &str| BufferEdit {
+            bufnr: 6,
+            base_changedtick,
+            changedtick,
+            delta: LineDelta {
+                first,
+                last_old,
+                lines: vec![line.to_string()],
+            },
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
 
+        assert_eq!(
+            sessions
+                .apply_edit(edit(1, 2, 0, 1, "# B"), &renderer, true)
+                .await,
+            EditOutcome::NoSession
+        );
         sessions
             .start_session(
                 BufferSnapshot {
-                    bufnr: 88,
+                    bufnr: 6,
                     changedtick: 1,
-                    markdown: String::from("![diagram](images/diagram.png)"),
+                    markdown: String::from("# A\n\nbody"),
                     cursor_line: 1,
                     cursor_col: 0,
-                    source_path: Some(markdown_path.to_string_lossy().to_string()),
+                    source_path: None,
+                    backend: None,
                 },
                 &renderer,
             )
             .await;
 
-        let resolved = sessions
-            .resolve_local_asset_path(88, "images/diagram.png")
-            .await
-            .expect("resolve relative image");
-        assert_eq!(resolved, image_path.canonicalize().expect("canonical path"));
+        assert_eq!(
+            sessions
+                .apply_edit(edit(1, 2, 2, 3, "first"), &renderer, false)
+                .await,
+            EditOutcome::Applied
+        );
+        assert_eq!(
+            sessions
+                .apply_edit(edit(2, 3, 0, 1, "# B"), &renderer, true)
+                .await,
+            EditOutcome::Rendered
+        );
+        let html = sessions.snapshot(6).await.expect("snapshot").html;
+        assert!(html.contains("B</h1>"), "{html}");
+        assert!(html.contains("first"), "{html}");
 
-        let encoded = sessions
-            .resolve_local_asset_path(88, "images/diagram%2Epng")
+        // An edit made against a document the session never saw.
+        assert_eq!(
+            sessions
+                .apply_edit(edit(2, 4, 0, 1, "# C"), &renderer, true)
+                .await,
+            EditOutcome::OutOfSync
+        );
+    }
+
+    #[tokio::test]
+    async fn renders_are_followed_by_render_stats() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |changedtick, markdown: &str| BufferSnapshot {
+            bufnr: 5,
+            changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session(snapshot(1, "# one"), &renderer)
             .await;
-        assert!(encoded.is_some());
+        let mut rx = sessions.subscribe(5).await.expect("valid subscription");
+        assert!(
+            sessions
+                .update_content(snapshot(2, "# two"), &renderer)
+                .await
+        );
 
-        let escaped = sessions.resolve_local_asset_path(88, "../secret.png").await;
-        assert!(escaped.is_none());
+        assert!(matches!(
+            rx.recv().await.expect("render event").event,
+            ServerEvent::RenderFull { .. }
+        ));
+        match rx.recv().await.expect("stats event").event {
+            ServerEvent::RenderStats { bufnr, stats } => {
+                assert_eq!(bufnr, 5);
+                assert_eq!(stats.renders, 2);
+                assert!(stats.slowest_us >= stats.last_us);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
 
-        let remote = sessions
-            .resolve_local_asset_path(88, "https://example.com/image.png")
+        let stats = sessions.render_stats(5).await.expect("stats");
+        assert_eq!(stats.renders, 2);
+        assert_eq!(sessions.list_sessions().await[0].render, stats);
+        assert!(sessions.render_stats(9).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn renamed_source_path_emits_title_changed() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 6,
+                    changedtick: 1,
+                    markdown: String::from("# title"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(String::from("/tmp/notes/draft.md")),
+                    backend: None,
+                },
+                &renderer,
+            )
             .await;
-        assert!(remote.is_none());
 
-        let missing_session = sessions
-            .resolve_local_asset_path(99, "images/diagram.png")
+        let snapshot = sessions.snapshot(6).await.expect("snapshot");
+        assert_eq!(snapshot.filename, "draft.md");
+        assert_eq!(snapshot.title, "draft.md \u{2014} live-markdown");
+
+        let mut rx = sessions.subscribe(6).await.expect("valid subscription");
+        assert!(
+            sessions
+                .rerender_content(
+                    BufferSnapshot {
+                        bufnr: 6,
+                        changedtick: 1,
+                        markdown: String::from("# title"),
+                        cursor_line: 1,
+                        cursor_col: 0,
+                        source_path: Some(String::from("/tmp/notes/README.md")),
+                        backend: None,
+                    },
+                    &renderer,
+                )
+                .await
+        );
+
+        match rx.recv().await.expect("title event").event {
+            ServerEvent::TitleChanged {
+                bufnr,
+                filename,
+                title,
+            } => {
+                assert_eq!(bufnr, 6);
+                assert_eq!(filename, "README.md");
+                assert_eq!(title, "README.md \u{2014} live-markdown");
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_source_path_follows_buffer_renames() {
+        let root = std::env::temp_dir().join(format!(
+            "live-markdown-rename-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        let moved = root.join("moved");
+        fs::create_dir_all(&moved).expect("create dirs");
+        fs::write(moved.join("diagram.png"), b"png").expect("write image");
+
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 14,
+                    changedtick: 1,
+                    markdown: String::from("![d](diagram.png)"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(root.join("draft.md").to_string_lossy().to_string()),
+                    backend: None,
+                },
+                &renderer,
+            )
             .await;
-        assert!(missing_session.is_none());
+        assert!(
+            sessions
+                .resolve_local_asset_path(14, "diagram.png")
+                .await
+                .is_none()
+        );
+
+        let mut rx = sessions.subscribe(14).await.expect("valid subscription");
+        let renamed = moved.join("final.md").to_string_lossy().to_string();
+        assert!(sessions.update_source_path(14, Some(renamed)).await);
+        assert!(!sessions.update_source_path(99, None).await);
+
+        assert!(matches!(
+            rx.recv().await.expect("title event").event,
+            ServerEvent::TitleChanged { ref filename, .. } if filename == "final.md"
+        ));
+        assert_eq!(
+            sessions.snapshot(14).await.expect("snapshot").filename,
+            "final.md"
+        );
+        assert!(
+            sessions
+                .resolve_local_asset_path(14, "diagram.png")
+                .await
+                .is_some()
+        );
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn changed_images_invalidate_only_their_path() {
+        let root = std::env::temp_dir().join(format!(
+            "live-markdown-asset-watch-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        fs::create_dir_all(&root).expect("create dir");
+        let edited = root.join("edited.png");
+        fs::write(&edited, b"png").expect("write image");
+        fs::write(root.join("kept.png"), b"png").expect("write image");
+
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 3,
+                    changedtick: 1,
+                    markdown: String::from("![a](edited.png) ![b](kept.png) ![c](edited.png)"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(root.join("note.md").to_string_lossy().into_owned()),
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        let mut rx = sessions.subscribe(3).await.expect("subscription");
+
+        assert_eq!(sessions.check_assets().await, 0);
+        assert_eq!(sessions.check_assets().await, 0);
+
+        fs::File::options()
+            .write(true)
+            .open(&edited)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(5)))
+            .expect("touch image");
+        assert_eq!(sessions.check_assets().await, 1);
+        assert_eq!(sessions.check_assets().await, 0);
+
+        match rx.recv().await.expect("invalidation event").event {
+            ServerEvent::AssetInvalidated { bufnr, path } => {
+                assert_eq!(bufnr, 3);
+                assert_eq!(path, "edited.png");
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
 
         let _ = fs::remove_dir_all(root);
     }
+
+    #[tokio::test]
+    async fn running_sessions_are_indexed_by_frontmatter_tags() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr, changedtick, markdown: &str| BufferSnapshot {
+            bufnr,
+            changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        for (bufnr, markdown) in [
+            (2, "---\ntags: [rust, notes]\n---\n# A"),
+            (3, "---\ntags: notes\n---\n# B"),
+            (4, "# Untagged"),
+        ] {
+            sessions
+                .start_session_with_options(
+                    snapshot(bufnr, 1, markdown),
+                    SessionOptions::default(),
+                    &renderer,
+                )
+                .await;
+        }
+
+        let index = sessions.tag_index().await;
+        assert_eq!(
+            index
+                .iter()
+                .map(|entry| (entry.tag.as_str(), entry.sessions.clone()))
+                .collect::<Vec<_>>(),
+            vec![("notes", vec![2, 3]), ("rust", vec![2])]
+        );
+        let tagged = |tag| {
+            let sessions = sessions.clone();
+            async move {
+                sessions
+                    .list_tagged_sessions(tag)
+                    .await
+                    .iter()
+                    .map(|session| session.bufnr)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(tagged("#rust").await, vec![2]);
+
+        let mut rx = sessions.subscribe(3).await.expect("subscription");
+        assert!(
+            sessions
+                .update_content(snapshot(3, 2, "---\ntags: rust\n---\n# B"), &renderer)
+                .await
+        );
+        assert_eq!(tagged("rust").await, vec![2, 3]);
+        assert_eq!(tagged("notes").await, vec![2]);
+        let mut listed = false;
+        while let Ok(SequencedEvent { event, .. }) = rx.try_recv() {
+            listed |= matches!(event, ServerEvent::Sessions { .. });
+        }
+        assert!(listed);
+    }
+
+    #[tokio::test]
+    async fn spelling_events_follow_changes_to_the_typos() {
+        let dictionary = std::env::temp_dir().join(format!(
+            "live-markdown-spell-{}-{}.dic",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        fs::write(&dictionary, "the\nquick\nbrown\nfox\n").expect("write dictionary");
+        let checker =
+            SpellChecker::load(&[], std::slice::from_ref(&dictionary), &[]).expect("dictionary");
+        let _ = fs::remove_file(dictionary);
+
+        let sessions = SessionManager::default().with_spellchecker(checker);
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |changedtick, markdown: &str| BufferSnapshot {
+            bufnr: 4,
+            changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        sessions
+            .start_session(snapshot(1, "The quikc fox"), &renderer)
+            .await;
+        let quikc = Typo {
+            line: 1,
+            word: String::from("quikc"),
+        };
+        assert_eq!(
+            sessions.snapshot(4).await.expect("snapshot").typos,
+            vec![quikc.clone()]
+        );
+        let mut rx = sessions.subscribe(4).await.expect("subscription");
+
+        assert!(
+            sessions
+                .update_content(snapshot(2, "The quikc brown fox"), &renderer)
+                .await
+        );
+        assert!(
+            sessions
+                .update_content(snapshot(3, "The quick brown fox"), &renderer)
+                .await
+        );
+
+        let mut spelling = Vec::new();
+        while let Ok(SequencedEvent { event, .. }) = rx.try_recv() {
+            if let ServerEvent::Spelling { typos, .. } = event {
+                spelling.push(typos);
+            }
+        }
+        assert_eq!(spelling, vec![Vec::<Typo>::new()]);
+    }
+
+    #[tokio::test]
+    async fn unsaved_buffers_resolve_assets_against_the_asset_root() {
+        let root = std::env::temp_dir().join(format!(
+            "live-markdown-asset-root-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        let pinned = root.join("pinned");
+        fs::create_dir_all(&pinned).expect("create dirs");
+        fs::write(root.join("shared.png"), b"png").expect("write image");
+        fs::write(pinned.join("own.png"), b"png").expect("write image");
+
+        let scratch = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("![s](shared.png)"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        let renderer = LiveMarkdownRenderer::default();
+
+        let sessions = SessionManager::default();
+        sessions.start_session(scratch(1), &renderer).await;
+        assert!(
+            sessions
+                .resolve_local_asset_path(1, "shared.png")
+                .await
+                .is_none()
+        );
+
+        let sessions = SessionManager::default().with_default_asset_root(root.clone());
+        sessions
+            .start_session_with_options(scratch(2), SessionOptions::default(), &renderer)
+            .await;
+        assert!(
+            sessions
+                .resolve_local_asset_path(2, "shared.png")
+                .await
+                .is_some()
+        );
+
+        let mut options = SessionOptions::default();
+        options
+            .set("asset_root", &pinned.to_string_lossy())
+            .expect("valid option");
+        sessions
+            .start_session_with_options(scratch(3), options, &renderer)
+            .await;
+        assert!(
+            sessions
+                .resolve_local_asset_path(3, "own.png")
+                .await
+                .is_some()
+        );
+        assert!(
+            sessions
+                .resolve_local_asset_path(3, "shared.png")
+                .await
+                .is_none()
+        );
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn resolves_image_asset_paths_from_buffer_directory() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+
+        let root = temp_test_dir("assets");
+        let image_dir = root.join("images");
+        fs::create_dir_all(&image_dir).expect("create image dir");
+
+        let markdown_path = root.join("note.md");
+        fs::write(&markdown_path, "# note").expect("write markdown file");
+
+        let image_path = image_dir.join("diagram.png");
+        fs::write(&image_path, [137u8, 80, 78, 71]).expect("write image file");
+
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 88,
+                    changedtick: 1,
+                    markdown: String::from("![diagram](images/diagram.png)"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(markdown_path.to_string_lossy().to_string()),
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+
+        let resolved = sessions
+            .resolve_local_asset_path(88, "images/diagram.png")
+            .await
+            .expect("resolve relative image");
+        assert_eq!(resolved, image_path.canonicalize().expect("canonical path"));
+
+        let encoded = sessions
+            .resolve_local_asset_path(88, "images/diagram%2Epng")
+            .await;
+        assert!(encoded.is_some());
+
+        let escaped = sessions.resolve_local_asset_path(88, "../secret.png").await;
+        assert!(escaped.is_none());
+
+        let remote = sessions
+            .resolve_local_asset_path(88, "https://example.com/image.png")
+            .await;
+        assert!(remote.is_none());
+
+        let missing_session = sessions
+            .resolve_local_asset_path(99, "images/diagram.png")
+            .await;
+        assert!(missing_session.is_none());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn sniffs_extensionless_image_assets() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+
+        let root = temp_test_dir("sniff");
+        fs::create_dir_all(&root).expect("create test dir");
+        let markdown_path = root.join("note.md");
+        fs::write(&markdown_path, "# note").expect("write markdown file");
+        fs::write(root.join("figure"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").expect("write image");
+        fs::write(root.join("notes"), "plain text").expect("write text");
+
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 7,
+                    changedtick: 1,
+                    markdown: String::from("![figure](./figure)"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: Some(markdown_path.to_string_lossy().to_string()),
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+
+        assert!(
+            sessions
+                .resolve_local_asset_path(7, "./figure")
+                .await
+                .is_some()
+        );
+        assert!(
+            sessions
+                .resolve_local_asset_path(7, "notes")
+                .await
+                .is_none()
+        );
+        assert!(sessions.resolve_local_file_path(7, "notes").await.is_some());
+        assert!(sessions.resolve_local_file_path(7, "../x").await.is_none());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn explicit_sessions_survive_buffer_follow() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: format!("# buffer {bufnr}"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session_with_options(snapshot(2), SessionOptions::default(), &renderer)
+            .await;
+        sessions.start_session(snapshot(5), &renderer).await;
+        sessions.start_session(snapshot(6), &renderer).await;
+
+        assert!(sessions.has_session(2).await);
+        assert!(!sessions.has_session(5).await);
+        assert!(sessions.has_session(6).await);
+        assert_eq!(sessions.active_bufnr().await, Some(6));
+        assert_eq!(sessions.session_count().await, 2);
+
+        assert!(sessions.stop_session(6, SessionEndReason::Stopped).await);
+        assert_eq!(sessions.active_bufnr().await, None);
+        assert!(sessions.snapshot(2).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn pinned_sessions_pause_while_another_buffer_is_followed() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: format!("# buffer {bufnr}"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session_with_options(snapshot(2), SessionOptions::default(), &renderer)
+            .await;
+        let mut rx = sessions.subscribe(2).await.expect("subscription");
+
+        sessions.start_session(snapshot(5), &renderer).await;
+        assert!(matches!(
+            rx.recv().await.expect("pause event").event,
+            ServerEvent::SessionPaused { bufnr: 2 }
+        ));
+        assert!(matches!(
+            rx.recv().await.expect("sessions event").event,
+            ServerEvent::Sessions { .. }
+        ));
+        assert!(sessions.snapshot(2).await.expect("snapshot").paused);
+
+        sessions.start_session(snapshot(2), &renderer).await;
+        assert!(matches!(
+            rx.recv().await.expect("resume event").event,
+            ServerEvent::SessionResumed { bufnr: 2 }
+        ));
+        assert!(!sessions.snapshot(2).await.expect("snapshot").paused);
+    }
+
+    #[tokio::test]
+    async fn scoped_previews_follow_only_their_own_group() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: format!("# buffer {bufnr}"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+        let scoped = |tab| SessionOptions {
+            follow_group: Some(FollowGroup::Tabpage(tab)),
+            ..SessionOptions::default()
+        };
+
+        sessions
+            .start_session_with_options(snapshot(1), scoped(1), &renderer)
+            .await;
+        sessions
+            .start_session_with_options(snapshot(2), scoped(2), &renderer)
+            .await;
+        assert!(sessions.snapshot(1).await.is_some_and(|s| !s.paused));
+
+        assert!(
+            sessions
+                .follow_in_group(snapshot(3), FollowGroup::Tabpage(1), &renderer)
+                .await
+        );
+        assert_eq!(sessions.group_bufnr(FollowGroup::Tabpage(1)).await, Some(3));
+        assert_eq!(sessions.group_bufnr(FollowGroup::Tabpage(2)).await, Some(2));
+        assert!(sessions.snapshot(1).await.is_some_and(|s| s.paused));
+
+        sessions
+            .follow_in_group(snapshot(4), FollowGroup::Tabpage(1), &renderer)
+            .await;
+        assert!(!sessions.has_session(3).await);
+        assert!(
+            !sessions
+                .follow_in_group(snapshot(5), FollowGroup::Tabpage(9), &renderer)
+                .await
+        );
+
+        assert!(sessions.focus_group(FollowGroup::Tabpage(2)).await);
+        assert_eq!(sessions.active_bufnr().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn share_tokens_expire_and_die_with_their_session() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("# shared"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions.start_session(snapshot(3), &renderer).await;
+        assert!(
+            sessions
+                .create_share(9, Duration::from_secs(60))
+                .await
+                .expect("randomness")
+                .is_none()
+        );
+
+        let token = sessions
+            .create_share(3, Duration::from_secs(60))
+            .await
+            .expect("randomness")
+            .expect("share token");
+        let expired = sessions
+            .create_share(3, Duration::ZERO)
+            .await
+            .expect("randomness")
+            .expect("share token");
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, expired);
+        assert_eq!(
+            sessions.resolve_share(&token).await.map(|(b, _)| b),
+            Some(3)
+        );
+        assert!(sessions.resolve_share(&expired).await.is_none());
+
+        sessions.start_session(snapshot(4), &renderer).await;
+        assert!(sessions.has_session(3).await);
+
+        assert!(sessions.stop_session(3, SessionEndReason::Stopped).await);
+        assert!(sessions.resolve_share(&token).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn resume_tokens_outlive_buffer_numbers() {
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("# notes"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: Some(String::from("/tmp/notes.md")),
+            backend: None,
+        };
+
+        let before = SessionManager::default();
+        before.start_session(snapshot(3), &renderer).await;
+        let token = before
+            .snapshot(3)
+            .await
+            .and_then(|snapshot| snapshot.resume)
+            .expect("resume token");
+        let mut rx = before.subscribe(3).await.expect("subscribe");
+        before.going_away(Duration::from_millis(500)).await;
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(SequencedEvent {
+                event: ServerEvent::Gone {
+                    bufnr: 3,
+                    retry_ms: 500
+                },
+                ..
+            })
+        ));
+
+        let after = SessionManager::default();
+        assert_eq!(after.resume(&token).await, None);
+        after.start_session(snapshot(8), &renderer).await;
+        assert_eq!(after.resume(&token).await, Some(8));
+    }
+
+    #[tokio::test]
+    async fn rotating_a_share_revokes_the_old_token() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 3,
+                    changedtick: 1,
+                    markdown: String::from("# shared"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        assert!(
+            sessions
+                .rotate_share(3)
+                .await
+                .expect("randomness")
+                .is_none()
+        );
+
+        let token = sessions
+            .create_share(3, Duration::from_secs(60))
+            .await
+            .expect("randomness")
+            .expect("share token");
+        let (_, expires_at) = sessions.resolve_share(&token).await.expect("live share");
+
+        let rotated = sessions
+            .rotate_share(3)
+            .await
+            .expect("randomness")
+            .expect("rotated token");
+        assert_ne!(rotated, token);
+        assert!(sessions.resolve_share(&token).await.is_none());
+        assert_eq!(
+            sessions.resolve_share(&rotated).await,
+            Some((3, expires_at))
+        );
+
+        let mut rx = sessions.subscribe(3).await.expect("subscribe");
+        assert!(sessions.reconnect(3, "http://127.0.0.1:1/share/x").await);
+        assert!(!sessions.reconnect(4, "http://127.0.0.1:1/share/x").await);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(SequencedEvent {
+                event: ServerEvent::Reconnect { bufnr: 3, .. },
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn presence_updates_are_broadcast_until_the_viewer_leaves() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 1,
+                    changedtick: 1,
+                    markdown: String::from("# doc"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        let mut rx = sessions.subscribe(1).await.expect("subscribe");
+
+        assert!(sessions.update_presence(1, "b", 12).await);
+        assert!(sessions.update_presence(1, "a", 3).await);
+        assert!(sessions.update_presence(1, "a", 3).await);
+        assert!(!sessions.update_presence(1, "", 3).await);
+        assert!(!sessions.update_presence(2, "a", 3).await);
+        sessions.remove_viewer(1, "b").await;
+
+        let mut presence = Vec::new();
+        while let Ok(SequencedEvent { event, .. }) = rx.try_recv() {
+            if let ServerEvent::Presence { viewers, .. } = event {
+                presence.push(
+                    viewers
+                        .into_iter()
+                        .map(|viewer| (viewer.client, viewer.line))
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        assert_eq!(
+            presence,
+            vec![
+                vec![(String::from("b"), 12)],
+                vec![(String::from("a"), 3), (String::from("b"), 12)],
+                vec![(String::from("a"), 3)],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn bookmarks_are_kept_sorted_and_broadcast() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 1,
+                    changedtick: 1,
+                    markdown: String::from("# doc\n\ntext\n\nmore"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        let mut rx = sessions.subscribe(1).await.expect("subscribe");
+        let update = |line, text: &str, remove| BookmarkUpdate {
+            line,
+            text: text.to_string(),
+            remove,
+        };
+
+        sessions
+            .update_bookmark(1, update(5, "more", false))
+            .await
+            .expect("bookmark");
+        let bookmarks = sessions
+            .update_bookmark(1, update(1, " doc\n  heading ", false))
+            .await
+            .expect("bookmark");
+        assert_eq!(
+            bookmarks,
+            vec![
+                Bookmark {
+                    line: 1,
+                    text: String::from("doc heading"),
+                },
+                Bookmark {
+                    line: 5,
+                    text: String::from("more"),
+                },
+            ]
+        );
+        assert!(
+            sessions
+                .update_bookmark(1, update(0, "", false))
+                .await
+                .is_none()
+        );
+        assert!(
+            sessions
+                .update_bookmark(2, update(1, "", false))
+                .await
+                .is_none()
+        );
+
+        sessions
+            .update_bookmark(1, update(1, "", true))
+            .await
+            .expect("remove");
+        let snapshot = sessions.snapshot(1).await.expect("snapshot");
+        assert_eq!(snapshot.bookmarks.len(), 1);
+        assert_eq!(snapshot.bookmarks[0].line, 5);
+
+        let mut broadcast = 0;
+        while let Ok(SequencedEvent { event, .. }) = rx.try_recv() {
+            if matches!(event, ServerEvent::Bookmarks { .. }) {
+                broadcast += 1;
+            }
+        }
+        assert_eq!(broadcast, 3);
+    }
+
+    #[tokio::test]
+    async fn comments_are_ordered_by_line_until_resolved() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 1,
+                    changedtick: 1,
+                    markdown: String::from("# doc\n\ntext"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+        let add = |line, text: &str| CommentAction::Add {
+            line,
+            text: text.to_string(),
+        };
+
+        sessions
+            .update_comments(1, add(3, "second"))
+            .await
+            .expect("add");
+        sessions
+            .update_comments(1, add(1, "first"))
+            .await
+            .expect("add");
+        let comments = sessions
+            .update_comments(1, add(3, " third "))
+            .await
+            .expect("add");
+        assert_eq!(
+            comments
+                .iter()
+                .map(|comment| (comment.id, comment.line, comment.text.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(2, 1, "first"), (1, 3, "second"), (3, 3, "third")]
+        );
+        assert!(sessions.update_comments(1, add(2, "  ")).await.is_none());
+        assert!(sessions.update_comments(2, add(2, "x")).await.is_none());
+
+        let comments = sessions
+            .update_comments(1, CommentAction::Resolve { id: 1 })
+            .await
+            .expect("resolve");
+        assert_eq!(comments.len(), 2);
+        assert!(
+            sessions
+                .update_comments(1, CommentAction::Resolve { id: 1 })
+                .await
+                .is_none()
+        );
+        assert_eq!(
+            sessions.snapshot(1).await.expect("snapshot").comments,
+            comments
+        );
+    }
+
+    #[tokio::test]
+    async fn detached_clients_are_forgotten_when_they_leave() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        sessions
+            .start_session(
+                BufferSnapshot {
+                    bufnr: 1,
+                    changedtick: 1,
+                    markdown: String::from("# doc"),
+                    cursor_line: 1,
+                    cursor_col: 0,
+                    source_path: None,
+                    backend: None,
+                },
+                &renderer,
+            )
+            .await;
+
+        assert!(sessions.set_detached(1, "a", true).await);
+        assert!(!sessions.set_detached(2, "a", true).await);
+        assert!(!sessions.set_detached(1, "", true).await);
+        assert!(sessions.is_detached(1, "a").await);
+        assert!(!sessions.is_detached(1, "b").await);
+
+        assert!(sessions.set_detached(1, "a", false).await);
+        assert!(!sessions.is_detached(1, "a").await);
+
+        sessions.set_detached(1, "a", true).await;
+        sessions.remove_viewer(1, "a").await;
+        assert!(!sessions.is_detached(1, "a").await);
+    }
+
+    #[tokio::test]
+    async fn session_set_changes_are_broadcast_to_every_session() {
+        let sessions = SessionManager::default();
+        let renderer = LiveMarkdownRenderer::default();
+        let snapshot = |bufnr| BufferSnapshot {
+            bufnr,
+            changedtick: 1,
+            markdown: String::from("# doc"),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        };
+
+        sessions
+            .start_session_with_options(snapshot(1), SessionOptions::default(), &renderer)
+            .await;
+        let mut rx = sessions.subscribe(1).await.expect("subscribe");
+        sessions
+            .start_session_with_options(snapshot(2), SessionOptions::default(), &renderer)
+            .await;
+
+        assert!(matches!(
+            rx.recv().await.expect("pause event").event,
+            ServerEvent::SessionPaused { bufnr: 1 }
+        ));
+        match rx.recv().await.expect("sessions event").event {
+            ServerEvent::Sessions { bufnr, sessions } => {
+                assert_eq!(bufnr, 1);
+                let listed: Vec<i64> = sessions.iter().map(|session| session.bufnr).collect();
+                assert_eq!(listed, vec![1, 2]);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        sessions.stop_session(2, SessionEndReason::Stopped).await;
+        match rx.recv().await.expect("sessions event").event {
+            ServerEvent::Sessions { sessions, .. } => assert_eq!(sessions.len(), 1),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,164 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories searched for a `spell_languages` entry: plain word lists
+/// first, then Hunspell dictionaries.
+const LANGUAGE_DIRS: &[(&str, &str)] = &[
+    ("/usr/share/dict", ""),
+    ("/usr/share/hunspell", ".dic"),
+    ("/usr/share/myspell", ".dic"),
+];
+
+/// Keeps one annotation event small on documents full of unknown words.
+const MAX_TYPOS: usize = 500;
+
+/// A word missing from every loaded dictionary, anchored to its source line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Typo {
+    pub line: usize,
+    pub word: String,
+}
+
+/// Word-list spell checking for the prose of a document. Hunspell `.dic`
+/// files are read as plain lists: their affix rules are not applied, so a
+/// list that already holds inflected forms gives far fewer false alarms.
+#[derive(Debug, Clone, Default)]
+pub struct SpellChecker {
+    words: HashSet<String>,
+}
+
+impl SpellChecker {
+    /// Loads every readable dictionary; `None` when no word was loaded, so a
+    /// missing dictionary never flags a whole document.
+    pub fn load(languages: &[String], dictionaries: &[PathBuf], extra: &[String]) -> Option<Self> {
+        let mut checker = Self::default();
+        for path in languages
+            .iter()
+            .filter_map(|language| language_path(language))
+            .chain(dictionaries.iter().cloned())
+        {
+            if let Ok(contents) = fs::read_to_string(&path) {
+                checker.add_words(&contents);
+            }
+        }
+        if checker.words.is_empty() {
+            return None;
+        }
+
+        for word in extra {
+            checker.words.insert(word.to_lowercase());
+        }
+        Some(checker)
+    }
+
+    fn add_words(&mut self, contents: &str) {
+        for entry in contents.lines() {
+            let word = entry.split('/').next().unwrap_or_default().trim();
+            if !word.is_empty() && !word.bytes().all(|byte| byte.is_ascii_digit()) {
+                self.words.insert(word.to_lowercase());
+            }
+        }
+    }
+
+    fn knows(&self, word: &str) -> bool {
+        let lower = word.to_lowercase();
+        self.words.contains(&lower)
+            || lower
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    /// Unknown words in the document's text, skipping code, HTML and frontmatter.
+    pub fn check(&self, markdown: &str) -> Vec<Typo> {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(markdown.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let line_of = |offset: usize| line_starts.partition_point(|start| *start <= offset);
+
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+        let mut typos = Vec::new();
+        let mut seen = HashSet::new();
+        let mut skipped_depth = 0usize;
+        for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+            match event {
+                Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_) | Tag::HtmlBlock) => {
+                    skipped_depth += 1;
+                }
+                Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_) | TagEnd::HtmlBlock) => {
+                    skipped_depth = skipped_depth.saturating_sub(1);
+                }
+                Event::Text(text) if skipped_depth == 0 => {
+                    let line = line_of(range.start);
+                    for word in words(&text) {
+                        if !self.knows(word) && seen.insert((line, word.to_string())) {
+                            typos.push(Typo {
+                                line,
+                                word: word.to_string(),
+                            });
+                            if typos.len() == MAX_TYPOS {
+                                return typos;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        typos
+    }
+}
+
+fn language_path(language: &str) -> Option<PathBuf> {
+    LANGUAGE_DIRS
+        .iter()
+        .map(|(dir, extension)| Path::new(dir).join(format!("{language}{extension}")))
+        .find(|path| path.is_file())
+}
+
+/// Words worth checking: runs of letters with inner apostrophes, leaving out
+/// acronyms, single letters and anything with digits.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|ch: char| !(ch.is_alphanumeric() || ch == '\'' || ch == '\u{2019}'))
+        .map(|word| word.trim_matches(|ch| ch == '\'' || ch == '\u{2019}'))
+        .filter(|word| {
+            word.chars().count() > 1
+                && word
+                    .chars()
+                    .all(|ch| ch.is_alphabetic() || ch == '\'' || ch == '\u{2019}')
+                && word.chars().any(char::is_lowercase)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpellChecker, Typo};
+
+    #[test]
+    fn flags_unknown_prose_words_by_line() {
+        let mut checker = SpellChecker::default();
+        checker.add_words("3\nthe/S\nquick\nbrown\nfox\njumps\nover\nlazy\ndog\n");
+
+        let markdown = "---\ntitle: Teh\n---\n\nThe quikc brown fox's\njumps over NASA\n\n```\nnot chekced\n```\n\nthe `codez` lazzy dog 42x";
+        assert_eq!(
+            checker.check(markdown),
+            vec![
+                Typo {
+                    line: 5,
+                    word: String::from("quikc"),
+                },
+                Typo {
+                    line: 12,
+                    word: String::from("lazzy"),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use crate::protocol::{ServerEvent, SessionOptions};
+use crate::render::LiveMarkdownRenderer;
+use crate::server::{ServerConfig, ServerController};
+use crate::session::{BufferSnapshot, SessionManager};
+use serde::de::DeserializeOwned;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// A documentation address (TEST-NET-1) standing in for another machine.
+const REMOTE_PEER: &str = "192.0.2.10:50000";
+
+/// A preview server on an ephemeral port, driven from tests over real HTTP.
+#[derive(Debug)]
+pub struct TestServer {
+    server: ServerController,
+    sessions: SessionManager,
+    renderer: LiveMarkdownRenderer,
+    addr: SocketAddr,
+    changedtick: u64,
+}
+
+impl TestServer {
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with(ServerConfig::default()).await
+    }
+
+    /// Starts with `config`, overriding its address and port so parallel
+    /// tests never collide.
+    pub async fn start_with(config: ServerConfig) -> io::Result<Self> {
+        Self::start_as(config, None).await
+    }
+
+    /// Starts a server that sees every request as coming from another
+    /// machine, the way a share link's viewer reaches it.
+    pub async fn start_remote() -> io::Result<Self> {
+        Self::start_as(
+            ServerConfig::default(),
+            Some(REMOTE_PEER.parse().expect("peer")),
+        )
+        .await
+    }
+
+    async fn start_as(config: ServerConfig, peer: Option<SocketAddr>) -> io::Result<Self> {
+        let config = ServerConfig {
+            port: 0,
+            bind_address: String::from("127.0.0.1"),
+            ..config
+        };
+        let renderer = LiveMarkdownRenderer::new(config.render.clone());
+        let sessions = SessionManager::default().with_max_document_bytes(config.max_document_bytes);
+        let mut server = ServerController::new(config, sessions.clone());
+        if let Some(peer) = peer {
+            server = server.with_peer_override(peer);
+        }
+        let addr = server.ensure_running().await?;
+
+        Ok(Self {
+            server,
+            sessions,
+            renderer,
+            addr,
+            changedtick: 0,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+
+    /// Starts a pinned preview session for `bufnr` showing `markdown`.
+    pub async fn open(&mut self, bufnr: i64, markdown: &str) {
+        let snapshot = self.snapshot(bufnr, markdown);
+        self.sessions
+            .start_session_with_options(snapshot, SessionOptions::default(), &self.renderer)
+            .await;
+    }
+
+    /// Replaces the buffer contents, as a debounced edit from Neovim would.
+    pub async fn edit(&mut self, bufnr: i64, markdown: &str) -> bool {
+        let snapshot = self.snapshot(bufnr, markdown);
+        self.sessions.update_content(snapshot, &self.renderer).await
+    }
+
+    fn snapshot(&mut self, bufnr: i64, markdown: &str) -> BufferSnapshot {
+        self.changedtick += 1;
+        BufferSnapshot {
+            bufnr,
+            changedtick: self.changedtick,
+            markdown: markdown.to_string(),
+            cursor_line: 1,
+            cursor_col: 0,
+            source_path: None,
+            backend: None,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> io::Result<HttpResponse> {
+        self.request("GET", path, None).await
+    }
+
+    pub async fn post(&self, path: &str, json: &str) -> io::Result<HttpResponse> {
+        self.request("POST", path, Some(json)).await
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> io::Result<HttpResponse> {
+        let mut stream = TcpStream::connect(self.addr).await?;
+        let mut request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            self.addr
+        );
+        if let Some(body) = body {
+            request.push_str("Content-Type: application/json\r\n");
+            request.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+        } else {
+            request.push_str("\r\n");
+        }
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = read_head(&mut reader).await?;
+        let mut raw = Vec::new();
+        reader.read_to_end(&mut raw).await?;
+        let chunked = header_value(&headers, "transfer-encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+        let body = if chunked { decode_chunked(&raw)? } else { raw };
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Opens an SSE connection such as `/events?buf=1`.
+    pub async fn events(&self, path: &str) -> io::Result<EventStream> {
+        let mut stream = TcpStream::connect(self.addr).await?;
+        let request = format!(
+            "GET {path} HTTP/1.1\r\nHost: {}\r\nAccept: text/event-stream\r\n\r\n",
+            self.addr
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = read_head(&mut reader).await?;
+        if status != 200 {
+            return Err(io::Error::other(format!("event stream returned {status}")));
+        }
+        let chunked = header_value(&headers, "transfer-encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+
+        Ok(EventStream {
+            reader,
+            chunked,
+            buffer: String::new(),
+        })
+    }
+
+    pub async fn stop(self) {
+        self.server.stop().await;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.headers, name)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> io::Result<T> {
+        serde_json::from_slice(&self.body).map_err(io::Error::other)
+    }
+}
+
+/// The events of one SSE connection, decoded as [`ServerEvent`]s.
+#[derive(Debug)]
+pub struct EventStream {
+    reader: BufReader<TcpStream>,
+    chunked: bool,
+    buffer: String,
+}
+
+impl EventStream {
+    /// The next event, or `None` once the server closes the stream.
+    pub async fn next(&mut self) -> io::Result<Option<ServerEvent>> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let block: String = self.buffer.drain(..end + 2).collect();
+                let data: Vec<&str> = block
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect();
+                if data.is_empty() {
+                    continue;
+                }
+                return serde_json::from_str(&data.join("\n"))
+                    .map(Some)
+                    .map_err(io::Error::other);
+            }
+
+            let read = tokio::time::timeout(EVENT_TIMEOUT, self.read_more())
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no event arrived"))??;
+            if !read {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Skips events until one satisfies `matches`.
+    pub async fn next_matching(
+        &mut self,
+        matches: impl Fn(&ServerEvent) -> bool,
+    ) -> io::Result<ServerEvent> {
+        loop {
+            match self.next().await? {
+                Some(event) if matches(&event) => return Ok(event),
+                Some(_) => {}
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            }
+        }
+    }
+
+    async fn read_more(&mut self) -> io::Result<bool> {
+        if !self.chunked {
+            let mut line = String::new();
+            let read = self.reader.read_line(&mut line).await?;
+            self.buffer.push_str(&line);
+            return Ok(read > 0);
+        }
+
+        let mut size_line = String::new();
+        if self.reader.read_line(&mut size_line).await? == 0 {
+            return Ok(false);
+        }
+        let size = parse_chunk_size(&size_line)?;
+        if size == 0 {
+            return Ok(false);
+        }
+        let mut chunk = vec![0; size + 2];
+        self.reader.read_exact(&mut chunk).await?;
+        chunk.truncate(size);
+        self.buffer
+            .push_str(&String::from_utf8_lossy(&chunk).replace("\r\n", "\n"));
+        Ok(true)
+    }
+}
+
+async fn read_head(reader: &mut BufReader<TcpStream>) -> io::Result<(u16, Vec<(String, String)>)> {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("bad status line: {status_line:?}")))?;
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    Ok((status, headers))
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn parse_chunk_size(line: &str) -> io::Result<usize> {
+    let size = line.trim().split(';').next().unwrap_or_default();
+    usize::from_str_radix(size, 16)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad chunk size"))
+}
+
+fn decode_chunked(mut raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated chunk"))?;
+        let size = parse_chunk_size(&String::from_utf8_lossy(&raw[..line_end]))?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = raw
+            .get(..size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated chunk"))?;
+        body.extend_from_slice(chunk);
+        raw = raw.get(size + 2..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestServer;
+    use crate::protocol::{ServerEvent, SessionEndReason, SnapshotResponse};
+    use crate::render::Link;
+    use crate::server::{ServerConfig, ServerStatus};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serves_snapshots_and_links_over_http() {
+        let mut server = TestServer::start().await.expect("server starts");
+        server.open(1, "# Title\n\n[home](#title)").await;
+
+        let snapshot = server.get("/snapshot?buf=1").await.expect("snapshot");
+        assert_eq!(snapshot.status, 200);
+        let snapshot: SnapshotResponse = snapshot.json().expect("snapshot json");
+        assert!(snapshot.html.contains("id=\"title\""));
+
+        let links: Vec<Link> = server
+            .get("/links?buf=1")
+            .await
+            .and_then(|response| response.json())
+            .expect("links json");
+        assert_eq!(links.len(), 1);
+        assert_eq!(server.get("/links?buf=9").await.expect("404").status, 404);
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn share_viewers_only_reach_their_share() {
+        let mut server = TestServer::start_remote().await.expect("server starts");
+        server.open(1, "# Shared").await;
+        server.open(2, "# Private").await;
+        let token = server
+            .sessions()
+            .create_share(1, Duration::from_secs(60))
+            .await
+            .expect("randomness")
+            .expect("share token");
+
+        let shared = server
+            .get(&format!("/share/{token}/snapshot"))
+            .await
+            .expect("shared snapshot");
+        assert_eq!(shared.status, 200);
+        assert!(shared.text().contains("Shared"));
+
+        for path in ["/sessions", "/snapshot?buf=2", "/recent", "/"] {
+            let denied = server.get(path).await.expect("response");
+            assert_eq!(denied.status, 403, "{path}");
+        }
+        let comment = server
+            .post(
+                "/comments?buf=2",
+                r#"{"action":"add","line":1,"text":"hi"}"#,
+            )
+            .await
+            .expect("response");
+        assert_eq!(comment.status, 403);
+        let shared_comment = server
+            .post(
+                &format!("/share/{token}/comments"),
+                r#"{"action":"add","line":1,"text":"hi"}"#,
+            )
+            .await
+            .expect("response");
+        assert_eq!(shared_comment.status, 405);
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn print_shell_turns_off_the_live_ui() {
+        let server = TestServer::start().await.expect("server starts");
+
+        let live = server.get("/").await.expect("shell").text();
+        assert!(live.contains("const PRINT_MODE = false;"));
+        assert!(live.contains("<body class=\"\">"));
+
+        let print = server.get("/?print=1").await.expect("print shell").text();
+        assert!(print.contains("const PRINT_MODE = true;"));
+        assert!(print.contains("<body class=\"print-mode\">"));
+        assert!(!print.contains("__"), "every placeholder is substituted");
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn render_anyway_lifts_the_document_cap() {
+        let mut server = TestServer::start_with(ServerConfig {
+            max_document_bytes: 16,
+            ..ServerConfig::default()
+        })
+        .await
+        .expect("server starts");
+        server.open(3, "first line\nsecond line\n").await;
+
+        let clipped = server
+            .get("/snapshot?buf=3")
+            .await
+            .expect("snapshot")
+            .text();
+        assert!(clipped.contains("data-render-anyway"));
+        assert!(!clipped.contains("second line"));
+
+        let forced = server.post("/render-anyway?buf=3", "").await.expect("post");
+        assert_eq!(forced.status, 204);
+        let full = server
+            .get("/snapshot?buf=3")
+            .await
+            .expect("snapshot")
+            .text();
+        assert!(full.contains("second line"));
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn streams_renders_and_session_end_over_sse() {
+        let mut server = TestServer::start().await.expect("server starts");
+        server.open(2, "one").await;
+        let mut events = server.events("/events?buf=2").await.expect("stream");
+
+        assert!(server.edit(2, "two").await);
+        match events
+            .next_matching(|event| matches!(event, ServerEvent::RenderFull { .. }))
+            .await
+            .expect("render event")
+        {
+            ServerEvent::RenderFull { html, .. } => assert!(html.contains("two")),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        server
+            .sessions()
+            .stop_session(2, SessionEndReason::Stopped)
+            .await;
+        let end = events
+            .next_matching(|event| matches!(event, ServerEvent::SessionEnd { .. }))
+            .await
+            .expect("session end");
+        assert!(matches!(
+            end,
+            ServerEvent::SessionEnd {
+                reason: SessionEndReason::Stopped,
+                ..
+            }
+        ));
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn a_start_during_a_stop_waits_for_it() {
+        let server = TestServer::start().await.expect("server starts");
+        let controller = server.server.clone();
+        assert_eq!(controller.status(), ServerStatus::Running(server.addr()));
+
+        let (_, restarted) = tokio::join!(controller.stop(), controller.ensure_running());
+        let restarted = restarted.expect("server restarts after the stop");
+        assert_eq!(controller.status(), ServerStatus::Running(restarted));
+
+        server.stop().await;
+        assert_eq!(controller.status(), ServerStatus::Stopped);
+    }
+}
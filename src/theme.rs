@@ -0,0 +1,260 @@
+use crate::protocol::ThemeMode;
+use std::collections::HashMap;
+
+/// Custom properties of the default dark palette.
+const DARK: &[(&str, &str)] = &[
+    ("--bg", "#151515"),
+    ("--surface", "#1a1a1a"),
+    ("--surface-2", "#181818"),
+    ("--border", "#333333"),
+    ("--text", "#dddddd"),
+    ("--text-strong", "#bbbbbb"),
+    ("--text-muted", "#888888"),
+    ("--accent", "#fd8000"),
+    ("--accent-soft", "rgba(253, 128, 0, 0.18)"),
+    ("--table-head", "#222222"),
+    ("--table-row", "#1a1a1a"),
+    ("--table-row-alt", "#1e1e1e"),
+    ("--task-bg", "#1f1a14"),
+    ("--task-border", "#4a3828"),
+    ("--task-done", "#888888"),
+    ("--code-bg", "#333333"),
+    ("--inline-bg", "#222222"),
+    ("--alert-note", "#fd8000"),
+    ("--alert-tip", "#4a9a6a"),
+    ("--alert-important", "#8a6ab3"),
+    ("--alert-warning", "#c09040"),
+    ("--alert-caution", "#c06050"),
+    ("--warn-bg", "#2a2018"),
+    ("--warn-border", "#5a4030"),
+    ("--warn-text", "#c09070"),
+];
+
+/// Overrides of the light palette; anything missing keeps its dark value.
+const LIGHT: &[(&str, &str)] = &[
+    ("--bg", "#fafafa"),
+    ("--surface", "#ffffff"),
+    ("--surface-2", "#f4f4f4"),
+    ("--border", "#d6d6d6"),
+    ("--text", "#222222"),
+    ("--text-strong", "#111111"),
+    ("--text-muted", "#6a6a6a"),
+    ("--accent-soft", "rgba(253, 128, 0, 0.14)"),
+    ("--table-head", "#efefef"),
+    ("--table-row", "#ffffff"),
+    ("--table-row-alt", "#f6f6f6"),
+    ("--task-bg", "#fff6ec"),
+    ("--task-border", "#e6c8a6"),
+    ("--task-done", "#8a8a8a"),
+    ("--code-bg", "#eeeeee"),
+    ("--inline-bg", "#f0f0f0"),
+    ("--warn-bg", "#fff3e6"),
+    ("--warn-border", "#e0b48a"),
+    ("--warn-text", "#8a5020"),
+];
+
+/// The colors of one editor highlight group, as `#rrggbb`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorHighlight {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    Fg,
+    Bg,
+}
+
+/// Where each custom property comes from in the editor's colorscheme; the
+/// first group that sets the channel wins.
+const EDITOR_PALETTE: &[(&str, &[(&str, Channel)])] = &[
+    ("--bg", &[("Normal", Channel::Bg)]),
+    (
+        "--surface",
+        &[("NormalFloat", Channel::Bg), ("Pmenu", Channel::Bg)],
+    ),
+    (
+        "--surface-2",
+        &[("CursorLine", Channel::Bg), ("NormalFloat", Channel::Bg)],
+    ),
+    (
+        "--border",
+        &[
+            ("WinSeparator", Channel::Fg),
+            ("VertSplit", Channel::Fg),
+            ("FloatBorder", Channel::Fg),
+        ],
+    ),
+    ("--text", &[("Normal", Channel::Fg)]),
+    (
+        "--text-strong",
+        &[("Title", Channel::Fg), ("Normal", Channel::Fg)],
+    ),
+    ("--text-muted", &[("Comment", Channel::Fg)]),
+    (
+        "--accent",
+        &[("Special", Channel::Fg), ("Function", Channel::Fg)],
+    ),
+    ("--accent-soft", &[("Visual", Channel::Bg)]),
+    (
+        "--table-head",
+        &[("StatusLine", Channel::Bg), ("CursorLine", Channel::Bg)],
+    ),
+    ("--table-row", &[("Normal", Channel::Bg)]),
+    ("--table-row-alt", &[("CursorLine", Channel::Bg)]),
+    (
+        "--code-bg",
+        &[("NormalFloat", Channel::Bg), ("CursorLine", Channel::Bg)],
+    ),
+    ("--inline-bg", &[("CursorLine", Channel::Bg)]),
+    ("--alert-note", &[("DiagnosticInfo", Channel::Fg)]),
+    (
+        "--alert-tip",
+        &[
+            ("DiagnosticOk", Channel::Fg),
+            ("DiagnosticHint", Channel::Fg),
+        ],
+    ),
+    ("--alert-important", &[("Statement", Channel::Fg)]),
+    ("--alert-warning", &[("DiagnosticWarn", Channel::Fg)]),
+    ("--alert-caution", &[("DiagnosticError", Channel::Fg)]),
+    ("--code-text", &[("Normal", Channel::Fg)]),
+    ("--tok-comment", &[("Comment", Channel::Fg)]),
+    (
+        "--tok-keyword",
+        &[("Keyword", Channel::Fg), ("Statement", Channel::Fg)],
+    ),
+    ("--tok-string", &[("String", Channel::Fg)]),
+    ("--tok-function", &[("Function", Channel::Fg)]),
+    ("--tok-constant", &[("Constant", Channel::Fg)]),
+    ("--tok-variable", &[("Identifier", Channel::Fg)]),
+    (
+        "--tok-property",
+        &[("@property", Channel::Fg), ("Identifier", Channel::Fg)],
+    ),
+];
+
+/// The highlight groups read from the editor for [`editor_palette_css`].
+pub fn editor_groups() -> Vec<&'static str> {
+    let mut groups: Vec<&str> = Vec::new();
+    for (_, sources) in EDITOR_PALETTE {
+        for (group, _) in *sources {
+            if !groups.contains(group) {
+                groups.push(group);
+            }
+        }
+    }
+    groups
+}
+
+/// A palette built from the editor's highlight groups. It overrides every
+/// `data-theme`; properties no group sets keep the built-in value, and an
+/// empty map gives an empty stylesheet.
+pub fn editor_palette_css(highlights: &HashMap<String, EditorHighlight>) -> String {
+    let properties: Vec<(&str, &str)> = EDITOR_PALETTE
+        .iter()
+        .filter_map(|(name, sources)| {
+            let value = sources.iter().find_map(|(group, channel)| {
+                let highlight = highlights.get(*group)?;
+                match channel {
+                    Channel::Fg => highlight.fg.as_deref(),
+                    Channel::Bg => highlight.bg.as_deref(),
+                }
+            })?;
+            Some((*name, value))
+        })
+        .collect();
+
+    let mut css = String::new();
+    if !properties.is_empty() {
+        push_rule(&mut css, ":root, :root[data-theme]", &properties);
+    }
+    css
+}
+
+/// The palettes as CSS: dark by default, light under `data-theme="light"`,
+/// and under `data-theme="auto"` whichever the browser's color scheme asks for.
+pub fn palette_css() -> String {
+    let mut css = String::new();
+    push_rule(&mut css, ":root", DARK);
+    push_rule(&mut css, ":root[data-theme=\"light\"]", LIGHT);
+    css.push_str("@media (prefers-color-scheme: light) {\n");
+    push_rule(&mut css, ":root[data-theme=\"auto\"]", LIGHT);
+    css.push_str("}\n");
+    css
+}
+
+/// The `data-theme` a page starts with, `None` for the dark default.
+pub fn default_theme_attr(mode: ThemeMode) -> Option<&'static str> {
+    match mode {
+        ThemeMode::Dark => None,
+        ThemeMode::Light => Some("light"),
+        ThemeMode::Auto => Some("auto"),
+    }
+}
+
+fn push_rule(css: &mut String, selector: &str, properties: &[(&str, &str)]) {
+    css.push_str(selector);
+    css.push_str(" {\n");
+    for (name, value) in properties {
+        css.push_str("    ");
+        css.push_str(name);
+        css.push_str(": ");
+        css.push_str(value);
+        css.push_str(";\n");
+    }
+    css.push_str("}\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DARK, EditorHighlight, LIGHT, editor_groups, editor_palette_css, palette_css};
+    use std::collections::HashMap;
+
+    #[test]
+    fn auto_theme_follows_the_color_scheme() {
+        let css = palette_css();
+        assert!(css.starts_with(":root {\n    --bg: #151515;"));
+        let (_, auto) = css
+            .split_once("@media (prefers-color-scheme: light) {\n:root[data-theme=\"auto\"] {")
+            .expect("auto rule");
+        assert!(auto.contains("--bg: #fafafa;"));
+        assert!(
+            LIGHT
+                .iter()
+                .all(|(name, _)| DARK.iter().any(|(dark, _)| dark == name))
+        );
+    }
+
+    #[test]
+    fn editor_palette_falls_back_across_groups() {
+        let highlight = |fg: Option<&str>, bg: Option<&str>| EditorHighlight {
+            fg: fg.map(str::to_string),
+            bg: bg.map(str::to_string),
+        };
+        let highlights = HashMap::from([
+            (
+                String::from("Normal"),
+                highlight(Some("#c0caf5"), Some("#1a1b26")),
+            ),
+            (String::from("Title"), highlight(None, None)),
+            (String::from("String"), highlight(Some("#9ece6a"), None)),
+        ]);
+
+        let css = editor_palette_css(&highlights);
+        assert!(css.starts_with(":root, :root[data-theme] {\n"));
+        assert!(css.contains("    --bg: #1a1b26;\n"));
+        assert!(css.contains("    --text-strong: #c0caf5;\n"));
+        assert!(css.contains("    --tok-string: #9ece6a;\n"));
+        assert!(!css.contains("--text-muted"));
+        assert!(editor_palette_css(&HashMap::new()).is_empty());
+        assert_eq!(
+            editor_groups()
+                .iter()
+                .filter(|group| **group == "Normal")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use image::ImageFormat;
+use image::imageops::FilterType;
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Images smaller than this are cheap enough to send as they are.
+pub const THUMBNAIL_MIN_BYTES: u64 = 512 * 1024;
+const CACHE_MAX_BYTES: usize = 64 * 1024 * 1024;
+/// Bounds the entries that hold no bytes, which the byte budget never sees.
+const CACHE_MAX_ENTRIES: usize = 4096;
+const WIDTH_BUCKETS: &[u32] = &[320, 640, 960, 1280, 1920, 2560];
+
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub bytes: Arc<Vec<u8>>,
+    pub content_type: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ThumbnailKey {
+    pub path: PathBuf,
+    pub etag: String,
+    pub width: u32,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    // `None` remembers images that did not need scaling.
+    entries: HashMap<ThumbnailKey, Option<Thumbnail>>,
+    order: VecDeque<ThumbnailKey>,
+    bytes: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ThumbnailCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl ThumbnailCache {
+    pub fn get(&self, key: &ThumbnailKey) -> Option<Option<Thumbnail>> {
+        self.lock().entries.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: ThumbnailKey, thumbnail: Option<Thumbnail>) {
+        let mut state = self.lock();
+        let size = thumbnail.as_ref().map_or(0, |thumb| thumb.bytes.len());
+        if let Some(previous) = state.entries.insert(key.clone(), thumbnail) {
+            state.bytes -= previous.map_or(0, |thumb| thumb.bytes.len());
+            state.order.retain(|existing| existing != &key);
+        }
+        state.bytes += size;
+        state.order.push_back(key);
+
+        while state.bytes > CACHE_MAX_BYTES || state.entries.len() > CACHE_MAX_ENTRIES {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.bytes -= evicted.map_or(0, |thumb| thumb.bytes.len());
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Rounds a requested width up to a fixed bucket so cache entries are shared
+/// between similar viewports. Widths beyond the largest bucket get originals.
+pub fn thumbnail_width(requested: u32) -> Option<u32> {
+    WIDTH_BUCKETS
+        .iter()
+        .copied()
+        .find(|bucket| *bucket >= requested.max(1))
+}
+
+/// Decodes and shrinks an image to at most `max_width` pixels wide. Returns
+/// `None` when the image is already narrow enough or cannot be decoded, and
+/// for GIFs, which would lose their animation.
+pub fn downscale(bytes: &[u8], max_width: u32) -> Option<Thumbnail> {
+    let format = image::guess_format(bytes).ok()?;
+    let (output_format, content_type) = match format {
+        ImageFormat::Jpeg => (ImageFormat::Jpeg, "image/jpeg"),
+        ImageFormat::Png | ImageFormat::WebP => (ImageFormat::Png, "image/png"),
+        _ => return None,
+    };
+
+    let decoded = image::load_from_memory_with_format(bytes, format).ok()?;
+    if decoded.width() <= max_width {
+        return None;
+    }
+
+    let scaled = decoded.resize(max_width, u32::MAX, FilterType::Triangle);
+    let mut out = Cursor::new(Vec::new());
+    scaled.write_to(&mut out, output_format).ok()?;
+
+    Some(Thumbnail {
+        bytes: Arc::new(out.into_inner()),
+        content_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CACHE_MAX_BYTES, CACHE_MAX_ENTRIES, Thumbnail, ThumbnailCache, ThumbnailKey, downscale,
+        thumbnail_width,
+    };
+    use image::{ImageFormat, RgbaImage};
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn key(name: &str) -> ThumbnailKey {
+        ThumbnailKey {
+            path: PathBuf::from(name),
+            etag: String::from("W/\"1-2\""),
+            width: 640,
+        }
+    }
+
+    #[test]
+    fn snaps_requested_widths_to_buckets() {
+        assert_eq!(thumbnail_width(0), Some(320));
+        assert_eq!(thumbnail_width(700), Some(960));
+        assert_eq!(thumbnail_width(1280), Some(1280));
+        assert_eq!(thumbnail_width(4000), None);
+    }
+
+    #[test]
+    fn evicts_oldest_entries_past_the_byte_budget() {
+        let cache = ThumbnailCache::default();
+        let big = || {
+            Some(Thumbnail {
+                bytes: Arc::new(vec![0; CACHE_MAX_BYTES / 2]),
+                content_type: "image/png",
+            })
+        };
+
+        cache.insert(key("a.png"), big());
+        cache.insert(key("narrow.png"), None);
+        cache.insert(key("b.png"), big());
+        assert!(cache.get(&key("a.png")).is_some());
+
+        cache.insert(key("c.png"), big());
+        assert!(cache.get(&key("a.png")).is_none());
+        assert!(matches!(cache.get(&key("narrow.png")), Some(None)));
+        assert!(matches!(cache.get(&key("b.png")), Some(Some(_))));
+        assert!(matches!(cache.get(&key("c.png")), Some(Some(_))));
+    }
+
+    #[test]
+    fn evicts_entries_without_bytes_past_the_entry_limit() {
+        let cache = ThumbnailCache::default();
+        for index in 0..=CACHE_MAX_ENTRIES {
+            cache.insert(key(&format!("{index}.png")), None);
+        }
+
+        assert!(cache.get(&key("0.png")).is_none());
+        assert!(cache.get(&key("1.png")).is_some());
+        assert_eq!(cache.lock().entries.len(), CACHE_MAX_ENTRIES);
+    }
+
+    #[test]
+    fn leaves_gifs_animated() {
+        let encode = |format| {
+            let mut out = Cursor::new(Vec::new());
+            RgbaImage::new(800, 4)
+                .write_to(&mut out, format)
+                .expect("encode");
+            out.into_inner()
+        };
+
+        assert!(downscale(&encode(ImageFormat::Png), 320).is_some());
+        assert!(downscale(&encode(ImageFormat::Gif), 320).is_none());
+    }
+}
@@ -0,0 +1,52 @@
+/// Bytes of randomness in a token: 128 bits.
+const TOKEN_BYTES: usize = 16;
+
+/// A fresh 128-bit token from the operating system's random source, as 32
+/// lowercase hex digits. Fails rather than mint a guessable token when the
+/// source is unavailable.
+pub fn random_token() -> std::io::Result<String> {
+    let mut bytes = [0; TOKEN_BYTES];
+    getrandom::fill(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Whether `token` has the shape [`random_token`] produces, so requests with
+/// anything else can be turned away before any comparison.
+pub fn is_well_formed(token: &str) -> bool {
+    token.len() == TOKEN_BYTES * 2
+        && token
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+/// Compares without stopping at the first difference, so how long it takes
+/// says nothing about how much of a guess was right.
+pub fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    let difference = left
+        .iter()
+        .zip(right)
+        .fold(0u8, |difference, (left, right)| difference | (left ^ right));
+    std::hint::black_box(difference) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, is_well_formed, random_token};
+
+    #[test]
+    fn tokens_are_random_hex_and_compare_whole() {
+        let token = random_token().expect("randomness");
+        assert!(is_well_formed(&token));
+        assert_ne!(token, random_token().expect("randomness"));
+
+        assert!(!is_well_formed("0123456789ABCDEF0123456789abcdef"));
+        assert!(!is_well_formed("../../etc/passwd"));
+
+        assert!(constant_time_eq(token.as_bytes(), token.clone().as_bytes()));
+        assert!(!constant_time_eq(b"abcd", b"abce"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}